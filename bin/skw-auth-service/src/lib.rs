@@ -3,56 +3,45 @@ pub mod util;
 pub mod routes;
 mod env;
 
+use std::{sync::Arc, time::Duration};
+
 use futures::{channel::{mpsc, oneshot}, SinkExt};
-use skw_mpc_storage::{db::{DBOpIn, DBOpOut}, types::MpcStorageError};
+use skw_mpc_storage::{
+    db::{DBOpIn, DBOpOut}, types::{MpcStorageError, Namespace},
+    ExpiringStorage, LevelDbStorage, MpcStorage, Sweeper,
+};
 
 #[derive(Clone)]
 pub struct ServerState {
-    storage_in_sender: mpsc::Sender<DBOpIn>
+    storage: Arc<ExpiringStorage<LevelDbStorage>>,
 }
 
 impl ServerState {
     pub fn new(storage_in_sender: &mpsc::Sender<DBOpIn>) -> Self {
-        Self { storage_in_sender: storage_in_sender.clone() }
+        let storage = LevelDbStorage::new(storage_in_sender.clone());
+        Self { storage: Arc::new(ExpiringStorage::new(storage)) }
+    }
+
+    /// Deletes expired challenges every `interval` until the sweeper is dropped
+    pub fn spawn_sweeper(&self, interval: Duration) -> Sweeper {
+        self.storage.clone().spawn_sweeper(interval)
     }
 
     pub async fn write_to_db(&mut self, key: [u8; 32], value: Vec<u8>) -> Result<(), MpcStorageError> {
-        let (i, o) = oneshot::channel();
-        let op = DBOpIn::WriteToDB { key, value, result_sender: i};
-        self.storage_in_sender.send(op).await.expect("db server must be running");
-        let res = o.await.expect("db server must be running");
+        self.storage.put(Namespace::Auth, &key, value).await
+    }
 
-        if let DBOpOut::WriteToDB { status } = res {
-            status
-        } else {
-            unreachable!()
-        }
+    /// Stores a record that reads as missing once `ttl` has passed
+    pub async fn write_to_db_with_ttl(&mut self, key: [u8; 32], value: Vec<u8>, ttl: Duration) -> Result<(), MpcStorageError> {
+        self.storage.put_with_ttl(Namespace::Auth, &key, value, ttl).await
     }
 
     pub async fn read_from_db(&mut self, key: [u8; 32]) -> Result<Vec<u8>, MpcStorageError> {
-        let (i, o) = oneshot::channel();
-        let op = DBOpIn::ReadFromDB { key, result_sender: i };
-        self.storage_in_sender.send(op).await.expect("db server must be running");
-        let res = o.await.expect("db server must be running");
-
-        if let DBOpOut::ReadFromDB { status } = res {
-            status
-        } else {
-            unreachable!()
-        }
+        self.storage.get(Namespace::Auth, &key).await
     }
 
     pub async fn shutdown_db(&mut self) -> Result<(), MpcStorageError> {
-        let (i, o) = oneshot::channel();
-        let op = DBOpIn::Shutdown { result_sender: i };
-        self.storage_in_sender.send(op).await.expect("db server must be running");
-        let res = o.await.expect("db server must be running");
-
-        if let DBOpOut::Shutdown { status } = res {
-            status
-        } else {
-            unreachable!()
-        }
+        self.storage.close().await
     }
 }
 
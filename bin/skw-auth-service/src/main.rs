@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use skw_auth_service::{
 	ServerState,
 	// routes::email::{email_auth_init, email_auth_validate},
@@ -6,7 +8,7 @@ use skw_auth_service::{
 	routes::misc::peer_ids, shutdown_db,
 	// routes::usage::{usage_link, usage_validate}, shutdown_db
 };
-use skw_mpc_storage::{run_db_server, default_mpc_storage_opt};
+use skw_mpc_storage::{open_db_server, default_mpc_storage_opt, Namespace};
 use tide::{utils::{After}, Response, StatusCode, http::headers::HeaderValue};
 use tide::security::{CorsMiddleware, Origin};
 
@@ -21,12 +23,21 @@ async fn main() {
 	let (storage_config, storage_in_sender) = default_mpc_storage_opt(
         format!("oauth-preimage-storage"), false
     );
-	run_db_server(storage_config);
+	// preimages stored before namespaces are moved into the auth namespace
+	let db_server = match open_db_server(storage_config.with_legacy_namespace(Namespace::Auth)) {
+		Ok(db_server) => db_server,
+		Err(e) => {
+			log::error!("Failed to open Level DB {:?}", e);
+			return;
+		}
+	};
+	async_std::task::spawn(db_server);
 	log::info!("Level DB server started.");
 
 
 	// --- Start web server ---
 	let state = ServerState::new(&storage_in_sender);
+	let _sweeper = state.spawn_sweeper(Duration::from_secs(60));
 	let mut app = tide::with_state(state);
 
 	app.with(
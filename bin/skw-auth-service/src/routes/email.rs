@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use skw_mpc_auth::GAProof;
 use skw_mpc_auth::{
     GAProofSystem,
@@ -33,10 +35,12 @@ pub async fn email_auth_init(mut req: Request<ServerState>) -> tide::Result<Emai
     let (verifier, credential_hash) = EmailProofOfOwnership::generate_challenge(&config, &email)
         .map_err(|e| tide::Error::from_str(500, format!("EmailProofOfOwnership Error {:?}", e)) )?;
 
+    // the challenge is of no use once the email auth timeout has passed
     server_state
-        .write_to_db(
+        .write_to_db_with_ttl(
             credential_hash.clone(), 
-            serde_json::to_vec(&verifier).expect("verifier should be able to serialize to json")
+            serde_json::to_vec(&verifier).expect("verifier should be able to serialize to json"),
+            Duration::from_secs(config.code_expiration_time()),
         ).await
         .map_err(|e| tide::Error::from_str(500, format!("EMailProofOfOwnership Error {:?}", e)) )?;
 
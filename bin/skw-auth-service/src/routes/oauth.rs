@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use skw_mpc_auth::ownership::oauth::OAuthCredential;
 use skw_mpc_auth::types::CryptoHash;
 use skw_mpc_auth::{
@@ -11,10 +13,14 @@ use serde::Deserialize;
 use crate::ServerState;
 use crate::env::EnvironmentVar;
 
-fn oauth_validation(provider: String, email: String, token: String) -> Result<Ed25519Proof, tide::Error> {
+fn oauth_config() -> OAuthTokenProofOfOwnershipConfig {
     let env = EnvironmentVar::load();
+    OAuthTokenProofOfOwnershipConfig::new(env.client_oauth_secret, env.ownership_prover_key)
+}
+
+fn oauth_validation(provider: String, email: String, token: String) -> Result<Ed25519Proof, tide::Error> {
     let credential = OAuthCredential::new(provider, email);
-    let config = OAuthTokenProofOfOwnershipConfig::new(env.client_oauth_secret, env.ownership_prover_key);
+    let config = oauth_config();
 
     let verifier = OAuthTokenProofOfOwnership::generate_challenge(&config, &credential)
         .map_err(|e| tide::Error::from_str(500, format!("OAuthProofOfOwnership Error {:?}", e)) )?;
@@ -30,10 +36,8 @@ fn oauth_validation(provider: String, email: String, token: String) -> Result<Ed
 }
 
 fn get_credential_hash(provider: String, email: String) -> Result<CryptoHash, tide::Error> {
-    let env = EnvironmentVar::load();
-
     let credential = OAuthCredential::new(provider, email);
-    let config = OAuthTokenProofOfOwnershipConfig::new(env.client_oauth_secret, env.ownership_prover_key);
+    let config = oauth_config();
     OAuthTokenProofOfOwnership::get_credential_hash(&config, &credential)
         .map_err(|e| tide::Error::from_str(500, format!("OAuthProofOfOwnership Error {:?}", e)) )
 
@@ -102,11 +106,12 @@ pub async fn oauth_auth_confirm(mut req: Request<ServerState>) -> tide::Result<O
 
     let credential_hash = get_credential_hash(provider, email)?;
 
-    // store the preimage
-    // if the preimage exists - overwriting is ok - values are the same
+    // store the preimage until it expires
+    // if the preimage exists - overwriting is ok - values are the same, the expiry is renewed
     server_state
-        .write_to_db(credential_hash.clone(), serde_json::to_vec(&credential)
-            .map_err(|e| tide::Error::from_str(500, format!("OAuthProofOfOwnership Error {:?}", e)) )?
+        .write_to_db_with_ttl(credential_hash.clone(), serde_json::to_vec(&credential)
+            .map_err(|e| tide::Error::from_str(500, format!("OAuthProofOfOwnership Error {:?}", e)) )?,
+            Duration::from_secs(oauth_config().preimage_expiration_time()),
         )
         .await
         .map_err(|e| tide::Error::from_str(500, format!("OAuthProofOfOwnership Error {:?}", e)) )?;
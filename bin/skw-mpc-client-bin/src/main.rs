@@ -4,11 +4,11 @@ use futures::channel::mpsc;
 use futures::StreamExt;
 
 use skw_mpc_client::{
-    swarm::{new_swarm_node},
+    swarm::{new_swarm_node, SkwMpcP2pCodec},
     async_executor,
 };
 use skw_mpc_node::{
-    node::{NodeClient, light_node_event_loop},
+    node::{NodeClient, light_node_event_loop, StorageBackend},
 };
 
 const LISTEN_ADDR: &str = "127.0.0.1";
@@ -25,7 +25,7 @@ async fn main() {
     .bootstrap_node(
         None,
         format!("/ip4/{}/tcp/2622/ws", LISTEN_ADDR),
-        "mpc-storage-db-light-node".to_string() // no db will actually be initialized
+        StorageBackend::InMemory // light nodes keep no key shards
     ).await;
 
     async_executor(async move {
@@ -42,7 +42,7 @@ async fn main() {
         mut client,
         event_loop,
         _termination_sender,
-    ) = new_swarm_node( light_node_client, None );
+    ) = new_swarm_node( light_node_client, None, SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     client
@@ -0,0 +1,122 @@
+//! Exports the key store of a full node to an encrypted archive, or imports one
+//!
+//! ```text
+//! skw-mpc-backup export <db path> <archive> [--key-file <path>]
+//! skw-mpc-backup import <db path> <archive> [--key-file <path>] [--dry-run]
+//! ```
+//!
+//! The archive is encrypted with the 32 byte key in `--key-file`, or a key derived from
+//! `MPC_BACKUP_PASSPHRASE`. The store itself is opened like `skw-mpc-node-bin` does, so the
+//! storage master key has to be configured the same way. The node must not be running.
+
+use std::{fs, path::PathBuf, process};
+
+use futures::channel::mpsc;
+use skw_mpc_node::{
+    node::{full_node_event_loop, NodeClient, MasterKeySource},
+    async_executor
+};
+
+mod storage;
+use storage::storage;
+
+const USAGE: &str = "usage: skw-mpc-backup <export|import> <db path> <archive> [--key-file <path>] [--dry-run]";
+
+enum Command {
+    Export,
+    Import { dry_run: bool },
+}
+
+struct Args {
+    command: Command,
+    db_path: String,
+    archive: PathBuf,
+    key: MasterKeySource,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut key_file = None;
+    let mut dry_run = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key-file" => key_file = Some(args.next().ok_or("--key-file needs a path")?),
+            "--dry-run" => dry_run = true,
+            _ => positional.push(arg),
+        }
+    }
+
+    let [command, db_path, archive]: [String; 3] = positional
+        .try_into()
+        .map_err(|_| USAGE.to_string())?;
+    let command = match command.as_str() {
+        "export" if !dry_run => Command::Export,
+        "import" => Command::Import { dry_run },
+        _ => return Err(USAGE.to_string()),
+    };
+    let key = match (key_file, std::env::var("MPC_BACKUP_PASSPHRASE")) {
+        (Some(path), _) => MasterKeySource::File(path.into()),
+        (None, Ok(passphrase)) => MasterKeySource::Passphrase(passphrase),
+        (None, Err(_)) => return Err("backup key missing, pass --key-file or set MPC_BACKUP_PASSPHRASE".to_string()),
+    };
+
+    Ok(Args { command, db_path, archive: archive.into(), key })
+}
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+
+    let (client_request_sender, client_request_receiver) = mpsc::channel(0);
+    async_executor(full_node_event_loop(client_request_receiver));
+
+    let mut client = NodeClient::new(client_request_sender);
+    // later errors of the node are reported to `_node_errors` until it is shut down
+    let _node_errors = client
+        .try_bootstrap_node(None, "/ip4/127.0.0.1/tcp/0/ws".to_string(), storage(&args.db_path))
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to open {}: {}", args.db_path, e);
+            process::exit(1);
+        });
+    let node = client.peer_id();
+
+    let result = match args.command {
+        Command::Export => client
+            .export_backup(node, args.key)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|archive| fs::write(&args.archive, archive).map_err(|e| e.to_string()))
+            .map(|_| format!("Exported backup to {}", args.archive.display())),
+        Command::Import { dry_run } => match fs::read(&args.archive) {
+            Ok(archive) => client
+                .import_backup(node, archive, args.key, dry_run)
+                .await
+                .map_err(|e| e.to_string())
+                .map(|report| format!(
+                    "{} shards, {} verified against their public key, {} without metadata{}",
+                    report.shards, report.verified, report.without_metadata,
+                    if report.applied { ", imported" } else { ", dry run - nothing written" },
+                )),
+            Err(e) => Err(e.to_string()),
+        },
+    };
+
+    if let Err(e) = client.shutdown(node).await {
+        log::error!("Failed to shut down the node {:?}", e);
+    }
+    match result {
+        Ok(summary) => println!("{}", summary),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -6,6 +6,9 @@ use skw_mpc_node::{
     async_executor
 };
 
+mod storage;
+use storage::storage;
+
 const LISTEN_ADDR: &str = "127.0.0.1";
 
 #[tokio::main]
@@ -22,7 +25,7 @@ async fn main() {
         .bootstrap_node(
             None, 
             format!("/ip4/{}/tcp/2620/ws", LISTEN_ADDR), 
-            "mpc-storage-db-fullnode1".to_string()
+            storage("mpc-storage-db-fullnode1")
         ).await;
     
     let peer_id_1 = client.peer_id();
@@ -31,7 +34,7 @@ async fn main() {
         .bootstrap_node(
             None, 
             format!("/ip4/{}/tcp/2621/ws", LISTEN_ADDR),
-            "mpc-storage-db-fullnode2".to_string()
+            storage("mpc-storage-db-fullnode2")
         ).await;
     
     let peer_id_2 = client.peer_id();
@@ -0,0 +1,47 @@
+//! Upgrades every record of a full node key store to the current schema version
+//!
+//! ```text
+//! skw-mpc-migrate <db path>
+//! ```
+//!
+//! The store is opened like `skw-mpc-node-bin` does, so the storage master key has to be
+//! configured the same way. The node must not be running.
+
+use std::process;
+
+use skw_mpc_node::{
+    node::{storage_schema, MigratingStorage, MpcStorage},
+    async_executor
+};
+
+mod storage;
+use storage::storage;
+
+#[tokio::main]
+async fn main() {
+    pretty_env_logger::init();
+
+    let db_path = match std::env::args().skip(1).collect::<Vec<_>>().as_slice() {
+        [db_path] => db_path.clone(),
+        _ => {
+            eprintln!("usage: skw-mpc-migrate <db path>");
+            process::exit(2);
+        }
+    };
+
+    let result = match storage(&db_path).open(async_executor).await {
+        Ok(storage) => {
+            let storage = MigratingStorage::new(storage, storage_schema());
+            let migrated = storage.migrate().await;
+            storage.close().await.and(migrated)
+        },
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(migrated) => println!("Migrated {} records", migrated),
+        Err(e) => {
+            eprintln!("Failed to migrate {}", e);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Replays a keygen or offline signing job recorded by a full node
+//!
+//! ```text
+//! skw-mpc-replay <transcript> [--key-file <path>]
+//! ```
+//!
+//! Nodes record jobs with `MPC_TRANSCRIPT_DIR` set. The secret inputs in the transcript are
+//! opened with the 32 byte key in `--key-file` or `MPC_TRANSCRIPT_KEY_FILE`, or a key derived
+//! from `MPC_TRANSCRIPT_PASSPHRASE` - the same the node recorded them with. The job is run again
+//! from the recorded messages and its outcome printed, a replay that stops sending the messages
+//! the node sent reports where it diverged.
+
+use std::{fs::File, io::BufReader, process};
+
+use skw_mpc_node::node::{transcripts, MasterKeySource};
+
+const USAGE: &str = "usage: skw-mpc-replay <transcript> [--key-file <path>]";
+
+struct Args {
+    transcript: String,
+    key: MasterKeySource,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut key_file = std::env::var("MPC_TRANSCRIPT_KEY_FILE").ok();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key-file" => key_file = Some(args.next().ok_or("--key-file needs a path")?),
+            _ => positional.push(arg),
+        }
+    }
+
+    let [transcript]: [String; 1] = positional
+        .try_into()
+        .map_err(|_| USAGE.to_string())?;
+    let key = match (key_file, std::env::var("MPC_TRANSCRIPT_PASSPHRASE")) {
+        (Some(path), _) => MasterKeySource::File(path.into()),
+        (None, Ok(passphrase)) => MasterKeySource::Passphrase(passphrase),
+        (None, Err(_)) => return Err("transcript key missing, pass --key-file or set MPC_TRANSCRIPT_PASSPHRASE".to_string()),
+    };
+
+    Ok(Args { transcript, key })
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(2);
+    });
+
+    let replayed = File::open(&args.transcript)
+        .map_err(|e| e.to_string())
+        .and_then(|file| transcripts::replay(BufReader::new(file), &args.key).map_err(|e| e.to_string()));
+    match replayed {
+        Ok(outcome) => println!("{}", outcome),
+        Err(e) => {
+            eprintln!("Failed to replay {}: {}", args.transcript, e);
+            process::exit(1);
+        }
+    }
+}
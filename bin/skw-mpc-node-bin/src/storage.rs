@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use skw_mpc_node::node::{StorageBackend, MasterKeySource, Durability};
+
+/// `MPC_STORAGE_GROUP_COMMIT_MS` trades a flush per write for one flush per N ms of writes
+fn durability() -> Durability {
+    match std::env::var("MPC_STORAGE_GROUP_COMMIT_MS").ok().and_then(|ms| ms.parse().ok()) {
+        Some(ms) => Durability::GroupCommit { interval: Duration::from_millis(ms) },
+        None => Durability::EveryWrite,
+    }
+}
+
+/// Key shards are encrypted at rest when a master key is configured, see `MasterKeySource::from_env`
+pub fn storage(path: &str) -> StorageBackend {
+    let backend = StorageBackend::LevelDb { path: path.to_string(), durability: durability() };
+    match MasterKeySource::from_env() {
+        Some(master_key) => StorageBackend::Encrypted { backend: Box::new(backend), master_key },
+        None => {
+            log::warn!("No storage master key configured, key shards are stored unencrypted");
+            backend
+        }
+    }
+}
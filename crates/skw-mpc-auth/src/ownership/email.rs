@@ -20,6 +20,11 @@ impl EmailProofOfOwnershipConfig {
         signature_secret_key: [u8; 32]) -> Self {
         Self { code_expiration_time, signature_secret_key }
     }
+
+    /// Seconds an issued code stays valid
+    pub fn code_expiration_time(&self) -> Timestamp {
+        self.code_expiration_time
+    }
 }
 
 impl Default for EmailProofOfOwnershipConfig {
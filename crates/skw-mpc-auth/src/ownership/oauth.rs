@@ -6,7 +6,7 @@ use crate::{
     ProofSystem, SelfProveableSystem,
     JweProofSystem, Ed25519SelfProveableSystem, Ed25519ProverConfig,
 };
-use crate::types::CryptoHash;
+use crate::types::{CryptoHash, Timestamp};
 
 pub struct OAuthTokenProofOfOwnership();
 
@@ -25,6 +25,7 @@ impl OAuthCredential {
 pub struct OAuthTokenProofOfOwnershipConfig {
     client_side_secret: String,
     signature_secret_key: [u8; 32],
+    preimage_expiration_time: Timestamp,
 }
 
 impl Into<Ed25519ProverConfig> for OAuthTokenProofOfOwnershipConfig {
@@ -34,8 +35,19 @@ impl Into<Ed25519ProverConfig> for OAuthTokenProofOfOwnershipConfig {
 }
 
 impl OAuthTokenProofOfOwnershipConfig {
+    /// Credential preimages expire after 10mins by default
     pub fn new(client_side_secret: String, signature_secret_key: [u8; 32]) -> Self {
-        Self {client_side_secret, signature_secret_key}
+        Self {client_side_secret, signature_secret_key, preimage_expiration_time: 600}
+    }
+
+    pub fn with_preimage_expiration_time(mut self, preimage_expiration_time: Timestamp) -> Self {
+        self.preimage_expiration_time = preimage_expiration_time;
+        self
+    }
+
+    /// Seconds a confirmed credential preimage is kept
+    pub fn preimage_expiration_time(&self) -> Timestamp {
+        self.preimage_expiration_time
     }
 }
 
@@ -1,10 +1,10 @@
 use futures::{channel::mpsc, StreamExt};
 use skw_mpc_client::{
-    swarm::{new_swarm_node},
+    swarm::{new_swarm_node, SkwMpcP2pCodec},
     async_executor,
 };
 use skw_mpc_node::{
-    node::{NodeClient, light_node_event_loop},
+    node::{NodeClient, light_node_event_loop, StorageBackend},
 };
 
 #[tokio::main]
@@ -19,7 +19,7 @@ async fn main() {
         .bootstrap_node(
             Some([3u8; 32]), 
             "/ip4/100.104.199.31/tcp/2622/ws".to_string(),
-            "mpc-storage-db-12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".to_string()
+            StorageBackend::InMemory
         ).await;
     
     async_executor(async move {
@@ -34,7 +34,7 @@ async fn main() {
         mut client,
         event_loop,
         _termination_sender,
-    ) = new_swarm_node( light_node_client, Some([4u8; 32]) );
+    ) = new_swarm_node( light_node_client, Some([4u8; 32]), SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     client
@@ -3,7 +3,7 @@
 use libp2p::{PeerId, Multiaddr};
 use skw_mpc_client::{
     async_executor,
-    swarm::{new_swarm_node, MpcP2pRequest}
+    swarm::{new_swarm_node, MpcP2pRequest, SkwMpcP2pCodec}
 };
 use skw_mpc_node::serde_support::{decode_signature, decode_key};
 use skw_mpc_payload::{PayloadHeader, header::PayloadType, AuthHeader};
@@ -19,17 +19,17 @@ async fn main() {
 
     let node1 = (
         "12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2622/ws/p2p/12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap()
+        Some("/ip4/100.104.199.31/tcp/2622/ws/p2p/12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap())
     );
 
     let node2 = (
         "12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2621/ws/p2p/12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap()
+        Some("/ip4/100.104.199.31/tcp/2621/ws/p2p/12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap())
     );
 
     let node3 = (
         "12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2620/ws/p2p/12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap()  
+        Some("/ip4/100.104.199.31/tcp/2620/ws/p2p/12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap())  
     );
 
     let keygen_request = PayloadHeader {
@@ -53,7 +53,7 @@ async fn main() {
 
     let sign_request = PayloadHeader {
         payload_id: [1u8; 32],
-        payload_type: PayloadType::SignOffline { message: [2u8; 32] },
+        payload_type: PayloadType::SignOffline { message: [2u8; 32], parties: vec![1, 2] },
         peers: vec![node1.clone(), node2.clone()],
         sender: node1.0,
 
@@ -71,7 +71,7 @@ async fn main() {
 
     let sign2_request = PayloadHeader {
         payload_id: [3u8; 32],
-        payload_type: PayloadType::SignOffline { message: [2u8; 32] },
+        payload_type: PayloadType::SignOffline { message: [2u8; 32], parties: vec![1, 2] },
         peers: vec![node1.clone(), node2.clone()],
         sender: node1.0,
 
@@ -80,7 +80,7 @@ async fn main() {
 
     println!("KeyGEN {:?}", serde_json::to_string(&AuthHeader::test_auth_header()));
 
-    let ( _, mut client, event_loop, _) = new_swarm_node( None );
+    let ( _, mut client, event_loop, _) = new_swarm_node( None, SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     let _ = client
@@ -1,4 +1,5 @@
 use serde::{Serialize, Deserialize};
+use skw_mpc_payload::JobType;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SwarmError {
@@ -11,6 +12,15 @@ pub enum SwarmError {
 pub enum SwarmP2pError {
     ResponseChannelClose,
     OutboundFailure,
+    MessageTooLarge { size: u64, limit: u64 },
+    ResponseLimitTooSmall { limit: u64, min: u64 },
+    ChunkUnavailable,
+    IncompleteTransfer,
+    TooManyChunks { chunks: u32, limit: u32 },
+    IncompatibleProtocolVersion { local: Vec<u16>, remote: Vec<u16> },
+    NoCommonCurve,
+    NoCommonEncoding,
+    UnsupportedJobType(JobType),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -4,12 +4,20 @@ use libp2p::{
 };
 
 // re-export
-pub use self::skw_mpc_p2p_behavior::{SkwMpcP2pCodec, SkwMpcP2pProtocol, MpcP2pRequest, MpcP2pResponse};
+pub use self::skw_mpc_p2p_behavior::{
+    SkwMpcP2pCodec, SkwMpcP2pProtocol, MpcP2pRequest, MpcP2pResponse,
+    DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
+};
+pub use self::skw_mpc_handshake_behavior::{
+    SkwMpcHandshakeCodec, SkwMpcHandshakeProtocol,
+};
 
 #[derive(NetworkBehaviour)]
 pub struct MpcSwarmBahavior {
     // node p2p behavior
     pub request_response: request_response::Behaviour<SkwMpcP2pCodec>,
+    // capability handshake ahead of any request
+    pub handshake: request_response::Behaviour<SkwMpcHandshakeCodec>,
 }
 
 // Sub protocol - p2p request-response
@@ -18,16 +26,127 @@ pub mod skw_mpc_p2p_behavior {
     use tokio::io;
     use futures::prelude::*;
 
-    use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+    use libp2p::core::upgrade::{read_varint, write_length_prefixed, ProtocolName};
     use libp2p::request_response::Codec;
-    use skw_mpc_payload::{AuthHeader, PayloadHeader};
+    use skw_mpc_payload::{AuthHeader, LegacyPayloadHeader, PayloadHeader, CryptoHash};
+
+    use crate::error::{MpcClientError, SwarmP2pError};
+
+    /// Default upper bound of a serialized `MpcP2pRequest`
+    pub const DEFAULT_MAX_REQUEST_SIZE: usize = 100_240;
+    /// Default upper bound of a serialized `MpcP2pResponse`
+    pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 100_240;
+
+    // room left in every chunk for the enum tag, payload_id and length prefixes
+    const CHUNK_ENVELOPE_SIZE: usize = 1_024;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SkwMpcP2pProtocol {
+        /// `/skw-mpc-request/1`: job headers are laid out as `LegacyPayloadHeader`
+        V1,
+        /// `/skw-mpc-request/2`: job headers may name peers without an address and signers other
+        /// than the first parties
+        V2,
+    }
+
+    impl SkwMpcP2pProtocol {
+        /// Every version this client speaks, most preferred first
+        pub fn supported() -> Vec<Self> {
+            vec![Self::V2, Self::V1]
+        }
 
-    use crate::error::MpcClientError;
+        pub fn version(&self) -> u16 {
+            match self {
+                Self::V1 => 1,
+                Self::V2 => 2,
+            }
+        }
 
+        pub fn from_version(version: u16) -> Option<Self> {
+            match version {
+                1 => Some(Self::V1),
+                2 => Some(Self::V2),
+                _ => None,
+            }
+        }
+    }
+
+    /// Codec for `/skw-mpc-request`. Frames larger than the configured limits are
+    /// rejected on both the read and the write side.
     #[derive(Debug, Clone)]
-    pub struct SkwMpcP2pProtocol();
-    #[derive(Clone)]
-    pub struct SkwMpcP2pCodec();
+    pub struct SkwMpcP2pCodec {
+        max_request_size: usize,
+        max_response_size: usize,
+    }
+
+    impl SkwMpcP2pCodec {
+        /// Fails if `max_response_size` leaves no room for a chunk of an outcome
+        pub fn new(max_request_size: usize, max_response_size: usize) -> Result<Self, MpcClientError> {
+            if max_response_size <= CHUNK_ENVELOPE_SIZE {
+                return Err(MpcClientError::SwarmP2pError(SwarmP2pError::ResponseLimitTooSmall {
+                    limit: max_response_size as u64,
+                    min: CHUNK_ENVELOPE_SIZE as u64 + 1,
+                }));
+            }
+            Ok(Self { max_request_size, max_response_size })
+        }
+
+        pub fn max_request_size(&self) -> usize {
+            self.max_request_size
+        }
+
+        pub fn max_response_size(&self) -> usize {
+            self.max_response_size
+        }
+
+        /// Largest outcome slice that fits into one response. Bigger outcomes are
+        /// sent as `MpcChunked` and the rest is pulled with `FetchChunk`.
+        pub fn chunk_size(&self) -> usize {
+            self.max_response_size - CHUNK_ENVELOPE_SIZE
+        }
+
+        /// Check an outgoing request against the request limit before it hits the wire
+        pub fn check_request_size(&self, request: &MpcP2pRequest) -> Result<(), MpcClientError> {
+            let size = bincode::serialized_size(request)
+                .expect("request message to be valid");
+            check_size(size as usize, self.max_request_size)
+                .map_err(MpcClientError::SwarmP2pError)
+        }
+    }
+
+    impl Default for SkwMpcP2pCodec {
+        fn default() -> Self {
+            Self {
+                max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+                max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            }
+        }
+    }
+
+    fn check_size(size: usize, limit: usize) -> Result<(), SwarmP2pError> {
+        if size > limit {
+            Err(SwarmP2pError::MessageTooLarge { size: size as u64, limit: limit as u64 })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn too_large(e: SwarmP2pError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+    }
+
+    /// Same as `read_length_prefixed`, but reports the offending size when the limit is exceeded
+    async fn read_limited<T>(io: &mut T, limit: usize) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let len = read_varint(io).await?;
+        check_size(len, limit).map_err(too_large)?;
+
+        let mut buf = vec![0; len];
+        io.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
 
     // Serialized Form of raw request
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -37,6 +156,11 @@ pub mod skw_mpc_p2p_behavior {
             job_header: PayloadHeader,
             maybe_local_key: Option<Vec<u8>>,
         },
+        /// Pull chunk `index` of an outcome previously answered with `MpcChunked`
+        FetchChunk {
+            payload_id: CryptoHash,
+            index: u32,
+        },
     }
 
     // Serialized Form of raw response
@@ -44,20 +168,76 @@ pub mod skw_mpc_p2p_behavior {
     pub enum MpcP2pResponse {
         Mpc {
             payload: Result<Vec<u8>, MpcClientError>, // can be either sign or keygen output
-        }
+        },
+        /// First chunk of an outcome larger than the response limit
+        MpcChunked {
+            payload_id: CryptoHash,
+            total_chunks: u32,
+            chunk: Vec<u8>,
+        },
+        Chunk {
+            chunk: Result<Vec<u8>, MpcClientError>,
+        },
     }
 
     impl MpcP2pResponse {
+        /// Chunked outcomes are reassembled by the event loop, callers only ever see `Mpc`
         pub fn payload(&self) -> Result<Vec<u8>, MpcClientError> {
             match self {
                 Self::Mpc{ payload } => payload.clone(),
+                Self::MpcChunked { .. } | Self::Chunk { .. } => 
+                    Err(MpcClientError::SwarmP2pError(SwarmP2pError::IncompleteTransfer)),
             }
         }
     }
 
+    /// `MpcP2pRequest` as laid out on `/skw-mpc-request/1`
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    enum MpcP2pRequestV1 {
+        Mpc {
+            auth_header: AuthHeader,
+            job_header: LegacyPayloadHeader,
+            maybe_local_key: Option<Vec<u8>>,
+        },
+        FetchChunk {
+            payload_id: CryptoHash,
+            index: u32,
+        },
+    }
+
+    fn encode_request(protocol: &SkwMpcP2pProtocol, request: MpcP2pRequest) -> io::Result<Vec<u8>> {
+        let request = match (protocol, request) {
+            (SkwMpcP2pProtocol::V1, MpcP2pRequest::Mpc { auth_header, job_header, maybe_local_key }) => {
+                let job_header = LegacyPayloadHeader::try_from(job_header)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                return Ok(bincode::serialize(&MpcP2pRequestV1::Mpc { auth_header, job_header, maybe_local_key })
+                    .expect("request message to be valid"));
+            },
+            (_, request) => request,
+        };
+        Ok(bincode::serialize(&request).expect("request message to be valid"))
+    }
+
+    fn decode_request(protocol: &SkwMpcP2pProtocol, data: &[u8]) -> io::Result<MpcP2pRequest> {
+        let request = match protocol {
+            SkwMpcP2pProtocol::V1 => match bincode::deserialize(data) {
+                Ok(MpcP2pRequestV1::Mpc { auth_header, job_header, maybe_local_key }) =>
+                    MpcP2pRequest::Mpc { auth_header, job_header: job_header.into(), maybe_local_key },
+                Ok(MpcP2pRequestV1::FetchChunk { payload_id, index }) => MpcP2pRequest::FetchChunk { payload_id, index },
+                Err(_) => return Err(io::ErrorKind::InvalidData.into()),
+            },
+            SkwMpcP2pProtocol::V2 => bincode::deserialize(data)
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?,
+        };
+        Ok(request)
+    }
+
     impl ProtocolName for SkwMpcP2pProtocol {
         fn protocol_name(&self) -> &[u8] {
-            b"/skw-mpc-request/1"
+            match self {
+                Self::V1 => b"/skw-mpc-request/1",
+                Self::V2 => b"/skw-mpc-request/2",
+            }
         }
     }
 
@@ -69,19 +249,18 @@ pub mod skw_mpc_p2p_behavior {
 
         async fn read_request<T>(
             &mut self,
-            _: &SkwMpcP2pProtocol,
+            protocol: &SkwMpcP2pProtocol,
             io: &mut T,
         ) -> io::Result<Self::Request>
         where
             T: AsyncRead + Unpin + Send,
         {
-            let vec = read_length_prefixed(io, 100_240).await?;
+            let vec = read_limited(io, self.max_request_size).await?;
 
             if vec.is_empty() {
                 return Err(io::ErrorKind::UnexpectedEof.into());
             }
-            bincode::deserialize( &vec )
-                .map_err(|_| io::ErrorKind::InvalidData.into() )
+            decode_request(protocol, &vec)
         }
 
         async fn read_response<T>(
@@ -92,7 +271,7 @@ pub mod skw_mpc_p2p_behavior {
         where
             T: AsyncRead + Unpin + Send,
         {
-            let vec = read_length_prefixed(io, 100_240).await?; // update transfer maximum
+            let vec = read_limited(io, self.max_response_size).await?;
 
             if vec.is_empty() {
                 return Err(io::ErrorKind::UnexpectedEof.into());
@@ -104,14 +283,15 @@ pub mod skw_mpc_p2p_behavior {
 
         async fn write_request<T>(
             &mut self,
-            _: &SkwMpcP2pProtocol,
+            protocol: &SkwMpcP2pProtocol,
             io: &mut T,
             raw: MpcP2pRequest,
         ) -> io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let data = bincode::serialize(&raw).expect("request message to be valid");
+            let data = encode_request(protocol, raw)?;
+            check_size(data.len(), self.max_request_size).map_err(too_large)?;
 
             write_length_prefixed(io, data).await?;
             io.close().await?;
@@ -129,11 +309,190 @@ pub mod skw_mpc_p2p_behavior {
             T: AsyncWrite + Unpin + Send,
         {
             let data = bincode::serialize(&raw).expect("response message to be valid");
+            check_size(data.len(), self.max_response_size).map_err(too_large)?;
+
             write_length_prefixed(io, data).await?;
             io.close().await?;
 
             Ok(())
         }
     }
-    
-}
\ No newline at end of file
+
+    #[cfg(test)]
+    mod test {
+        use futures::io::Cursor;
+
+        use super::*;
+
+        #[test]
+        fn response_limit_must_fit_a_chunk() {
+            assert_eq!(
+                SkwMpcP2pCodec::new(1_024, CHUNK_ENVELOPE_SIZE).unwrap_err(),
+                MpcClientError::SwarmP2pError(SwarmP2pError::ResponseLimitTooSmall {
+                    limit: CHUNK_ENVELOPE_SIZE as u64,
+                    min: CHUNK_ENVELOPE_SIZE as u64 + 1,
+                })
+            );
+        }
+
+        #[tokio::test]
+        async fn oversized_messages_are_rejected() {
+            let mut codec = SkwMpcP2pCodec::new(128, 2_048).unwrap();
+            let request = MpcP2pRequest::Mpc {
+                auth_header: AuthHeader::default(),
+                job_header: PayloadHeader::default(),
+                maybe_local_key: Some(vec![0; 256]),
+            };
+            let size = bincode::serialized_size(&request).unwrap();
+            assert_eq!(
+                codec.check_request_size(&request),
+                Err(MpcClientError::SwarmP2pError(SwarmP2pError::MessageTooLarge { size, limit: 128 }))
+            );
+
+            // write side
+            let mut wire = Cursor::new(Vec::new());
+            let err = codec.write_request(&SkwMpcP2pProtocol::V1, &mut wire, request.clone()).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(wire.get_ref().is_empty());
+
+            // read side, from a peer with a larger limit
+            let mut wire = Cursor::new(Vec::new());
+            SkwMpcP2pCodec::default()
+                .write_request(&SkwMpcP2pProtocol::V1, &mut wire, request)
+                .await
+                .unwrap();
+            wire.set_position(0);
+            let err = codec.read_request(&SkwMpcP2pProtocol::V1, &mut wire).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn v1_requests_keep_the_legacy_header_layout() {
+            let request = MpcP2pRequest::Mpc {
+                auth_header: AuthHeader::default(),
+                job_header: PayloadHeader::default(),
+                maybe_local_key: None,
+            };
+            let legacy = MpcP2pRequestV1::Mpc {
+                auth_header: AuthHeader::default(),
+                job_header: LegacyPayloadHeader::try_from(PayloadHeader::default()).unwrap(),
+                maybe_local_key: None,
+            };
+            let data = encode_request(&SkwMpcP2pProtocol::V1, request.clone()).unwrap();
+            assert_eq!(data, bincode::serialize(&legacy).unwrap());
+            assert_eq!(decode_request(&SkwMpcP2pProtocol::V1, &data).unwrap(), request);
+
+            // a header naming a peer without an address only goes out on V2
+            let mut job_header = PayloadHeader::default();
+            job_header.peers[0].1 = None;
+            let request = MpcP2pRequest::Mpc { auth_header: AuthHeader::default(), job_header, maybe_local_key: None };
+            assert!(encode_request(&SkwMpcP2pProtocol::V1, request.clone()).is_err());
+            let data = encode_request(&SkwMpcP2pProtocol::V2, request.clone()).unwrap();
+            assert_eq!(decode_request(&SkwMpcP2pProtocol::V2, &data).unwrap(), request);
+        }
+    }
+}
+
+// Sub protocol - capability handshake
+pub mod skw_mpc_handshake_behavior {
+    use tokio::io;
+    use futures::prelude::*;
+
+    use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+    use libp2p::request_response::Codec;
+
+    use crate::swarm::handshake::Handshake;
+
+    // a handshake is a few version numbers and enum lists
+    const MAX_HANDSHAKE_SIZE: usize = 4_096;
+
+    /// `/skw-mpc-handshake/1`. Peers that do not speak it are treated as `Handshake::legacy`.
+    #[derive(Debug, Clone)]
+    pub struct SkwMpcHandshakeProtocol();
+
+    #[derive(Debug, Clone, Default)]
+    pub struct SkwMpcHandshakeCodec();
+
+    impl ProtocolName for SkwMpcHandshakeProtocol {
+        fn protocol_name(&self) -> &[u8] {
+            b"/skw-mpc-handshake/1"
+        }
+    }
+
+    async fn read_handshake<T>(io: &mut T) -> io::Result<Handshake>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let vec = read_length_prefixed(io, MAX_HANDSHAKE_SIZE).await?;
+
+        if vec.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        bincode::deserialize( &vec )
+            .map_err(|_| io::ErrorKind::InvalidData.into() )
+    }
+
+    async fn write_handshake<T>(io: &mut T, handshake: Handshake) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = bincode::serialize(&handshake).expect("handshake to be valid");
+        write_length_prefixed(io, data).await?;
+        io.close().await?;
+
+        Ok(())
+    }
+
+    #[async_trait::async_trait]
+    impl Codec for SkwMpcHandshakeCodec {
+        type Protocol = SkwMpcHandshakeProtocol;
+        type Request = Handshake;
+        type Response = Handshake;
+
+        async fn read_request<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+        ) -> io::Result<Self::Request>
+        where
+            T: AsyncRead + Unpin + Send,
+        {
+            read_handshake(io).await
+        }
+
+        async fn read_response<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+        ) -> io::Result<Self::Response>
+        where
+            T: AsyncRead + Unpin + Send,
+        {
+            read_handshake(io).await
+        }
+
+        async fn write_request<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+            raw: Handshake,
+        ) -> io::Result<()>
+        where
+            T: AsyncWrite + Unpin + Send,
+        {
+            write_handshake(io, raw).await
+        }
+
+        async fn write_response<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+            raw: Handshake,
+        ) -> io::Result<()>
+        where
+            T: AsyncWrite + Unpin + Send,
+        {
+            write_handshake(io, raw).await
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use std::collections::HashMap;
+
 use libp2p::{PeerId, Multiaddr};
 use futures::{SinkExt};
 use futures::channel::{mpsc, oneshot};
 
+use skw_mpc_payload::JobType;
+
 use crate::error::MpcClientError;
 
 use super::behavior::{MpcP2pRequest, MpcP2pResponse};
+use super::handshake::RelayProtocol;
 
 #[derive(Debug)]
 pub enum MpcSwarmCommand {
@@ -24,13 +29,27 @@ pub enum MpcSwarmCommand {
         request: MpcP2pRequest,
         result_sender: oneshot::Sender<Result<MpcP2pResponse, MpcClientError>>,
     },
+    Handshake {
+        peer_id: PeerId,
+        result_sender: oneshot::Sender<Result<RelayProtocol, MpcClientError>>,
+    },
 }
 
 pub struct MpcSwarmClient {
-    pub command_sender: mpsc::UnboundedSender<MpcSwarmCommand>
+    pub command_sender: mpsc::UnboundedSender<MpcSwarmCommand>,
+
+    // outcome of the capability handshake with each relay
+    relay_protocols: HashMap<PeerId, RelayProtocol>,
 }
 
 impl MpcSwarmClient {
+    pub fn new(command_sender: mpsc::UnboundedSender<MpcSwarmCommand>) -> Self {
+        Self {
+            command_sender,
+            relay_protocols: Default::default(),
+        }
+    }
+
     /// Listen for incoming connections on the given address.
     #[cfg(feature = "full-node")]
     pub async fn start_listening(
@@ -66,7 +85,20 @@ impl MpcSwarmClient {
         result_receiver.await.expect("Sender not to be dropped.")
     }
 
+    /// Send a request to a relay. `Mpc` requests are preceded by a capability handshake
+    /// the first time the relay is used.
     pub async fn send_request(&mut self, to: PeerId, request: MpcP2pRequest) -> Result<MpcP2pResponse,  MpcClientError> {
+        if let MpcP2pRequest::Mpc { job_header, .. } = &request {
+            if !self.relay_protocols.contains_key(&to) {
+                let protocol = self.handshake(to).await?;
+                self.relay_protocols.insert(to, protocol);
+            }
+
+            self.relay_protocols[&to]
+                .ensure_job_type(JobType::from(&job_header.payload_type))
+                .map_err(|e| MpcClientError::SwarmP2pError(e.into()))?;
+        }
+
         let (result_sender, result_receiver) = oneshot::channel();
         self.command_sender
             .send(MpcSwarmCommand::SendP2pRequest { to, request, result_sender })
@@ -75,4 +107,14 @@ impl MpcSwarmClient {
         let status = result_receiver.await.expect("Sender not to be dropped.");
         status
     }
-}
\ No newline at end of file
+
+    /// Exchange capabilities with the given relay and settle on a protocol version.
+    pub async fn handshake(&mut self, peer_id: PeerId) -> Result<RelayProtocol, MpcClientError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.command_sender
+            .send(MpcSwarmCommand::Handshake { peer_id, result_sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        result_receiver.await.expect("Sender not to be dropped.")
+    }
+}
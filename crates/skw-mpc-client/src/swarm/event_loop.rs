@@ -1,34 +1,66 @@
 use core::panic;
 use std::collections::{HashMap, hash_map::Entry};
+#[cfg(feature = "full-node")]
+use std::time::{Duration, Instant};
 
 use libp2p::{
     swarm::{SwarmEvent, ConnectionHandlerUpgrErr}, PeerId,
     Swarm,
     multiaddr, 
-    request_response::{self, RequestId,}, 
+    request_response::{self, RequestId, OutboundFailure}, 
 };
 use futures::{StreamExt, FutureExt};
 use futures::channel::{oneshot, mpsc};
 
+use skw_mpc_payload::CryptoHash;
+
 #[cfg(feature = "full-node")]
 use skw_mpc_node::node::NodeClient;
 
 use crate::error::{MpcClientError, SwarmError, SwarmP2pError};
 
 use super::{
-    behavior::{MpcSwarmBahavior, MpcSwarmBahaviorEvent, MpcP2pRequest, MpcP2pResponse}, 
+    behavior::{MpcSwarmBahavior, MpcSwarmBahaviorEvent, MpcP2pRequest, MpcP2pResponse, SkwMpcP2pCodec}, 
     client::MpcSwarmCommand,
+    handshake::{self, Handshake, RelayProtocol},
 };
 
+/// How long the chunks of an outcome are kept for the requester after its last fetch
+#[cfg(feature = "full-node")]
+const OUTGOING_TRANSFER_TTL: Duration = Duration::from_secs(120);
+
+/// Upper bound of the chunks of an incoming transfer, a relay announcing more is refused
+const MAX_INCOMING_CHUNKS: u32 = 1_024;
+/// Upper bound of an outcome pulled chunk by chunk, the transfer is dropped once it grows past it
+const MAX_INCOMING_TRANSFER_SIZE: usize = 16 * 1024 * 1024;
+
+/// An outcome being pulled chunk by chunk from the relay
+struct IncomingTransfer {
+    peer: PeerId,
+    total_chunks: u32,
+    received_chunks: u32,
+    payload: Vec<u8>,
+    result_sender: oneshot::Sender<Result<MpcP2pResponse, MpcClientError>>,
+}
+
 pub struct MpcSwarmEventLoop {
     #[cfg(feature = "full-node")]
     light_node_client: NodeClient,
 
     swarm: Swarm<MpcSwarmBahavior>,
+    codec: SkwMpcP2pCodec,
     command_receiver: mpsc::UnboundedReceiver<MpcSwarmCommand>,
 
     pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), MpcClientError>>>,
     pending_request: HashMap<RequestId, oneshot::Sender<Result<MpcP2pResponse, MpcClientError>>>,
+    pending_handshake: HashMap<RequestId, oneshot::Sender<Result<RelayProtocol, MpcClientError>>>,
+
+    // chunked transfer of large outcomes
+    #[cfg(feature = "full-node")]
+    outgoing_transfers: OutgoingTransfers,
+    incoming_transfers: HashMap<CryptoHash, IncomingTransfer>,
+    pending_chunk_request: HashMap<RequestId, CryptoHash>,
+
     swarm_termination_receiver: mpsc::Receiver<()>,
 }
 
@@ -39,6 +71,7 @@ impl MpcSwarmEventLoop {
         light_node_client: NodeClient,
 
         swarm: Swarm<MpcSwarmBahavior>,
+        codec: SkwMpcP2pCodec,
         command_receiver: mpsc::UnboundedReceiver<MpcSwarmCommand>,
         swarm_termination_receiver: mpsc::Receiver<()>,
     ) -> Self {
@@ -47,10 +80,18 @@ impl MpcSwarmEventLoop {
             light_node_client,
 
             swarm,            
+            codec,
             command_receiver, 
 
             pending_dial: Default::default(),
             pending_request: Default::default(),
+            pending_handshake: Default::default(),
+
+            #[cfg(feature = "full-node")]
+            outgoing_transfers: Default::default(),
+            incoming_transfers: Default::default(),
+            pending_chunk_request: Default::default(),
+
             swarm_termination_receiver,
         }
     }
@@ -101,8 +142,12 @@ impl MpcSwarmEventLoop {
                     }
                 }
             }
-            SwarmEvent::ConnectionClosed { peer_id, cause, ..} => {
+            SwarmEvent::ConnectionClosed { peer_id, cause, num_established, ..} => {
                 log::info!("{:?} is disconnected because {:?}", peer_id, cause);
+                if num_established == 0 {
+                    #[cfg(feature = "full-node")]
+                    self.outgoing_transfers.expire_peer(&peer_id);
+                }
             }
             SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
                 if let Some(peer_id) = peer_id {
@@ -119,7 +164,7 @@ impl MpcSwarmEventLoop {
 
             // p2p events
             SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::RequestResponse(
-                request_response::Event::Message { message, .. },
+                request_response::Event::Message { peer, message },
             )) => {
                 match message {
                     // p2p message request hanlder
@@ -134,6 +179,7 @@ impl MpcSwarmEventLoop {
                         #[cfg(feature = "full-node")]
                         match request {
                             MpcP2pRequest::Mpc { auth_header, job_header, maybe_local_key } => {    
+                                let payload_id = job_header.payload_id;
                                 match self.light_node_client.send_request(
                                     job_header, auth_header, maybe_local_key
                                 )
@@ -142,9 +188,11 @@ impl MpcSwarmEventLoop {
                                     Ok(client_outcome) => match self.swarm
                                         .behaviour_mut()
                                         .request_response
-                                        .send_response(channel, MpcP2pResponse::Mpc { 
-                                            payload: Ok(client_outcome.payload()) 
-                                        })
+                                        .send_response(channel, self.outgoing_transfers.start(
+                                            peer, self.codec.chunk_size(),
+                                            payload_id, client_outcome.payload(),
+                                            Instant::now(),
+                                        ))
                                     {
                                         Ok(_) => { } // let the - Response - section take over
                                         Err(response) => {
@@ -179,6 +227,19 @@ impl MpcSwarmEventLoop {
 
                                 }  
                             },
+
+                            MpcP2pRequest::FetchChunk { payload_id, index } => {
+                                let chunk = self.outgoing_transfers
+                                    .chunk(&peer, payload_id, index, Instant::now());
+
+                                if let Err(response) = self.swarm
+                                    .behaviour_mut()
+                                    .request_response
+                                    .send_response(channel, MpcP2pResponse::Chunk { chunk })
+                                {
+                                    log::debug!("Mpc FetchChunk Reponse channel closed {:?}", response);
+                                }
+                            },
                         }
 
                         // Light Node never receive requests
@@ -188,11 +249,75 @@ impl MpcSwarmEventLoop {
 
                     // p2p message response hanlder
                     request_response::Message::Response { request_id, response } => {
-                        let _ = self
-                            .pending_request
-                            .remove(&request_id)
-                            .expect("Request to still be pending.")
-                            .send(Ok(response));
+                        match response {
+                            MpcP2pResponse::MpcChunked { payload_id, total_chunks, chunk } => {
+                                let result_sender = match self.pending_request.remove(&request_id) {
+                                    Some(result_sender) => result_sender,
+                                    None => {
+                                        log::debug!("Unexpected chunked outcome from {:?} for request {:?}", peer, request_id);
+                                        return;
+                                    }
+                                };
+                                if let Err(e) = check_incoming_transfer(total_chunks, chunk.len()) {
+                                    log::error!("Refused chunked transfer {:?} from {:?} {:?}", payload_id, peer, e);
+                                    let _ = result_sender.send(Err(e));
+                                    return;
+                                }
+                                match self.incoming_transfers.entry(payload_id) {
+                                    Entry::Occupied(_) => {
+                                        log::debug!("Chunked transfer {:?} is already being pulled", payload_id);
+                                        let _ = result_sender.send(Err(MpcClientError::SwarmP2pError(SwarmP2pError::IncompleteTransfer)));
+                                        return;
+                                    },
+                                    Entry::Vacant(entry) => {
+                                        entry.insert(IncomingTransfer {
+                                            peer, total_chunks, 
+                                            received_chunks: 1, 
+                                            payload: chunk, 
+                                            result_sender,
+                                        });
+                                    },
+                                }
+                                self.fetch_next_chunk(payload_id);
+                            },
+                            MpcP2pResponse::Chunk { chunk } => {
+                                let payload_id = match self.pending_chunk_request.remove(&request_id) {
+                                    Some(payload_id) => payload_id,
+                                    None => {
+                                        log::debug!("Unexpected chunk from {:?} for request {:?}", peer, request_id);
+                                        return;
+                                    }
+                                };
+                                match chunk {
+                                    Ok(chunk) => match self.incoming_transfers.get_mut(&payload_id) {
+                                        Some(transfer) => {
+                                            let size = transfer.payload.len() + chunk.len();
+                                            if let Err(e) = check_incoming_transfer(transfer.total_chunks, size) {
+                                                log::error!("Dropped chunked transfer {:?} from {:?} {:?}", payload_id, peer, e);
+                                                if let Some(transfer) = self.incoming_transfers.remove(&payload_id) {
+                                                    let _ = transfer.result_sender.send(Err(e));
+                                                }
+                                                return;
+                                            }
+                                            transfer.payload.extend(chunk);
+                                            transfer.received_chunks += 1;
+                                            self.fetch_next_chunk(payload_id);
+                                        },
+                                        None => log::debug!("Chunk of {:?} arrived after its transfer ended", payload_id),
+                                    },
+                                    Err(e) => {
+                                        log::error!("Chunked transfer {:?} failed {:?}", payload_id, e);
+                                        if let Some(transfer) = self.incoming_transfers.remove(&payload_id) {
+                                            let _ = transfer.result_sender.send(Err(e));
+                                        }
+                                    }
+                                }
+                            },
+                            response => match self.pending_request.remove(&request_id) {
+                                Some(result_sender) => { let _ = result_sender.send(Ok(response)); },
+                                None => log::debug!("Unexpected response from {:?} for request {:?}", peer, request_id),
+                            }
+                        }
                     }
                 }
             },
@@ -203,16 +328,79 @@ impl MpcSwarmEventLoop {
                 },
             )) => {
                 log::error!("p2p outbound request failure to {peer} because {:?}", error);
+                let error = match error {
+                    // no `/skw-mpc-request` version in common, the relay did not tell which ones it has
+                    OutboundFailure::UnsupportedProtocols => SwarmP2pError::IncompatibleProtocolVersion {
+                        local: handshake::local().versions,
+                        remote: Vec::new(),
+                    },
+                    _ => SwarmP2pError::OutboundFailure,
+                };
+
+                if let Some(payload_id) = self.pending_chunk_request.remove(&request_id) {
+                    if let Some(transfer) = self.incoming_transfers.remove(&payload_id) {
+                        let _ = transfer.result_sender
+                            .send(Err(MpcClientError::SwarmP2pError(error)));
+                    }
+                    return;
+                }
+
                 let _ = self
                     .pending_request
                     .remove(&request_id)
                     .expect("Request to still be pending.")
-                    .send(Err(MpcClientError::SwarmP2pError(SwarmP2pError::OutboundFailure)))
+                    .send(Err(MpcClientError::SwarmP2pError(error)))
                     .expect("p2p response receiver not to be dropped");
             }
             SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::RequestResponse(
                 request_response::Event::ResponseSent { .. },
             )) => {},
+
+            // capability handshake
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Handshake(
+                request_response::Event::Message { message, peer },
+            )) => match message {
+                request_response::Message::Request { channel, .. } => {
+                    if self.swarm
+                        .behaviour_mut()
+                        .handshake
+                        .send_response(channel, handshake::local())
+                        .is_err()
+                    {
+                        log::debug!("Handshake response channel to {:?} closed", peer);
+                    }
+                }
+                request_response::Message::Response { request_id, response } => {
+                    let result = handshake::negotiate(&response)
+                        .map_err(MpcClientError::SwarmP2pError);
+                    log::debug!("Handshake with {:?} {:?}", peer, result);
+                    let _ = self
+                        .pending_handshake
+                        .remove(&request_id)
+                        .expect("Handshake to still be pending.")
+                        .send(result);
+                }
+            },
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Handshake(
+                request_response::Event::OutboundFailure {
+                    request_id, error, peer,
+                },
+            )) => {
+                let result = match error {
+                    // the relay predates the handshake
+                    OutboundFailure::UnsupportedProtocols => handshake::negotiate(&Handshake::legacy())
+                        .map_err(MpcClientError::SwarmP2pError),
+                    _ => {
+                        log::error!("handshake failure with {peer} because {:?}", error);
+                        Err(MpcClientError::SwarmP2pError(SwarmP2pError::OutboundFailure))
+                    }
+                };
+                let _ = self
+                    .pending_handshake
+                    .remove(&request_id)
+                    .expect("Handshake to still be pending.")
+                    .send(result);
+            }
             
             _ => {}
         }
@@ -238,6 +426,10 @@ impl MpcSwarmEventLoop {
                         .behaviour_mut()
                         .request_response
                         .add_address(&peer_id, peer_addr.clone());
+                    self.swarm
+                        .behaviour_mut()
+                        .handshake
+                        .add_address(&peer_id, peer_addr.clone());
 
                     match self
                         .swarm
@@ -257,12 +449,229 @@ impl MpcSwarmEventLoop {
                 }
             },
             MpcSwarmCommand::SendP2pRequest { to, request, result_sender } => {
+                // fail early with a readable error instead of an opaque outbound failure
+                if let Err(e) = self.codec.check_request_size(&request) {
+                    log::error!("Refusing to send oversized request to {:?} {:?}", to, e);
+                    result_sender
+                        .send(Err(e))
+                        .expect("swarm command result receiver not to be dropped");
+                    return;
+                }
+
                 let request_id = self.swarm
                     .behaviour_mut()
                     .request_response
                     .send_request(&to, request.clone());
                 self.pending_request.insert(request_id, result_sender);
             }
+            MpcSwarmCommand::Handshake { peer_id, result_sender } => {
+                let request_id = self.swarm
+                    .behaviour_mut()
+                    .handshake
+                    .send_request(&peer_id, handshake::local());
+                self.pending_handshake.insert(request_id, result_sender);
+            }
+        }
+    }
+
+    /// Request the next chunk of an incoming transfer, or hand the reassembled
+    /// outcome to the original requester once all chunks arrived
+    fn fetch_next_chunk(&mut self, payload_id: CryptoHash) {
+        let transfer = match self.incoming_transfers.get(&payload_id) {
+            Some(transfer) => transfer,
+            None => return,
+        };
+
+        if transfer.received_chunks >= transfer.total_chunks {
+            if let Some(transfer) = self.incoming_transfers.remove(&payload_id) {
+                let _ = transfer.result_sender
+                    .send(Ok(MpcP2pResponse::Mpc { payload: Ok(transfer.payload) }));
+            }
+        } else {
+            let peer = transfer.peer;
+            let index = transfer.received_chunks;
+            let request_id = self.swarm
+                .behaviour_mut()
+                .request_response
+                .send_request(&peer, MpcP2pRequest::FetchChunk { payload_id, index });
+            self.pending_chunk_request.insert(request_id, payload_id);
+        }
+    }
+}
+
+/// Refuses an incoming transfer of more than `MAX_INCOMING_CHUNKS` chunks or, with `size` bytes
+/// received so far, more than `MAX_INCOMING_TRANSFER_SIZE` bytes
+fn check_incoming_transfer(total_chunks: u32, size: usize) -> Result<(), MpcClientError> {
+    if total_chunks > MAX_INCOMING_CHUNKS {
+        return Err(MpcClientError::SwarmP2pError(SwarmP2pError::TooManyChunks {
+            chunks: total_chunks,
+            limit: MAX_INCOMING_CHUNKS,
+        }));
+    }
+    if size > MAX_INCOMING_TRANSFER_SIZE {
+        return Err(MpcClientError::SwarmP2pError(SwarmP2pError::MessageTooLarge {
+            size: size as u64,
+            limit: MAX_INCOMING_TRANSFER_SIZE as u64,
+        }));
+    }
+    Ok(())
+}
+
+/// Outcomes too large for one response, kept until the peer that requested them has pulled
+/// their chunks. A transfer is only served to that peer and is dropped once it disconnects or
+/// has not fetched a chunk for `OUTGOING_TRANSFER_TTL`. Chunks are not dropped as they are
+/// served, so a failed fetch can be retried.
+#[cfg(feature = "full-node")]
+#[derive(Default)]
+struct OutgoingTransfers {
+    transfers: HashMap<(PeerId, CryptoHash), OutgoingTransfer>,
+}
+
+#[cfg(feature = "full-node")]
+struct OutgoingTransfer {
+    chunks: Vec<Vec<u8>>,
+    expires_at: Instant,
+}
+
+#[cfg(feature = "full-node")]
+impl OutgoingTransfers {
+    /// Split an outcome that does not fit into one response. The first chunk goes
+    /// out with the response, the rest is kept until `peer` pulls it.
+    fn start(
+        &mut self,
+        peer: PeerId,
+        chunk_size: usize,
+        payload_id: CryptoHash,
+        payload: Vec<u8>,
+        now: Instant,
+    ) -> MpcP2pResponse {
+        self.expire(now);
+        if payload.len() <= chunk_size {
+            return MpcP2pResponse::Mpc { payload: Ok(payload) };
+        }
+
+        let chunks: Vec<Vec<u8>> = payload
+            .chunks(chunk_size)
+            .map(|c| c.to_vec())
+            .collect();
+        let total_chunks = chunks.len() as u32;
+        let chunk = chunks[0].clone();
+
+        log::debug!("Outcome {:?} is {} bytes, sending in {} chunks", payload_id, payload.len(), total_chunks);
+        self.transfers.insert((peer, payload_id), OutgoingTransfer {
+            chunks,
+            expires_at: now + OUTGOING_TRANSFER_TTL,
+        });
+        MpcP2pResponse::MpcChunked { payload_id, total_chunks, chunk }
+    }
+
+    /// Chunk `index` of the outcome `peer` requested
+    fn chunk(
+        &mut self,
+        peer: &PeerId,
+        payload_id: CryptoHash,
+        index: u32,
+        now: Instant,
+    ) -> Result<Vec<u8>, MpcClientError> {
+        self.expire(now);
+        self.transfers
+            .get_mut(&(*peer, payload_id))
+            .and_then(|transfer| {
+                transfer.expires_at = now + OUTGOING_TRANSFER_TTL;
+                transfer.chunks.get(index as usize).cloned()
+            })
+            .ok_or(MpcClientError::SwarmP2pError(SwarmP2pError::ChunkUnavailable))
+    }
+
+    fn expire_peer(&mut self, peer: &PeerId) {
+        self.transfers.retain(|(owner, _), _| owner != peer);
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.transfers.retain(|_, transfer| transfer.expires_at > now);
+    }
+}
+
+#[cfg(all(test, feature = "full-node"))]
+mod test {
+    use futures::io::Cursor;
+    use libp2p::request_response::Codec;
+
+    use super::*;
+    use crate::swarm::behavior::SkwMpcP2pProtocol;
+
+    #[tokio::test]
+    async fn chunked_round_trip() {
+        let mut codec = SkwMpcP2pCodec::new(4_096, 2_048).unwrap();
+        let payload: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let (peer, payload_id, now) = (PeerId::random(), [7u8; 32], Instant::now());
+
+        let mut transfers = OutgoingTransfers::default();
+        let first = transfers.start(peer, codec.chunk_size(), payload_id, payload.clone(), now);
+
+        // every response of the transfer goes over the wire within the response limit
+        let mut wire = Cursor::new(Vec::new());
+        codec.write_response(&SkwMpcP2pProtocol::V1, &mut wire, first).await.unwrap();
+        wire.set_position(0);
+        let (total_chunks, mut received) = match codec.read_response(&SkwMpcP2pProtocol::V1, &mut wire).await.unwrap() {
+            MpcP2pResponse::MpcChunked { payload_id: id, total_chunks, chunk } => {
+                assert_eq!(id, payload_id);
+                (total_chunks, chunk)
+            },
+            response => panic!("expected a chunked outcome, got {:?}", response),
+        };
+        assert_eq!(total_chunks, 10);
+
+        for index in 1..total_chunks {
+            let chunk = transfers.chunk(&peer, payload_id, index, now);
+            let mut wire = Cursor::new(Vec::new());
+            codec.write_response(&SkwMpcP2pProtocol::V1, &mut wire, MpcP2pResponse::Chunk { chunk }).await.unwrap();
+            wire.set_position(0);
+            match codec.read_response(&SkwMpcP2pProtocol::V1, &mut wire).await.unwrap() {
+                MpcP2pResponse::Chunk { chunk } => received.extend(chunk.unwrap()),
+                response => panic!("expected a chunk, got {:?}", response),
+            }
         }
+        assert_eq!(received, payload);
+
+        // a failed fetch of the last chunk can be retried
+        assert!(transfers.chunk(&peer, payload_id, total_chunks - 1, now).is_ok());
+    }
+
+    #[test]
+    fn oversized_incoming_transfers_are_refused() {
+        assert_eq!(check_incoming_transfer(MAX_INCOMING_CHUNKS, MAX_INCOMING_TRANSFER_SIZE), Ok(()));
+        assert_eq!(
+            check_incoming_transfer(u32::MAX, 1),
+            Err(MpcClientError::SwarmP2pError(SwarmP2pError::TooManyChunks { chunks: u32::MAX, limit: MAX_INCOMING_CHUNKS }))
+        );
+        assert_eq!(
+            check_incoming_transfer(2, MAX_INCOMING_TRANSFER_SIZE + 1),
+            Err(MpcClientError::SwarmP2pError(SwarmP2pError::MessageTooLarge {
+                size: MAX_INCOMING_TRANSFER_SIZE as u64 + 1,
+                limit: MAX_INCOMING_TRANSFER_SIZE as u64,
+            }))
+        );
+    }
+
+    #[test]
+    fn transfers_are_only_served_to_their_requester_until_expiry() {
+        let (peer, other, payload_id, now) = (PeerId::random(), PeerId::random(), [7u8; 32], Instant::now());
+        let unavailable = Err(MpcClientError::SwarmP2pError(SwarmP2pError::ChunkUnavailable));
+
+        let mut transfers = OutgoingTransfers::default();
+        transfers.start(peer, 4, payload_id, vec![1; 10], now);
+        assert_eq!(transfers.chunk(&other, payload_id, 1, now), unavailable);
+        assert_eq!(transfers.chunk(&peer, payload_id, 3, now), unavailable);
+        assert_eq!(transfers.chunk(&peer, payload_id, 2, now), Ok(vec![1; 2]));
+
+        // a fetch keeps the transfer alive
+        let later = now + OUTGOING_TRANSFER_TTL - Duration::from_secs(1);
+        assert!(transfers.chunk(&peer, payload_id, 1, later).is_ok());
+        assert!(transfers.chunk(&peer, payload_id, 1, later + OUTGOING_TRANSFER_TTL).is_err());
+
+        transfers.start(peer, 4, payload_id, vec![1; 10], now);
+        transfers.expire_peer(&peer);
+        assert_eq!(transfers.chunk(&peer, payload_id, 1, now), unavailable);
     }
 }
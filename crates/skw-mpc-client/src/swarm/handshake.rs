@@ -0,0 +1,89 @@
+use skw_mpc_payload::capability::{Encoding, HandshakeError, ProtocolVersion};
+
+pub use skw_mpc_payload::capability::{Capabilities, Handshake};
+
+use crate::error::SwarmP2pError;
+
+use super::behavior::SkwMpcP2pProtocol;
+
+/// Outcome of a handshake with one relay
+pub type RelayProtocol = skw_mpc_payload::capability::PeerProtocol<SkwMpcP2pProtocol>;
+
+impl ProtocolVersion for SkwMpcP2pProtocol {
+    fn version(&self) -> u16 {
+        SkwMpcP2pProtocol::version(self)
+    }
+
+    fn from_version(version: u16) -> Option<Self> {
+        SkwMpcP2pProtocol::from_version(version)
+    }
+}
+
+impl From<HandshakeError> for SwarmP2pError {
+    fn from(e: HandshakeError) -> Self {
+        match e {
+            HandshakeError::IncompatibleProtocolVersion { local, remote } =>
+                Self::IncompatibleProtocolVersion { local, remote },
+            HandshakeError::NoCommonCurve => Self::NoCommonCurve,
+            HandshakeError::NoCommonEncoding => Self::NoCommonEncoding,
+            HandshakeError::UnsupportedJobType(job_type) => Self::UnsupportedJobType(job_type),
+        }
+    }
+}
+
+/// What this client or relay is able to do. Key shards travel as JSON over every
+/// `/skw-mpc-request` version, so JSON is the only encoding announced.
+pub fn local() -> Handshake {
+    Handshake::new(
+        &SkwMpcP2pProtocol::supported(),
+        Capabilities::with_encodings(vec![Encoding::Json]),
+    )
+}
+
+/// Settle on a protocol with a relay that answered the handshake with `remote`
+pub fn negotiate(remote: &Handshake) -> Result<RelayProtocol, SwarmP2pError> {
+    local()
+        .negotiate(remote)
+        .map_err(SwarmP2pError::from)
+}
+
+#[cfg(test)]
+mod test {
+    use skw_mpc_payload::JobType;
+
+    use super::*;
+
+    #[test]
+    fn negotiate_with_relays() {
+        let relay = negotiate(&local()).unwrap();
+        assert_eq!(relay.version, SkwMpcP2pProtocol::V2);
+        assert_eq!(relay.encoding, Encoding::Json);
+
+        // relays that predate the handshake
+        let legacy = negotiate(&Handshake::legacy()).unwrap();
+        assert_eq!((legacy.version, legacy.encoding), (SkwMpcP2pProtocol::V1, Encoding::Json));
+    }
+
+    #[test]
+    fn relay_without_a_common_version() {
+        let mut remote = local();
+        remote.versions = vec![3];
+
+        assert_eq!(
+            negotiate(&remote),
+            Err(SwarmP2pError::IncompatibleProtocolVersion { local: vec![2, 1], remote: vec![3] })
+        );
+    }
+
+    #[test]
+    fn relay_without_the_job_type() {
+        let mut remote = local();
+        remote.capabilities.job_types = vec![JobType::KeyGen, JobType::SignOffline];
+
+        let relay = negotiate(&remote).unwrap();
+        assert_eq!(
+            relay.ensure_job_type(JobType::KeyRefresh).map_err(SwarmP2pError::from),
+            Err(SwarmP2pError::UnsupportedJobType(JobType::KeyRefresh))
+        );
+    }
+}
@@ -1,6 +1,7 @@
 mod behavior;
 mod client;
 mod event_loop;
+mod handshake;
 
 use libp2p::request_response::ProtocolSupport;
 use libp2p::{
@@ -14,15 +15,19 @@ use futures::channel::mpsc;
 #[cfg(feature = "full-node")]
 use skw_mpc_node::node::NodeClient;
 
-use self::behavior::{MpcSwarmBahavior, SkwMpcP2pCodec, SkwMpcP2pProtocol};
+use self::behavior::{
+    MpcSwarmBahavior, SkwMpcP2pCodec, SkwMpcP2pProtocol,
+    SkwMpcHandshakeCodec, SkwMpcHandshakeProtocol,
+};
 
 // re-export
 pub use client::MpcSwarmClient;
 pub use event_loop::MpcSwarmEventLoop;
-pub use behavior::{MpcP2pRequest, MpcP2pResponse};
+pub use behavior::{MpcP2pRequest, MpcP2pResponse, SkwMpcP2pCodec, SkwMpcP2pProtocol};
+pub use handshake::{Capabilities, Handshake, RelayProtocol};
 
 #[cfg(feature = "tcp-ws-transport")]
-fn build_swarm(local_key: identity::Keypair) -> Swarm<MpcSwarmBahavior> {
+fn build_swarm(local_key: identity::Keypair, codec: SkwMpcP2pCodec) -> Swarm<MpcSwarmBahavior> {
     use std::time::Duration;
 
     use libp2p::{websocket, tcp, dns};
@@ -59,17 +64,24 @@ fn build_swarm(local_key: identity::Keypair) -> Swarm<MpcSwarmBahavior> {
     };
 
     let request_response = request_response::Behaviour::<SkwMpcP2pCodec>::new(
-        SkwMpcP2pCodec(),
-        std::iter::once((SkwMpcP2pProtocol(), ProtocolSupport::Full)),
+        codec,
+        SkwMpcP2pProtocol::supported()
+            .into_iter()
+            .map(|protocol| (protocol, ProtocolSupport::Full)),
+        Default::default(),
+    );
+    let handshake = request_response::Behaviour::<SkwMpcHandshakeCodec>::new(
+        SkwMpcHandshakeCodec::default(),
+        std::iter::once((SkwMpcHandshakeProtocol(), ProtocolSupport::Full)),
         Default::default(),
     );
-    let behaviour = MpcSwarmBahavior {  request_response, };
+    let behaviour = MpcSwarmBahavior {  request_response, handshake, };
     Swarm::with_tokio_executor(transport, behaviour, local_peer_id)
 }
 
 
 #[cfg(all(feature = "wasm-transport", target_arch = "wasm32"))]
-fn build_swarm(local_key: identity::Keypair) -> Swarm<MpcSwarmBahavior> {
+fn build_swarm(local_key: identity::Keypair, codec: SkwMpcP2pCodec) -> Swarm<MpcSwarmBahavior> {
     use libp2p::wasm_ext;
     let local_peer_id = PeerId::from(local_key.public());
 
@@ -101,17 +113,25 @@ fn build_swarm(local_key: identity::Keypair) -> Swarm<MpcSwarmBahavior> {
     };
 
     let request_response = request_response::Behaviour::<SkwMpcP2pCodec>::new(
-        SkwMpcP2pCodec(),
-        std::iter::once((SkwMpcP2pProtocol(), ProtocolSupport::Full)),
+        codec,
+        SkwMpcP2pProtocol::supported()
+            .into_iter()
+            .map(|protocol| (protocol, ProtocolSupport::Full)),
+        Default::default(),
+    );
+    let handshake = request_response::Behaviour::<SkwMpcHandshakeCodec>::new(
+        SkwMpcHandshakeCodec::default(),
+        std::iter::once((SkwMpcHandshakeProtocol(), ProtocolSupport::Full)),
         Default::default(),
     );
-    let behaviour = MpcSwarmBahavior {  request_response, };
+    let behaviour = MpcSwarmBahavior {  request_response, handshake, };
     Swarm::with_wasm_executor(transport, behaviour, local_peer_id)
 }
 
 pub fn new_swarm_node(
     #[cfg(feature = "full-node")] bootstrapped_client: NodeClient,
-    local_key: Option<[u8; 32]>
+    local_key: Option<[u8; 32]>,
+    codec: SkwMpcP2pCodec,
 ) -> (
     PeerId, // local peer id
     MpcSwarmClient, 
@@ -128,7 +148,7 @@ pub fn new_swarm_node(
     };
 
     let local_peer_id = PeerId::from(local_key.public());
-    let swarm = build_swarm(local_key);
+    let swarm = build_swarm(local_key, codec.clone());
 
     // the main outgoing channel
     // we give it one buffer so that outgoing can be synced
@@ -137,10 +157,11 @@ pub fn new_swarm_node(
 
     (
         local_peer_id, 
-        MpcSwarmClient { command_sender },
+        MpcSwarmClient::new(command_sender),
         MpcSwarmEventLoop::new(
             #[cfg(feature = "full-node")] bootstrapped_client,
             swarm, 
+            codec,
             command_receiver,
             swarm_termination_receiver
         ),
@@ -1,6 +1,6 @@
 use futures::{channel::mpsc, StreamExt};
 use skw_mpc_node::{
-    node::{full_node_event_loop, NodeClient},
+    node::{full_node_event_loop, NodeClient, StorageBackend, Durability},
     async_executor
 };
 
@@ -16,14 +16,14 @@ async fn main() {
         .bootstrap_node(
             Some([1u8; 32]), 
             "/ip4/100.104.199.31/tcp/2620/ws".to_string(), 
-            "mpc-storage-db-12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".to_string()
+            StorageBackend::LevelDb { path: "mpc-storage-db-12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".to_string(), durability: Durability::default() }
         ).await;
 
     let mut err_steam_full_node2 = client
         .bootstrap_node(
             Some([2u8; 32]), 
             "/ip4/100.104.199.31/tcp/2621/ws".to_string(), 
-            "mpc-storage-db-12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".to_string()
+            StorageBackend::LevelDb { path: "mpc-storage-db-12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".to_string(), durability: Durability::default() }
         ).await;
 
     async_executor(async move {
@@ -1,6 +1,6 @@
 use futures::{channel::mpsc, StreamExt};
 use skw_mpc_node::{
-    node::{NodeClient, light_node_event_loop},
+    node::{NodeClient, light_node_event_loop, StorageBackend},
     async_executor, serde_support::decode_key
 };
 use skw_mpc_payload::{PayloadHeader, header::PayloadType, AuthHeader};
@@ -17,7 +17,7 @@ async fn main() {
         .bootstrap_node(
             Some([3u8; 32]), 
             "/ip4/100.104.199.31/tcp/2619/ws".to_string(),
-            "mpc-storage-db-12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".to_string()
+            StorageBackend::InMemory
         ).await;
     async_executor(async move {
         loop {
@@ -36,17 +36,17 @@ async fn main() {
     
     let node1 = (
         "12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2619/ws/p2p/12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap()
+        Some("/ip4/100.104.199.31/tcp/2619/ws/p2p/12D3KooWRndVhVZPCiQwHBBBdg769GyrPUW13zxwqQyf9r3ANaba".parse().unwrap())
     );
 
     let node2 = (
         "12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2620/ws/p2p/12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap()
+        Some("/ip4/100.104.199.31/tcp/2620/ws/p2p/12D3KooWK99VoVxNE7XzyBwXEzW7xhK7Gpv85r9F3V3fyKSUKPH5".parse().unwrap())
     );
 
     let node3 = (
         "12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap(), 
-        "/ip4/100.104.199.31/tcp/2621/ws/p2p/12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap()
+        Some("/ip4/100.104.199.31/tcp/2621/ws/p2p/12D3KooWJWoaqZhDaoEFshF7Rh1bpY9ohihFhzcW6d69Lr2NASuq".parse().unwrap())
     );
 
     let keygen_request = PayloadHeader {
@@ -60,7 +60,7 @@ async fn main() {
 
     let sign_request = PayloadHeader {
         payload_id: [1u8; 32],
-        payload_type: PayloadType::SignOffline { message: [2u8; 32] },
+        payload_type: PayloadType::SignOffline { message: [2u8; 32], parties: vec![1, 2] },
         peers: vec![node1.clone(), node2.clone()],
         sender: node1.0,
 
@@ -78,7 +78,7 @@ async fn main() {
 
     let sign2_request = PayloadHeader {
         payload_id: [3u8; 32],
-        payload_type: PayloadType::SignOffline { message: [2u8; 32] },
+        payload_type: PayloadType::SignOffline { message: [2u8; 32], parties: vec![1, 2] },
         peers: vec![node1.clone(), node2.clone()],
         sender: node1.0,
 
@@ -1,5 +1,6 @@
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
+use skw_mpc_payload::JobType;
 
 #[cfg(feature = "full-node")]
 use skw_mpc_storage::MpcStorageError;
@@ -12,6 +13,8 @@ pub enum SwarmError {
     FailToDailPeer,
     #[error("Swarm: already dailing the peer")]
     AlreadyDailingPeer,
+    #[error("Swarm: peer could not be found through discovery")]
+    PeerNotFound,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
@@ -22,6 +25,18 @@ pub enum SwarmP2pError {
     ResponseChannelClose,
     #[error("SwarmP2p: outbound failure. Peer closed?")]
     OutboundFailure,
+    #[error("SwarmP2p: message of {size} bytes exceeds the {limit} bytes limit")]
+    MessageTooLarge { size: u64, limit: u64 },
+    #[error("SwarmP2p: no common protocol version, local supports {local:?}, peer supports {remote:?}")]
+    IncompatibleProtocolVersion { local: Vec<u16>, remote: Vec<u16> },
+    #[error("SwarmP2p: peer shares no curve with the local node")]
+    NoCommonCurve,
+    #[error("SwarmP2p: peer shares no payload encoding with the local node")]
+    NoCommonEncoding,
+    #[error("SwarmP2p: peer does not support {0:?} jobs")]
+    UnsupportedJobType(JobType),
+    #[error("SwarmP2p: failed to publish on the job broadcast topic")]
+    GossipPublish,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
@@ -32,6 +47,10 @@ pub enum SerdeError {
     DeserializeLocalKey,
     #[error("SerdeError: failed to deserialize SignatureRecid")]
     DeserializeSignature,
+    #[error("SerdeError: failed to deserialize KeyMetadata")]
+    DeserializeKeyMetadata,
+    #[error("SerdeError: unknown binary encoding version {0}")]
+    UnknownEncodingVersion(u8),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
@@ -44,6 +63,12 @@ pub enum NodeError {
     LocalKeyMissing,
     #[error("NodeError: an local key with this key_shard_id exists. Aborting to prevent overwriting key, use key-refresh instead")]
     LocalKeyExists,
+    #[error("NodeError: request is not supported by this type of node")]
+    UnsupportedRequest,
+    #[error("NodeError: shard {0:?} does not match the public key in its metadata")]
+    PublicKeyMismatch([u8; 32]),
+    #[error("NodeError: no running node with this peer id")]
+    NodeNotFound,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Error)]
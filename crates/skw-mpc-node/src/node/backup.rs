@@ -0,0 +1,198 @@
+use futures::channel::oneshot;
+use skw_mpc_payload::CryptoHash;
+use skw_mpc_storage::{
+    Backup, InMemoryStorage, MasterKeySource, MigratingStorage, MpcStorage, MpcStorageError, Namespace, ShardStore,
+};
+
+use crate::error::{MpcNodeError, NodeError};
+
+use super::metadata;
+use super::schema::storage_schema;
+
+/// Namespaces a backup of a full node holds
+const BACKUP_NAMESPACES: [Namespace; 2] = [Namespace::Shards, Namespace::Metadata];
+
+/// Outcome of a backup import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    /// Unix seconds the backup was taken at
+    pub created_at: u64,
+    pub shards: usize,
+    /// Shards whose versions all match the public key in their metadata
+    pub verified: usize,
+    /// Shards stored before metadata was recorded, only checked to decode
+    pub without_metadata: usize,
+    /// `false` on a dry run
+    pub applied: bool,
+}
+
+/// Served by the event loop of the node, in between jobs
+#[derive(Debug)]
+pub(super) enum BackupRequest {
+    Export {
+        key: MasterKeySource,
+        result_sender: oneshot::Sender<Result<Vec<u8>, MpcNodeError>>,
+    },
+    Import {
+        archive: Vec<u8>,
+        key: MasterKeySource,
+        dry_run: bool,
+        result_sender: oneshot::Sender<Result<BackupReport, MpcNodeError>>,
+    },
+}
+
+/// Archive of the shards, all of their versions, and metadata of every key in `storage`. Records
+/// are archived as stored, behind their schema version.
+pub async fn export(storage: &dyn MpcStorage, key: &MasterKeySource) -> Result<Vec<u8>, MpcNodeError> {
+    Backup::snapshot(storage, &BACKUP_NAMESPACES)
+        .await
+        .and_then(|backup| backup.seal(key))
+        .map_err(MpcNodeError::StorageError)
+}
+
+/// Checks that every version of each shard in the archive decodes and matches the public key in
+/// its metadata - a refresh keeps the public key, so pending and retired versions share the one of
+/// the active version - then writes the archive to `storage`, the store as stored rather than behind a
+/// [MigratingStorage], in one batch unless `dry_run`
+pub async fn import(
+    storage: &dyn MpcStorage,
+    archive: &[u8],
+    key: &MasterKeySource,
+    dry_run: bool,
+) -> Result<BackupReport, MpcNodeError> {
+    let backup = Backup::open(archive, key)
+        .map_err(MpcNodeError::StorageError)?;
+    if backup.records.iter().any(|record| !BACKUP_NAMESPACES.contains(&record.namespace)) {
+        return Err(MpcNodeError::StorageError(MpcStorageError::MalformedRecord));
+    }
+
+    // read back the way the node reads its own store, records of an older schema are upgraded
+    let staged = ShardStore::new(MigratingStorage::new(InMemoryStorage::default(), storage_schema()));
+    backup.restore(staged.storage().inner())
+        .await
+        .map_err(MpcNodeError::StorageError)?;
+
+    let mut report = BackupReport {
+        created_at: backup.created_at,
+        shards: 0,
+        verified: 0,
+        without_metadata: 0,
+        applied: false,
+    };
+    for record in backup.records_in(Namespace::Shards) {
+        let key_shard_id: CryptoHash = record.key
+            .as_slice()
+            .try_into()
+            .map_err(|_| MpcNodeError::StorageError(MpcStorageError::MalformedRecord))?;
+        let active = staged
+            .active(key_shard_id)
+            .await
+            .map_err(MpcNodeError::StorageError)?;
+        let public_key = super::full::public_key_of(&active.shard)?;
+        let history = staged
+            .history(key_shard_id)
+            .await
+            .map_err(MpcNodeError::StorageError)?;
+        for version in history {
+            if super::full::public_key_of(&version.shard)? != public_key {
+                return Err(MpcNodeError::NodeError(NodeError::PublicKeyMismatch(key_shard_id)));
+            }
+        }
+
+        report.shards += 1;
+        match metadata::read_metadata(staged.storage(), key_shard_id).await {
+            Ok(metadata) if metadata.public_key == public_key => report.verified += 1,
+            Ok(_) => return Err(MpcNodeError::NodeError(NodeError::PublicKeyMismatch(key_shard_id))),
+            Err(MpcNodeError::StorageError(MpcStorageError::KeyNotInDB)) => report.without_metadata += 1,
+            Err(e) => return Err(e),
+        }
+    }
+
+    if !dry_run {
+        backup.restore(storage)
+            .await
+            .map_err(MpcNodeError::StorageError)?;
+        report.applied = true;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use skw_crypto_curv::elliptic::curves::Secp256k1;
+    use skw_mpc_payload::PayloadHeader;
+    use skw_mpc_protocol::gg20::state_machine::keygen::{Keygen, LocalKey};
+    use skw_round_based::dev::Simulation;
+
+    use super::*;
+    use crate::error::SerdeError;
+    use crate::serde_support::{encode_key, Encoding};
+
+    fn key() -> MasterKeySource {
+        MasterKeySource::Passphrase("correct horse".to_string())
+    }
+
+    /// Shards of a 1-of-2 key
+    fn local_keys() -> Vec<LocalKey<Secp256k1>> {
+        let mut simulation = Simulation::new();
+        for i in 1..=2 {
+            simulation.add_party(Keygen::new(i, 1, 2).unwrap());
+        }
+        simulation.run().unwrap()
+    }
+
+    #[tokio::test]
+    async fn verifies_every_version_of_a_shard() {
+        let keys = local_keys();
+        let public_key = keys[0].public_key().to_bytes(true).to_vec();
+
+        // a refreshed shard is staged next to the active one, both share the public key
+        let shards = ShardStore::new(InMemoryStorage::default());
+        shards.put_active([1u8; 32], [0u8; 32], encode_key(&keys[0], Encoding::Binary)).await.unwrap();
+        shards.add_pending([1u8; 32], [1u8; 32], encode_key(&keys[1], Encoding::Binary)).await.unwrap();
+        metadata::record_keygen(shards.storage(), [1u8; 32], &PayloadHeader::default(), public_key, 0).await.unwrap();
+        let archive = export(shards.storage(), &key()).await.unwrap();
+
+        let report = import(&InMemoryStorage::default(), &archive, &key(), false).await.unwrap();
+        assert_eq!((report.shards, report.verified, report.without_metadata), (1, 1, 0));
+
+        // a retired version that no longer decodes fails the import
+        shards.add_pending([1u8; 32], [2u8; 32], b"not a key".to_vec()).await.unwrap();
+        shards.add_pending([1u8; 32], [3u8; 32], encode_key(&keys[1], Encoding::Binary)).await.unwrap();
+        let archive = export(shards.storage(), &key()).await.unwrap();
+        assert_eq!(
+            import(&InMemoryStorage::default(), &archive, &key(), true).await,
+            Err(MpcNodeError::SerdeError(SerdeError::DeserializeLocalKey))
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_rejects_undecodable_shard() {
+        let shards = ShardStore::new(InMemoryStorage::default());
+        shards.put_active([1u8; 32], [0u8; 32], b"not a key".to_vec()).await.unwrap();
+        let archive = export(shards.storage(), &key()).await.unwrap();
+
+        let target = InMemoryStorage::default();
+        assert_eq!(
+            import(&target, &archive, &key(), true).await,
+            Err(MpcNodeError::SerdeError(SerdeError::DeserializeLocalKey))
+        );
+        assert_eq!(target.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::KeyNotInDB));
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_store_untouched() {
+        let storage = InMemoryStorage::default();
+        metadata::record_keygen(&storage, [1u8; 32], &PayloadHeader::default(), vec![2; 33], 0).await.unwrap();
+        let archive = export(&storage, &key()).await.unwrap();
+
+        let target = InMemoryStorage::default();
+        let report = import(&target, &archive, &key(), true).await.unwrap();
+        assert_eq!((report.shards, report.applied), (0, false));
+        assert!(metadata::read_metadata(&target, [1u8; 32]).await.is_err());
+
+        let report = import(&target, &archive, &key(), false).await.unwrap();
+        assert!(report.applied);
+        assert_eq!(metadata::read_metadata(&target, [1u8; 32]).await.unwrap().public_key, vec![2; 33]);
+    }
+}
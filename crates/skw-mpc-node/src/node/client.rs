@@ -1,19 +1,25 @@
 use futures::{channel::{mpsc, oneshot}, SinkExt, StreamExt};
 use libp2p::{PeerId, Multiaddr};
+use skw_mpc_storage::StorageBackend;
+#[cfg(feature = "full-node")]
+use skw_mpc_storage::{MasterKeySource, Namespace, ScanPage};
 
 use crate::error::MpcNodeError;
 
-use super::ClientRequest;
+use super::{ClientRequest, RoundTimeouts};
 
 #[cfg(feature = "light-node")]
 use super::client_outcome::ClientOutcome;
+#[cfg(feature = "full-node")]
+use super::{BackupReport, KeyMetadata};
 #[cfg(feature = "light-node")]
 use skw_mpc_payload::{PayloadHeader, AuthHeader};
 
 #[derive(Clone)]
 pub struct NodeClient {
     self_peer_id: Option<PeerId>,
-    external_request_sender: mpsc::Sender<ClientRequest>
+    external_request_sender: mpsc::Sender<ClientRequest>,
+    round_timeouts: Option<RoundTimeouts>,
 }
 
 impl NodeClient {
@@ -21,9 +27,16 @@ impl NodeClient {
         Self {
             self_peer_id: None,
             external_request_sender,
+            round_timeouts: None,
         }
     }
 
+    /// Round timeouts of the nodes bootstrapped from now on, instead of `RoundTimeouts::from_env`
+    pub fn with_round_timeouts(mut self, round_timeouts: RoundTimeouts) -> Self {
+        self.round_timeouts = Some(round_timeouts);
+        self
+    }
+
     pub fn peer_id(&self) -> PeerId {
         self.self_peer_id.unwrap()
     }
@@ -32,21 +45,48 @@ impl NodeClient {
         &mut self,
         local_key: Option<[u8; 32]>,
         listen_addr: String, 
-        db_name: String,
+        storage: StorageBackend,
     ) -> mpsc::Receiver<Result<(PeerId, Multiaddr), MpcNodeError>> {
+        let (result_receiver, result) = self.bootstrap(local_key, listen_addr, storage).await;
+        if let Err(e) = result {
+            log::error!("Node Throw Error {:?}", e);
+        }
+        result_receiver
+    }
+
+    /// Like `bootstrap_node`, but fails with the error the node could not be started with
+    pub async fn try_bootstrap_node(
+        &mut self,
+        local_key: Option<[u8; 32]>,
+        listen_addr: String, 
+        storage: StorageBackend,
+    ) -> Result<mpsc::Receiver<Result<(PeerId, Multiaddr), MpcNodeError>>, MpcNodeError> {
+        let (result_receiver, result) = self.bootstrap(local_key, listen_addr, storage).await;
+        result.map(|_| result_receiver)
+    }
+
+    async fn bootstrap(
+        &mut self,
+        local_key: Option<[u8; 32]>,
+        listen_addr: String, 
+        storage: StorageBackend,
+    ) -> (mpsc::Receiver<Result<(PeerId, Multiaddr), MpcNodeError>>, Result<(), MpcNodeError>) {
         let (result_sender, mut result_receiver) = mpsc::channel(0);
         self.external_request_sender
-            .send(ClientRequest::BootstrapNode { local_key, listen_addr, db_name, result_sender })
+            .send(ClientRequest::BootstrapNode {
+                local_key, listen_addr, storage,
+                round_timeouts: self.round_timeouts.clone(),
+                result_sender,
+            })
             .await
             .expect("mpc node exteranl request receiver not to be droppped");
 
         // Result on the initial bootstrapping
-        let result = result_receiver.select_next_some().await;
-        match result {
-            Ok((peer_id, _peer_addr)) => { self.self_peer_id = Some(peer_id);  },
-            Err(e) => { log::error!("Node Throw Error {:?}", e); }
-        };
-        result_receiver
+        let result = result_receiver
+            .select_next_some()
+            .await
+            .map(|(peer_id, _peer_addr)| self.self_peer_id = Some(peer_id));
+        (result_receiver, result)
     }
 
     #[cfg(feature = "light-node")]
@@ -91,4 +131,106 @@ impl NodeClient {
             .await
             .expect("mpc node not to dropped")
     }
+
+    /// Public key, committee and usage of a key the full node `node` holds a shard of
+    #[cfg(feature = "full-node")]
+    pub async fn key_metadata(&mut self, node: PeerId, key_shard_id: [u8; 32]) -> Result<KeyMetadata, MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::KeyMetadata { node, key_shard_id, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
+
+    /// A page of the records of the full node `node` in `namespace` whose key starts with
+    /// `prefix`. Pass the cursor of a page back to get the next one.
+    #[cfg(feature = "full-node")]
+    pub async fn scan(
+        &mut self,
+        node: PeerId,
+        namespace: Namespace,
+        prefix: Vec<u8>,
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::Scan { node, namespace, prefix, limit, cursor, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
+
+    /// Encrypted archive of every shard and key metadata of the full node `node`, taken in
+    /// between two jobs of the node
+    #[cfg(feature = "full-node")]
+    pub async fn export_backup(&mut self, node: PeerId, key: MasterKeySource) -> Result<Vec<u8>, MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::ExportBackup { node, key, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
+
+    /// Verifies an archive of [NodeClient::export_backup] against the public keys in its
+    /// metadata and, unless `dry_run`, writes it to the store of `node`
+    #[cfg(feature = "full-node")]
+    pub async fn import_backup(
+        &mut self,
+        node: PeerId,
+        archive: Vec<u8>,
+        key: MasterKeySource,
+        dry_run: bool,
+    ) -> Result<BackupReport, MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::ImportBackup { node, archive, key, dry_run, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
+
+    /// Promotes a shard staged on the full node `node`, e.g. one restored from a backup. Shards of a
+    /// finished key refresh job are promoted by the node itself.
+    #[cfg(feature = "full-node")]
+    pub async fn commit_refresh(&mut self, node: PeerId, key_shard_id: [u8; 32], epoch: u64) -> Result<(), MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::CommitRefresh { node, key_shard_id, epoch, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
+
+    /// Discards the shard a key refresh staged on the full node `node`, e.g. because another
+    /// committee member failed to store its own
+    #[cfg(feature = "full-node")]
+    pub async fn rollback_refresh(&mut self, node: PeerId, key_shard_id: [u8; 32], epoch: u64) -> Result<(), MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.external_request_sender
+            .send(ClientRequest::RollbackRefresh { node, key_shard_id, epoch, result_sender })
+            .await
+            .expect("mpc node exteranl request receiver not to be droppped");
+
+        result_receiver
+            .await
+            .expect("mpc node not to dropped")
+    }
 }
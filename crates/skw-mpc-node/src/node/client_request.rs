@@ -1,10 +1,17 @@
 use futures::channel::{oneshot, mpsc};
 use libp2p::{PeerId, Multiaddr};
+use skw_mpc_storage::StorageBackend;
+#[cfg(feature = "full-node")]
+use skw_mpc_storage::{MasterKeySource, Namespace, ScanPage};
 
 use crate::error::MpcNodeError;
 
+use super::RoundTimeouts;
+
 #[cfg(feature = "light-node")]
 use super::client_outcome::ClientOutcome;
+#[cfg(feature = "full-node")]
+use super::{BackupReport, KeyMetadata};
 #[cfg(feature = "light-node")]
 use skw_mpc_payload::{PayloadHeader, AuthHeader};
 
@@ -13,7 +20,10 @@ pub enum ClientRequest {
     BootstrapNode {
         local_key: Option<[u8; 32]>,
         listen_addr: String,
-        db_name: String,
+        // where a full node keeps its key shards, ignored by light nodes
+        storage: StorageBackend,
+        // None reads them from the environment
+        round_timeouts: Option<RoundTimeouts>,
 
         // the node might keep emitting errors
         result_sender: mpsc::Sender< 
@@ -32,6 +42,63 @@ pub enum ClientRequest {
         result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
     },
 
+    #[cfg(feature = "full-node")]
+    KeyMetadata {
+        node: PeerId,
+        key_shard_id: [u8; 32],
+
+        result_sender: oneshot::Sender<Result<KeyMetadata, MpcNodeError>>,
+    },
+
+    #[cfg(feature = "full-node")]
+    Scan {
+        node: PeerId,
+        namespace: Namespace,
+        prefix: Vec<u8>,
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+
+        result_sender: oneshot::Sender<Result<ScanPage, MpcNodeError>>,
+    },
+
+    #[cfg(feature = "full-node")]
+    ExportBackup {
+        node: PeerId,
+        key: MasterKeySource,
+
+        result_sender: oneshot::Sender<Result<Vec<u8>, MpcNodeError>>,
+    },
+
+    #[cfg(feature = "full-node")]
+    ImportBackup {
+        node: PeerId,
+        archive: Vec<u8>,
+        key: MasterKeySource,
+        dry_run: bool,
+
+        result_sender: oneshot::Sender<Result<BackupReport, MpcNodeError>>,
+    },
+
+    /// every committee member holds its shard of the refresh at `epoch`
+    #[cfg(feature = "full-node")]
+    CommitRefresh {
+        node: PeerId,
+        key_shard_id: [u8; 32],
+        epoch: u64,
+
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+    },
+
+    /// the refresh at `epoch` did not complete for the whole committee
+    #[cfg(feature = "full-node")]
+    RollbackRefresh {
+        node: PeerId,
+        key_shard_id: [u8; 32],
+        epoch: u64,
+
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+    },
+
     #[cfg(feature = "light-node")]
     MpcRequest {
         from: PeerId,
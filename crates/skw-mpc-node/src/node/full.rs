@@ -1,53 +1,159 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 
-use futures::{channel::{oneshot, mpsc}, StreamExt, SinkExt, stream::FuturesUnordered};
+use futures::{channel::{oneshot, mpsc}, FutureExt, StreamExt, SinkExt, stream::FuturesUnordered};
 use libp2p::PeerId;
 use skw_crypto_curv::elliptic::curves::Secp256k1;
 use skw_mpc_payload::{header::PayloadType, PayloadHeader, CryptoHash};
 use skw_mpc_protocol::gg20::state_machine::keygen::LocalKey;
-use skw_mpc_storage::{default_mpc_storage_opt, run_db_server, DBOpIn, DBOpOut};
+use skw_mpc_storage::{MigratingStorage, MpcStorage, Namespace, ShardStore};
 
 use crate::{
     async_executor,
     error::{MpcNodeError, NodeError}, 
-    swarm::{ new_full_swarm_node }, 
-    serde_support::{decode_key}, 
+    swarm::{ new_full_swarm_node, SkwMpcP2pCodec, DiscoveryConfig, BroadcastMode }, 
+    serde_support::{decode_key, Encoding}, 
     node::client_request::ClientRequest,
     node::client_outcome::ClientOutcome, wire_outgoing_pipe,
+    node::metadata,
 };
 
+use super::backup::{self, BackupRequest};
 use super::job_manager::JobManager;
+use super::timeouts::RoundTimeouts;
+use super::transcripts::TranscriptConfig;
+use super::schema::storage_schema;
 
-async fn get_local_key(db_in: &mut mpsc::Sender<DBOpIn>, keygen_id: CryptoHash) -> Result<LocalKey<Secp256k1>, MpcNodeError> {
-    let (result_sender, result_receiver) = oneshot::channel();
+/// How long shards replaced by a key refresh are kept for rollback
+const RETIRED_SHARD_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
 
-    db_in
-        .send(DBOpIn::ReadFromDB { key: keygen_id, result_sender })
+async fn get_local_key(shards: &ShardStore<Arc<dyn MpcStorage>>, keygen_id: CryptoHash) -> Result<LocalKey<Secp256k1>, MpcNodeError> {
+    let active = shards
+        .active(keygen_id)
         .await
-        .expect("local db must remain open");
-    
-    let raw_local_key = result_receiver
-        .await
-        .expect("local db must remain open");
-    
-    let raw_local_key = match raw_local_key {
-        DBOpOut::ReadFromDB { status } => 
-            status.map_err(|e| MpcNodeError::StorageError(e))?,
-        _ => unreachable!(),
+        .map_err(|e| MpcNodeError::StorageError(e))?;
+    decode_key(&active.shard)
+}
+
+pub(super) fn public_key_of(raw_key: &[u8]) -> Result<Vec<u8>, MpcNodeError> {
+    Ok(decode_key(raw_key)?.public_key().to_bytes(true).to_vec())
+}
+
+async fn store_generated_key(
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
+    header: &PayloadHeader,
+    key_shard_id: CryptoHash,
+    local_key: Vec<u8>,
+) -> Result<(), MpcNodeError> {
+    let public_key = public_key_of(&local_key)?;
+    let epoch = shards
+        .put_active(key_shard_id, header.payload_id, local_key).await
+        .map_err(MpcNodeError::StorageError)?;
+    metadata::record_keygen(shards.storage().as_ref(), key_shard_id, header, public_key, epoch).await
+}
+
+/// Stages the refreshed shard, returns its epoch. The active shard stays in use until the
+/// refresh is committed.
+async fn store_refreshed_key(
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
+    header: &PayloadHeader,
+    key_shard_id: CryptoHash,
+    new_key: Vec<u8>,
+) -> Result<u64, MpcNodeError> {
+    let public_key = public_key_of(&new_key)?;
+    let active_epoch = shards
+        .active(key_shard_id).await
+        .map_err(MpcNodeError::StorageError)?
+        .epoch;
+    let epoch = shards
+        .add_pending(key_shard_id, header.payload_id, new_key).await
+        .map_err(MpcNodeError::StorageError)?;
+    metadata::record_pending_refresh(shards.storage().as_ref(), key_shard_id, header, public_key, active_epoch, epoch).await?;
+    Ok(epoch)
+}
+
+/// The whole committee holds shards of `epoch`, they replace the active ones
+async fn commit_refresh(
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
+    key_shard_id: CryptoHash,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    shards
+        .promote(key_shard_id, epoch).await
+        .map_err(MpcNodeError::StorageError)?;
+    shards
+        .prune(key_shard_id, RETIRED_SHARD_RETENTION).await
+        .map_err(MpcNodeError::StorageError)?;
+    metadata::record_refresh(shards.storage().as_ref(), key_shard_id, epoch).await
+}
+
+/// The refresh job finished, which it only does once every committee member sent its refresh
+/// message - so the whole committee holds shards of the new epoch. Stages the refreshed shard and
+/// commits it, returns its epoch.
+async fn refresh_key(
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
+    header: &PayloadHeader,
+    key_shard_id: CryptoHash,
+    new_key: Vec<u8>,
+) -> Result<u64, MpcNodeError> {
+    let epoch = store_refreshed_key(shards, header, key_shard_id, new_key).await?;
+    commit_refresh(shards, key_shard_id, epoch).await?;
+    Ok(epoch)
+}
+
+/// The refresh of `epoch` failed for part of the committee, the active shards stay in use
+async fn rollback_refresh(
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
+    key_shard_id: CryptoHash,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    shards
+        .discard(key_shard_id, epoch).await
+        .map_err(MpcNodeError::StorageError)?;
+    metadata::drop_pending_refresh(shards.storage().as_ref(), key_shard_id, epoch).await
+}
+
+/// Decision of the committee on a staged refresh, served by the event loop of the node in
+/// between jobs
+#[derive(Debug)]
+enum RefreshRequest {
+    Commit {
+        key_shard_id: CryptoHash,
+        epoch: u64,
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+    },
+    Rollback {
+        key_shard_id: CryptoHash,
+        epoch: u64,
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+    },
+}
+
+async fn serve_refresh_request(shards: &ShardStore<Arc<dyn MpcStorage>>, request: RefreshRequest) {
+    let (result, result_sender) = match request {
+        RefreshRequest::Commit { key_shard_id, epoch, result_sender } => {
+            log::info!("Committing refresh of key {:?} at epoch {}", key_shard_id, epoch);
+            (commit_refresh(shards, key_shard_id, epoch).await, result_sender)
+        },
+        RefreshRequest::Rollback { key_shard_id, epoch, result_sender } => {
+            log::info!("Rolling back refresh of key {:?} at epoch {}", key_shard_id, epoch);
+            (rollback_refresh(shards, key_shard_id, epoch).await, result_sender)
+        },
     };
-    decode_key(&raw_local_key)
+    result_sender
+        .send(result)
+        .expect("result receiver not to be dropped");
 }
 
 async fn assign_job(
     key_shard_id: CryptoHash,
     payload_header: PayloadHeader, 
     result_sender: oneshot::Sender<Result< ClientOutcome, MpcNodeError>>,
-    db_in_channel: &mut mpsc::Sender<DBOpIn>,
+    shards: &ShardStore<Arc<dyn MpcStorage>>,
     job_manager: &mut JobManager<'_>
 ) -> Result<(), MpcNodeError> {
     match payload_header.clone().payload_type {
         PayloadType::KeyGen => {
-            let maybe_local_key = get_local_key(db_in_channel, key_shard_id).await;
+            let maybe_local_key = get_local_key(shards, key_shard_id).await;
             if maybe_local_key.is_ok() {
                 result_sender.send(Err(MpcNodeError::NodeError(NodeError::LocalKeyExists)))
                     .expect("request result receiver not to be dropped");
@@ -55,11 +161,11 @@ async fn assign_job(
                 job_manager.keygen_accept_new_job( key_shard_id, payload_header.clone(), result_sender );
             }
         },
-        PayloadType::SignOffline { message }=> {
+        PayloadType::SignOffline { message, .. } => {
             job_manager.sign_accept_new_job(
                 key_shard_id,
                 payload_header.clone(), 
-                get_local_key(db_in_channel, key_shard_id).await?, 
+                get_local_key(shards, key_shard_id).await?, 
                 message, result_sender
             ).await;
         },
@@ -67,7 +173,7 @@ async fn assign_job(
             job_manager.key_refresh_accept_new_job(
                 key_shard_id,
                 payload_header.clone(), 
-                Some(get_local_key(db_in_channel, key_shard_id).await?), // on fullnode - we should always have the key
+                Some(get_local_key(shards, key_shard_id).await?), // on fullnode - we should always have the key
                 result_sender
             ).await;
         },
@@ -76,27 +182,76 @@ async fn assign_job(
     Ok(())
 }
 
+async fn serve_backup_request(storage: &dyn MpcStorage, request: BackupRequest) {
+    match request {
+        BackupRequest::Export { key, result_sender } => {
+            result_sender
+                .send(backup::export(storage, &key).await)
+                .expect("result receiver not to be dropped");
+        },
+        BackupRequest::Import { archive, key, dry_run, result_sender } => {
+            let report = backup::import(storage, &archive, &key, dry_run).await;
+            if let Ok(report) = &report {
+                log::info!("Imported backup of {} shards, applied: {}", report.shards, report.applied);
+            }
+            result_sender
+                .send(report)
+                .expect("result receiver not to be dropped");
+        },
+    }
+}
+
+/// Hands `request` to the task of a node, or back if the node is unknown or gone
+async fn send_to_node<T>(channel: Option<&mut mpsc::Sender<T>>, request: T) -> Result<(), T> {
+    let channel = match channel {
+        Some(channel) => channel,
+        None => return Err(request),
+    };
+    if futures::future::poll_fn(|cx| channel.poll_ready(cx)).await.is_err() {
+        return Err(request);
+    }
+    channel.try_send(request).map_err(|e| e.into_inner())
+}
+
+fn node_not_found<T>() -> Result<T, MpcNodeError> {
+    Err(MpcNodeError::NodeError(NodeError::NodeNotFound))
+}
+
 pub async fn full_node_event_loop(
     mut client_in: mpsc::Receiver<ClientRequest>
 ) {
     let mut shutdown_channels: HashMap<PeerId, mpsc::Sender<()>> = HashMap::new();
-    let mut db_in_channels: HashMap<PeerId, mpsc::Sender<DBOpIn>> = HashMap::new();
+    let mut storages: HashMap<PeerId, Arc<dyn MpcStorage>> = HashMap::new();
+    let mut backup_channels: HashMap<PeerId, mpsc::Sender<BackupRequest>> = HashMap::new();
+    let mut refresh_channels: HashMap<PeerId, mpsc::Sender<RefreshRequest>> = HashMap::new();
 
     loop {
         let client_request = client_in.select_next_some().await;
 
         match client_request {
-            ClientRequest::BootstrapNode { local_key, listen_addr, db_name, mut result_sender } => {                
+            ClientRequest::BootstrapNode { local_key, listen_addr, storage, round_timeouts, mut result_sender } => {                
+                let storage = match storage.open(async_executor).await {
+                    Ok(storage) => storage,
+                    Err(e) => {
+                        log::error!("Failed To Open Storage {:?}", e);
+                        result_sender
+                            .send(Err(MpcNodeError::StorageError(e))).await
+                            .expect("result_receiver should not be dropped for client_reuqest");
+                        continue;
+                    }
+                };
+                // records are upgraded as they are read, backups keep them as stored
+                let raw_storage = storage;
+                let storage: Arc<dyn MpcStorage> = Arc::new(MigratingStorage::new(raw_storage.clone(), storage_schema()));
                 let (shutdown_sender, mut shutdown_receiver) = mpsc::channel(0);
+                // served in between jobs, so a backup never sees a job half written
+                let (backup_sender, mut backup_receiver) = mpsc::channel(0);
+                let (refresh_sender, mut refresh_receiver) = mpsc::channel(0);
                 let mut result_sender_inside = result_sender.clone();
 
                 // wire up this node to emit PeerId & Listening Addr
                 let (peer_id_sender, peer_id_receiver) = oneshot::channel();            
-                let (storage_config, mut storage_in_sender) = default_mpc_storage_opt(
-                    db_name, false
-                );
-                run_db_server(storage_config);
-                let db_in_chanel = storage_in_sender.clone();
+                let node_storage = storage.clone();
 
                 async_executor(async move {
                     let (
@@ -109,8 +264,12 @@ pub async fn full_node_event_loop(
                         mut job_assignment_receiver,
                         mut swarm_message_receiver,
                         mut swarm_termination_sender,
-                    ) = new_full_swarm_node(local_key);
+                    ) = new_full_swarm_node(
+                        local_key, SkwMpcP2pCodec::default(), 
+                        DiscoveryConfig::from_env(), BroadcastMode::from_env(),
+                    );
 
+                    let shards = ShardStore::new(storage);
                     async_executor(swarm_event_loop.run());
                     let mut interal_results = FuturesUnordered::new();
                     
@@ -134,11 +293,14 @@ pub async fn full_node_event_loop(
 
                     let mut job_manager = JobManager::new(
                         local_peer_id, &mut swarm_client,
+                        // key shards are stored locally
+                        Encoding::Binary,
+                        round_timeouts.unwrap_or_else(RoundTimeouts::from_env),
                         keygen_outgoing_sender, sign_offline_outgoing_sender,
                         sign_fianlize_partial_signature_outgoing_sender,
                         key_refresh_join_message_outgoing_sender,
                         key_refresh_refresh_message_outgoing_sender,
-                    );
+                    ).with_transcripts(TranscriptConfig::from_env());
 
                     loop {
                         futures::select! {
@@ -150,9 +312,10 @@ pub async fn full_node_event_loop(
                                 // Just in case - we filter out request address to ourselves
                                 if payload_header.sender != local_peer_id {
                                     let (inner_result_sender, inner_result_receiver) = oneshot::channel();
-                                    interal_results.push(inner_result_receiver);
+                                    let job_header = payload_header.clone();
+                                    interal_results.push(inner_result_receiver.map(move |outcome| (job_header, outcome)));
 
-                                    match assign_job(key_shard_id, payload_header, inner_result_sender, &mut storage_in_sender, &mut job_manager).await {
+                                    match assign_job(key_shard_id, payload_header, inner_result_sender, &shards, &mut job_manager).await {
                                         Ok(_) => {  }
                                         Err(e) => { 
                                             log::error!("FATAL ERROR: Assigning Job Failed {:?}", e); 
@@ -178,56 +341,34 @@ pub async fn full_node_event_loop(
                                 }
                             },
 
-                            outcome = interal_results.select_next_some() => {
+                            (job_header, outcome) = interal_results.select_next_some() => {
                                 match outcome.expect("internal result sender not to be dropped") {
                                     Ok(outcome) => {
                                         match outcome {
                                             ClientOutcome::KeyGen { local_key, key_shard_id, .. } => {
                                                 log::info!("Writing Key {:?}", key_shard_id);
 
-                                                let (db_res_sender, db_res_receiver) = oneshot::channel();
-                                                storage_in_sender.send(DBOpIn::WriteToDB { 
-                                                    key: key_shard_id, 
-                                                    value: local_key, 
-                                                    result_sender: db_res_sender
-                                                }).await.expect("DB must remain open");
-        
-                                                if let DBOpOut::WriteToDB { status } = db_res_receiver.await.expect("DB must remain open") {
-                                                    match status {
-                                                        Ok(_) => {},
-                                                        Err(e) => { 
-                                                            log::error!("Internal result write to db error {:?}", e); 
-                                                            result_sender_inside
-                                                                .send(Err(MpcNodeError::StorageError(e))).await
-                                                                .expect("bootstrapping result sender not to be dropped");
-                                                        }
-                                                    }
+                                                if let Err(e) = store_generated_key(&shards, &job_header, key_shard_id, local_key).await {
+                                                    log::error!("Internal result write to db error {:?}", e); 
+                                                    result_sender_inside
+                                                        .send(Err(e)).await
+                                                        .expect("bootstrapping result sender not to be dropped");
                                                 }
                                             },
-                                            ClientOutcome::Sign { .. } => {
-                                                // Nop for sign for fullnodes
-                                                // log::info!("Sign Result {:?} {:?} {:?} ", peer_id, payload_id, decode_signature(&sig));
+                                            ClientOutcome::Sign { key_shard_id, .. } => {
+                                                // usage stats only - a failure here doesn't affect the signature
+                                                if let Err(e) = metadata::record_sign(shards.storage().as_ref(), key_shard_id).await {
+                                                    log::warn!("Failed to update key metadata {:?}", e);
+                                                }
                                             },
                                             ClientOutcome::KeyRefresh { new_key, key_shard_id, .. } => {
-
-                                                log::info!("Updating Key {:?}", key_shard_id);
-
-                                                let (db_res_sender, db_res_receiver) = oneshot::channel();
-                                                storage_in_sender.send(DBOpIn::WriteToDB { 
-                                                    key: key_shard_id, 
-                                                    value: new_key, 
-                                                    result_sender: db_res_sender
-                                                }).await.expect("DB must remain open");
-        
-                                                if let DBOpOut::WriteToDB { status } = db_res_receiver.await.expect("DB must remain open") {
-                                                    match status {
-                                                        Ok(_) => {},
-                                                        Err(e) => { 
-                                                            log::error!("Internal result write to db error {:?}", e); 
-                                                            result_sender_inside
-                                                                .send(Err(MpcNodeError::StorageError(e))).await
-                                                                .expect("bootstrapping result sender not to be dropped");
-                                                        }
+                                                match refresh_key(&shards, &job_header, key_shard_id, new_key).await {
+                                                    Ok(epoch) => log::info!("Refreshed key {:?} to epoch {}", key_shard_id, epoch),
+                                                    Err(e) => {
+                                                        log::error!("Internal result write to db error {:?}", e); 
+                                                        result_sender_inside
+                                                            .send(Err(e)).await
+                                                            .expect("bootstrapping result sender not to be dropped");
                                                     }
                                                 }
                                             },
@@ -240,26 +381,25 @@ pub async fn full_node_event_loop(
                                 }
                             },
 
+                            request = backup_receiver.select_next_some() => {
+                                serve_backup_request(raw_storage.as_ref(), request).await;
+                            },
+
+                            request = refresh_receiver.select_next_some() => {
+                                serve_refresh_request(&shards, request).await;
+                            },
+
                             _ = shutdown_receiver.select_next_some() => {
                                 // 1. shutdown the swarm
                                 swarm_termination_sender.send(()).await
                                     .expect("swarm node should not be dropped");
 
-                                // 2. shutdown the db server
-                                let (result_sender, result_receiver) = oneshot::channel();
-                                storage_in_sender
-                                    .send(DBOpIn::Shutdown { result_sender }).await
-                                    .expect("shutdown swarm should not fail");
-                                if let DBOpOut::Shutdown { status }= result_receiver.await.expect("DB must remain open") {
-                                    match status {
-                                        Ok(_) => {},
-                                        Err(e) => { 
-                                            log::error!("Internal result write to db error {:?}", e); 
-                                            result_sender_inside
-                                                .send(Err(MpcNodeError::StorageError(e))).await
-                                                .expect("bootstrapping result sender not to be dropped");
-                                        }
-                                    }
+                                // 2. close the storage
+                                if let Err(e) = shards.storage().close().await {
+                                    log::error!("Internal result write to db error {:?}", e); 
+                                    result_sender_inside
+                                        .send(Err(MpcNodeError::StorageError(e))).await
+                                        .expect("bootstrapping result sender not to be dropped");
                                 }
                                 // 3. shutdown node event loop for node
                                 break;
@@ -270,47 +410,194 @@ pub async fn full_node_event_loop(
 
                 let local_swarm_info = peer_id_receiver.await.expect("cannot be canceled");
                 shutdown_channels.insert(local_swarm_info.0, shutdown_sender);
-                db_in_channels.insert(local_swarm_info.0, db_in_chanel);
+                storages.insert(local_swarm_info.0, node_storage);
+                backup_channels.insert(local_swarm_info.0, backup_sender);
+                refresh_channels.insert(local_swarm_info.0, refresh_sender);
                 result_sender
                     .send(Ok(local_swarm_info)).await
                     .expect("result_receiver should not be dropped for client_reuqest");
             },
 
             ClientRequest::Shutdown { node, result_sender} => {
-                shutdown_channels
-                    .get_mut(&node)
-                    .expect("shutdown channel not found")
-                    .send(())
-                    .await
-                    .expect("shutdown receiver not to be dropped");
+                storages.remove(&node);
+                backup_channels.remove(&node);
+                refresh_channels.remove(&node);
+                let status = match send_to_node(shutdown_channels.remove(&node).as_mut(), ()).await {
+                    Ok(()) => Ok(()),
+                    Err(()) => node_not_found(),
+                };
                 result_sender
-                    .send(Ok(()))
+                    .send(status)
                     .expect("result receiver not to be dropped");
             }
             ClientRequest::WriteToDB { node, key, value, result_sender } => {
-                let (db_write_result_sender, db_write_result_receiver) = oneshot::channel();
-
-                db_in_channels
-                    .get_mut(&node)
-                    .expect("shutdown channel not found")
-                    .send(DBOpIn::WriteToDB { key, value, result_sender: db_write_result_sender })
-                    .await
-                    .expect("db_in_receiver should not be dropped");
-                let x = db_write_result_receiver
-                    .await
-                    .expect("db_write_result_receiver is not dropped");
-                if let DBOpOut::WriteToDB { status } = x {
-                    match status {
-                        Ok(_) => { result_sender.send(Ok(())).expect("result receiver not to be dropped"); }
-                        Err(e) => { 
-                            log::error!("Internal result write to db error {:?}", e); 
-                            result_sender
-                                .send(Err(MpcNodeError::StorageError(e)))
-                                .expect("bootstrapping result sender not to be dropped");
-                        }
-                    }
+                let status = match storages.get(&node) {
+                    Some(storage) => storage
+                        .put(Namespace::Shards, &key, value)
+                        .await
+                        .map_err(|e| {
+                            log::error!("Internal result write to db error {:?}", e);
+                            MpcNodeError::StorageError(e)
+                        }),
+                    None => node_not_found(),
+                };
+                result_sender
+                    .send(status)
+                    .expect("result receiver not to be dropped");
+            }
+            ClientRequest::KeyMetadata { node, key_shard_id, result_sender } => {
+                let key_metadata = match storages.get(&node) {
+                    Some(storage) => metadata::read_metadata(storage.as_ref(), key_shard_id).await,
+                    None => node_not_found(),
+                };
+                result_sender
+                    .send(key_metadata)
+                    .expect("result receiver not to be dropped");
+            }
+            ClientRequest::Scan { node, namespace, prefix, limit, cursor, result_sender } => {
+                let page = match storages.get(&node) {
+                    Some(storage) => storage
+                        .scan(namespace, &prefix, limit, cursor)
+                        .await
+                        .map_err(MpcNodeError::StorageError),
+                    None => node_not_found(),
+                };
+                result_sender
+                    .send(page)
+                    .expect("result receiver not to be dropped");
+            }
+
+            ClientRequest::ExportBackup { node, key, result_sender } => {
+                let request = BackupRequest::Export { key, result_sender };
+                if let Err(BackupRequest::Export { result_sender, .. }) = send_to_node(backup_channels.get_mut(&node), request).await {
+                    result_sender
+                        .send(node_not_found())
+                        .expect("result receiver not to be dropped");
+                }
+            }
+            ClientRequest::ImportBackup { node, archive, key, dry_run, result_sender } => {
+                let request = BackupRequest::Import { archive, key, dry_run, result_sender };
+                if let Err(BackupRequest::Import { result_sender, .. }) = send_to_node(backup_channels.get_mut(&node), request).await {
+                    result_sender
+                        .send(node_not_found())
+                        .expect("result receiver not to be dropped");
+                }
+            }
+            ClientRequest::CommitRefresh { node, key_shard_id, epoch, result_sender } => {
+                let request = RefreshRequest::Commit { key_shard_id, epoch, result_sender };
+                if let Err(RefreshRequest::Commit { result_sender, .. }) = send_to_node(refresh_channels.get_mut(&node), request).await {
+                    result_sender
+                        .send(node_not_found())
+                        .expect("result receiver not to be dropped");
                 }
             }
+            ClientRequest::RollbackRefresh { node, key_shard_id, epoch, result_sender } => {
+                let request = RefreshRequest::Rollback { key_shard_id, epoch, result_sender };
+                if let Err(RefreshRequest::Rollback { result_sender, .. }) = send_to_node(refresh_channels.get_mut(&node), request).await {
+                    result_sender
+                        .send(node_not_found())
+                        .expect("result receiver not to be dropped");
+                }
+            }
+
+            // served by the light node event loop
+            #[cfg(feature = "light-node")]
+            ClientRequest::MpcRequest { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use skw_mpc_protocol::gg20::state_machine::keygen::Keygen;
+    use skw_mpc_storage::{InMemoryStorage, MpcStorageError, ShardState};
+    use skw_round_based::dev::Simulation;
+
+    use super::*;
+    use crate::serde_support::encode_key;
+
+    async fn staged_refresh() -> ShardStore<Arc<dyn MpcStorage>> {
+        let shards = ShardStore::new(Arc::new(InMemoryStorage::default()) as Arc<dyn MpcStorage>);
+        let key_shard_id = [1u8; 32];
+        let header = PayloadHeader::default();
+        shards.put_active(key_shard_id, [0u8; 32], vec![1]).await.unwrap();
+        metadata::record_keygen(shards.storage().as_ref(), key_shard_id, &header, vec![2; 33], 0).await.unwrap();
+
+        let mut refresh = header.clone();
+        refresh.peers.pop();
+        let epoch = shards.add_pending(key_shard_id, [2u8; 32], vec![2]).await.unwrap();
+        metadata::record_pending_refresh(shards.storage().as_ref(), key_shard_id, &refresh, vec![2; 33], 0, epoch).await.unwrap();
+        shards
+    }
+
+    #[tokio::test]
+    async fn committed_refresh_replaces_the_active_shard() {
+        let shards = staged_refresh().await;
+        // signing keeps using the keygen shard until the committee commits
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, vec![1]);
+
+        commit_refresh(&shards, [1u8; 32], 1).await.unwrap();
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, vec![2]);
+        let metadata = metadata::read_metadata(shards.storage().as_ref(), [1u8; 32]).await.unwrap();
+        assert_eq!((metadata.epoch, metadata.pending_refresh), (1, None));
+    }
+
+    #[tokio::test]
+    async fn finished_refresh_job_replaces_the_active_shard() {
+        let mut simulation = Simulation::new();
+        for i in 1..=2 {
+            simulation.add_party(Keygen::new(i, 1, 2).unwrap());
+        }
+        let keys = simulation.run().unwrap();
+
+        let shards = ShardStore::new(Arc::new(InMemoryStorage::default()) as Arc<dyn MpcStorage>);
+        let header = PayloadHeader::default();
+        store_generated_key(&shards, &header, [1u8; 32], encode_key(&keys[0], Encoding::Binary)).await.unwrap();
+
+        // no commit needed once the refresh job is done
+        let new_key = encode_key(&keys[1], Encoding::Binary);
+        let epoch = refresh_key(&shards, &header, [1u8; 32], new_key.clone()).await.unwrap();
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, new_key);
+        assert_eq!(shards.pending([1u8; 32]).await, Ok(None));
+        let metadata = metadata::read_metadata(shards.storage().as_ref(), [1u8; 32]).await.unwrap();
+        assert_eq!((metadata.epoch, metadata.pending_refresh), (epoch, None));
+    }
+
+    #[tokio::test]
+    async fn rolled_back_refresh_keeps_the_active_shard() {
+        let shards = staged_refresh().await;
+
+        rollback_refresh(&shards, [1u8; 32], 1).await.unwrap();
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, vec![1]);
+        assert_eq!(shards.pending([1u8; 32]).await, Ok(None));
+        assert_eq!(shards.history([1u8; 32]).await.unwrap()[1].state, ShardState::Retired);
+
+        let metadata = metadata::read_metadata(shards.storage().as_ref(), [1u8; 32]).await.unwrap();
+        assert_eq!((metadata.epoch, metadata.pending_refresh), (0, None));
+        assert_eq!(metadata.committee.len(), PayloadHeader::default().peers.len());
+
+        // a rolled back refresh can't be committed anymore
+        assert_eq!(
+            commit_refresh(&shards, [1u8; 32], 1).await,
+            Err(MpcNodeError::StorageError(MpcStorageError::VersionNotPending(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_to_unknown_nodes_are_refused() {
+        let (request_sender, request_receiver) = mpsc::channel(0);
+        tokio::spawn(full_node_event_loop(request_receiver));
+        let mut client = crate::node::NodeClient::new(request_sender);
+        let node = PeerId::random();
+        let not_found = MpcNodeError::NodeError(NodeError::NodeNotFound);
+
+        assert!(matches!(client.key_metadata(node, [1u8; 32]).await, Err(e) if e == not_found));
+        assert_eq!(client.commit_refresh(node, [1u8; 32], 1).await, Err(not_found.clone()));
+        assert_eq!(client.export_backup(node, skw_mpc_storage::MasterKeySource::Passphrase("passphrase".to_string())).await, Err(not_found.clone()));
+        assert_eq!(client.shutdown(node).await, Err(not_found));
+    }
+}
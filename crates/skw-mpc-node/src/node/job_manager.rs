@@ -1,24 +1,27 @@
 use std::{collections::HashMap, fmt::Debug};
 
 use futures::{channel::{mpsc, oneshot}, StreamExt, TryStreamExt};
-use libp2p::{PeerId};
+use libp2p::{PeerId, Multiaddr};
 use serde::{Serialize, de::DeserializeOwned};
 
 use skw_crypto_curv::elliptic::curves::secp256_k1::Secp256k1;
 use skw_crypto_curv::{BigInt, arithmetic::Converter};
 
-use skw_mpc_payload::{CryptoHash, PayloadHeader, Payload, header::PayloadType};
-use skw_round_based::{async_runtime::AsyncProtocol, Msg};
+use skw_mpc_payload::{CryptoHash, PayloadHeader, Payload, JobType, header::PayloadType};
+use skw_round_based::{async_runtime::{offload, watcher::TracingWatcher, AsyncProtocol}, Msg};
 use skw_mpc_protocol::{gg20::state_machine::{keygen::{self, LocalKey}, sign::{self, SignManual, PartialSignature}}, key_refresh::{JoinMessage, RefreshMessage}};
 
 use crate::{
     async_executor,
-    swarm::{MpcSwarmClient, MpcP2pRequest, MpcP2pResponse}, 
-    serde_support::{decode_payload, encode_payload, encode_key, encode_signature}, 
+    swarm::{MpcSwarmClient, MpcP2pRequest, MpcP2pResponse, PeerProtocol}, 
+    serde_support::{decode_payload, encode_payload, encode_key, encode_signature, encoded_size, Encoding}, 
     error::{MpcNodeError, MpcProtocolError, NodeError}, wire_incoming_pipe, 
 };
 
 use crate::node::client_outcome::ClientOutcome;
+use crate::node::timeouts::RoundTimeouts;
+#[cfg(feature = "full-node")]
+use crate::node::transcripts::TranscriptConfig;
 
 type KeyGenMessage = Msg<keygen::ProtocolMessage>;
 type SignOfflineMessage = Msg<sign::OfflineProtocolMessage>;
@@ -29,11 +32,49 @@ type RefreshMessageMsg = Msg<RefreshMessage>;
 #[cfg(feature = "light-node")]
 use skw_mpc_payload::AuthHeader;
 
+/// Where the heavy protocol rounds run - there are no blocking threads in the browser
+#[cfg(not(target_arch = "wasm32"))]
+type ProceedOffload = offload::TokioBlocking;
+#[cfg(target_arch = "wasm32")]
+type ProceedOffload = offload::Inline;
+
+/// Keygen party index of each signer of `header`, checked against the index of the local shard
+fn signing_parties(header: &PayloadHeader, local_index: u16, local_party: u16) -> Result<Vec<u16>, MpcNodeError> {
+    let parties: Vec<u16> = match &header.payload_type {
+        PayloadType::SignOffline { parties, .. } if !parties.is_empty() => parties.clone(),
+        _ => (1..=header.peers.len() as u16).collect(),
+    };
+
+    let local_entry = usize::from(local_index)
+        .checked_sub(1)
+        .and_then(|i| parties.get(i));
+    if parties.len() != header.peers.len() || local_entry != Some(&local_party) {
+        return Err(MpcNodeError::NodeError(NodeError::InvalidOutgoingParameter));
+    }
+    Ok(parties)
+}
+
+/// Parent span of everything traced while running the protocol of a job
+fn job_span(header: &PayloadHeader) -> tracing::Span {
+    let payload_id: String = header.payload_id.iter().map(|b| format!("{:02x}", b)).collect();
+    tracing::info_span!("mpc_job", payload_id = %payload_id, payload_type = ?header.payload_type)
+}
+
 // 'node should be the same as 'static for most of the time
 pub struct JobManager<'node> {
     local_peer_id: PeerId,
     client: &'node mut MpcSwarmClient,
 
+    // encoding of the LocalKey handed out in ClientOutcome
+    key_encoding: Encoding,
+    // how long each protocol round waits for the other parties
+    round_timeouts: RoundTimeouts,
+    // where keygen and offline signing jobs are recorded, if anywhere
+    #[cfg(feature = "full-node")]
+    transcripts: Option<TranscriptConfig>,
+    // outcome of the capability handshake with each peer
+    peer_protocols: HashMap<PeerId, PeerProtocol>,
+
     // Protocol IO For KeyGen
     keygen_protocol_incoming_channel: HashMap<CryptoHash, mpsc::Sender<Result<Payload<KeyGenMessage>, std::io::Error>>>,
     keygen_outgoing_sender: mpsc::UnboundedSender<Payload<KeyGenMessage>>,
@@ -57,6 +98,8 @@ impl<'node> JobManager<'node> {
     pub fn new(
         local_peer_id: PeerId,
         client: &'node mut MpcSwarmClient,
+        key_encoding: Encoding,
+        round_timeouts: RoundTimeouts,
 
         keygen_outgoing_sender: mpsc::UnboundedSender<Payload<KeyGenMessage>>,
         
@@ -71,6 +114,12 @@ impl<'node> JobManager<'node> {
 
             client,
 
+            key_encoding,
+            round_timeouts,
+            #[cfg(feature = "full-node")]
+            transcripts: None,
+            peer_protocols: Default::default(),
+
             keygen_protocol_incoming_channel: Default::default(),            
             keygen_outgoing_sender,
 
@@ -87,6 +136,13 @@ impl<'node> JobManager<'node> {
         }
     }
 
+    /// Records keygen and offline signing jobs, see [transcripts](crate::node::transcripts)
+    #[cfg(feature = "full-node")]
+    pub fn with_transcripts(mut self, transcripts: Option<TranscriptConfig>) -> Self {
+        self.transcripts = transcripts;
+        self
+    }
+
     #[cfg(feature = "light-node")]
     pub async fn init_new_job(&mut self, 
         new_auth_header: AuthHeader, 
@@ -99,6 +155,9 @@ impl<'node> JobManager<'node> {
                 self.client
                     .dial(peer.clone(), peer_addr.clone())
                     .await?;
+                self.negotiate(peer.clone(), JobType::from(&new_header.payload_type))
+                    .await?;
+
                 let res = self.client.send_request( peer.clone(), 
                     MpcP2pRequest::StartJob { 
                         auth_header: new_auth_header.clone(),
@@ -122,37 +181,62 @@ impl<'node> JobManager<'node> {
     ) {
         let job_id = new_header.clone().payload_id;
         let local_peer_id = self.local_peer_id.clone();
+        let key_encoding = self.key_encoding;
+        let round_timeouts = self.round_timeouts.clone();
+        #[cfg(feature = "full-node")]
+        let transcripts = self.transcripts.clone();
         let (incoming_sender, incoming_receiver) = mpsc::channel(2);
         let outgoing_sender = self.keygen_outgoing_sender.clone();
         self.keygen_protocol_incoming_channel.insert(job_id, incoming_sender.clone());
+        let job_topic = self.client.join_job_topic(&new_header);
 
         // spin up the thread to handle these tasks
         async_executor(async move {
+            // stay on the broadcast topic until the job is done
+            let _job_topic = job_topic;
+
             let local_index = new_header.peers.iter()
                 .position(|p| p.0.clone() == local_peer_id)
                 .unwrap()
                 .saturating_add(1);
 
-            match keygen::Keygen::new(
+            let (i, t, n) = (
                 local_index.try_into().unwrap(), 
                 new_header.t.saturating_sub(1), // we need to sub t by 1 - ref to kzen-curv's VSS impl
                 new_header.n
-            ) {
+            );
+            match keygen::Keygen::new(i, t, n) {
                 Ok(keygen_sm) => {
-                    match AsyncProtocol::new(keygen_sm, 
+                    let keygen_sm = round_timeouts.keygen(keygen_sm);
+                    #[cfg(feature = "full-node")]
+                    let transcript = transcripts.and_then(|transcripts| transcripts.keygen(&new_header, i, t, n));
+                    #[cfg(feature = "full-node")]
+                    let keygen_sm = match &transcript {
+                        Some(transcript) => keygen_sm.with_rng_seed(transcript.seed),
+                        None => keygen_sm,
+                    };
+
+                    let protocol = AsyncProtocol::new(keygen_sm, 
                         incoming_receiver, outgoing_sender,
                         new_header.clone()
                     )
-                        .run()
-                        .await
-                    {
+                        .set_watcher(TracingWatcher::new(job_span(&new_header)).with_message_size(encoded_size::<keygen::ProtocolMessage>))
+                        // keep the heavy rounds off the executor shared with the swarm and other jobs
+                        .set_offload(ProceedOffload::default());
+                    #[cfg(feature = "full-node")]
+                    let protocol = match transcript {
+                        Some(transcript) => protocol.set_recorder(transcript.writer),
+                        None => protocol,
+                    };
+
+                    match protocol.run().await {
                         Ok(local_key) => {
                             result_sender
                             .send(Ok(ClientOutcome::KeyGen {
                                 peer_id: local_peer_id,
                                 payload_id: new_header.payload_id,
                                 key_shard_id,
-                                local_key: encode_key(&local_key)
+                                local_key: encode_key(&local_key, key_encoding)
                             }))
                             .expect("result_receiver not to be dropped")
                         },
@@ -182,6 +266,9 @@ impl<'node> JobManager<'node> {
     ) {
         let job_id = new_header.clone().payload_id;
         let local_peer_id = self.local_peer_id.clone();
+        let round_timeouts = self.round_timeouts.clone();
+        #[cfg(feature = "full-node")]
+        let transcripts = self.transcripts.clone();
 
         let (incoming_sender, incoming_receiver) = mpsc::channel(2);
         let (incoming_partial_sig_sender, incoming_partial_sig_receiver) = mpsc::channel(2);
@@ -191,29 +278,59 @@ impl<'node> JobManager<'node> {
 
         self.sign_fianlize_partial_signature_incoming_channel.insert(job_id, incoming_partial_sig_sender.clone());
         self.sign_offline_protocol_incoming_channel.insert(job_id, incoming_sender.clone());
+        let job_topic = self.client.join_job_topic(&new_header);
 
         // spin up the thread to handle these tasks
         async_executor(async move {
+            // stay on the broadcast topic until the job is done
+            let _job_topic = job_topic;
+
             let local_index: u16 = new_header.clone().peers.iter()
                 .position(|p| p.0.clone() == local_peer_id)
                 .unwrap()
                 .saturating_add(1)
                 .try_into().unwrap();
 
-            // TODO: we hardcode the node to call to peers
-            let peers_index = [1u16, 2u16]; 
+            let peers_index = match signing_parties(&new_header, local_index, local_key.i) {
+                Ok(peers_index) => peers_index,
+                Err(e) => {
+                    result_sender
+                        .send(Err(e))
+                        .expect("result_receiver not to be dropped");
+                    return;
+                }
+            };
+
+            #[cfg(feature = "full-node")]
+            let transcript = transcripts.and_then(|transcripts| {
+                transcripts.sign_offline(&new_header, local_index, &peers_index, &local_key)
+            });
 
             match sign::OfflineStage::new(
-                local_index, peers_index.to_vec(), local_key
+                local_index, peers_index, local_key
             ) {
                 Ok(offline_sign_sm) => {
-                    match AsyncProtocol::new(offline_sign_sm, 
+                    let offline_sign_sm = round_timeouts.sign_offline(offline_sign_sm);
+                    #[cfg(feature = "full-node")]
+                    let offline_sign_sm = match &transcript {
+                        Some(transcript) => offline_sign_sm.with_rng_seed(transcript.seed),
+                        None => offline_sign_sm,
+                    };
+
+                    let protocol = AsyncProtocol::new(offline_sign_sm, 
                         incoming_receiver, outgoing_sender,
                         new_header.clone()
                     )
-                        .run()
-                        .await
-                    {
+                        .set_watcher(TracingWatcher::new(job_span(&new_header)).with_message_size(encoded_size::<sign::OfflineProtocolMessage>))
+                        // keep the heavy rounds off the executor shared with the swarm and other jobs
+                        .set_offload(ProceedOffload::default());
+                    #[cfg(feature = "full-node")]
+                    let protocol = match transcript {
+                        Some(transcript) => protocol.set_recorder(transcript.writer),
+                        None => protocol,
+                    };
+
+                    match protocol.run().await {
                         Ok(completed_offline_stage) => {
                             match SignManual::new(
                                 BigInt::from_bytes(&message[..]), 
@@ -297,6 +414,7 @@ impl<'node> JobManager<'node> {
     ) {
         let job_id = new_header.clone().payload_id;
         let local_peer_id = self.local_peer_id.clone();
+        let key_encoding = self.key_encoding;
 
         let (incoming_join_msg_sender, incoming_join_msg_receiver) = mpsc::channel(2);
         let (incoming_refresh_msg_sender, incoming_refresh_msg_receiver) = mpsc::channel(2);
@@ -306,9 +424,13 @@ impl<'node> JobManager<'node> {
 
         self.key_refresh_join_message_incoming_channel.insert(job_id, incoming_join_msg_sender.clone());
         self.key_refresh_refresh_message_incoming_channel.insert(job_id, incoming_refresh_msg_sender.clone());
+        let job_topic = self.client.join_job_topic(&new_header);
 
         // spin up the thread to handle these tasks
         async_executor(async move {
+            // stay on the broadcast topic until the job is done
+            let _job_topic = job_topic;
+
             let local_index: u16 = new_header.peers.iter()
                 .position(|p| p.0.clone() == local_peer_id)
                 .unwrap()
@@ -374,7 +496,7 @@ impl<'node> JobManager<'node> {
                                                         peer_id: local_peer_id, 
                                                         payload_id: new_header.payload_id, 
                                                         key_shard_id,
-                                                        new_key: encode_key(&local_key) 
+                                                        new_key: encode_key(&local_key, key_encoding) 
                                                     }))
                                                     .expect("result_receiver not to be dropped"),
                                                 Err(e) => result_sender
@@ -443,7 +565,7 @@ impl<'node> JobManager<'node> {
                                             peer_id: local_peer_id, 
                                             key_shard_id,
                                             payload_id: new_header.clone().payload_id, 
-                                            new_key: encode_key(&k)
+                                            new_key: encode_key(&k, key_encoding)
                                         }))
                                         .expect("result_receiver not to be dropped"),
                                     Err(e) => result_sender
@@ -482,7 +604,8 @@ impl<'node> JobManager<'node> {
     ) -> Result<(), MpcNodeError>
         where M: Clone + Serialize + DeserializeOwned + Debug
     {
-        let local_peer_id = self.local_peer_id.clone();
+        let mut payload_out = payload.clone();
+        payload_out.payload_header.sender = self.local_peer_id.clone();
 
         match payload.body.receiver {
             // this is a p2p message - only one receiver is assigned
@@ -491,44 +614,18 @@ impl<'node> JobManager<'node> {
                     return Err(MpcNodeError::NodeError(NodeError::InvalidOutgoingParameter));
                 }
                 let to_peer = payload.payload_header.peers[(to - 1) as usize].clone();
-
-                self.client
-                    .dial(to_peer.0, to_peer.1)
-                    .await?;
-                
-                let mut payload_out = payload.clone();
-                payload_out.payload_header.sender = local_peer_id;
-                if let MpcP2pResponse::RawMessage { status } = self.client
-                    .send_request(to_peer.0, MpcP2pRequest::RawMessage { 
-                        payload: encode_payload(&payload_out)
-                        })
-                    .await? 
-                {
-                    status?;
-                } else {
-                    unreachable!()
-                }
+                self.send_payload(to_peer, &payload_out).await?;
             },
             // this is a broadcast message
             None => {
-                for peer in payload.clone().payload_header.peers {
-                    if peer.0.to_string() != self.local_peer_id.to_string() {
-                        self.client
-                            .dial(peer.0, peer.1)
-                            .await?;
-
-                        let mut payload_out = payload.clone();
-                        payload_out.payload_header.sender = local_peer_id;
-                        if let MpcP2pResponse::RawMessage { status } = self.client
-                            .send_request(peer.0, MpcP2pRequest::RawMessage { 
-                                payload: encode_payload(&payload_out)
-                                })
-                            .await? 
-                        {
-                            status?;
-                        } else {
-                            unreachable!()
-                        }
+                let peers: Vec<(PeerId, Option<Multiaddr>)> = payload.clone().payload_header.peers
+                    .into_iter()
+                    .filter(|peer| peer.0.to_string() != self.local_peer_id.to_string())
+                    .collect();
+
+                if !self.try_gossip(&peers, &payload_out).await {
+                    for peer in peers {
+                        self.send_payload(peer, &payload_out).await?;
                     }
                 }
             }
@@ -536,4 +633,112 @@ impl<'node> JobManager<'node> {
     
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn send_payload<M>(&mut self, 
+        peer: (PeerId, Option<Multiaddr>),
+        payload_out: &Payload<Msg<M>>,
+    ) -> Result<(), MpcNodeError>
+        where M: Clone + Serialize + DeserializeOwned + Debug
+    {
+        self.client
+            .dial(peer.0, peer.1)
+            .await?;
+
+        let encoding = self
+            .negotiate(peer.0, JobType::from(&payload_out.payload_header.payload_type))
+            .await?;
+        let response = self.client
+            .send_request(peer.0, MpcP2pRequest::RawMessage { 
+                payload: encode_payload(payload_out, encoding)
+            })
+            .await?;
+
+        if let MpcP2pResponse::RawMessage { status } = response {
+            status
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// Publish a broadcast on the job topic when every peer listens on it.
+    /// Returns false when the caller should fall back to request-response.
+    async fn try_gossip<M>(&mut self, 
+        peers: &[(PeerId, Option<Multiaddr>)],
+        payload_out: &Payload<Msg<M>>,
+    ) -> bool
+        where M: Clone + Serialize + DeserializeOwned + Debug
+    {
+        let payload_id = payload_out.payload_header.payload_id;
+        let peer_ids = peers.iter().map(|(peer, _)| *peer).collect();
+        if !self.client.gossip_ready(payload_id, peer_ids).await {
+            return false;
+        }
+
+        // one message for everyone - binary only if every peer reads it
+        let job_type = JobType::from(&payload_out.payload_header.payload_type);
+        let mut encoding = Encoding::Binary;
+        for (peer, _) in peers {
+            match self.negotiate(*peer, job_type).await {
+                Ok(Encoding::Binary) => {},
+                Ok(Encoding::Json) => encoding = Encoding::Json,
+                // let request-response surface the error
+                Err(_) => return false,
+            }
+        }
+
+        match self.client.publish(payload_id, encode_payload(payload_out, encoding)).await {
+            Ok(()) => true,
+            Err(e) => {
+                log::error!("Gossip broadcast failed, falling back to request-response {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// Handshake with the peer once, then check it is able to take part in this kind of job.
+    /// Returns the payload encoding to use towards the peer.
+    async fn negotiate(&mut self, 
+        peer: PeerId,
+        job_type: JobType,
+    ) -> Result<Encoding, MpcNodeError> {
+        if !self.peer_protocols.contains_key(&peer) {
+            let protocol = self.client.handshake(peer).await?;
+            self.peer_protocols.insert(peer, protocol);
+        }
+
+        let protocol = &self.peer_protocols[&peer];
+        protocol
+            .ensure_job_type(job_type)
+            .map_err(|e| MpcNodeError::SwarmP2pError(e.into()))?;
+        Ok(protocol.encoding)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sign_header(parties: Vec<u16>) -> PayloadHeader {
+        let mut header = PayloadHeader::default();
+        header.peers.truncate(2);
+        header.payload_type = PayloadType::SignOffline { message: [0u8; 32], parties };
+        header
+    }
+
+    #[test]
+    fn signing_parties_from_the_header() {
+        // the second and third party of the keygen sign
+        let header = sign_header(vec![2, 3]);
+        assert_eq!(signing_parties(&header, 2, 3), Ok(vec![2, 3]));
+        assert_eq!(
+            signing_parties(&header, 2, 2),
+            Err(MpcNodeError::NodeError(NodeError::InvalidOutgoingParameter))
+        );
+
+        // without indices the signers are the first parties of the keygen
+        let header = sign_header(vec![]);
+        assert_eq!(signing_parties(&header, 1, 1), Ok(vec![1, 2]));
+        assert!(signing_parties(&header, 2, 3).is_err());
+
+        assert!(signing_parties(&sign_header(vec![1, 2, 3]), 1, 1).is_err());
+    }
+}
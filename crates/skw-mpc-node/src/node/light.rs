@@ -9,13 +9,14 @@ use crate::{
     node::client_request::{ClientRequest},
     node::client_outcome::ClientOutcome,
     error::{MpcNodeError, NodeError}, 
-    swarm::{ new_light_swarm_node }, 
-    serde_support::decode_key, 
+    swarm::{ new_light_swarm_node, SkwMpcP2pCodec, DiscoveryConfig, BroadcastMode }, 
+    serde_support::{decode_key, Encoding}, 
     
     wire_outgoing_pipe,
 };
 
 use super::job_manager::JobManager;
+use super::timeouts::RoundTimeouts;
 
 async fn assign_job(
     key_shard_id: CryptoHash,
@@ -65,7 +66,7 @@ pub async fn light_node_event_loop(
     loop {
         let client_request = client_in.select_next_some().await;
         match client_request {
-            ClientRequest::BootstrapNode { local_key, listen_addr, mut result_sender, .. } => {    
+            ClientRequest::BootstrapNode { local_key, listen_addr, round_timeouts, mut result_sender, .. } => {    
                 // Wire up this node to receive external request
                 let (external_request_sender, mut external_request_receiver) = mpsc::channel::<(
                     PayloadHeader, AuthHeader, Option<Vec<u8>>,
@@ -87,7 +88,10 @@ pub async fn light_node_event_loop(
                         mut addr_receiver,
                         mut swarm_message_receiver,
                         mut swarm_termination_sender,
-                    ) = new_light_swarm_node(local_key);
+                    ) = new_light_swarm_node(
+                        local_key, SkwMpcP2pCodec::default(), 
+                        DiscoveryConfig::from_env(), BroadcastMode::from_env(),
+                    );
 
                     async_executor(swarm_event_loop.run());                    
                     swarm_client.start_listening(listen_addr.parse().expect("address need to be valid"))
@@ -108,6 +112,9 @@ pub async fn light_node_event_loop(
 
                     let mut job_manager = JobManager::new(
                         local_peer_id, &mut swarm_client,
+                        // the light client expects its key shard as JSON
+                        Encoding::Json,
+                        round_timeouts.unwrap_or_else(RoundTimeouts::from_env),
                         keygen_outgoing_sender, sign_offline_outgoing_sender,
                         sign_fianlize_partial_signature_outgoing_sender,
                         key_refresh_join_message_outgoing_sender,
@@ -196,6 +203,50 @@ pub async fn light_node_event_loop(
                     .await.expect("external request receiver not to be dropped.");
             },
 
+            // served by the full node event loop
+            #[cfg(feature = "full-node")]
+            ClientRequest::WriteToDB { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::KeyMetadata { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::Scan { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::ExportBackup { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::ImportBackup { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::CommitRefresh { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+            #[cfg(feature = "full-node")]
+            ClientRequest::RollbackRefresh { result_sender, .. } => {
+                result_sender
+                    .send(Err(MpcNodeError::NodeError(NodeError::UnsupportedRequest)))
+                    .expect("result receiver not to be dropped");
+            }
+
             ClientRequest::Shutdown { node, result_sender} => {
                 shutdown_channels
                     .get_mut(&node)
@@ -0,0 +1,239 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libp2p::PeerId;
+use serde::{Serialize, Deserialize};
+use skw_mpc_payload::{CryptoHash, PayloadHeader};
+use skw_mpc_storage::{MpcStorage, MpcStorageError, Namespace};
+
+use crate::error::{MpcNodeError, SerdeError};
+
+/// What a full node knows about a key it holds a shard of, stored under its `key_shard_id` in
+/// the metadata namespace and updated on keygen, refresh and sign. Times are unix seconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMetadata {
+    pub key_shard_id: CryptoHash,
+    /// compressed secp256k1 point
+    pub public_key: Vec<u8>,
+    pub t: u16,
+    pub n: u16,
+    /// committee of the keygen or of the latest refresh
+    pub committee: Vec<PeerId>,
+    /// `None` for keys generated before metadata was recorded
+    pub created_at: Option<u64>,
+    pub refreshed_at: Option<u64>,
+    pub last_signed_at: Option<u64>,
+    pub sign_count: u64,
+    /// epoch of the active shard
+    pub epoch: u64,
+    /// refresh staged on this node, waiting for the committee to confirm it
+    #[serde(default)]
+    pub pending_refresh: Option<PendingRefresh>,
+}
+
+/// Parameters of a refreshed shard, applied to the metadata once the shard is promoted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRefresh {
+    /// epoch of the pending shard
+    pub epoch: u64,
+    pub t: u16,
+    pub n: u16,
+    pub committee: Vec<PeerId>,
+}
+
+impl KeyMetadata {
+    fn new(key_shard_id: CryptoHash, header: &PayloadHeader, public_key: Vec<u8>, epoch: u64) -> Self {
+        Self {
+            key_shard_id,
+            public_key,
+            t: header.t,
+            n: header.n,
+            committee: header.peers.iter().map(|(peer_id, _)| *peer_id).collect(),
+            created_at: None,
+            refreshed_at: None,
+            last_signed_at: None,
+            sign_count: 0,
+            epoch,
+            pending_refresh: None,
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub async fn read_metadata(storage: &dyn MpcStorage, key_shard_id: CryptoHash) -> Result<KeyMetadata, MpcNodeError> {
+    let raw = storage
+        .get(Namespace::Metadata, &key_shard_id)
+        .await
+        .map_err(MpcNodeError::StorageError)?;
+    serde_json::from_slice(&raw)
+        .map_err(|_| MpcNodeError::SerdeError(SerdeError::DeserializeKeyMetadata))
+}
+
+async fn write_metadata(storage: &dyn MpcStorage, metadata: &KeyMetadata) -> Result<(), MpcNodeError> {
+    let raw = serde_json::to_vec(metadata)
+        .expect("a valid key metadata");
+    storage
+        .put(Namespace::Metadata, &metadata.key_shard_id, raw)
+        .await
+        .map_err(MpcNodeError::StorageError)
+}
+
+async fn maybe_read_metadata(storage: &dyn MpcStorage, key_shard_id: CryptoHash) -> Result<Option<KeyMetadata>, MpcNodeError> {
+    match read_metadata(storage, key_shard_id).await {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(MpcNodeError::StorageError(MpcStorageError::KeyNotInDB)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn record_keygen(
+    storage: &dyn MpcStorage,
+    key_shard_id: CryptoHash,
+    header: &PayloadHeader,
+    public_key: Vec<u8>,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    let mut metadata = KeyMetadata::new(key_shard_id, header, public_key, epoch);
+    metadata.created_at = Some(unix_now());
+    write_metadata(storage, &metadata).await
+}
+
+/// Remembers the committee of a staged refresh until it is committed or rolled back. Keys without
+/// metadata get a record of their `active_epoch` here.
+pub async fn record_pending_refresh(
+    storage: &dyn MpcStorage,
+    key_shard_id: CryptoHash,
+    header: &PayloadHeader,
+    public_key: Vec<u8>,
+    active_epoch: u64,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    let metadata = match maybe_read_metadata(storage, key_shard_id).await? {
+        Some(metadata) => metadata,
+        None => KeyMetadata::new(key_shard_id, header, public_key, active_epoch),
+    };
+    let pending_refresh = PendingRefresh {
+        epoch,
+        t: header.t,
+        n: header.n,
+        committee: header.peers.iter().map(|(peer_id, _)| *peer_id).collect(),
+    };
+    write_metadata(storage, &KeyMetadata { pending_refresh: Some(pending_refresh), ..metadata }).await
+}
+
+/// The refresh committee replaces the keygen one once the refresh of `epoch` is committed, sign
+/// counters carry over
+pub async fn record_refresh(
+    storage: &dyn MpcStorage,
+    key_shard_id: CryptoHash,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    let metadata = match maybe_read_metadata(storage, key_shard_id).await? {
+        Some(metadata) => metadata,
+        None => return Ok(()),
+    };
+    let metadata = match metadata.pending_refresh.clone() {
+        Some(refresh) if refresh.epoch == epoch => KeyMetadata {
+            t: refresh.t,
+            n: refresh.n,
+            committee: refresh.committee,
+            ..metadata
+        },
+        _ => metadata,
+    };
+    write_metadata(storage, &KeyMetadata {
+        epoch,
+        refreshed_at: Some(unix_now()),
+        pending_refresh: None,
+        ..metadata
+    }).await
+}
+
+/// Forgets the staged refresh of `epoch`, the keygen or last committed committee stays
+pub async fn drop_pending_refresh(
+    storage: &dyn MpcStorage,
+    key_shard_id: CryptoHash,
+    epoch: u64,
+) -> Result<(), MpcNodeError> {
+    match maybe_read_metadata(storage, key_shard_id).await? {
+        Some(metadata) if metadata.pending_refresh.as_ref().map(|r| r.epoch) == Some(epoch) => {
+            write_metadata(storage, &KeyMetadata { pending_refresh: None, ..metadata }).await
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Keys without metadata are left alone, they get a record on their next refresh
+pub async fn record_sign(storage: &dyn MpcStorage, key_shard_id: CryptoHash) -> Result<(), MpcNodeError> {
+    match maybe_read_metadata(storage, key_shard_id).await? {
+        Some(mut metadata) => {
+            metadata.sign_count += 1;
+            metadata.last_signed_at = Some(unix_now());
+            write_metadata(storage, &metadata).await
+        },
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use skw_mpc_storage::InMemoryStorage;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn lifecycle() {
+        let storage = InMemoryStorage::default();
+        let key_shard_id = [1u8; 32];
+        let header = PayloadHeader::default();
+
+        record_sign(&storage, key_shard_id).await.unwrap();
+        assert!(read_metadata(&storage, key_shard_id).await.is_err());
+
+        record_keygen(&storage, key_shard_id, &header, vec![2; 33], 0).await.unwrap();
+        record_sign(&storage, key_shard_id).await.unwrap();
+        record_sign(&storage, key_shard_id).await.unwrap();
+
+        let mut refresh = header.clone();
+        refresh.peers.pop();
+        record_pending_refresh(&storage, key_shard_id, &refresh, vec![2; 33], 0, 1).await.unwrap();
+        // nothing changes until the refresh is committed
+        let metadata = read_metadata(&storage, key_shard_id).await.unwrap();
+        assert_eq!((metadata.epoch, metadata.committee.len()), (0, header.peers.len()));
+        assert_eq!(metadata.pending_refresh.map(|r| r.epoch), Some(1));
+
+        record_refresh(&storage, key_shard_id, 1).await.unwrap();
+
+        let metadata = read_metadata(&storage, key_shard_id).await.unwrap();
+        assert_eq!(metadata.sign_count, 2);
+        assert_eq!(metadata.epoch, 1);
+        assert_eq!(metadata.committee.len(), header.peers.len() - 1);
+        assert_eq!(metadata.pending_refresh, None);
+        assert!(metadata.created_at.is_some() && metadata.refreshed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn rolled_back_refresh() {
+        let storage = InMemoryStorage::default();
+        let key_shard_id = [1u8; 32];
+        let header = PayloadHeader::default();
+        record_keygen(&storage, key_shard_id, &header, vec![2; 33], 0).await.unwrap();
+
+        let mut refresh = header.clone();
+        refresh.peers.pop();
+        record_pending_refresh(&storage, key_shard_id, &refresh, vec![2; 33], 0, 1).await.unwrap();
+        // a stale rollback leaves the staged refresh alone
+        drop_pending_refresh(&storage, key_shard_id, 2).await.unwrap();
+        assert!(read_metadata(&storage, key_shard_id).await.unwrap().pending_refresh.is_some());
+
+        drop_pending_refresh(&storage, key_shard_id, 1).await.unwrap();
+        let metadata = read_metadata(&storage, key_shard_id).await.unwrap();
+        assert_eq!((metadata.epoch, metadata.committee.len()), (0, header.peers.len()));
+        assert_eq!((metadata.pending_refresh, metadata.refreshed_at), (None, None));
+    }
+}
@@ -2,9 +2,18 @@ mod client_outcome;
 mod client_request;
 mod client;
 mod job_manager;
+mod timeouts;
 
 #[cfg(feature = "full-node")]
 mod full;
+#[cfg(feature = "full-node")]
+mod metadata;
+#[cfg(feature = "full-node")]
+mod backup;
+#[cfg(feature = "full-node")]
+mod schema;
+#[cfg(feature = "full-node")]
+pub mod transcripts;
 
 #[cfg(feature = "light-node")]
 mod light;
@@ -12,6 +21,14 @@ mod light;
 // re-exports 
 #[cfg(feature = "full-node")]
 pub use full::full_node_event_loop;
+#[cfg(feature = "full-node")]
+pub use metadata::{KeyMetadata, PendingRefresh};
+#[cfg(feature = "full-node")]
+pub use backup::BackupReport;
+#[cfg(feature = "full-node")]
+pub use schema::storage_schema;
+#[cfg(feature = "full-node")]
+pub use transcripts::TranscriptConfig;
 
 #[cfg(feature = "light-node")]
 pub use light::light_node_event_loop;
@@ -19,6 +36,10 @@ pub use light::light_node_event_loop;
 pub use client_request::ClientRequest;
 pub use client::NodeClient;
 pub use client_outcome::ClientOutcome;
+pub use timeouts::RoundTimeouts;
+pub use skw_mpc_storage::StorageBackend;
+#[cfg(feature = "full-node")]
+pub use skw_mpc_storage::{Durability, MasterKeySource, MigratingStorage, MpcStorage, Namespace, ScanPage};
 
 #[macro_export]
 macro_rules! wire_outgoing_pipe {
@@ -0,0 +1,37 @@
+use skw_mpc_storage::{Namespace, Schema};
+
+/// Versions of the records a full node stores
+///
+/// Bump a namespace with a migration whenever the encoding of what it stores changes - a key
+/// shard (`LocalKey<Secp256k1>` and the binary encoding), its history record, or
+/// [KeyMetadata](super::KeyMetadata) - and test it against records of the previous version.
+pub fn storage_schema() -> Schema {
+    Schema::new()
+        .with_namespace(Namespace::Shards)
+        .with_namespace(Namespace::Metadata)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use skw_mpc_payload::PayloadHeader;
+    use skw_mpc_storage::{InMemoryStorage, MigratingStorage, MpcStorage, ShardStore};
+
+    use super::*;
+    use crate::node::metadata;
+
+    #[tokio::test]
+    async fn reads_records_of_the_current_release() {
+        let db = Arc::new(InMemoryStorage::default());
+        ShardStore::new(db.clone()).put_active([1u8; 32], [0u8; 32], vec![0, 1, 2]).await.unwrap();
+        metadata::record_keygen(db.as_ref(), [1u8; 32], &PayloadHeader::default(), vec![2; 33], 0).await.unwrap();
+
+        let storage = MigratingStorage::new(db.clone(), storage_schema());
+        assert_eq!(storage.migrate().await, Ok(2));
+        let shards = ShardStore::new(storage);
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, vec![0, 1, 2]);
+        assert_eq!(metadata::read_metadata(shards.storage(), [1u8; 32]).await.unwrap().public_key, vec![2; 33]);
+        assert_eq!(shards.storage().migrate().await, Ok(0));
+    }
+}
@@ -0,0 +1,108 @@
+use std::{collections::HashMap, time::Duration};
+
+use skw_mpc_protocol::gg20::state_machine::{keygen::Keygen, sign::OfflineStage};
+
+/// How long a protocol round waits for the other parties before the job fails, blaming them
+const DEFAULT_ROUND_TIMEOUT: Duration = Duration::from_secs(120);
+/// Rounds waiting on the Paillier keys of keygen round 0, or on the MtA range proofs of offline
+/// signing round 1
+const EXPENSIVE_ROUND_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Round timeouts of the protocols a node runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTimeouts {
+    /// every round without an override
+    pub default: Duration,
+    /// keygen rounds waiting longer or shorter than `default`
+    pub keygen: HashMap<u16, Duration>,
+    /// offline signing rounds waiting longer or shorter than `default`
+    pub sign_offline: HashMap<u16, Duration>,
+}
+
+impl Default for RoundTimeouts {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_ROUND_TIMEOUT,
+            keygen: HashMap::from([(1, EXPENSIVE_ROUND_TIMEOUT)]),
+            sign_offline: HashMap::from([(2, EXPENSIVE_ROUND_TIMEOUT)]),
+        }
+    }
+}
+
+impl RoundTimeouts {
+    /// Every round waits `timeout`
+    pub fn uniform(timeout: Duration) -> Self {
+        Self {
+            default: timeout,
+            keygen: HashMap::new(),
+            sign_offline: HashMap::new(),
+        }
+    }
+
+    /// Read `MPC_ROUND_TIMEOUT_SECS`, and `MPC_KEYGEN_ROUND_TIMEOUTS` and `MPC_SIGN_ROUND_TIMEOUTS`,
+    /// comma separated `<round>=<secs>` overrides. Malformed values are skipped.
+    pub fn from_env() -> Self {
+        let mut timeouts = Self::default();
+        if let Ok(secs) = std::env::var("MPC_ROUND_TIMEOUT_SECS") {
+            match secs.trim().parse() {
+                Ok(secs) => timeouts.default = Duration::from_secs(secs),
+                Err(_) => log::warn!("Ignoring malformed round timeout {:?}", secs),
+            }
+        }
+        if let Ok(overrides) = std::env::var("MPC_KEYGEN_ROUND_TIMEOUTS") {
+            timeouts.keygen.extend(parse_overrides(&overrides));
+        }
+        if let Ok(overrides) = std::env::var("MPC_SIGN_ROUND_TIMEOUTS") {
+            timeouts.sign_offline.extend(parse_overrides(&overrides));
+        }
+        timeouts
+    }
+
+    pub(crate) fn keygen(&self, mut keygen: Keygen) -> Keygen {
+        keygen = keygen.with_round_timeout(self.default);
+        for (round, timeout) in &self.keygen {
+            keygen = keygen.with_round_timeout_override(*round, *timeout);
+        }
+        keygen
+    }
+
+    pub(crate) fn sign_offline(&self, mut offline_stage: OfflineStage) -> OfflineStage {
+        offline_stage = offline_stage.with_round_timeout(self.default);
+        for (round, timeout) in &self.sign_offline {
+            offline_stage = offline_stage.with_round_timeout_override(*round, *timeout);
+        }
+        offline_stage
+    }
+}
+
+/// `<round>=<secs>` pairs, comma separated
+fn parse_overrides(overrides: &str) -> Vec<(u16, Duration)> {
+    overrides
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let parsed = entry
+                .split_once('=')
+                .and_then(|(round, secs)| Some((round.trim().parse().ok()?, secs.trim().parse().ok()?)));
+            if parsed.is_none() {
+                log::warn!("Ignoring malformed round timeout override {:?}", entry);
+            }
+            parsed.map(|(round, secs)| (round, Duration::from_secs(secs)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_round_overrides() {
+        assert_eq!(
+            parse_overrides("1=300, 3 = 30,,bogus,4=x"),
+            vec![(1, Duration::from_secs(300)), (3, Duration::from_secs(30))]
+        );
+        assert_eq!(parse_overrides(""), vec![]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Opt-in transcripts of the protocols a full node runs
+//!
+//! With a [TranscriptConfig], every keygen and offline signing job of the node is recorded to
+//! `<dir>/<payload id>.jsonl` (see [skw_round_based::transcript]). The party runs with a fresh
+//! RNG seed, which the transcript header keeps sealed under the configured key, together with
+//! the key shard a signing job was started with. Given that key, [replay] reproduces the job
+//! offline message by message - that is what `skw-mpc-replay` does. Key refresh jobs aren't
+//! recorded.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use zeroize::Zeroizing;
+
+use skw_crypto_curv::elliptic::curves::secp256_k1::Secp256k1;
+use skw_mpc_payload::{Payload, PayloadHeader};
+use skw_mpc_protocol::gg20::state_machine::{keygen::{self, Keygen, LocalKey}, sign::{self, CompletedOfflineStage, OfflineStage}};
+use skw_mpc_storage::{MasterKeySource, MpcStorageError};
+use skw_mpc_storage::encryption::{open_archive, seal_archive};
+use skw_round_based::{Msg, transcript::{self, read_transcript, Entry, ReplayError, TranscriptEntry, TranscriptHeader, TranscriptWriter}};
+
+const MAGIC: &[u8; 4] = b"SKWT";
+const KEYGEN: &str = "gg20-keygen";
+const SIGN_OFFLINE: &str = "gg20-sign-offline";
+
+type KeyGenEnvelope = Payload<Msg<keygen::ProtocolMessage>>;
+type SignOfflineEnvelope = Payload<Msg<sign::OfflineProtocolMessage>>;
+
+/// Where transcripts are written and the key sealing the secret inputs in them
+#[derive(Debug, Clone)]
+pub struct TranscriptConfig {
+    pub dir: PathBuf,
+    pub key: MasterKeySource,
+}
+
+impl TranscriptConfig {
+    /// Read `MPC_TRANSCRIPT_DIR`, sealing with the key in `MPC_TRANSCRIPT_KEY_FILE`, or else
+    /// `MPC_TRANSCRIPT_PASSPHRASE`. `None` unless both a directory and a key are set.
+    pub fn from_env() -> Option<Self> {
+        let dir = std::env::var("MPC_TRANSCRIPT_DIR").ok()?;
+        let key = match std::env::var("MPC_TRANSCRIPT_KEY_FILE") {
+            Ok(path) => MasterKeySource::File(path.into()),
+            Err(_) => match std::env::var("MPC_TRANSCRIPT_PASSPHRASE") {
+                Ok(passphrase) => MasterKeySource::Passphrase(passphrase),
+                Err(_) => {
+                    log::warn!("Not recording transcripts to {:?}: no key to seal them with", dir);
+                    return None;
+                }
+            },
+        };
+        Some(Self { dir: dir.into(), key })
+    }
+
+    /// Starts the transcript of a keygen job for party `i`
+    pub(crate) fn keygen(&self, header: &PayloadHeader, i: u16, t: u16, n: u16) -> Option<JobTranscript> {
+        self.start(header, KEYGEN, &KeygenInputs { i, t, n }, None)
+    }
+
+    /// Starts the transcript of an offline signing job for party `i` of the signers `s_l`
+    pub(crate) fn sign_offline(
+        &self,
+        header: &PayloadHeader,
+        i: u16,
+        s_l: &[u16],
+        local_key: &LocalKey<Secp256k1>,
+    ) -> Option<JobTranscript> {
+        let inputs = SignOfflineInputs { i, s_l: s_l.to_vec() };
+        self.start(header, SIGN_OFFLINE, &inputs, Some(local_key))
+    }
+
+    /// Recording is best effort, a job runs unrecorded if its transcript can't be created
+    fn start(
+        &self,
+        header: &PayloadHeader,
+        protocol: &str,
+        inputs: &impl Serialize,
+        local_key: Option<&LocalKey<Secp256k1>>,
+    ) -> Option<JobTranscript> {
+        let path = self.dir.join(format!("{}.jsonl", hex_string(&header.payload_id)));
+        match self.create(&path, protocol, inputs, local_key) {
+            Ok(transcript) => Some(transcript),
+            Err(e) => {
+                log::warn!("Not recording job to {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    fn create(
+        &self,
+        path: &Path,
+        protocol: &str,
+        inputs: &impl Serialize,
+        local_key: Option<&LocalKey<Secp256k1>>,
+    ) -> Result<JobTranscript, TranscriptError> {
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        let secrets = Zeroizing::new(serde_json::to_vec(&SealedInputs { seed, local_key })?);
+        let header = TranscriptHeader {
+            protocol: protocol.to_string(),
+            inputs: serde_json::to_value(inputs)?,
+            sealed_inputs: Some(seal_archive(MAGIC, &secrets, &self.key)?),
+        };
+
+        std::fs::create_dir_all(&self.dir)?;
+        let mut writer = TranscriptWriter::new(File::create(path)?);
+        writer.write(&Entry::<()>::Header(&header))?;
+        Ok(JobTranscript { seed, writer })
+    }
+}
+
+/// Transcript of a job being recorded, the party has to be seeded with `seed`
+pub(crate) struct JobTranscript {
+    pub seed: [u8; 32],
+    pub writer: TranscriptWriter<File>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeygenInputs {
+    i: u16,
+    t: u16,
+    n: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SignOfflineInputs {
+    i: u16,
+    s_l: Vec<u16>,
+}
+
+/// What the header of a transcript keeps sealed
+#[derive(Serialize, Deserialize)]
+struct SealedInputs<K> {
+    seed: [u8; 32],
+    local_key: Option<K>,
+}
+
+#[derive(Debug, Error)]
+pub enum TranscriptError {
+    #[error("transcript: {0}")]
+    Io(#[from] io::Error),
+    #[error("transcript: malformed entry or inputs: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("transcript: sealed inputs: {0:?}")]
+    Sealed(MpcStorageError),
+    #[error("transcript: doesn't start with a header")]
+    MissingHeader,
+    #[error("transcript: recorded without its secret inputs")]
+    NoSealedInputs,
+    #[error("transcript: unknown protocol {0:?}")]
+    UnknownProtocol(String),
+    #[error("transcript: party can't be constructed from the recorded inputs: {0}")]
+    Construct(String),
+}
+
+impl From<MpcStorageError> for TranscriptError {
+    fn from(e: MpcStorageError) -> Self {
+        Self::Sealed(e)
+    }
+}
+
+/// How a replayed job ended
+pub enum Replayed {
+    KeyGen(Result<Box<LocalKey<Secp256k1>>, ReplayError<keygen::Error>>),
+    SignOffline(Result<Box<CompletedOfflineStage>, ReplayError<sign::Error>>),
+}
+
+impl fmt::Display for Replayed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyGen(Ok(local_key)) => write!(
+                f, "keygen finished, public key {}",
+                hex_string(&local_key.public_key().to_bytes(true))
+            ),
+            Self::KeyGen(Err(e)) => write!(f, "keygen failed: {}", e),
+            Self::SignOffline(Ok(_)) => write!(f, "offline signing finished"),
+            Self::SignOffline(Err(e)) => write!(f, "offline signing failed: {}", e),
+        }
+    }
+}
+
+/// Replays a transcript recorded by a node, opening its secret inputs with `key`
+pub fn replay(mut transcript: impl Read, key: &MasterKeySource) -> Result<Replayed, TranscriptError> {
+    let mut raw = String::new();
+    transcript.read_to_string(&mut raw)?;
+    let header = match raw.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) => match serde_json::from_str::<TranscriptEntry<()>>(line)? {
+            TranscriptEntry::Header(header) => header,
+            _ => return Err(TranscriptError::MissingHeader),
+        },
+        None => return Err(TranscriptError::MissingHeader),
+    };
+    let sealed = header.sealed_inputs.as_ref().ok_or(TranscriptError::NoSealedInputs)?;
+    let secrets: SealedInputs<LocalKey<Secp256k1>> = serde_json::from_slice(&open_archive(MAGIC, sealed, key)?)?;
+
+    match header.protocol.as_str() {
+        KEYGEN => {
+            let KeygenInputs { i, t, n } = serde_json::from_value(header.inputs)?;
+            let party = Keygen::new(i, t, n)
+                .map_err(|e| TranscriptError::Construct(e.to_string()))?
+                .with_rng_seed(secrets.seed);
+            let entries = read_transcript::<KeyGenEnvelope, _>(raw.as_bytes())?;
+            Ok(Replayed::KeyGen(transcript::replay(party, entries).map(Box::new)))
+        }
+        SIGN_OFFLINE => {
+            let SignOfflineInputs { i, s_l } = serde_json::from_value(header.inputs)?;
+            let local_key = secrets.local_key.ok_or(TranscriptError::NoSealedInputs)?;
+            let party = OfflineStage::new(i, s_l, local_key)
+                .map_err(|e| TranscriptError::Construct(e.to_string()))?
+                .with_rng_seed(secrets.seed);
+            let entries = read_transcript::<SignOfflineEnvelope, _>(raw.as_bytes())?;
+            Ok(Replayed::SignOffline(transcript::replay(party, entries).map(Box::new)))
+        }
+        protocol => Err(TranscriptError::UnknownProtocol(protocol.to_string())),
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use skw_round_based::{envelope::Envelope, transcript::Recorder, StateMachine};
+
+    use super::*;
+
+    /// Runs a 2 party keygen, recording party 1 the way the job manager does
+    fn record_keygen(config: &TranscriptConfig, header: &PayloadHeader) -> LocalKey<Secp256k1> {
+        let mut transcript = config.keygen(header, 1, 1, 2).unwrap();
+        let mut parties = [
+            Keygen::new(1, 1, 2).unwrap().with_rng_seed(transcript.seed),
+            Keygen::new(2, 1, 2).unwrap(),
+        ];
+
+        while !parties.iter().all(|p| p.is_finished()) {
+            let mut outgoing = vec![];
+            for p in parties.iter_mut() {
+                if p.wants_to_proceed() {
+                    p.proceed().unwrap();
+                }
+                outgoing.append(p.message_queue());
+            }
+            for msg in outgoing {
+                let payload = KeyGenEnvelope::wrap(header.clone(), msg);
+                let (sender, receiver) = if payload.msg().sender == 1 { (0, 1) } else { (1, 0) };
+                if sender == 0 {
+                    transcript.writer.record(Entry::Outgoing(&payload));
+                } else {
+                    transcript.writer.record(Entry::Incoming(&payload));
+                }
+                parties[receiver].handle_incoming(payload.unwrap()).unwrap();
+            }
+        }
+        parties[0].pick_output().unwrap().unwrap()
+    }
+
+    #[test]
+    fn recorded_keygen_replays_with_the_key() {
+        let header = PayloadHeader { payload_id: [7u8; 32], ..Default::default() };
+        let config = TranscriptConfig {
+            dir: std::env::temp_dir().join(format!("skw-mpc-transcripts-{}", OsRng.next_u64())),
+            key: MasterKeySource::Passphrase("correct horse".to_string()),
+        };
+        let local_key = record_keygen(&config, &header);
+
+        let path = config.dir.join(format!("{}.jsonl", hex_string(&[7u8; 32])));
+        let raw = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&config.dir).unwrap();
+        // the seed is sealed, the transcript alone doesn't give it away
+        assert!(!String::from_utf8_lossy(&raw).contains("seed"));
+
+        match replay(&raw[..], &config.key).unwrap() {
+            Replayed::KeyGen(Ok(replayed)) => assert_eq!(replayed.public_key(), local_key.public_key()),
+            replayed => panic!("unexpected replay outcome: {}", replayed),
+        }
+        assert!(matches!(
+            replay(&raw[..], &MasterKeySource::Passphrase("wrong".to_string())),
+            Err(TranscriptError::Sealed(MpcStorageError::FailToDecrypt))
+        ));
+    }
+}
@@ -1,7 +1,8 @@
-use serde::{Serialize, de::DeserializeOwned};
-use skw_mpc_payload::Payload;
+use bincode::Options;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use skw_mpc_payload::{Payload, PayloadHeader};
 use skw_mpc_protocol::gg20::{
-    state_machine::{keygen::LocalKey}, 
+    state_machine::{keygen::LocalKey},
     party_i::SignatureRecid
 };
 
@@ -9,28 +10,103 @@ use skw_crypto_curv::elliptic::curves::secp256_k1::Secp256k1;
 
 use crate::error::{MpcNodeError, SerdeError};
 
-pub fn encode_payload<M>(payload: &Payload<M>) -> Vec<u8>
-    where M: Serialize + DeserializeOwned 
+/// First byte of every binary encoded value. Legacy JSON always starts with `{` or whitespace.
+const BINARY_MAGIC: u8 = 0x00;
+
+/// Layout version of the binary encoding, bumped on any incompatible change
+pub const BINARY_ENCODING_VERSION: u8 = 1;
+
+/// Encoding of payloads and key shards, shared with the handshake
+///
+/// Decoding never needs to be told which one is used - binary values carry a
+/// `[magic, version]` header, everything else is treated as legacy JSON.
+pub use skw_mpc_payload::Encoding;
+
+/// Tell which encoding a raw value uses
+pub fn encoding_of(raw: &[u8]) -> Encoding {
+    match raw.first() {
+        Some(&BINARY_MAGIC) => Encoding::Binary,
+        _ => Encoding::Json,
+    }
+}
+
+fn binary_options() -> impl Options {
+    // payload types are guessed by trial decoding - never accept a partial match
+    bincode::DefaultOptions::new()
+        .reject_trailing_bytes()
+}
+
+fn encode<T: Serialize>(value: &T, encoding: Encoding) -> Vec<u8> {
+    match encoding {
+        Encoding::Json => serde_json::to_vec(value)
+            .expect("a valid outgoing payload"),
+        Encoding::Binary => {
+            let mut raw = vec![BINARY_MAGIC, BINARY_ENCODING_VERSION];
+            binary_options()
+                .serialize_into(&mut raw, value)
+                .expect("a valid outgoing payload");
+            raw
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(raw: &[u8], err: SerdeError) -> Result<T, MpcNodeError> {
+    match raw {
+        [BINARY_MAGIC, BINARY_ENCODING_VERSION, body @ ..] => binary_options()
+            .deserialize(body)
+            .map_err(|_| err),
+        [BINARY_MAGIC, version, ..] => Err(SerdeError::UnknownEncodingVersion(*version)),
+        _ => serde_json::from_slice(raw)
+            .map_err(|_| err),
+    }
+    .map_err(MpcNodeError::SerdeError)
+}
+
+pub fn encode_payload<M>(payload: &Payload<M>, encoding: Encoding) -> Vec<u8>
+    where M: Serialize + DeserializeOwned
 {
-    serde_json::to_vec(payload)
-        .expect("a valid outgoing payload")
+    encode(payload, encoding)
 }
 
 pub fn decode_payload<M>(payload: &[u8]) -> Result<M, MpcNodeError>
-    where M: Serialize + DeserializeOwned 
+    where M: Serialize + DeserializeOwned
 {
-    serde_json::from_slice(payload)
-        .map_err(|_| MpcNodeError::SerdeError(SerdeError::DeserializePayload))
+    decode(payload, SerdeError::DeserializePayload)
+}
+
+/// The header of an encoded `Payload<M>`, without knowing `M`
+pub fn decode_payload_header(payload: &[u8]) -> Result<PayloadHeader, MpcNodeError> {
+    #[derive(Deserialize)]
+    struct HeaderOnly {
+        payload_header: PayloadHeader,
+    }
+
+    let err = MpcNodeError::SerdeError(SerdeError::DeserializePayload);
+    match payload {
+        // the body follows the header, trailing bytes are expected here
+        [BINARY_MAGIC, BINARY_ENCODING_VERSION, body @ ..] => bincode::DefaultOptions::new()
+            .allow_trailing_bytes()
+            .deserialize::<HeaderOnly>(body)
+            .map_err(|_| err),
+        [BINARY_MAGIC, version, ..] => Err(MpcNodeError::SerdeError(SerdeError::UnknownEncodingVersion(*version))),
+        _ => serde_json::from_slice::<HeaderOnly>(payload)
+            .map_err(|_| err),
+    }
+    .map(|h| h.payload_header)
 }
 
-pub fn encode_key(key: &LocalKey<Secp256k1>) -> Vec<u8> {
-    serde_json::to_vec(key)
-        .expect("a valid outgoing payload")
+/// Size of `value` in the binary encoding, as reported to protocol watchers
+pub fn encoded_size<T: Serialize>(value: &T) -> usize {
+    encode(value, Encoding::Binary).len()
 }
 
+pub fn encode_key(key: &LocalKey<Secp256k1>, encoding: Encoding) -> Vec<u8> {
+    encode(key, encoding)
+}
+
+/// Reads both the binary and the legacy JSON form, so keys stored by older releases stay usable
 pub fn decode_key(raw_key: &[u8]) -> Result<LocalKey<Secp256k1>, MpcNodeError> {
-    serde_json::from_slice(raw_key)
-        .map_err(|_| MpcNodeError::SerdeError(SerdeError::DeserializeLocalKey))
+    decode(raw_key, SerdeError::DeserializeLocalKey)
 }
 
 pub fn encode_signature(sig: &SignatureRecid) -> Vec<u8> {
@@ -41,4 +117,65 @@ pub fn encode_signature(sig: &SignatureRecid) -> Vec<u8> {
 pub fn decode_signature(raw_sig: &[u8]) -> Result<SignatureRecid, MpcNodeError> {
     serde_json::from_slice(raw_sig)
     .map_err(|_| MpcNodeError::SerdeError(SerdeError::DeserializeSignature))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use skw_mpc_payload::PayloadHeader;
+
+    #[test]
+    fn payload_roundtrip() {
+        let payload = Payload {
+            payload_header: PayloadHeader::default(),
+            body: vec![1u64, 2, 3],
+        };
+
+        for encoding in [Encoding::Json, Encoding::Binary] {
+            let raw = encode_payload(&payload, encoding);
+            assert_eq!(encoding_of(&raw), encoding);
+
+            let decoded: Payload<Vec<u64>> = decode_payload(&raw).unwrap();
+            assert_eq!(decoded, payload);
+        }
+    }
+
+    #[test]
+    fn binary_is_smaller() {
+        let payload = Payload {
+            payload_header: PayloadHeader::default(),
+            body: vec![u64::MAX; 16],
+        };
+
+        let json = encode_payload(&payload, Encoding::Json);
+        let binary = encode_payload(&payload, Encoding::Binary);
+        assert!(binary.len() < json.len());
+    }
+
+    #[test]
+    fn header_without_body_type() {
+        let payload = Payload {
+            payload_header: PayloadHeader::default(),
+            body: vec![1u64, 2, 3],
+        };
+
+        for encoding in [Encoding::Json, Encoding::Binary] {
+            let raw = encode_payload(&payload, encoding);
+            assert_eq!(decode_payload_header(&raw), Ok(payload.payload_header.clone()));
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut raw = encode_payload(&Payload {
+            payload_header: PayloadHeader::default(),
+            body: 1u8,
+        }, Encoding::Binary);
+        raw[1] = BINARY_ENCODING_VERSION + 1;
+
+        assert_eq!(
+            decode_payload::<Payload<u8>>(&raw),
+            Err(MpcNodeError::SerdeError(SerdeError::UnknownEncodingVersion(BINARY_ENCODING_VERSION + 1)))
+        );
+    }
+}
@@ -1,15 +1,31 @@
 use libp2p::{
-    swarm::{NetworkBehaviour},
-    request_response,
+    swarm::{NetworkBehaviour, behaviour::toggle::Toggle},
+    request_response, identify, kad, mdns, gossipsub,
 };
 
 // re-export
-pub use self::skw_mpc_p2p_behavior::{SkwMpcP2pCodec, SkwMpcP2pProtocol, MpcP2pRequest, MpcP2pResponse};
+pub use self::skw_mpc_p2p_behavior::{
+    SkwMpcP2pCodec, SkwMpcP2pProtocol, MpcP2pRequest, MpcP2pResponse,
+    DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE,
+};
+pub use self::skw_mpc_handshake_behavior::{
+    SkwMpcHandshakeCodec, SkwMpcHandshakeProtocol,
+};
 
 #[derive(NetworkBehaviour)]
 pub struct MpcSwarmBahavior {
     // node p2p behavior
     pub request_response: request_response::Behaviour<SkwMpcP2pCodec>,
+    // capability handshake ahead of any job
+    pub handshake: request_response::Behaviour<SkwMpcHandshakeCodec>,
+
+    // peer discovery - resolve committee members named only by PeerId
+    pub kademlia: kad::Kademlia<kad::store::MemoryStore>,
+    pub identify: identify::Behaviour,
+    pub mdns: Toggle<mdns::tokio::Behaviour>,
+
+    // optional per job broadcast topics
+    pub gossipsub: Toggle<gossipsub::Behaviour>,
 }
 
 // Sub protocol - p2p request-response
@@ -18,16 +34,118 @@ pub mod skw_mpc_p2p_behavior {
     use tokio::io;
     use futures::prelude::*;
 
-    use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+    use libp2p::core::upgrade::{read_varint, write_length_prefixed, ProtocolName};
     use libp2p::request_response::Codec;
-    use skw_mpc_payload::{AuthHeader, PayloadHeader};
+    use skw_mpc_payload::{AuthHeader, LegacyPayloadHeader, PayloadHeader};
 
-    use crate::error::MpcNodeError;
+    use crate::error::{MpcNodeError, SwarmP2pError};
+    use crate::serde_support::Encoding;
+
+    /// Default upper bound of a serialized `MpcP2pRequest`
+    pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1_000_000;
+    /// Default upper bound of a serialized `MpcP2pResponse`
+    pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 10_240;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum SkwMpcP2pProtocol {
+        /// `/skw-mpc-p2p/1`: `RawMessage` payloads are JSON, job headers are laid out as
+        /// `LegacyPayloadHeader`
+        V1,
+        /// `/skw-mpc-p2p/2`: `RawMessage` payloads use the versioned binary encoding, job
+        /// headers may name peers without an address and signers other than the first parties
+        V2,
+    }
+
+    impl SkwMpcP2pProtocol {
+        /// Every version this node speaks, most preferred first
+        pub fn supported() -> Vec<Self> {
+            vec![Self::V2, Self::V1]
+        }
+
+        pub fn version(&self) -> u16 {
+            match self {
+                Self::V1 => 1,
+                Self::V2 => 2,
+            }
+        }
+
+        pub fn from_version(version: u16) -> Option<Self> {
+            match version {
+                1 => Some(Self::V1),
+                2 => Some(Self::V2),
+                _ => None,
+            }
+        }
+
+        /// Payload encoding a peer on this protocol version is able to read
+        pub fn payload_encoding(&self) -> Encoding {
+            match self {
+                Self::V1 => Encoding::Json,
+                Self::V2 => Encoding::Binary,
+            }
+        }
+    }
 
+    /// Codec for `/skw-mpc-p2p`. Frames larger than the configured limits are
+    /// rejected on both the read and the write side.
     #[derive(Debug, Clone)]
-    pub struct SkwMpcP2pProtocol();
-    #[derive(Clone)]
-    pub struct SkwMpcP2pCodec();
+    pub struct SkwMpcP2pCodec {
+        max_request_size: usize,
+        max_response_size: usize,
+    }
+
+    impl SkwMpcP2pCodec {
+        pub fn new(max_request_size: usize, max_response_size: usize) -> Self {
+            Self { max_request_size, max_response_size }
+        }
+
+        pub fn max_request_size(&self) -> usize {
+            self.max_request_size
+        }
+
+        pub fn max_response_size(&self) -> usize {
+            self.max_response_size
+        }
+
+        /// Check an outgoing request against the request limit before it hits the wire
+        pub fn check_request_size(&self, request: &MpcP2pRequest) -> Result<(), MpcNodeError> {
+            let size = bincode::serialized_size(request)
+                .expect("request message to be valid");
+            check_size(size as usize, self.max_request_size)
+                .map_err(MpcNodeError::SwarmP2pError)
+        }
+    }
+
+    impl Default for SkwMpcP2pCodec {
+        fn default() -> Self {
+            Self::new(DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MAX_RESPONSE_SIZE)
+        }
+    }
+
+    fn check_size(size: usize, limit: usize) -> Result<(), SwarmP2pError> {
+        if size > limit {
+            Err(SwarmP2pError::MessageTooLarge { size: size as u64, limit: limit as u64 })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn too_large(e: SwarmP2pError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+    }
+
+    /// Same as `read_length_prefixed`, but reports the offending size when the limit is exceeded
+    async fn read_limited<T>(io: &mut T, limit: usize) -> io::Result<Vec<u8>>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let len = read_varint(io).await?;
+        check_size(len, limit).map_err(too_large)?;
+
+        let mut buf = vec![0; len];
+        io.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
 
     // Serialized Form of raw request
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -41,11 +159,50 @@ pub mod skw_mpc_p2p_behavior {
         },
     }
 
+    /// `MpcP2pRequest` as laid out on `/skw-mpc-p2p/1`
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    enum MpcP2pRequestV1 {
+        StartJob {
+            auth_header: AuthHeader,
+            job_header: LegacyPayloadHeader,
+        },
+        RawMessage {
+            payload: Vec<u8>,
+        },
+    }
+
+    fn encode_request(protocol: &SkwMpcP2pProtocol, request: MpcP2pRequest) -> io::Result<Vec<u8>> {
+        let request = match (protocol, request) {
+            (SkwMpcP2pProtocol::V1, MpcP2pRequest::StartJob { auth_header, job_header }) => {
+                let job_header = LegacyPayloadHeader::try_from(job_header)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                return Ok(bincode::serialize(&MpcP2pRequestV1::StartJob { auth_header, job_header })
+                    .expect("request message to be valid"));
+            },
+            (_, request) => request,
+        };
+        Ok(bincode::serialize(&request).expect("request message to be valid"))
+    }
+
+    fn decode_request(protocol: &SkwMpcP2pProtocol, data: &[u8]) -> io::Result<MpcP2pRequest> {
+        let request = match protocol {
+            SkwMpcP2pProtocol::V1 => match bincode::deserialize(data) {
+                Ok(MpcP2pRequestV1::StartJob { auth_header, job_header }) =>
+                    MpcP2pRequest::StartJob { auth_header, job_header: job_header.into() },
+                Ok(MpcP2pRequestV1::RawMessage { payload }) => MpcP2pRequest::RawMessage { payload },
+                Err(_) => return Err(io::ErrorKind::InvalidData.into()),
+            },
+            SkwMpcP2pProtocol::V2 => bincode::deserialize(data)
+                .map_err(|_| io::Error::from(io::ErrorKind::InvalidData))?,
+        };
+        Ok(request)
+    }
+
     // Serialized Form of raw response
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
     pub enum MpcP2pResponse {
         StartJob {
-            status: Result<(), MpcNodeError>
+            status: Result<(), MpcNodeError>,
         },
         RawMessage {
             status: Result<(), MpcNodeError>,
@@ -55,7 +212,10 @@ pub mod skw_mpc_p2p_behavior {
 
     impl ProtocolName for SkwMpcP2pProtocol {
         fn protocol_name(&self) -> &[u8] {
-            b"/skw-mpc-p2p/1"
+            match self {
+                Self::V1 => b"/skw-mpc-p2p/1",
+                Self::V2 => b"/skw-mpc-p2p/2",
+            }
         }
     }
 
@@ -67,19 +227,18 @@ pub mod skw_mpc_p2p_behavior {
 
         async fn read_request<T>(
             &mut self,
-            _: &SkwMpcP2pProtocol,
+            protocol: &SkwMpcP2pProtocol,
             io: &mut T,
         ) -> io::Result<Self::Request>
         where
             T: AsyncRead + Unpin + Send,
         {
-            let vec = read_length_prefixed(io, 1_000_000).await?;
+            let vec = read_limited(io, self.max_request_size).await?;
 
             if vec.is_empty() {
                 return Err(io::ErrorKind::UnexpectedEof.into());
             }
-            bincode::deserialize( &vec )
-                .map_err(|_| io::ErrorKind::InvalidData.into() )
+            decode_request(protocol, &vec)
         }
 
         async fn read_response<T>(
@@ -90,7 +249,7 @@ pub mod skw_mpc_p2p_behavior {
         where
             T: AsyncRead + Unpin + Send,
         {
-            let vec = read_length_prefixed(io, 10_240).await?; // update transfer maximum
+            let vec = read_limited(io, self.max_response_size).await?;
 
             if vec.is_empty() {
                 return Err(io::ErrorKind::UnexpectedEof.into());
@@ -102,14 +261,15 @@ pub mod skw_mpc_p2p_behavior {
 
         async fn write_request<T>(
             &mut self,
-            _: &SkwMpcP2pProtocol,
+            protocol: &SkwMpcP2pProtocol,
             io: &mut T,
             raw: MpcP2pRequest,
         ) -> io::Result<()>
         where
             T: AsyncWrite + Unpin + Send,
         {
-            let data = bincode::serialize(&raw).expect("request message to be valid");
+            let data = encode_request(protocol, raw)?;
+            check_size(data.len(), self.max_request_size).map_err(too_large)?;
 
             write_length_prefixed(io, data).await?;
             io.close().await?;
@@ -127,11 +287,236 @@ pub mod skw_mpc_p2p_behavior {
             T: AsyncWrite + Unpin + Send,
         {
             let data = bincode::serialize(&raw).expect("response message to be valid");
+            check_size(data.len(), self.max_response_size).map_err(too_large)?;
+
             write_length_prefixed(io, data).await?;
             io.close().await?;
 
             Ok(())
         }
     }
-    
-}
\ No newline at end of file
+
+    #[cfg(test)]
+    mod test {
+        use futures::io::Cursor;
+        use libp2p::{Multiaddr, PeerId};
+        use serde_hex::{SerHex, Strict};
+        use skw_mpc_payload::{CryptoHash, header::PayloadType};
+
+        use super::*;
+
+        /// Request types of the release before protocol versions, as they were declared
+        mod baseline {
+            use super::*;
+
+            #[derive(Serialize)]
+            pub enum PayloadType {
+                SignOffline {
+                    #[serde(with = "SerHex::<Strict>")]
+                    message: CryptoHash,
+                },
+                SignFinalize,
+                KeyGen,
+                KeyRefresh,
+            }
+
+            #[derive(Serialize)]
+            pub struct PayloadHeader {
+                #[serde(with = "SerHex::<Strict>")]
+                pub payload_id: CryptoHash,
+                pub payload_type: PayloadType,
+                pub peers: Vec<(PeerId, Multiaddr)>,
+                pub sender: PeerId,
+                pub t: u16,
+                pub n: u16,
+            }
+
+            #[derive(Serialize)]
+            pub enum MpcP2pRequest {
+                StartJob {
+                    auth_header: AuthHeader,
+                    job_header: PayloadHeader,
+                },
+            }
+        }
+
+        fn baseline_start_job(header: &PayloadHeader) -> Vec<u8> {
+            let payload_type = match header.payload_type {
+                PayloadType::SignOffline { message, .. } => baseline::PayloadType::SignOffline { message },
+                PayloadType::SignFinalize => baseline::PayloadType::SignFinalize,
+                PayloadType::KeyGen => baseline::PayloadType::KeyGen,
+                PayloadType::KeyRefresh => baseline::PayloadType::KeyRefresh,
+            };
+            let job_header = baseline::PayloadHeader {
+                payload_id: header.payload_id,
+                payload_type,
+                peers: header.peers.iter().map(|(peer, addr)| (*peer, addr.clone().unwrap())).collect(),
+                sender: header.sender,
+                t: header.t,
+                n: header.n,
+            };
+            bincode::serialize(&baseline::MpcP2pRequest::StartJob {
+                auth_header: AuthHeader::default(),
+                job_header,
+            }).unwrap()
+        }
+
+        fn start_job(job_header: PayloadHeader) -> MpcP2pRequest {
+            MpcP2pRequest::StartJob { auth_header: AuthHeader::default(), job_header }
+        }
+
+        #[tokio::test]
+        async fn v1_start_job_keeps_the_baseline_layout() {
+            let header = PayloadHeader::default();
+            let baseline = baseline_start_job(&header);
+
+            // read from a peer of the previous release
+            let mut wire = Cursor::new(Vec::new());
+            write_length_prefixed(&mut wire, baseline.clone()).await.unwrap();
+            wire.set_position(0);
+            let request = SkwMpcP2pCodec::default()
+                .read_request(&SkwMpcP2pProtocol::V1, &mut wire)
+                .await
+                .unwrap();
+            assert_eq!(request, start_job(header.clone()));
+
+            // and written the way it reads it
+            assert_eq!(encode_request(&SkwMpcP2pProtocol::V1, start_job(header.clone())).unwrap(), baseline);
+            assert_ne!(encode_request(&SkwMpcP2pProtocol::V2, start_job(header)).unwrap(), baseline);
+        }
+
+        #[test]
+        fn v1_sign_offline_keeps_the_baseline_layout() {
+            let mut header = PayloadHeader::default();
+            header.payload_type = PayloadType::SignOffline { message: [7u8; 32], parties: Vec::new() };
+            let baseline = baseline_start_job(&header);
+
+            assert_eq!(decode_request(&SkwMpcP2pProtocol::V1, &baseline).unwrap(), start_job(header.clone()));
+            assert_eq!(encode_request(&SkwMpcP2pProtocol::V1, start_job(header.clone())).unwrap(), baseline);
+
+            // signers other than the first parties can only be named on V2
+            header.payload_type = PayloadType::SignOffline { message: [7u8; 32], parties: vec![1, 3, 4] };
+            let err = encode_request(&SkwMpcP2pProtocol::V1, start_job(header.clone())).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            let data = encode_request(&SkwMpcP2pProtocol::V2, start_job(header.clone())).unwrap();
+            assert_eq!(decode_request(&SkwMpcP2pProtocol::V2, &data).unwrap(), start_job(header));
+        }
+
+        #[test]
+        fn v1_start_job_needs_every_address() {
+            let mut header = PayloadHeader::default();
+            header.peers[2].1 = None;
+
+            let err = encode_request(&SkwMpcP2pProtocol::V1, start_job(header.clone())).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+            let data = encode_request(&SkwMpcP2pProtocol::V2, start_job(header.clone())).unwrap();
+            assert_eq!(decode_request(&SkwMpcP2pProtocol::V2, &data).unwrap(), start_job(header));
+        }
+    }
+}
+
+// Sub protocol - capability handshake
+pub mod skw_mpc_handshake_behavior {
+    use tokio::io;
+    use futures::prelude::*;
+
+    use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed, ProtocolName};
+    use libp2p::request_response::Codec;
+
+    use crate::swarm::handshake::Handshake;
+
+    // a handshake is a few version numbers and enum lists
+    const MAX_HANDSHAKE_SIZE: usize = 4_096;
+
+    /// `/skw-mpc-handshake/1`. Peers that do not speak it are treated as `Handshake::legacy`.
+    #[derive(Debug, Clone)]
+    pub struct SkwMpcHandshakeProtocol();
+
+    #[derive(Debug, Clone, Default)]
+    pub struct SkwMpcHandshakeCodec();
+
+    impl ProtocolName for SkwMpcHandshakeProtocol {
+        fn protocol_name(&self) -> &[u8] {
+            b"/skw-mpc-handshake/1"
+        }
+    }
+
+    async fn read_handshake<T>(io: &mut T) -> io::Result<Handshake>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let vec = read_length_prefixed(io, MAX_HANDSHAKE_SIZE).await?;
+
+        if vec.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        bincode::deserialize( &vec )
+            .map_err(|_| io::ErrorKind::InvalidData.into() )
+    }
+
+    async fn write_handshake<T>(io: &mut T, handshake: Handshake) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let data = bincode::serialize(&handshake).expect("handshake to be valid");
+        write_length_prefixed(io, data).await?;
+        io.close().await?;
+
+        Ok(())
+    }
+
+    #[async_trait::async_trait]
+    impl Codec for SkwMpcHandshakeCodec {
+        type Protocol = SkwMpcHandshakeProtocol;
+        type Request = Handshake;
+        type Response = Handshake;
+
+        async fn read_request<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+        ) -> io::Result<Self::Request>
+        where
+            T: AsyncRead + Unpin + Send,
+        {
+            read_handshake(io).await
+        }
+
+        async fn read_response<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+        ) -> io::Result<Self::Response>
+        where
+            T: AsyncRead + Unpin + Send,
+        {
+            read_handshake(io).await
+        }
+
+        async fn write_request<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+            raw: Handshake,
+        ) -> io::Result<()>
+        where
+            T: AsyncWrite + Unpin + Send,
+        {
+            write_handshake(io, raw).await
+        }
+
+        async fn write_response<T>(
+            &mut self,
+            _: &SkwMpcHandshakeProtocol,
+            io: &mut T,
+            raw: Handshake,
+        ) -> io::Result<()>
+        where
+            T: AsyncWrite + Unpin + Send,
+        {
+            write_handshake(io, raw).await
+        }
+    }
+}
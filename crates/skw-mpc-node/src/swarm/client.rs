@@ -2,9 +2,12 @@ use libp2p::{PeerId, Multiaddr};
 use futures::{SinkExt};
 use futures::channel::{mpsc, oneshot};
 
+use skw_mpc_payload::{CryptoHash, PayloadHeader};
+
 use crate::error::MpcNodeError;
 
 use super::behavior::{MpcP2pRequest, MpcP2pResponse};
+use super::handshake::PeerProtocol;
 
 #[derive(Debug)]
 pub enum MpcSwarmCommand {
@@ -15,7 +18,8 @@ pub enum MpcSwarmCommand {
     },
     Dial {
         peer_id: PeerId,
-        peer_addr: Multiaddr,
+        // `None` leaves it to discovery
+        peer_addr: Option<Multiaddr>,
         result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
     },
     // CORE: Command to ReqRes P2p sub-protocol 
@@ -24,6 +28,42 @@ pub enum MpcSwarmCommand {
         request: MpcP2pRequest,
         result_sender: oneshot::Sender<Result<MpcP2pResponse, MpcNodeError>>,
     },
+    Handshake {
+        peer_id: PeerId,
+        result_sender: oneshot::Sender<Result<PeerProtocol, MpcNodeError>>,
+    },
+    // Command to the gossipsub broadcast transport, NOP when it is disabled
+    JoinJobTopic {
+        payload_id: CryptoHash,
+        committee: Vec<PeerId>,
+    },
+    LeaveJobTopic {
+        payload_id: CryptoHash,
+    },
+    GossipReady {
+        payload_id: CryptoHash,
+        peers: Vec<PeerId>,
+        result_sender: oneshot::Sender<bool>,
+    },
+    Publish {
+        payload_id: CryptoHash,
+        payload: Vec<u8>,
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+    },
+}
+
+/// Membership of a job broadcast topic, the topic is left when this is dropped
+pub struct JobTopicGuard {
+    payload_id: CryptoHash,
+    command_sender: mpsc::UnboundedSender<MpcSwarmCommand>,
+}
+
+impl Drop for JobTopicGuard {
+    fn drop(&mut self) {
+        // the swarm might be shutting down already
+        let _ = self.command_sender
+            .unbounded_send(MpcSwarmCommand::LeaveJobTopic { payload_id: self.payload_id });
+    }
 }
 
 pub struct MpcSwarmClient {
@@ -50,7 +90,7 @@ impl MpcSwarmClient {
     pub async fn dial(
         &mut self,
         peer_id: PeerId,
-        peer_addr: Multiaddr,
+        peer_addr: Option<Multiaddr>,
     ) -> Result<(), MpcNodeError> {
         let (result_sender, result_receiver) = oneshot::channel();
         
@@ -74,4 +114,48 @@ impl MpcSwarmClient {
         let status = result_receiver.await.expect("Sender not to be dropped.");
         status
     }
-}
\ No newline at end of file
+
+    /// Exchange capabilities with the given peer and settle on a protocol version.
+    pub async fn handshake(&mut self, peer_id: PeerId) -> Result<PeerProtocol, MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.command_sender
+            .send(MpcSwarmCommand::Handshake { peer_id, result_sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        result_receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Join the broadcast topic of a job for as long as the returned guard is alive
+    pub fn join_job_topic(&self, header: &PayloadHeader) -> JobTopicGuard {
+        let _ = self.command_sender
+            .unbounded_send(MpcSwarmCommand::JoinJobTopic { 
+                payload_id: header.payload_id,
+                committee: header.peers.iter().map(|(peer, _)| *peer).collect(),
+            });
+
+        JobTopicGuard {
+            payload_id: header.payload_id,
+            command_sender: self.command_sender.clone(),
+        }
+    }
+
+    /// Whether every given peer listens on the broadcast topic of the job
+    pub async fn gossip_ready(&mut self, payload_id: CryptoHash, peers: Vec<PeerId>) -> bool {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.command_sender
+            .send(MpcSwarmCommand::GossipReady { payload_id, peers, result_sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        result_receiver.await.expect("Sender not to be dropped.")
+    }
+
+    /// Publish an encoded payload on the broadcast topic of the job
+    pub async fn publish(&mut self, payload_id: CryptoHash, payload: Vec<u8>) -> Result<(), MpcNodeError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.command_sender
+            .send(MpcSwarmCommand::Publish { payload_id, payload, result_sender })
+            .await
+            .expect("Command receiver not to be dropped.");
+        result_receiver.await.expect("Sender not to be dropped.")
+    }
+}
@@ -0,0 +1,70 @@
+use libp2p::{PeerId, Multiaddr, multiaddr::Protocol};
+
+/// Kademlia protocol spoken between mpc nodes, kept apart from the public IPFS DHT
+pub const KADEMLIA_PROTOCOL_NAME: &[u8] = b"/skw-mpc/kad/1.0.0";
+/// Protocol version announced over identify
+pub const IDENTIFY_PROTOCOL_VERSION: &str = "/skw-mpc/id/1.0.0";
+
+/// How a node finds the addresses of peers named only by `PeerId`
+#[derive(Debug, Clone, Default)]
+pub struct DiscoveryConfig {
+    /// known nodes used to join the Kademlia DHT
+    pub bootstrap_peers: Vec<(PeerId, Multiaddr)>,
+    /// discover nodes on the local network, meant for devnets
+    pub enable_mdns: bool,
+}
+
+impl DiscoveryConfig {
+    /// Read `MPC_BOOTSTRAP_PEERS`, comma separated multiaddrs ending in `/p2p/<peer id>`,
+    /// and `MPC_ENABLE_MDNS`. Malformed bootstrap entries are skipped.
+    pub fn from_env() -> Self {
+        let bootstrap_peers = std::env::var("MPC_BOOTSTRAP_PEERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+            .filter_map(|addr| {
+                let peer = parse_peer_addr(addr);
+                if peer.is_none() {
+                    log::warn!("Ignoring malformed bootstrap peer {:?}", addr);
+                }
+                peer
+            })
+            .collect();
+
+        let enable_mdns = std::env::var("MPC_ENABLE_MDNS")
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(false);
+
+        Self { bootstrap_peers, enable_mdns }
+    }
+}
+
+/// Split `/ip4/../p2p/<peer id>` into the peer and its address
+pub fn parse_peer_addr(addr: &str) -> Option<(PeerId, Multiaddr)> {
+    let mut addr: Multiaddr = addr.parse().ok()?;
+    match addr.pop()? {
+        Protocol::P2p(hash) => PeerId::from_multihash(hash)
+            .ok()
+            .map(|peer_id| (peer_id, addr)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_bootstrap_peer() {
+        let peer_id = PeerId::random();
+        let (parsed_id, addr) = parse_peer_addr(&format!("/ip4/127.0.0.1/tcp/2620/ws/p2p/{}", peer_id))
+            .unwrap();
+
+        assert_eq!(parsed_id, peer_id);
+        assert_eq!(addr, "/ip4/127.0.0.1/tcp/2620/ws".parse::<Multiaddr>().unwrap());
+
+        assert_eq!(parse_peer_addr("/ip4/127.0.0.1/tcp/2620/ws"), None);
+        assert_eq!(parse_peer_addr("not an address"), None);
+    }
+}
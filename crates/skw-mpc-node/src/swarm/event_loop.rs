@@ -2,29 +2,34 @@ use core::panic;
 use std::collections::{HashMap, hash_map::Entry};
 
 use libp2p::{
-    swarm::{SwarmEvent, ConnectionHandlerUpgrErr}, PeerId,
+    swarm::{SwarmEvent, ConnectionHandlerUpgrErr, DialError, dial_opts::DialOpts}, PeerId,
     Swarm,
     multiaddr, 
-    request_response::{self, RequestId,}, Multiaddr, 
+    request_response::{self, RequestId, OutboundFailure}, Multiaddr, 
+    kad::{KademliaEvent, QueryResult, QueryId},
+    gossipsub::{self, TopicHash, MessageAcceptance},
+    identify, mdns,
 };
 use futures::{StreamExt, SinkExt};
 use futures::channel::{oneshot, mpsc};
 
-#[cfg(feature = "full-node")]
 use skw_mpc_payload::CryptoHash;
 
 #[cfg(feature = "full-node")]
 use skw_mpc_payload::{PayloadHeader};
 
 use super::{
-    behavior::{MpcSwarmBahavior, MpcSwarmBahaviorEvent, MpcP2pRequest, MpcP2pResponse}, 
+    behavior::{MpcSwarmBahavior, MpcSwarmBahaviorEvent, MpcP2pRequest, MpcP2pResponse, SkwMpcP2pCodec}, 
     client::MpcSwarmCommand,
+    handshake::{self, Handshake, PeerProtocol},
+    gossip::{job_topic, validate_job_message},
 };
 
 use crate::error::{ MpcNodeError, SwarmError, SwarmP2pError };
 
 pub struct MpcSwarmEventLoop {
     swarm: Swarm<MpcSwarmBahavior>,
+    codec: SkwMpcP2pCodec,
 
     swarm_incoming_message_sender: mpsc::UnboundedSender< Vec<u8> >,
 
@@ -35,6 +40,11 @@ pub struct MpcSwarmEventLoop {
 
     pending_dial: HashMap<PeerId, oneshot::Sender<Result<(), MpcNodeError>>>,
     pending_request: HashMap<RequestId, oneshot::Sender<Result<MpcP2pResponse, MpcNodeError>>>,
+    pending_handshake: HashMap<RequestId, oneshot::Sender<Result<PeerProtocol, MpcNodeError>>>,
+    // dials waiting for a Kademlia lookup of the peer address
+    pending_lookup: HashMap<QueryId, (PeerId, oneshot::Sender<Result<(), MpcNodeError>>)>,
+    // joined job broadcast topics with the job id and committee
+    job_topics: HashMap<TopicHash, (CryptoHash, Vec<PeerId>)>,
     
     listen_to_addr_sender: mpsc::Sender< Multiaddr >,
     swarm_termination_receiver: mpsc::Receiver<()>,
@@ -43,6 +53,7 @@ pub struct MpcSwarmEventLoop {
 impl MpcSwarmEventLoop {
     pub fn new(
         swarm: Swarm<MpcSwarmBahavior>,
+        codec: SkwMpcP2pCodec,
 
         swarm_incoming_message_sender: mpsc::UnboundedSender< Vec<u8> >,
 
@@ -57,6 +68,7 @@ impl MpcSwarmEventLoop {
     ) -> Self {
         Self {
             swarm,
+            codec,
 
             swarm_incoming_message_sender, 
             
@@ -67,6 +79,9 @@ impl MpcSwarmEventLoop {
 
             pending_dial: Default::default(),
             pending_request: Default::default(),
+            pending_handshake: Default::default(),
+            pending_lookup: Default::default(),
+            job_topics: Default::default(),
             listen_to_addr_sender,
             swarm_termination_receiver,
         }
@@ -182,11 +197,11 @@ impl MpcSwarmEventLoop {
                                         }
                                     }
                                 } else {
+                                    // peers named without an address are resolved on dial
                                     for (peer, address) in job_header.peers.iter() {
-                                        self.swarm
-                                            .behaviour_mut()
-                                            .request_response
-                                            .add_address(peer, address.clone());
+                                        if let Some(address) = address {
+                                            self.add_address(peer, address.clone());
+                                        }
                                     }
 
                                     match self.swarm
@@ -266,16 +281,154 @@ impl MpcSwarmEventLoop {
                 },
             )) => {
                 log::error!("p2p outbound request failure to {peer} because {:?}", error);
+                let error = match error {
+                    // no `/skw-mpc-p2p` version in common, the peer did not tell which ones it has
+                    OutboundFailure::UnsupportedProtocols => SwarmP2pError::IncompatibleProtocolVersion {
+                        local: handshake::local().versions,
+                        remote: Vec::new(),
+                    },
+                    _ => SwarmP2pError::OutboundFailure,
+                };
                 let _ = self
                     .pending_request
                     .remove(&request_id)
                     .expect("Request to still be pending.")
-                    .send(Err(MpcNodeError::SwarmP2pError(SwarmP2pError::OutboundFailure)))
+                    .send(Err(MpcNodeError::SwarmP2pError(error)))
                     .expect("p2p response receiver not to be dropped");
             }
             SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::RequestResponse(
                 request_response::Event::ResponseSent { .. },
             )) => {},
+
+            // capability handshake
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Handshake(
+                request_response::Event::Message { message, peer },
+            )) => match message {
+                request_response::Message::Request { channel, .. } => {
+                    if self.swarm
+                        .behaviour_mut()
+                        .handshake
+                        .send_response(channel, handshake::local())
+                        .is_err()
+                    {
+                        log::debug!("Handshake response channel to {:?} closed", peer);
+                    }
+                }
+                request_response::Message::Response { request_id, response } => {
+                    let result = handshake::negotiate(&response)
+                        .map_err(MpcNodeError::SwarmP2pError);
+                    log::debug!("Handshake with {:?} {:?}", peer, result);
+                    self
+                        .pending_handshake
+                        .remove(&request_id)
+                        .expect("Handshake to still be pending.")
+                        .send(result)
+                        .expect("handshake result receiver not to be dropped");
+                }
+            },
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Handshake(
+                request_response::Event::OutboundFailure {
+                    request_id, error, peer,
+                },
+            )) => {
+                let result = match error {
+                    // the peer predates the handshake
+                    OutboundFailure::UnsupportedProtocols => handshake::negotiate(&Handshake::legacy())
+                        .map_err(MpcNodeError::SwarmP2pError),
+                    _ => {
+                        log::error!("handshake failure with {peer} because {:?}", error);
+                        Err(MpcNodeError::SwarmP2pError(SwarmP2pError::OutboundFailure))
+                    }
+                };
+                self
+                    .pending_handshake
+                    .remove(&request_id)
+                    .expect("Handshake to still be pending.")
+                    .send(result)
+                    .expect("handshake result receiver not to be dropped");
+            }
+
+            // peer discovery
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Kademlia(
+                KademliaEvent::OutboundQueryProgressed {
+                    id, result: QueryResult::GetClosestPeers(result), ..
+                },
+            )) => {
+                if let Some((peer_id, result_sender)) = self.pending_lookup.remove(&id) {
+                    let found = matches!(&result, Ok(ok) if ok.peers.contains(&peer_id));
+                    // the lookup reached the peer, which put it in the routing table
+                    let addresses = if found {
+                        self.routing_table_addresses(&peer_id)
+                    } else {
+                        Vec::new()
+                    };
+
+                    if self.swarm.is_connected(&peer_id) {
+                        result_sender
+                            .send(Ok(()))
+                            .expect("dailing result receiver not to be dropped");
+                    } else if !addresses.is_empty() {
+                        self.dial_known_peer(peer_id, addresses, result_sender, false);
+                    } else {
+                        log::error!("Kademlia lookup for {:?} came back empty", peer_id);
+                        result_sender
+                            .send(Err(MpcNodeError::SwarmError(SwarmError::PeerNotFound)))
+                            .expect("dailing result receiver not to be dropped");
+                    }
+                }
+            }
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Identify(
+                identify::Event::Received { peer_id, info },
+            )) => {
+                for address in info.listen_addrs {
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, address);
+                }
+            }
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Mdns(
+                mdns::Event::Discovered(peers),
+            )) => {
+                for (peer_id, address) in peers {
+                    log::debug!("mDNS discovered {:?} at {:?}", peer_id, address);
+                    self.add_address(&peer_id, address);
+                }
+            }
+            // job broadcast
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Gossipsub(
+                gossipsub::Event::Message { propagation_source, message_id, message },
+            )) => {
+                let valid = match self.job_topics.get(&message.topic) {
+                    Some((payload_id, committee)) => validate_job_message(&message, payload_id, committee),
+                    None => false,
+                };
+                let acceptance = if valid {
+                    MessageAcceptance::Accept
+                } else {
+                    log::error!("Rejecting gossip message from {:?}", message.source);
+                    MessageAcceptance::Reject
+                };
+
+                if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+                    let _ = gossipsub.report_message_validation_result(&message_id, &propagation_source, acceptance);
+                }
+                if valid {
+                    self.swarm_incoming_message_sender
+                        .unbounded_send( message.data )
+                        .expect("swarm_incoming_message_sender should not be dropped. qed.");
+                }
+            }
+            SwarmEvent::Behaviour(MpcSwarmBahaviorEvent::Mdns(
+                mdns::Event::Expired(peers),
+            )) => {
+                for (peer_id, address) in peers {
+                    let behaviour = self.swarm.behaviour_mut();
+                    behaviour.request_response.remove_address(&peer_id, &address);
+                    behaviour.handshake.remove_address(&peer_id, &address);
+                    behaviour.kademlia.remove_address(&peer_id, &address);
+                }
+            }
             
             _ => {}
         }
@@ -294,12 +447,36 @@ impl MpcSwarmEventLoop {
                     .send(res)
                     .expect("swarm command result receiver not to be dropped");
             },
-            MpcSwarmCommand::Dial { peer_id, peer_addr, result_sender } => {
+            // no address given - leave it to discovery
+            MpcSwarmCommand::Dial { peer_id, peer_addr: None, result_sender } => {
+                if self.swarm.is_connected(&peer_id) {
+                    result_sender
+                        .send(Ok(()))
+                        .expect("swarm command result receiver not to be dropped");
+                } else if self.pending_dial.contains_key(&peer_id) 
+                    || self.pending_lookup.values().any(|(p, _)| *p == peer_id)
+                {
+                    result_sender
+                        .send(Err(MpcNodeError::SwarmError(SwarmError::AlreadyDailingPeer)))
+                        .expect("swarm command result receiver not to be dropped");
+                } else {
+                    self.dial_known_peer(peer_id, Vec::new(), result_sender, true);
+                }
+            },
+            MpcSwarmCommand::Dial { peer_id, peer_addr: Some(peer_addr), result_sender } => {
                 if let Entry::Vacant(e) = self.pending_dial.entry(peer_id) {
                     self.swarm
                         .behaviour_mut()
                         .request_response
                         .add_address(&peer_id, peer_addr.clone());
+                    self.swarm
+                        .behaviour_mut()
+                        .handshake
+                        .add_address(&peer_id, peer_addr.clone());
+                    self.swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, peer_addr.clone());
 
                     match self
                         .swarm
@@ -319,12 +496,129 @@ impl MpcSwarmEventLoop {
                 }
             },
             MpcSwarmCommand::SendP2pRequest { to, request, result_sender } => {
+                // fail early with a readable error instead of an opaque outbound failure
+                if let Err(e) = self.codec.check_request_size(&request) {
+                    log::error!("Refusing to send oversized request to {:?} {:?}", to, e);
+                    result_sender
+                        .send(Err(e))
+                        .expect("swarm command result receiver not to be dropped");
+                    return;
+                }
+
                 let request_id = self.swarm
                     .behaviour_mut()
                     .request_response
                     .send_request(&to, request.clone());
                 self.pending_request.insert(request_id, result_sender);
             }
+            MpcSwarmCommand::Handshake { peer_id, result_sender } => {
+                let request_id = self.swarm
+                    .behaviour_mut()
+                    .handshake
+                    .send_request(&peer_id, handshake::local());
+                self.pending_handshake.insert(request_id, result_sender);
+            }
+            MpcSwarmCommand::JoinJobTopic { payload_id, committee } => {
+                if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+                    let topic = job_topic(&payload_id);
+                    match gossipsub.subscribe(&topic) {
+                        Ok(_) => { self.job_topics.insert(topic.hash(), (payload_id, committee)); },
+                        Err(e) => log::error!("Failed to join job topic {:?}", e),
+                    }
+                }
+            }
+            MpcSwarmCommand::LeaveJobTopic { payload_id } => {
+                if let Some(gossipsub) = self.swarm.behaviour_mut().gossipsub.as_mut() {
+                    let topic = job_topic(&payload_id);
+                    let _ = gossipsub.unsubscribe(&topic);
+                    self.job_topics.remove(&topic.hash());
+                }
+            }
+            MpcSwarmCommand::GossipReady { payload_id, peers, result_sender } => {
+                let topic = job_topic(&payload_id).hash();
+                let ready = self.job_topics.contains_key(&topic) && match self.swarm.behaviour().gossipsub.as_ref() {
+                    Some(gossipsub) => {
+                        let subscribed: Vec<&PeerId> = gossipsub
+                            .all_peers()
+                            .filter(|(_, topics)| topics.contains(&&topic))
+                            .map(|(peer, _)| peer)
+                            .collect();
+                        peers.iter().all(|peer| subscribed.contains(&peer))
+                    },
+                    None => false,
+                };
+                let _ = result_sender.send(ready);
+            }
+            MpcSwarmCommand::Publish { payload_id, payload, result_sender } => {
+                let result = match self.swarm.behaviour_mut().gossipsub.as_mut() {
+                    Some(gossipsub) => gossipsub
+                        .publish(job_topic(&payload_id), payload)
+                        .map(|_| ())
+                        .map_err(|e| {
+                            log::error!("Gossip publish failed {:?}", e);
+                            MpcNodeError::SwarmP2pError(SwarmP2pError::GossipPublish)
+                        }),
+                    None => Err(MpcNodeError::SwarmP2pError(SwarmP2pError::GossipPublish)),
+                };
+                result_sender
+                    .send(result)
+                    .expect("swarm command result receiver not to be dropped");
+            }
+        }
+    }
+
+    fn add_address(&mut self, peer: &PeerId, address: Multiaddr) {
+        let behaviour = self.swarm.behaviour_mut();
+        behaviour.request_response.add_address(peer, address.clone());
+        behaviour.handshake.add_address(peer, address.clone());
+        behaviour.kademlia.add_address(peer, address);
+    }
+
+    /// Addresses of a peer in the Kademlia routing table
+    fn routing_table_addresses(&mut self, peer_id: &PeerId) -> Vec<Multiaddr> {
+        self.swarm
+            .behaviour_mut()
+            .kademlia
+            .kbuckets()
+            .flat_map(|bucket| bucket
+                .iter()
+                .filter(|entry| entry.node.key.preimage() == peer_id)
+                .flat_map(|entry| entry.node.value.iter().cloned())
+                .collect::<Vec<_>>()
+            )
+            .collect()
+    }
+
+    /// Dial a peer through `addresses` and those the behaviours already know of it,
+    /// optionally looking it up on the DHT when there are none
+    fn dial_known_peer(&mut self, 
+        peer_id: PeerId, 
+        addresses: Vec<Multiaddr>,
+        result_sender: oneshot::Sender<Result<(), MpcNodeError>>,
+        lookup_on_miss: bool,
+    ) {
+        for address in addresses.iter() {
+            self.add_address(&peer_id, address.clone());
+        }
+
+        let opts = DialOpts::peer_id(peer_id)
+            .addresses(addresses)
+            .extend_addresses_through_behaviour()
+            .build();
+        match self.swarm.dial(opts) {
+            Ok(_) => { self.pending_dial.insert(peer_id, result_sender); },
+            Err(DialError::NoAddresses) if lookup_on_miss => {
+                let query_id = self.swarm
+                    .behaviour_mut()
+                    .kademlia
+                    .get_closest_peers(peer_id);
+                self.pending_lookup.insert(query_id, (peer_id, result_sender));
+            },
+            Err(error) => {
+                log::error!("Dailing Error {:?}", error);
+                result_sender.send(Err(MpcNodeError::SwarmError(SwarmError::FailToDailPeer)))
+                    .expect("swarm command result receiver not to be dropped");
+            }
         }
     }
 }
@@ -0,0 +1,118 @@
+use libp2p::{gossipsub::{IdentTopic, Message}, PeerId};
+use skw_mpc_payload::CryptoHash;
+
+use crate::serde_support::decode_payload_header;
+
+/// How messages with `receiver: None` reach the rest of the committee
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BroadcastMode {
+    /// one request-response call per committee member
+    #[default]
+    RequestResponse,
+    /// publish once on a per job gossipsub topic, falling back to
+    /// request-response while the topic is not joined by every member yet
+    Gossip,
+}
+
+impl BroadcastMode {
+    /// `MPC_BROADCAST=gossip` turns on the gossipsub transport
+    pub fn from_env() -> Self {
+        match std::env::var("MPC_BROADCAST").as_deref() {
+            Ok("gossip") => Self::Gossip,
+            _ => Self::RequestResponse,
+        }
+    }
+}
+
+/// Topic carrying the broadcast rounds of one job
+pub fn job_topic(payload_id: &CryptoHash) -> IdentTopic {
+    let id: String = payload_id.iter().map(|b| format!("{:02x}", b)).collect();
+    IdentTopic::new(format!("/skw-mpc/job/{}", id))
+}
+
+/// Only committee members may publish, and only payloads of the job on its own topic.
+/// The gossipsub signature already ties `source` to the publishing key.
+pub fn validate_job_message(
+    message: &Message,
+    payload_id: &CryptoHash,
+    committee: &[PeerId],
+) -> bool {
+    let source = match message.source {
+        Some(source) => source,
+        None => return false,
+    };
+
+    message.topic == job_topic(payload_id).hash()
+        && committee.contains(&source)
+        && match decode_payload_header(&message.data) {
+            Ok(header) => header.payload_id == *payload_id && header.sender == source,
+            Err(_) => false,
+        }
+}
+
+#[cfg(test)]
+mod test {
+    use skw_mpc_payload::{Payload, PayloadHeader};
+
+    use super::*;
+    use crate::serde_support::{encode_payload, Encoding};
+
+    fn message(header: &PayloadHeader, source: PeerId, topic: &IdentTopic) -> Message {
+        Message {
+            source: Some(source),
+            data: encode_payload(&Payload { payload_header: header.clone(), body: 1u8 }, Encoding::Binary),
+            sequence_number: Some(0),
+            topic: topic.hash(),
+        }
+    }
+
+    fn job() -> (PayloadHeader, Vec<PeerId>, IdentTopic) {
+        let mut header = PayloadHeader::default();
+        header.payload_id = [1u8; 32];
+        header.sender = header.peers[1].0;
+        let committee = header.peers.iter().map(|(peer, _)| *peer).collect();
+        let topic = job_topic(&header.payload_id);
+        (header, committee, topic)
+    }
+
+    #[test]
+    fn accepts_committee_member() {
+        let (header, committee, topic) = job();
+        let accepted = message(&header, header.sender, &topic);
+        assert!(validate_job_message(&accepted, &header.payload_id, &committee));
+    }
+
+    #[test]
+    fn rejects_sender_outside_the_committee() {
+        let (mut header, committee, topic) = job();
+        let outsider = PeerId::random();
+        header.sender = outsider;
+        assert!(!validate_job_message(&message(&header, outsider, &topic), &header.payload_id, &committee));
+    }
+
+    #[test]
+    fn rejects_header_sender_other_than_the_source() {
+        let (header, committee, topic) = job();
+        // a committee member relaying a payload it claims another member sent
+        let relayed = message(&header, committee[2], &topic);
+        assert!(!validate_job_message(&relayed, &header.payload_id, &committee));
+
+        let mut unsigned = relayed.clone();
+        unsigned.source = None;
+        assert!(!validate_job_message(&unsigned, &header.payload_id, &committee));
+    }
+
+    #[test]
+    fn rejects_foreign_topic() {
+        let (header, committee, _) = job();
+        let other_job = job_topic(&[2u8; 32]);
+        let on_other_topic = message(&header, header.sender, &other_job);
+        assert!(!validate_job_message(&on_other_topic, &header.payload_id, &committee));
+
+        // a payload of another job on this job's topic
+        let mut foreign = header.clone();
+        foreign.payload_id = [2u8; 32];
+        let foreign_payload = message(&foreign, header.sender, &job_topic(&header.payload_id));
+        assert!(!validate_job_message(&foreign_payload, &header.payload_id, &committee));
+    }
+}
@@ -0,0 +1,97 @@
+use skw_mpc_payload::capability::{Encoding, HandshakeError, ProtocolVersion};
+
+pub use skw_mpc_payload::capability::{Capabilities, Handshake};
+
+use crate::error::SwarmP2pError;
+
+use super::behavior::SkwMpcP2pProtocol;
+
+/// Outcome of a handshake with one peer
+pub type PeerProtocol = skw_mpc_payload::capability::PeerProtocol<SkwMpcP2pProtocol>;
+
+impl ProtocolVersion for SkwMpcP2pProtocol {
+    fn version(&self) -> u16 {
+        SkwMpcP2pProtocol::version(self)
+    }
+
+    fn from_version(version: u16) -> Option<Self> {
+        SkwMpcP2pProtocol::from_version(version)
+    }
+
+    /// The peer must be able to read what we send, and the protocol must carry it
+    fn carries(&self, encoding: Encoding) -> bool {
+        encoding == Encoding::Json || encoding == self.payload_encoding()
+    }
+}
+
+impl From<HandshakeError> for SwarmP2pError {
+    fn from(e: HandshakeError) -> Self {
+        match e {
+            HandshakeError::IncompatibleProtocolVersion { local, remote } =>
+                Self::IncompatibleProtocolVersion { local, remote },
+            HandshakeError::NoCommonCurve => Self::NoCommonCurve,
+            HandshakeError::NoCommonEncoding => Self::NoCommonEncoding,
+            HandshakeError::UnsupportedJobType(job_type) => Self::UnsupportedJobType(job_type),
+        }
+    }
+}
+
+/// What this node is able to do, over every `/skw-mpc-p2p` version it speaks
+pub fn local() -> Handshake {
+    Handshake::new(
+        &SkwMpcP2pProtocol::supported(),
+        Capabilities::with_encodings(vec![Encoding::Binary, Encoding::Json]),
+    )
+}
+
+/// Settle on a protocol with a peer that answered the handshake with `remote`
+pub fn negotiate(remote: &Handshake) -> Result<PeerProtocol, SwarmP2pError> {
+    local()
+        .negotiate(remote)
+        .map_err(SwarmP2pError::from)
+}
+
+#[cfg(test)]
+mod test {
+    use skw_mpc_payload::JobType;
+
+    use super::*;
+
+    #[test]
+    fn negotiate_highest_common_version() {
+        let peer = negotiate(&local()).unwrap();
+        assert_eq!(peer.version, SkwMpcP2pProtocol::V2);
+        assert_eq!(peer.encoding, Encoding::Binary);
+
+        let peer = negotiate(&Handshake::legacy()).unwrap();
+        assert_eq!(peer.version, SkwMpcP2pProtocol::V1);
+        assert_eq!(peer.encoding, Encoding::Json);
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        let mut remote = local();
+        remote.versions = vec![42];
+
+        assert_eq!(
+            negotiate(&remote),
+            Err(SwarmP2pError::IncompatibleProtocolVersion {
+                local: local().versions,
+                remote: vec![42],
+            })
+        );
+    }
+
+    #[test]
+    fn unsupported_job_type() {
+        let mut remote = local();
+        remote.capabilities.job_types = vec![JobType::KeyGen];
+
+        let peer = negotiate(&remote).unwrap();
+        assert_eq!(peer.ensure_job_type(JobType::KeyGen), Ok(()));
+        assert_eq!(
+            peer.ensure_job_type(JobType::KeyRefresh).map_err(SwarmP2pError::from),
+            Err(SwarmP2pError::UnsupportedJobType(JobType::KeyRefresh))
+        );
+    }
+}
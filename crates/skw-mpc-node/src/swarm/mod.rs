@@ -1,22 +1,32 @@
 mod behavior;
 mod client;
 mod event_loop;
+mod handshake;
+mod discovery;
+mod gossip;
 
 use libp2p::request_response::ProtocolSupport;
 use libp2p::{
     identity, PeerId, Swarm,
     request_response, Multiaddr,
     mplex, noise, yamux, core, InboundUpgradeExt, OutboundUpgradeExt, Transport,
+    core::transport,
 };
 
 use futures::channel::mpsc;
 
-use self::behavior::{MpcSwarmBahavior, SkwMpcP2pCodec, SkwMpcP2pProtocol};
+use self::behavior::{
+    MpcSwarmBahavior, SkwMpcP2pCodec, SkwMpcP2pProtocol,
+    SkwMpcHandshakeCodec, SkwMpcHandshakeProtocol,
+};
 
 // re-export
-pub use client::MpcSwarmClient;
+pub use client::{MpcSwarmClient, JobTopicGuard};
 pub use event_loop::MpcSwarmEventLoop;
-pub use behavior::{MpcP2pRequest, MpcP2pResponse};
+pub use behavior::{MpcP2pRequest, MpcP2pResponse, SkwMpcP2pCodec, SkwMpcP2pProtocol};
+pub use handshake::{Capabilities, Handshake, PeerProtocol};
+pub use discovery::DiscoveryConfig;
+pub use gossip::BroadcastMode;
 
 #[cfg(feature = "full-node")]
 pub use swarm_full::new_full_swarm_node;
@@ -24,49 +34,140 @@ pub use swarm_full::new_full_swarm_node;
 #[cfg(feature = "light-node")]
 pub use swarm_light::new_light_swarm_node;
 
-#[cfg(feature = "tcp-ws-transport")]
-fn build_swarm(local_key: identity::Keypair) -> Swarm<MpcSwarmBahavior> {
+/// Noise authenticated, yamux/mplex multiplexed transport
+///
+/// TCP + websocket with `tcp-ws-transport`. Tests and `memory-transport` add libp2p's in-process
+/// `MemoryTransport` on `/memory/<port>`, so that whole committees can run inside one test. The
+/// address listened on or dialed picks the transport.
+#[cfg(any(feature = "tcp-ws-transport", feature = "memory-transport", test))]
+fn build_transport(local_key: &identity::Keypair) -> transport::Boxed<(PeerId, core::muxing::StreamMuxerBox)> {
     use std::time::Duration;
 
-    use libp2p::{websocket, tcp, dns};
-    let local_peer_id = PeerId::from(local_key.public());
+    let multiplexing_config = {
+        let mut mplex_config = mplex::MplexConfig::new();
+        mplex_config.set_max_buffer_behaviour(mplex::MaxBufferBehaviour::Block);
+        mplex_config.set_max_buffer_size(usize::MAX);
+        
+        let mut yamux_config = yamux::YamuxConfig::default();
+        // Enable proper flow-control: window updates are only sent when
+        // buffered data has been consumed.
+        yamux_config.set_window_update_mode(yamux::WindowUpdateMode::on_read());
 
-    let transport = {
-        let multiplexing_config = {
-            let mut mplex_config = mplex::MplexConfig::new();
-            mplex_config.set_max_buffer_behaviour(mplex::MaxBufferBehaviour::Block);
-            mplex_config.set_max_buffer_size(usize::MAX);
-            
-            let mut yamux_config = yamux::YamuxConfig::default();
-            // Enable proper flow-control: window updates are only sent when
-            // buffered data has been consumed.
-            yamux_config.set_window_update_mode(yamux::WindowUpdateMode::on_read());
-
-            core::upgrade::SelectUpgrade::new(yamux_config, mplex_config)
-                .map_inbound(core::muxing::StreamMuxerBox::new)
-                .map_outbound(core::muxing::StreamMuxerBox::new)
-        };
+        core::upgrade::SelectUpgrade::new(yamux_config, mplex_config)
+            .map_inbound(core::muxing::StreamMuxerBox::new)
+            .map_outbound(core::muxing::StreamMuxerBox::new)
+    };
 
+    #[cfg(feature = "tcp-ws-transport")]
+    let tcp_ws = {
+        use libp2p::{websocket, tcp, dns};
         websocket::WsConfig::new(dns::TokioDnsConfig::system(
             tcp::tokio::Transport::new(tcp::Config::default()),
         ).unwrap())
-            .upgrade(libp2p::core::upgrade::Version::V1)
-            .authenticate(
-                noise::NoiseAuthenticated::xx(&local_key)
-                    .expect("Signing libp2p-noise static DH keypair failed."),
-            )
-            .multiplex(multiplexing_config)
-            .timeout(Duration::from_secs(10))
-            .boxed()
     };
 
+    #[cfg(all(any(feature = "memory-transport", test), feature = "tcp-ws-transport"))]
+    let base = transport::MemoryTransport::default().or_transport(tcp_ws);
+
+    #[cfg(all(any(feature = "memory-transport", test), not(feature = "tcp-ws-transport")))]
+    let base = transport::MemoryTransport::default();
+
+    #[cfg(not(any(feature = "memory-transport", test)))]
+    let base = tcp_ws;
+
+    base
+        .upgrade(libp2p::core::upgrade::Version::V1)
+        .authenticate(
+            noise::NoiseAuthenticated::xx(local_key)
+                .expect("Signing libp2p-noise static DH keypair failed."),
+        )
+        .multiplex(multiplexing_config)
+        .timeout(Duration::from_secs(10))
+        .boxed()
+}
+
+#[cfg(any(feature = "tcp-ws-transport", feature = "memory-transport", test))]
+fn build_swarm(
+    local_key: identity::Keypair,
+    codec: SkwMpcP2pCodec,
+    discovery: DiscoveryConfig,
+    broadcast: BroadcastMode,
+) -> Swarm<MpcSwarmBahavior> {
+    use std::borrow::Cow;
+
+    use libp2p::{identify, kad, mdns, gossipsub};
+    use self::discovery::{KADEMLIA_PROTOCOL_NAME, IDENTIFY_PROTOCOL_VERSION};
+    let local_peer_id = PeerId::from(local_key.public());
+
+    let transport = build_transport(&local_key);
+
+    let max_transmit_size = codec.max_request_size();
     let request_response = request_response::Behaviour::<SkwMpcP2pCodec>::new(
-        SkwMpcP2pCodec(),
-        std::iter::once((SkwMpcP2pProtocol(), ProtocolSupport::Full)),
+        codec,
+        SkwMpcP2pProtocol::supported()
+            .into_iter()
+            .map(|protocol| (protocol, ProtocolSupport::Full)),
+        Default::default(),
+    );
+    let handshake = request_response::Behaviour::<SkwMpcHandshakeCodec>::new(
+        SkwMpcHandshakeCodec::default(),
+        std::iter::once((SkwMpcHandshakeProtocol(), ProtocolSupport::Full)),
         Default::default(),
     );
-    let behaviour = MpcSwarmBahavior {  request_response, };
-    Swarm::with_tokio_executor(transport, behaviour, local_peer_id)
+
+    let kademlia = {
+        let mut config = kad::KademliaConfig::default();
+        config.set_protocol_names(vec![Cow::Borrowed(KADEMLIA_PROTOCOL_NAME)]);
+
+        let mut kademlia = kad::Kademlia::with_config(
+            local_peer_id, kad::store::MemoryStore::new(local_peer_id), config,
+        );
+        for (peer, address) in discovery.bootstrap_peers.iter() {
+            kademlia.add_address(peer, address.clone());
+        }
+        kademlia
+    };
+    let identify = identify::Behaviour::new(identify::Config::new(
+        IDENTIFY_PROTOCOL_VERSION.to_string(), local_key.public(),
+    ));
+    let mdns = if discovery.enable_mdns {
+        mdns::tokio::Behaviour::new(mdns::Config::default(), local_peer_id)
+            .map_err(|e| log::error!("mDNS unavailable, continuing without it {:?}", e))
+            .ok()
+    } else {
+        None
+    };
+
+    let gossipsub = match broadcast {
+        BroadcastMode::RequestResponse => None,
+        BroadcastMode::Gossip => {
+            // messages are signed and handed to the event loop for validation before propagation
+            let config = gossipsub::ConfigBuilder::default()
+                .validation_mode(gossipsub::ValidationMode::Strict)
+                .validate_messages()
+                .max_transmit_size(max_transmit_size)
+                .build()
+                .expect("valid gossipsub config");
+            Some(
+                gossipsub::Behaviour::new(gossipsub::MessageAuthenticity::Signed(local_key.clone()), config)
+                    .expect("valid gossipsub behaviour")
+            )
+        }
+    };
+
+    let behaviour = MpcSwarmBahavior { 
+        request_response, handshake,
+        kademlia, identify, mdns: mdns.into(),
+        gossipsub: gossipsub.into(),
+    };
+    let mut swarm = Swarm::with_tokio_executor(transport, behaviour, local_peer_id);
+
+    if !discovery.bootstrap_peers.is_empty() {
+        if let Err(e) = swarm.behaviour_mut().kademlia.bootstrap() {
+            log::error!("Kademlia bootstrap failed {:?}", e);
+        }
+    }
+    swarm
 }
 
 #[cfg(feature = "full-node")]
@@ -75,7 +176,10 @@ mod swarm_full {
     use skw_mpc_payload::{PayloadHeader, CryptoHash};
 
     pub fn new_full_swarm_node(
-        local_key: Option<[u8; 32]>
+        local_key: Option<[u8; 32]>,
+        codec: SkwMpcP2pCodec,
+        discovery: DiscoveryConfig,
+        broadcast: BroadcastMode,
     ) -> (
         PeerId, // local peer id
         
@@ -100,7 +204,7 @@ mod swarm_full {
         let local_peer_id = PeerId::from(local_key.public());
         // eprintln!("Local peer id: {local_peer_id}");
     
-        let swarm = build_swarm(local_key);
+        let swarm = build_swarm(local_key, codec.clone(), discovery, broadcast);
     
         // the main message INCOMING channel 
         let (swarm_incoming_message_sender, swarm_incoming_message_receiver) = mpsc::unbounded();
@@ -120,6 +224,7 @@ mod swarm_full {
             MpcSwarmClient { command_sender },
             MpcSwarmEventLoop::new(
                 swarm, 
+                codec,
                 swarm_incoming_message_sender,
                 swarm_incoming_job_sender, 
                 command_receiver,
@@ -141,7 +246,10 @@ mod swarm_light {
     use super::*;
     
     pub fn new_light_swarm_node(
-        local_key: Option<[u8; 32]>
+        local_key: Option<[u8; 32]>,
+        codec: SkwMpcP2pCodec,
+        discovery: DiscoveryConfig,
+        broadcast: BroadcastMode,
     ) -> (
         PeerId, // local peer id
         
@@ -163,7 +271,7 @@ mod swarm_light {
         };
     
         let local_peer_id = PeerId::from(local_key.public());
-        let swarm = build_swarm(local_key);
+        let swarm = build_swarm(local_key, codec.clone(), discovery, broadcast);
     
         // the main message INCOMING channel 
         let (swarm_incoming_message_sender, swarm_incoming_message_receiver) = mpsc::unbounded();
@@ -180,7 +288,11 @@ mod swarm_light {
             MpcSwarmClient { command_sender },
             MpcSwarmEventLoop::new(
                 swarm, 
+                codec,
                 swarm_incoming_message_sender,
+                // light nodes are never assigned jobs, even when built next to full nodes
+                #[cfg(feature = "full-node")]
+                mpsc::channel(0).0,
                 command_receiver,
                 addr_sender,
                 swarm_termination_receiver
@@ -193,3 +305,59 @@ mod swarm_light {
         )
     }    
 }
+
+#[cfg(all(test, feature = "light-node"))]
+mod test {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::error::{MpcNodeError, SwarmError};
+
+    /// A swarm listening on `/memory/<port>`, its termination sender keeps it running
+    async fn spawn_swarm(port: u64, discovery: DiscoveryConfig) -> (PeerId, Multiaddr, MpcSwarmClient, mpsc::Sender<()>) {
+        let (peer_id, mut client, event_loop, mut addr_receiver, _, termination_sender) = new_light_swarm_node(
+            None, SkwMpcP2pCodec::default(), discovery, BroadcastMode::RequestResponse,
+        );
+        tokio::spawn(event_loop.run());
+
+        let addr: Multiaddr = format!("/memory/{}", port).parse().unwrap();
+        client.start_listening(addr.clone()).await.unwrap();
+        addr_receiver.next().await.expect("a listen address");
+        (peer_id, addr, client, termination_sender)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dial_falls_back_to_discovery() {
+        let (bootstrap, bootstrap_addr, _bootstrap_client, _bootstrap_node) =
+            spawn_swarm(3620, DiscoveryConfig::default()).await;
+        let discovery = DiscoveryConfig {
+            bootstrap_peers: vec![(bootstrap, bootstrap_addr)],
+            enable_mdns: false,
+        };
+        let (target, _, _target_client, _target_node) = spawn_swarm(3621, discovery.clone()).await;
+        let (_, _, mut client, _node) = spawn_swarm(3622, discovery).await;
+
+        // the target is named by its PeerId only, its address is only known to the bootstrap node
+        // once identify went through
+        let mut dialed = client.dial(target, None).await;
+        for _ in 0..20 {
+            if dialed.is_ok() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            dialed = client.dial(target, None).await;
+        }
+        assert_eq!(dialed, Ok(()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unknown_peer_is_not_found() {
+        let (_, _, mut client, _node) = spawn_swarm(3623, DiscoveryConfig::default()).await;
+        assert_eq!(
+            client.dial(PeerId::random(), None).await,
+            Err(MpcNodeError::SwarmError(SwarmError::PeerNotFound))
+        );
+    }
+}
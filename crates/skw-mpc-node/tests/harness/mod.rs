@@ -0,0 +1,139 @@
+//! In-process mpc committee over libp2p's `MemoryTransport`
+//!
+//! Like the examples, requests are authenticated with `AuthHeader::test_auth_header()`, so
+//! `OWNERSHIP_VERIFY_KEY` and `OWNERSHIP_PROOF_KEY` need to be set (or present in `.env`).
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use futures::{channel::mpsc, StreamExt};
+use libp2p::{Multiaddr, PeerId};
+
+use skw_mpc_node::{
+    async_executor,
+    error::MpcNodeError,
+    node::{full_node_event_loop, light_node_event_loop, ClientOutcome, NodeClient, RoundTimeouts, StorageBackend},
+};
+use skw_mpc_payload::{header::PayloadType, AuthHeader, CryptoHash, PayloadHeader};
+
+/// Memory transport ports are process wide, and tests run in parallel
+static NEXT_PORT: AtomicU64 = AtomicU64::new(2620);
+static NEXT_PAYLOAD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Generous upper bound for one job, including the slow keygen rounds in debug builds
+pub const JOB_TIMEOUT: Duration = Duration::from_secs(300);
+
+pub struct Node {
+    pub client: NodeClient,
+    pub peer_id: PeerId,
+    pub addr: Multiaddr,
+}
+
+/// One light node issuing requests and `n` full nodes, all on the current runtime
+pub struct TestNetwork {
+    pub light: Node,
+    pub full: Vec<Node>,
+}
+
+impl TestNetwork {
+    pub async fn spawn(full_nodes: usize) -> Self {
+        Self::spawn_with_round_timeouts(full_nodes, RoundTimeouts::default()).await
+    }
+
+    /// Like `spawn`, with every node giving up on a round after `round_timeouts`
+    pub async fn spawn_with_round_timeouts(full_nodes: usize, round_timeouts: RoundTimeouts) -> Self {
+        let (light_request_sender, light_request_receiver) = mpsc::channel(0);
+        async_executor(light_node_event_loop(light_request_receiver));
+        let light_client = NodeClient::new(light_request_sender).with_round_timeouts(round_timeouts.clone());
+        let light = bootstrap(light_client, "light").await;
+
+        let (full_request_sender, full_request_receiver) = mpsc::channel(0);
+        async_executor(full_node_event_loop(full_request_receiver));
+        let full_client = NodeClient::new(full_request_sender).with_round_timeouts(round_timeouts);
+        let mut full = vec![];
+        for i in 0..full_nodes {
+            full.push(bootstrap(full_client.clone(), &format!("full{}", i)).await);
+        }
+
+        Self { light, full }
+    }
+
+    /// Header of a new job run by the light node and the listed full nodes, in that party order
+    pub fn header(&self, payload_type: PayloadType, full_nodes: &[usize], t: u16) -> PayloadHeader {
+        let mut payload_id: CryptoHash = [0u8; 32];
+        payload_id[..8].copy_from_slice(&NEXT_PAYLOAD_ID.fetch_add(1, Ordering::SeqCst).to_le_bytes());
+
+        let mut peers = vec![self.light.peer()];
+        peers.extend(full_nodes.iter().map(|i| self.full[*i].peer()));
+
+        PayloadHeader::new(
+            payload_id, payload_type,
+            peers, self.light.peer_id,
+            t, self.full.len() as u16 + 1,
+        )
+    }
+
+    /// Send a request through the light node, failing on `JOB_TIMEOUT`
+    pub async fn request(
+        &mut self,
+        header: PayloadHeader,
+        maybe_local_key: Option<Vec<u8>>,
+    ) -> Result<ClientOutcome, MpcNodeError> {
+        self.light
+            .request_within(JOB_TIMEOUT, header, maybe_local_key)
+            .await
+            .expect("job did not finish in time")
+    }
+}
+
+impl Node {
+    fn peer(&self) -> (PeerId, Option<Multiaddr>) {
+        (self.peer_id, Some(self.addr.clone()))
+    }
+
+    /// `None` if the job neither succeeded nor failed within `timeout`
+    pub async fn request_within(
+        &mut self,
+        timeout: Duration,
+        header: PayloadHeader,
+        maybe_local_key: Option<Vec<u8>>,
+    ) -> Option<Result<ClientOutcome, MpcNodeError>> {
+        tokio::time::timeout(
+            timeout,
+            self.client.send_request(header, AuthHeader::test_auth_header(), maybe_local_key),
+        )
+        .await
+        .ok()
+    }
+
+    /// Stop a full node - its swarm, storage and event loop go away
+    pub async fn shutdown(&mut self) {
+        let peer_id = self.peer_id;
+        self.client
+            .shutdown(peer_id)
+            .await
+            .expect("shutdown not to be failed");
+    }
+}
+
+async fn bootstrap(mut client: NodeClient, name: &str) -> Node {
+    let port = NEXT_PORT.fetch_add(1, Ordering::SeqCst);
+    let addr: Multiaddr = format!("/memory/{}", port).parse().unwrap();
+
+    // a distinct identity per node, stable for a given port
+    let mut local_key = [0u8; 32];
+    local_key[..8].copy_from_slice(&port.to_le_bytes());
+
+    let mut errors = client
+        .bootstrap_node(Some(local_key), addr.to_string(), StorageBackend::InMemory)
+        .await;
+
+    let name = name.to_string();
+    async_executor(async move {
+        while let Some(error) = errors.next().await {
+            log::error!("Err in {} {:?}", name, error);
+        }
+    });
+
+    Node { peer_id: client.peer_id(), client, addr }
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use skw_crypto_curv::{arithmetic::Converter, BigInt};
+use skw_mpc_node::{
+    error::{MpcNodeError, MpcProtocolError},
+    node::{ClientOutcome, RoundTimeouts},
+    serde_support::{decode_key, decode_signature},
+};
+use skw_mpc_payload::header::PayloadType;
+use skw_mpc_protocol::gg20::party_i::verify;
+
+use crate::harness::{TestNetwork, JOB_TIMEOUT};
+
+mod harness;
+
+const MESSAGE: [u8; 32] = [2u8; 32];
+
+fn assert_valid_signature(outcome: &ClientOutcome, local_key: &[u8]) {
+    let local_key = decode_key(local_key).expect("a valid key shard");
+    let sig = decode_signature(&outcome.payload()).expect("a valid signature");
+    verify(&sig, &local_key.public_key(), &BigInt::from_bytes(&MESSAGE[..]))
+        .expect("signature to verify against the shared public key");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn keygen_sign_refresh_sign() {
+    let mut net = TestNetwork::spawn(2).await;
+
+    let local_key = net
+        .request(net.header(PayloadType::KeyGen, &[0, 1], 2), None)
+        .await
+        .expect("keygen")
+        .payload();
+
+    let sig = net
+        .request(
+            net.header(PayloadType::SignOffline { message: MESSAGE, parties: vec![1, 2] }, &[0], 2),
+            Some(local_key.clone()),
+        )
+        .await
+        .expect("sign");
+    assert_valid_signature(&sig, &local_key);
+
+    // the light node lost its shard, the full nodes refresh theirs and hand out a new one
+    let new_key = net
+        .request(net.header(PayloadType::KeyRefresh, &[0, 1], 2), None)
+        .await
+        .expect("key refresh")
+        .payload();
+    assert_eq!(
+        decode_key(&new_key).unwrap().public_key(),
+        decode_key(&local_key).unwrap().public_key(),
+    );
+
+    let sig = net
+        .request(
+            // full[1] was party 3 of the keygen
+            net.header(PayloadType::SignOffline { message: MESSAGE, parties: vec![1, 3] }, &[1], 2),
+            Some(new_key.clone()),
+        )
+        .await
+        .expect("sign with the refreshed key");
+    assert_valid_signature(&sig, &new_key);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sign_with_a_node_offline() {
+    let mut net = TestNetwork::spawn(2).await;
+
+    let local_key = net
+        .request(net.header(PayloadType::KeyGen, &[0, 1], 2), None)
+        .await
+        .expect("keygen")
+        .payload();
+
+    // two out of three parties are enough to sign
+    net.full[1].shutdown().await;
+
+    let sig = net
+        .request(
+            net.header(PayloadType::SignOffline { message: MESSAGE, parties: vec![1, 2] }, &[0], 2),
+            Some(local_key.clone()),
+        )
+        .await
+        .expect("sign without the offline node");
+    assert_valid_signature(&sig, &local_key);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn node_offline_mid_keygen() {
+    // short rounds, but enough for the honest parties to generate their Paillier keys
+    let mut round_timeouts = RoundTimeouts::uniform(Duration::from_secs(10));
+    round_timeouts.keygen.insert(1, Duration::from_secs(60));
+    let mut net = TestNetwork::spawn_with_round_timeouts(2, round_timeouts).await;
+    let header = net.header(PayloadType::KeyGen, &[0, 1], 2);
+
+    let TestNetwork { light, full } = &mut net;
+    let (outcome, _) = futures::join!(
+        light.request_within(JOB_TIMEOUT, header, None),
+        async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            full[1].shutdown().await;
+        },
+    );
+
+    // the job fails on a round timeout, blaming full[1] - party 3 of the keygen
+    match outcome {
+        Some(Err(MpcNodeError::MpcProtocolError(MpcProtocolError::KeyGenError(e)))) => {
+            assert!(e.starts_with("round timeout reached"), "unexpected keygen error {}", e);
+            assert!(e.ends_with("parties [3]"), "unexpected parties blamed {}", e);
+        },
+        Some(outcome) => panic!("expected a round timeout, got {:?}", outcome.map(|_| ())),
+        None => panic!("keygen did not fail in time"),
+    }
+}
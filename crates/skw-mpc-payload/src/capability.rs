@@ -0,0 +1,262 @@
+use serde::{Serialize, Deserialize};
+
+use crate::header::PayloadType;
+
+/// Kind of job a node is able to run, `PayloadType` without its parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JobType {
+    KeyGen,
+    SignOffline,
+    SignFinalize,
+    KeyRefresh,
+}
+
+impl JobType {
+    pub fn all() -> Vec<Self> {
+        vec![Self::KeyGen, Self::SignOffline, Self::SignFinalize, Self::KeyRefresh]
+    }
+}
+
+impl From<&PayloadType> for JobType {
+    fn from(payload_type: &PayloadType) -> Self {
+        match payload_type {
+            PayloadType::KeyGen => Self::KeyGen,
+            PayloadType::SignOffline { .. } => Self::SignOffline,
+            PayloadType::SignFinalize => Self::SignFinalize,
+            PayloadType::KeyRefresh => Self::KeyRefresh,
+        }
+    }
+}
+
+/// Elliptic curve a node holds key shards on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Curve {
+    Secp256k1,
+}
+
+impl Curve {
+    pub fn all() -> Vec<Self> {
+        vec![Self::Secp256k1]
+    }
+}
+
+/// Encoding of payloads and key shards
+///
+/// Defaults to JSON, what every peer that did not negotiate an encoding reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Encoding {
+    /// serde_json, the original format
+    #[default]
+    Json,
+    /// bincode with varint integers behind a `[magic, version]` header
+    Binary,
+}
+
+/// Version of a request-response protocol settled on by a [Handshake]
+pub trait ProtocolVersion: Copy {
+    fn version(&self) -> u16;
+
+    fn from_version(version: u16) -> Option<Self>;
+
+    /// Whether payloads in `encoding` can be sent over this version
+    fn carries(&self, _encoding: Encoding) -> bool {
+        true
+    }
+}
+
+/// Why two peers could not settle on a protocol, or why a job can not run over it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HandshakeError {
+    IncompatibleProtocolVersion { local: Vec<u16>, remote: Vec<u16> },
+    NoCommonCurve,
+    NoCommonEncoding,
+    UnsupportedJobType(JobType),
+}
+
+/// What a peer is able to do, exchanged over `/skw-mpc-handshake/1`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub job_types: Vec<JobType>,
+    pub curves: Vec<Curve>,
+    /// encodings the peer can read, most preferred first
+    pub encodings: Vec<Encoding>,
+}
+
+impl Capabilities {
+    /// Every job type and curve, reading `encodings`
+    pub fn with_encodings(encodings: Vec<Encoding>) -> Self {
+        Self {
+            job_types: JobType::all(),
+            curves: Curve::all(),
+            encodings,
+        }
+    }
+
+    /// Assumed for peers that predate the handshake
+    pub fn legacy() -> Self {
+        Self::with_encodings(vec![Encoding::Json])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Handshake {
+    /// protocol versions, most preferred first
+    pub versions: Vec<u16>,
+    pub capabilities: Capabilities,
+}
+
+impl Handshake {
+    pub fn new<P: ProtocolVersion>(supported: &[P], capabilities: Capabilities) -> Self {
+        Self {
+            versions: supported.iter().map(P::version).collect(),
+            capabilities,
+        }
+    }
+
+    /// Assumed for peers that do not speak `/skw-mpc-handshake/1`, they only know version 1
+    pub fn legacy() -> Self {
+        Self {
+            versions: vec![1],
+            capabilities: Capabilities::legacy(),
+        }
+    }
+
+    /// Settle on the highest common protocol version and the capabilities both sides share.
+    /// The encoding is the most preferred one the peer reads and the version carries.
+    pub fn negotiate<P: ProtocolVersion>(&self, remote: &Handshake) -> Result<PeerProtocol<P>, HandshakeError> {
+        let version = self.versions
+            .iter()
+            .filter(|v| remote.versions.contains(v))
+            .max()
+            .and_then(|v| P::from_version(*v))
+            .ok_or_else(|| HandshakeError::IncompatibleProtocolVersion {
+                local: self.versions.clone(),
+                remote: remote.versions.clone(),
+            })?;
+
+        let curves: Vec<Curve> = self.capabilities.curves
+            .iter()
+            .filter(|c| remote.capabilities.curves.contains(c))
+            .copied()
+            .collect();
+        if curves.is_empty() {
+            return Err(HandshakeError::NoCommonCurve);
+        }
+
+        let encoding = self.capabilities.encodings
+            .iter()
+            .filter(|e| remote.capabilities.encodings.contains(e))
+            .find(|e| version.carries(**e))
+            .copied()
+            .ok_or(HandshakeError::NoCommonEncoding)?;
+
+        let job_types = self.capabilities.job_types
+            .iter()
+            .filter(|j| remote.capabilities.job_types.contains(j))
+            .copied()
+            .collect();
+
+        Ok(PeerProtocol { version, encoding, job_types, curves })
+    }
+}
+
+/// Outcome of a handshake with one peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerProtocol<P> {
+    pub version: P,
+    /// encoding to use for what is sent to this peer
+    pub encoding: Encoding,
+    pub job_types: Vec<JobType>,
+    pub curves: Vec<Curve>,
+}
+
+impl<P> PeerProtocol<P> {
+    pub fn ensure_job_type(&self, job_type: JobType) -> Result<(), HandshakeError> {
+        if self.job_types.contains(&job_type) {
+            Ok(())
+        } else {
+            Err(HandshakeError::UnsupportedJobType(job_type))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Protocol {
+        V1,
+        V2,
+    }
+
+    impl ProtocolVersion for Protocol {
+        fn version(&self) -> u16 {
+            match self {
+                Self::V1 => 1,
+                Self::V2 => 2,
+            }
+        }
+
+        fn from_version(version: u16) -> Option<Self> {
+            match version {
+                1 => Some(Self::V1),
+                2 => Some(Self::V2),
+                _ => None,
+            }
+        }
+
+        fn carries(&self, encoding: Encoding) -> bool {
+            encoding == Encoding::Json || *self == Self::V2
+        }
+    }
+
+    fn local() -> Handshake {
+        Handshake::new(
+            &[Protocol::V2, Protocol::V1],
+            Capabilities::with_encodings(vec![Encoding::Binary, Encoding::Json]),
+        )
+    }
+
+    #[test]
+    fn negotiate_highest_common_version() {
+        let peer = local().negotiate::<Protocol>(&local()).unwrap();
+        assert_eq!((peer.version, peer.encoding), (Protocol::V2, Encoding::Binary));
+
+        let peer = local().negotiate::<Protocol>(&Handshake::legacy()).unwrap();
+        assert_eq!((peer.version, peer.encoding), (Protocol::V1, Encoding::Json));
+    }
+
+    #[test]
+    fn encoding_must_be_carried_by_the_version() {
+        let mut remote = local();
+        remote.versions = vec![1];
+        remote.capabilities.encodings = vec![Encoding::Binary];
+
+        assert_eq!(local().negotiate::<Protocol>(&remote), Err(HandshakeError::NoCommonEncoding));
+    }
+
+    #[test]
+    fn incompatible_versions() {
+        let mut remote = local();
+        remote.versions = vec![42];
+
+        assert_eq!(
+            local().negotiate::<Protocol>(&remote),
+            Err(HandshakeError::IncompatibleProtocolVersion { local: vec![2, 1], remote: vec![42] })
+        );
+    }
+
+    #[test]
+    fn unsupported_job_type() {
+        let mut remote = local();
+        remote.capabilities.job_types = vec![JobType::KeyGen];
+
+        let peer = local().negotiate::<Protocol>(&remote).unwrap();
+        assert_eq!(peer.ensure_job_type(JobType::KeyGen), Ok(()));
+        assert_eq!(
+            peer.ensure_job_type(JobType::KeyRefresh),
+            Err(HandshakeError::UnsupportedJobType(JobType::KeyRefresh))
+        );
+    }
+}
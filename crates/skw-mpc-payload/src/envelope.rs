@@ -0,0 +1,47 @@
+//! Payloads as the envelope of round-based protocol messages
+
+use skw_round_based::envelope::{Envelope, Header, InvalidMessage};
+use skw_round_based::Msg;
+
+use crate::{Payload, PayloadHeader};
+
+impl Header for PayloadHeader {
+    /// Peers are compared by their ids only, their addresses may be resolved differently by
+    /// different nodes
+    fn check(&self, job: &Self) -> Result<(), InvalidMessage> {
+        if self.payload_id != job.payload_id {
+            return Err(InvalidMessage::ForeignJob);
+        }
+
+        let same_peers = self
+            .peers
+            .iter()
+            .map(|(peer_id, _)| peer_id)
+            .eq(job.peers.iter().map(|(peer_id, _)| peer_id));
+        if self.t != job.t || self.n != job.n || !same_peers {
+            return Err(InvalidMessage::HeaderMismatch);
+        }
+
+        Ok(())
+    }
+}
+
+impl<B> Envelope<B> for Payload<Msg<B>> {
+    type Header = PayloadHeader;
+
+    fn wrap(payload_header: PayloadHeader, body: Msg<B>) -> Self {
+        Self { payload_header, body }
+    }
+
+    fn header(&self) -> &PayloadHeader {
+        &self.payload_header
+    }
+
+    fn msg(&self) -> &Msg<B> {
+        &self.body
+    }
+
+    fn unwrap(self) -> Msg<B> {
+        self.body
+    }
+}
@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use libp2p::{PeerId, Multiaddr};
 use serde::{Serialize, Deserialize};
 use serde_hex::{SerHex, Strict};
@@ -11,7 +11,11 @@ pub enum  PayloadType {
     // with the hash of the message to be signed. 
     SignOffline {
         #[serde(with = "SerHex::<Strict>")]
-        message: CryptoHash 
+        message: CryptoHash,
+        /// keygen party index of each of `peers`, in order. Empty when the
+        /// signers are parties `1..=peers.len()` of the keygen. Not carried by
+        /// version 1 of the wire protocols.
+        parties: Vec<u16>,
     },
 
     SignFinalize,
@@ -31,7 +35,9 @@ pub struct PayloadHeader {
     pub payload_id: CryptoHash,
     pub payload_type: PayloadType,
 
-    pub peers: Vec<(PeerId, Multiaddr)>,
+    /// committee members in party index order. Members without an address
+    /// are resolved through peer discovery.
+    pub peers: Vec<(PeerId, Option<Multiaddr>)>,
     pub sender: PeerId,
 
     pub t: u16, 
@@ -42,7 +48,7 @@ impl PayloadHeader {
     pub fn new(
         payload_id: CryptoHash,
         payload_type: PayloadType,
-        peers: Vec<(PeerId, Multiaddr)>,
+        peers: Vec<(PeerId, Option<Multiaddr>)>,
         sender: PeerId,
 
         t: u16, n: u16,
@@ -55,12 +61,116 @@ impl PayloadHeader {
     }
 }
 
+/// [PayloadType] as laid out by version 1 of the wire protocols, where the signers are always
+/// parties `1..=peers.len()` of the keygen.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LegacyPayloadType {
+    SignOffline {
+        #[serde(with = "SerHex::<Strict>")]
+        message: CryptoHash,
+    },
+    SignFinalize,
+    KeyGen,
+    KeyRefresh,
+}
+
+impl From<LegacyPayloadType> for PayloadType {
+    fn from(payload_type: LegacyPayloadType) -> Self {
+        match payload_type {
+            LegacyPayloadType::SignOffline { message } => Self::SignOffline { message, parties: Vec::new() },
+            LegacyPayloadType::SignFinalize => Self::SignFinalize,
+            LegacyPayloadType::KeyGen => Self::KeyGen,
+            LegacyPayloadType::KeyRefresh => Self::KeyRefresh,
+        }
+    }
+}
+
+/// [PayloadHeader] as laid out by version 1 of the wire protocols, where every peer comes with
+/// its address. Peers of that version can't decode anything else.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LegacyPayloadHeader {
+    #[serde(with = "SerHex::<Strict>")]
+    pub payload_id: CryptoHash,
+    pub payload_type: LegacyPayloadType,
+
+    pub peers: Vec<(PeerId, Multiaddr)>,
+    pub sender: PeerId,
+
+    pub t: u16, 
+    pub n: u16,
+}
+
+/// Why a header can't be sent to a peer on version 1 of the wire protocols
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LegacyHeaderError {
+    /// committee member to be resolved through peer discovery
+    PeerWithoutAddress(PeerId),
+    /// signers other than parties `1..=peers.len()` of the keygen
+    CustomSigners(Vec<u16>),
+}
+
+impl fmt::Display for LegacyHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PeerWithoutAddress(peer) => write!(f, "peer {peer} has no address, version 1 peers need one"),
+            Self::CustomSigners(parties) => write!(f, "signers {parties:?} can't be named to version 1 peers"),
+        }
+    }
+}
+
+impl TryFrom<PayloadHeader> for LegacyPayloadHeader {
+    type Error = LegacyHeaderError;
+
+    fn try_from(header: PayloadHeader) -> Result<Self, Self::Error> {
+        let payload_type = match header.payload_type {
+            PayloadType::SignOffline { message, parties } => {
+                let default_signers = (1..=header.peers.len() as u16).collect::<Vec<_>>();
+                if !parties.is_empty() && parties != default_signers {
+                    return Err(LegacyHeaderError::CustomSigners(parties));
+                }
+                LegacyPayloadType::SignOffline { message }
+            },
+            PayloadType::SignFinalize => LegacyPayloadType::SignFinalize,
+            PayloadType::KeyGen => LegacyPayloadType::KeyGen,
+            PayloadType::KeyRefresh => LegacyPayloadType::KeyRefresh,
+        };
+        let peers = header.peers
+            .into_iter()
+            .map(|(peer, addr)| addr
+                .map(|addr| (peer, addr))
+                .ok_or(LegacyHeaderError::PeerWithoutAddress(peer))
+            )
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            payload_id: header.payload_id,
+            payload_type,
+            peers,
+            sender: header.sender,
+            t: header.t,
+            n: header.n,
+        })
+    }
+}
+
+impl From<LegacyPayloadHeader> for PayloadHeader {
+    fn from(header: LegacyPayloadHeader) -> Self {
+        Self {
+            payload_id: header.payload_id,
+            payload_type: header.payload_type.into(),
+            peers: header.peers.into_iter().map(|(peer, addr)| (peer, Some(addr))).collect(),
+            sender: header.sender,
+            t: header.t,
+            n: header.n,
+        }
+    }
+}
+
 impl Default for PayloadHeader {
     fn default() -> Self {
         let peers = vec![
-            (PeerId::random(), "/ip4/127.0.0.1/tcp/5001".parse().unwrap()),
-            (PeerId::random(), "/ip4/127.0.0.1/tcp/5001".parse().unwrap()),
-            (PeerId::random(), "/ip4/127.0.0.1/tcp/5001".parse().unwrap())
+            (PeerId::random(), Some("/ip4/127.0.0.1/tcp/5001".parse().unwrap())),
+            (PeerId::random(), Some("/ip4/127.0.0.1/tcp/5001".parse().unwrap())),
+            (PeerId::random(), Some("/ip4/127.0.0.1/tcp/5001".parse().unwrap()))
         ];
         Self {
             payload_id: [0u8; 32],
@@ -75,7 +185,7 @@ impl Default for PayloadHeader {
 
 #[cfg(test)]
 mod test {
-    use super::{PayloadHeader};
+    use super::{LegacyHeaderError, LegacyPayloadHeader, PayloadHeader, PayloadType};
 
     #[test]
     fn serde_payload_header() {
@@ -89,4 +199,35 @@ mod test {
 
         println!("{:?}", restructred);
     }
+
+    #[test]
+    fn legacy_layout_needs_every_address() {
+        let header = PayloadHeader::default();
+        let legacy = LegacyPayloadHeader::try_from(header.clone()).unwrap();
+        assert_eq!(PayloadHeader::from(legacy), header);
+
+        let mut discovered = header;
+        discovered.peers[1].1 = None;
+        assert_eq!(
+            LegacyPayloadHeader::try_from(discovered.clone()),
+            Err(LegacyHeaderError::PeerWithoutAddress(discovered.peers[1].0))
+        );
+    }
+
+    #[test]
+    fn legacy_layout_signs_with_the_first_parties() {
+        let mut header = PayloadHeader::default();
+        header.payload_type = PayloadType::SignOffline { message: [1u8; 32], parties: vec![1, 2, 3] };
+        let legacy = LegacyPayloadHeader::try_from(header.clone()).unwrap();
+        assert_eq!(
+            PayloadHeader::from(legacy).payload_type,
+            PayloadType::SignOffline { message: [1u8; 32], parties: Vec::new() }
+        );
+
+        header.payload_type = PayloadType::SignOffline { message: [1u8; 32], parties: vec![1, 3, 4] };
+        assert_eq!(
+            LegacyPayloadHeader::try_from(header),
+            Err(LegacyHeaderError::CustomSigners(vec![1, 3, 4]))
+        );
+    }
 }
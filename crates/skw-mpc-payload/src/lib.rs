@@ -1,14 +1,17 @@
 pub mod header;
 pub mod types;
 pub mod auth_header;
+pub mod capability;
 
 mod env;
+mod envelope;
 use serde::{Serialize, Deserialize};
 
 // re-export
-pub use crate::header::PayloadHeader; 
+pub use crate::header::{PayloadHeader, LegacyPayloadHeader, LegacyPayloadType, LegacyHeaderError};
 pub use crate::auth_header::AuthHeader;
 pub use crate::types::{CryptoHash, SecertKey};
+pub use crate::capability::{JobType, Curve, Encoding};
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Payload<B> {
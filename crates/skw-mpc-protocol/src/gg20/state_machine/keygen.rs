@@ -2,11 +2,13 @@
 
 use std::fmt;
 use std::mem::replace;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use skw_crypto_curv::cryptographic_primitives::proofs::sigma_dlog::DLogProof;
 use skw_crypto_curv::cryptographic_primitives::secret_sharing::feldman_vss::VerifiableSS;
 use skw_crypto_curv::elliptic::curves::{secp256_k1::Secp256k1, Scalar};
+use skw_crypto_curv::rng;
 
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
@@ -31,6 +33,10 @@ use rounds::{Round0, Round1, Round2, Round3, Round4};
 ///
 /// Successfully completed keygen protocol produces [LocalKey] that can be used in further
 /// [signing](super::sign) protocol.
+///
+/// Stores for all rounds exist from the start, so messages of a future round sent by a party
+/// that got ahead are kept until this party reaches that round. Repeated messages are rejected
+/// with a non-critical error and don't abort the protocol.
 pub struct Keygen {
     round: R,
 
@@ -43,6 +49,10 @@ pub struct Keygen {
 
     party_i: u16,
     party_n: u16,
+
+    round_timeout: Option<Duration>,
+    round_timeout_overrides: HashMap<u16, Duration>,
+    rng_seed: Option<[u8; 32]>,
 }
 
 impl Keygen {
@@ -78,12 +88,43 @@ impl Keygen {
 
             party_i: i,
             party_n: n,
+
+            round_timeout: None,
+            round_timeout_overrides: HashMap::new(),
+            rng_seed: None,
         };
 
         state.proceed_round(false)?;
         Ok(state)
     }
 
+    /// Limits how long every round may wait for messages of other parties
+    ///
+    /// Once it's exceeded, the protocol fails with [Error::RoundTimeout] naming the parties
+    /// which haven't sent their messages. Without a timeout, a round waits forever.
+    pub fn with_round_timeout(mut self, timeout: Duration) -> Self {
+        self.round_timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides [with_round_timeout](Self::with_round_timeout) for `round`, for rounds waiting
+    /// on messages which take the other parties longer to compute
+    pub fn with_round_timeout_override(mut self, round: u16, timeout: Duration) -> Self {
+        self.round_timeout_overrides.insert(round, timeout);
+        self
+    }
+
+    /// Draws all the randomness of this party from `seed` instead of the OS
+    ///
+    /// Two parties constructed with the same arguments and seed send the same messages and
+    /// produce the same output when fed the same incoming messages, which makes a recorded run
+    /// [replayable](skw_round_based::transcript::replay). The seed is as secret as the party's
+    /// shares: whoever knows it can recompute them from the messages.
+    pub fn with_rng_seed(mut self, seed: [u8; 32]) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
     fn gmap_queue<'a, T, F>(&'a mut self, mut f: F) -> impl Push<Msg<T>> + 'a
     where
         F: FnMut(T) -> M + 'a,
@@ -94,6 +135,16 @@ impl Keygen {
     /// Proceeds round state if it received enough messages and if it's cheap to compute or
     /// `may_block == true`
     fn proceed_round(&mut self, may_block: bool) -> Result<()> {
+        match self.rng_seed {
+            Some(seed) => {
+                let seed = super::round_seed(&seed, self.current_round());
+                rng::with_seed(seed, || self.proceed_current_round(may_block))
+            }
+            None => self.proceed_current_round(may_block),
+        }
+    }
+
+    fn proceed_current_round(&mut self, may_block: bool) -> Result<()> {
         let store1_wants_more = self.msgs1.as_ref().map(|s| s.wants_more()).unwrap_or(false);
         let store2_wants_more = self.msgs2.as_ref().map(|s| s.wants_more()).unwrap_or(false);
         let store3_wants_more = self.msgs3.as_ref().map(|s| s.wants_more()).unwrap_or(false);
@@ -292,11 +343,18 @@ impl StateMachine for Keygen {
     }
 
     fn round_timeout(&self) -> Option<Duration> {
-        None
+        self.round_timeout_overrides
+            .get(&self.current_round())
+            .copied()
+            .or(self.round_timeout)
     }
 
     fn round_timeout_reached(&mut self) -> Self::Err {
-        panic!("no timeout was set")
+        let (_, missing) = super::traits::RoundBlame::round_blame(self);
+        Error::RoundTimeout {
+            round: self.current_round(),
+            missing,
+        }
     }
 
     fn is_finished(&self) -> bool {
@@ -458,6 +516,9 @@ pub enum Error {
         "didn't expect to receive message from round {msg_round} (being at round {current_round})"
     )]
     ReceivedOutOfOrderMessage { current_round: u16, msg_round: u16 },
+    /// Round timeout was reached before all the parties sent their messages
+    #[error("round {round} timed out waiting for messages from parties {missing:?}")]
+    RoundTimeout { round: u16, missing: Vec<u16> },
     /// [Keygen::pick_output] called twice
     #[error("pick_output called twice")]
     DoublePickOutput,
@@ -470,7 +531,16 @@ pub enum Error {
 
 impl IsCritical for Error {
     fn is_critical(&self) -> bool {
-        true
+        match self {
+            // a late repeat of a message from a round we already left, it's dropped
+            Error::ReceivedOutOfOrderMessage {
+                current_round,
+                msg_round,
+            } => msg_round >= current_round,
+            // a repeat from the same sender, the first message is kept
+            Error::HandleMessage(StoreErr::MsgOverwrite) => false,
+            _ => true,
+        }
     }
 }
 
@@ -494,7 +564,11 @@ mod private {
 
 #[cfg(test)]
 pub mod test {
-    use skw_round_based::dev::Simulation;
+    use skw_crypto_curv::BigInt;
+    use skw_round_based::dev::{Fault, FaultRule, NetworkFaults, Simulation};
+    use skw_round_based::transcript::{
+        read_transcript, replay, Entry, Recorder, ReplayError, TranscriptHeader, TranscriptWriter,
+    };
 
     use super::*;
 
@@ -514,6 +588,139 @@ pub mod test {
         keys
     }
 
+    #[test]
+    fn simulate_keygen_over_unreliable_network() {
+        let mut faults = NetworkFaults::new(34);
+        faults
+            .add_rule(FaultRule::new(Fault::Duplicate).with_probability(0.2))
+            .add_rule(FaultRule::new(Fault::Reorder));
+
+        let mut simulation = Simulation::new();
+        simulation.inject_faults(faults);
+        for i in 1..=3 {
+            simulation.add_party(Keygen::new(i, 1, 3).unwrap());
+        }
+
+        let keys = simulation.run().unwrap();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.iter().all(|k| k.y_sum_s == keys[0].y_sum_s));
+    }
+
+    #[test]
+    fn keygen_round_timeout_blames_silent_party() {
+        let mut faults = NetworkFaults::new(35);
+        faults.add_rule(FaultRule::new(Fault::Drop).from(3));
+
+        let mut simulation = Simulation::new();
+        simulation.inject_faults(faults);
+        for i in 1..=3 {
+            simulation.add_party(
+                Keygen::new(i, 1, 3)
+                    .unwrap()
+                    .with_round_timeout(Duration::from_secs(1)),
+            );
+        }
+
+        match simulation.run() {
+            Err(Error::RoundTimeout { round, missing }) => {
+                assert_eq!((round, missing), (1, vec![3]))
+            }
+            result => panic!("expected round timeout, got {:?}", result.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn keygen_round_timeout_override_applies_to_its_round() {
+        let mut party = Keygen::new(1, 1, 3)
+            .unwrap()
+            .with_round_timeout(Duration::from_secs(60))
+            .with_round_timeout_override(0, Duration::from_secs(5));
+        assert_eq!(party.current_round(), 0);
+        assert_eq!(party.round_timeout(), Some(Duration::from_secs(5)));
+
+        party.proceed().unwrap();
+        assert_eq!(party.current_round(), 1);
+        assert_eq!(party.round_timeout(), Some(Duration::from_secs(60)));
+    }
+
+    /// Runs seeded parties, tampering with the decommitment party 3 sends to party 1, and
+    /// records party 1 until it fails
+    fn record_keygen_failure() -> (Error, Vec<u8>) {
+        let mut parties: Vec<_> = (1..=3)
+            .map(|i| Keygen::new(i, 1, 3).unwrap().with_rng_seed([i as u8; 32]))
+            .collect();
+        let mut recorder = TranscriptWriter::new(vec![]);
+        recorder.record(Entry::<Msg<ProtocolMessage>>::Header(&TranscriptHeader {
+            protocol: "gg20-keygen".into(),
+            inputs: serde_json::json!({ "i": 1, "t": 1, "n": 3 }),
+            sealed_inputs: None,
+        }));
+        let mut round = None;
+
+        loop {
+            let mut outgoing = vec![];
+            for p in parties.iter_mut() {
+                if p.wants_to_proceed() {
+                    if let Err(err) = p.proceed() {
+                        assert_eq!(p.party_ind(), 1, "only party 1 gets a bad message");
+                        return (err, recorder.into_inner());
+                    }
+                }
+                outgoing.append(p.message_queue());
+            }
+            if round != Some(parties[0].current_round()) {
+                round = Some(parties[0].current_round());
+                recorder.record(Entry::<Msg<ProtocolMessage>>::Round(parties[0].current_round()));
+            }
+            assert!(!outgoing.is_empty(), "parties got stuck");
+
+            for msg in outgoing {
+                if msg.sender == 1 {
+                    recorder.record(Entry::Outgoing(&msg));
+                }
+                for p in parties.iter_mut() {
+                    let i = p.party_ind();
+                    if i == msg.sender || msg.receiver.map(|r| r != i).unwrap_or(false) {
+                        continue;
+                    }
+                    let mut msg = msg.clone();
+                    if i == 1 {
+                        if let (3, M::Round2(decommit)) = (msg.sender, &mut msg.body.0) {
+                            decommit.blind_factor = &decommit.blind_factor + BigInt::from(1);
+                        }
+                        recorder.record(Entry::Incoming(&msg));
+                    }
+                    p.handle_incoming(msg).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_keygen_failure() {
+        let (err, raw) = record_keygen_failure();
+        assert!(matches!(
+            err,
+            Error::ProceedRound(ProceedError::Round2VerifyCommitments(_))
+        ));
+        let transcript = read_transcript::<Msg<ProtocolMessage>, _>(&raw[..]).unwrap();
+
+        let party = Keygen::new(1, 1, 3).unwrap().with_rng_seed([1; 32]);
+        match replay(party, transcript.clone()) {
+            Err(ReplayError::Proceed(replayed)) => {
+                assert_eq!(format!("{:?}", replayed), format!("{:?}", err))
+            }
+            result => panic!("unexpected replay result: {:?}", result.map(|_| ())),
+        }
+
+        // without the seed, party 1 commits to other values than it did
+        let party = Keygen::new(1, 1, 3).unwrap();
+        assert!(matches!(
+            replay(party, transcript),
+            Err(ReplayError::Diverged { round: 1 })
+        ));
+    }
+
     #[test]
     fn simulate_keygen_t1_n2() {
         simulate_keygen(1, 2);
@@ -1,3 +1,15 @@
 pub mod keygen;
 pub mod sign;
 pub mod traits;
+
+use sha2::{Digest, Sha256};
+
+/// Seed of the randomness sampled while proceeding `round`, so every round draws the same samples
+/// however its computation interleaves with incoming messages
+fn round_seed(seed: &[u8; 32], round: u16) -> [u8; 32] {
+    Sha256::new()
+        .chain(seed)
+        .chain(round.to_be_bytes())
+        .finalize()
+        .into()
+}
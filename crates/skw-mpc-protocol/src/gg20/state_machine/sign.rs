@@ -22,6 +22,7 @@
 
 use std::convert::TryFrom;
 use std::mem::replace;
+use std::collections::HashMap;
 use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
@@ -30,6 +31,7 @@ use thiserror::Error;
 use crate::utilities::mta::MessageA;
 
 use skw_crypto_curv::elliptic::curves::secp256_k1::Secp256k1;
+use skw_crypto_curv::rng;
 
 use skw_round_based::{
     containers::{push::Push, BroadcastMsgs, MessageStore, P2PMsgs, Store, StoreErr},
@@ -68,6 +70,10 @@ pub struct OfflineStage {
 
     party_i: u16,
     party_n: u16,
+
+    round_timeout: Option<Duration>,
+    round_timeout_overrides: HashMap<u16, Duration>,
+    rng_seed: Option<[u8; 32]>,
 }
 
 impl OfflineStage {
@@ -121,15 +127,56 @@ impl OfflineStage {
 
             party_i: i,
             party_n: n,
+
+            round_timeout: None,
+            round_timeout_overrides: HashMap::new(),
+            rng_seed: None,
         })
     }
 
+    /// Limits how long every round may wait for messages of other parties
+    ///
+    /// Once it's exceeded, the protocol fails with [Error::RoundTimeout] naming the parties
+    /// which haven't sent their messages. Without a timeout, a round waits forever.
+    pub fn with_round_timeout(mut self, timeout: Duration) -> Self {
+        self.round_timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides [with_round_timeout](Self::with_round_timeout) for `round`, for rounds waiting
+    /// on messages which take the other parties longer to compute
+    pub fn with_round_timeout_override(mut self, round: u16, timeout: Duration) -> Self {
+        self.round_timeout_overrides.insert(round, timeout);
+        self
+    }
+
+    /// Draws all the randomness of this party from `seed` instead of the OS
+    ///
+    /// Two parties constructed with the same arguments and seed send the same messages and
+    /// produce the same output when fed the same incoming messages, which makes a recorded run
+    /// [replayable](skw_round_based::transcript::replay). The seed is as secret as the party's
+    /// shares: whoever knows it can recompute them from the messages.
+    pub fn with_rng_seed(mut self, seed: [u8; 32]) -> Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
     // fn proceed_state(&mut self, may_block: bool) -> Result<()> {
     //     self.proceed_round(may_block)?;
     //     self.proceed_decommit_round(may_block)
     // }
 
     fn proceed_round(&mut self, may_block: bool) -> Result<()> {
+        match self.rng_seed {
+            Some(seed) => {
+                let seed = super::round_seed(&seed, self.current_round());
+                rng::with_seed(seed, || self.proceed_current_round(may_block))
+            }
+            None => self.proceed_current_round(may_block),
+        }
+    }
+
+    fn proceed_current_round(&mut self, may_block: bool) -> Result<()> {
         let store1_wants_more = self.msgs1.as_ref().map(|s| s.wants_more()).unwrap_or(false);
         let store2_wants_more = self.msgs2.as_ref().map(|s| s.wants_more()).unwrap_or(false);
         let store3_wants_more = self.msgs3.as_ref().map(|s| s.wants_more()).unwrap_or(false);
@@ -302,7 +349,7 @@ impl StateMachine for OfflineStage {
                     .as_mut()
                     .ok_or(Error::ReceivedOutOfOrderMessage {
                         current_round,
-                        msg_round: 2,
+                        msg_round: 3,
                     })?;
                 store
                     .push_msg(Msg {
@@ -318,7 +365,7 @@ impl StateMachine for OfflineStage {
                     .as_mut()
                     .ok_or(Error::ReceivedOutOfOrderMessage {
                         current_round,
-                        msg_round: 2,
+                        msg_round: 4,
                     })?;
                 store
                     .push_msg(Msg {
@@ -334,7 +381,7 @@ impl StateMachine for OfflineStage {
                     .as_mut()
                     .ok_or(Error::ReceivedOutOfOrderMessage {
                         current_round,
-                        msg_round: 2,
+                        msg_round: 5,
                     })?;
                 store
                     .push_msg(Msg {
@@ -350,7 +397,7 @@ impl StateMachine for OfflineStage {
                     .as_mut()
                     .ok_or(Error::ReceivedOutOfOrderMessage {
                         current_round,
-                        msg_round: 2,
+                        msg_round: 6,
                     })?;
                 store
                     .push_msg(Msg {
@@ -393,11 +440,18 @@ impl StateMachine for OfflineStage {
     }
 
     fn round_timeout(&self) -> Option<Duration> {
-        None
+        self.round_timeout_overrides
+            .get(&self.current_round())
+            .copied()
+            .or(self.round_timeout)
     }
 
     fn round_timeout_reached(&mut self) -> Self::Err {
-        panic!("no timeout was set")
+        let (_, missing) = super::traits::RoundBlame::round_blame(self);
+        Error::RoundTimeout {
+            round: self.current_round(),
+            missing,
+        }
     }
 
     fn is_finished(&self) -> bool {
@@ -550,6 +604,9 @@ pub enum Error {
         "didn't expect to receive message from round {msg_round} (being at round {current_round})"
     )]
     ReceivedOutOfOrderMessage { current_round: u16, msg_round: u16 },
+    /// Round timeout was reached before all the parties sent their messages
+    #[error("round {round} timed out waiting for messages from parties {missing:?}")]
+    RoundTimeout { round: u16, missing: Vec<u16> },
     /// Received message didn't pass pre-validation
     #[error("received message didn't pass pre-validation: {0}")]
     HandleMessage(#[source] StoreErr),
@@ -589,6 +646,7 @@ impl IsCritical for Error {
             Error::ProceedRound(_) => true,
             Error::ReceivedOutOfOrderMessage { .. } => false,
             Error::HandleMessage(_) => false,
+            Error::RoundTimeout { .. } => true,
             Error::DoublePickOutput => true,
             Error::Bug(_) => true,
         }
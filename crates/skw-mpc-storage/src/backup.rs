@@ -0,0 +1,155 @@
+//! Encrypted backup archives
+//!
+//! A [Backup] holds the records of some namespaces of a store. It is sealed into a single
+//! XChaCha20-Poly1305 message under a key from a [MasterKeySource], usually not the one the
+//! store itself is encrypted with, so a truncated or tampered archive fails to open as a whole.
+//!
+//! ## Format
+//! An [archive](crate::encryption::seal_archive) with the magic `"SKWB"`. The plaintext is
+//! `created at (u64 LE, unix seconds) | count (u32 LE)`, then per record
+//! `namespace id | key length (u32 LE) | key | value length (u32 LE) | value`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zeroize::Zeroizing;
+
+use crate::encryption::{open_archive, seal_archive, MasterKeySource};
+use crate::storage::MpcStorage;
+use crate::types::{MpcStorageError, Namespace, WriteBatch};
+use crate::versions::Reader;
+
+const MAGIC: &[u8; 4] = b"SKWB";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupRecord {
+    pub namespace: Namespace,
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// Unix seconds
+    pub created_at: u64,
+    pub records: Vec<BackupRecord>,
+}
+
+impl Backup {
+    /// Every record of `namespaces`, each namespace read by a single scan
+    pub async fn snapshot<S: MpcStorage + ?Sized>(
+        storage: &S,
+        namespaces: &[Namespace],
+    ) -> Result<Self, MpcStorageError> {
+        let mut records = Vec::new();
+        for namespace in namespaces {
+            let page = storage.scan(*namespace, b"", usize::MAX, None).await?;
+            records.extend(page.entries
+                .into_iter()
+                .map(|(key, value)| BackupRecord { namespace: *namespace, key, value })
+            );
+        }
+        Ok(Self { created_at: unix_now(), records })
+    }
+
+    /// Writes every record to `storage` in one batch, replacing what is there under the same keys
+    pub async fn restore<S: MpcStorage + ?Sized>(&self, storage: &S) -> Result<(), MpcStorageError> {
+        let mut batch = WriteBatch::new();
+        for record in &self.records {
+            batch.put(record.namespace, &record.key, record.value.clone());
+        }
+        storage.write_batch(batch).await
+    }
+
+    pub fn records_in(&self, namespace: Namespace) -> impl Iterator<Item = &BackupRecord> {
+        self.records.iter().filter(move |record| record.namespace == namespace)
+    }
+
+    pub fn seal(&self, key: &MasterKeySource) -> Result<Vec<u8>, MpcStorageError> {
+        let mut plaintext = Zeroizing::new(Vec::new());
+        plaintext.extend_from_slice(&self.created_at.to_le_bytes());
+        plaintext.extend_from_slice(&len_u32(self.records.len())?.to_le_bytes());
+        for record in &self.records {
+            plaintext.push(record.namespace.id());
+            plaintext.extend_from_slice(&len_u32(record.key.len())?.to_le_bytes());
+            plaintext.extend_from_slice(&record.key);
+            plaintext.extend_from_slice(&len_u32(record.value.len())?.to_le_bytes());
+            plaintext.extend_from_slice(&record.value);
+        }
+        seal_archive(MAGIC, &plaintext, key)
+    }
+
+    pub fn open(archive: &[u8], key: &MasterKeySource) -> Result<Self, MpcStorageError> {
+        let plaintext = open_archive(MAGIC, archive, key)?;
+        let mut reader = Reader::new(&plaintext);
+        let created_at = reader.u64()?;
+        let count = reader.u32()?;
+        let mut records = Vec::with_capacity(count.min(1024) as usize);
+        for _ in 0..count {
+            let namespace = Namespace::from_id(reader.take(1)?[0])
+                .ok_or(MpcStorageError::MalformedRecord)?;
+            let len = reader.u32()? as usize;
+            let key = reader.take(len)?.to_vec();
+            let len = reader.u32()? as usize;
+            let value = reader.take(len)?.to_vec();
+            records.push(BackupRecord { namespace, key, value });
+        }
+        Ok(Self { created_at, records })
+    }
+}
+
+fn len_u32(len: usize) -> Result<u32, MpcStorageError> {
+    len.try_into().map_err(|_| MpcStorageError::FailToEncrypt)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::InMemoryStorage;
+
+    #[async_std::test]
+    async fn snapshot_seal_restore() {
+        let db = InMemoryStorage::default();
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+        db.put(Namespace::Metadata, &[0u8; 32], vec![4]).await.unwrap();
+        db.put(Namespace::Auth, b"not backed up", vec![5]).await.unwrap();
+
+        let key = MasterKeySource::Passphrase("correct horse".to_string());
+        let backup = Backup::snapshot(&db, &[Namespace::Shards, Namespace::Metadata]).await.unwrap();
+        let archive = backup.seal(&key).unwrap();
+        assert!(!archive.windows(3).any(|w| w == [1, 2, 3]));
+
+        let opened = Backup::open(&archive, &key).unwrap();
+        assert_eq!(opened, backup);
+
+        let restored = InMemoryStorage::default();
+        opened.restore(&restored).await.unwrap();
+        assert_eq!(restored.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![1, 2, 3]));
+        assert_eq!(restored.get(Namespace::Auth, b"not backed up").await, Err(MpcStorageError::KeyNotInDB));
+    }
+
+    #[async_std::test]
+    async fn tampered_archive_fails_to_open() {
+        let key = MasterKeySource::Passphrase("correct horse".to_string());
+        let backup = Backup {
+            created_at: 1,
+            records: vec![BackupRecord { namespace: Namespace::Shards, key: vec![0], value: vec![1] }],
+        };
+        let mut archive = backup.seal(&key).unwrap();
+
+        assert_eq!(
+            Backup::open(&archive, &MasterKeySource::Passphrase("wrong".to_string())),
+            Err(MpcStorageError::FailToDecrypt)
+        );
+        let last = archive.len() - 1;
+        archive[last] ^= 1;
+        assert_eq!(Backup::open(&archive, &key), Err(MpcStorageError::FailToDecrypt));
+        assert_eq!(Backup::open(&archive[..10], &key), Err(MpcStorageError::MalformedRecord));
+    }
+}
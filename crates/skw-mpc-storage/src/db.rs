@@ -1,26 +1,46 @@
-use crate::types::{MpcStorageError};
+use std::time::Duration;
 
-use futures::channel::{mpsc, oneshot};
+use crate::types::{MpcStorageError, Namespace, ScanPage, WriteBatch};
 
-type CryptoHash = [u8; 32];
+use futures::channel::{mpsc, oneshot};
+use futures::future::BoxFuture;
 
 #[derive(Debug)]
 pub enum DBOpIn  {
     WriteToDB {
-        key: CryptoHash,
+        namespace: Namespace,
+        key: Vec<u8>,
         value: Vec<u8>,
         
         result_sender: oneshot::Sender<DBOpOut>,
     },
 
     ReadFromDB {
-        key: CryptoHash,
+        namespace: Namespace,
+        key: Vec<u8>,
 
         result_sender: oneshot::Sender<DBOpOut>,
     },
 
     DeleteFromDB {
-        key: CryptoHash,
+        namespace: Namespace,
+        key: Vec<u8>,
+
+        result_sender: oneshot::Sender<DBOpOut>,
+    },
+
+    WriteBatch {
+        batch: WriteBatch,
+
+        result_sender: oneshot::Sender<DBOpOut>,
+    },
+
+    /// See [MpcStorage::scan](crate::MpcStorage::scan)
+    Scan {
+        namespace: Namespace,
+        prefix: Vec<u8>,
+        limit: usize,
+        cursor: Option<Vec<u8>>,
 
         result_sender: oneshot::Sender<DBOpOut>,
     },
@@ -48,6 +68,14 @@ pub enum DBOpOut {
         status: Result<(), MpcStorageError>,
     },
 
+    WriteBatch {
+        status: Result<(), MpcStorageError>,
+    },
+
+    Scan {
+        status: Result<ScanPage, MpcStorageError>,
+    },
+
 	ForceFlush {
         status: Result<(), MpcStorageError>,
     },
@@ -57,9 +85,37 @@ pub enum DBOpOut {
     },
 }
 
+/// A DB server serving the pending ops of its [MpcStorageConfig], to run on the caller's runtime
+pub type DbServer = BoxFuture<'static, ()>;
+
+/// Runs a [DbServer], e.g. `|server| { tokio::spawn(server); }`
+pub type Spawner = fn(DbServer);
+
+/// When the DB server makes writes durable, a write is only acknowledged once it is - except
+/// for the single puts and deletes under `PerBatch`
+///
+/// Reads are served as soon as the writes queued ahead of them are applied, they don't wait for
+/// those writes to be flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// Every put, delete and batch is flushed before it is acknowledged, the writes queued
+    /// together share a flush
+    #[default]
+    EveryWrite,
+    /// Writes are applied right away and flushed together at most `interval` after the first of
+    /// them. Reads in between see them without waiting for the flush.
+    GroupCommit { interval: Duration },
+    /// Batches are flushed before they are acknowledged. Single puts and deletes are acknowledged
+    /// once applied and become durable with the next batch, flush or shutdown, weakening the
+    /// [MpcStorage](crate::MpcStorage) contract for callers that batch what must survive a crash.
+    PerBatch,
+}
+
 pub struct MpcStorageConfig {
     db_name_or_path: String,
     in_memory: bool,
+    legacy_namespace: Namespace,
+    durability: Durability,
 
     db_in_receiver: mpsc::Receiver<DBOpIn>,
 }
@@ -74,10 +130,32 @@ impl MpcStorageConfig {
     ) -> Self {
         Self {
             db_name_or_path, in_memory, 
+            legacy_namespace: Namespace::Shards,
+            durability: Durability::default(),
             db_in_receiver
         }
     }
 
+    /// Namespace that records of a database predating namespaces are moved into, `Shards` by
+    /// default
+    pub fn with_legacy_namespace(mut self, namespace: Namespace) -> Self {
+        self.legacy_namespace = namespace;
+        self
+    }
+
+    pub fn legacy_namespace(&self) -> Namespace {
+        self.legacy_namespace
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
     pub fn is_in_memory(&self) -> bool {
         self.in_memory
     }
@@ -0,0 +1,420 @@
+//! Encryption at rest
+//!
+//! [EncryptedStorage] wraps another [MpcStorage] and seals every value with XChaCha20-Poly1305
+//! under a random data key. The record's namespaced key (the `key_shard_id` for key shards) is
+//! bound to the ciphertext as associated data, so a record copied or swapped under another key fails to
+//! decrypt instead of handing out the wrong shard.
+//!
+//! The data key itself is kept in the wrapped store, sealed by a master key that never touches
+//! the disk next to it: it is read from a file or derived from a passphrase with Argon2id.
+//! Rotating the master key rewraps the data key only, records are left as they are.
+//!
+//! ## Format
+//! Records are `nonce (24 bytes) | ciphertext`. The keyring lives under [KEYRING_KEY] in
+//! [Namespace::System] as
+//! `version | kdf | salt (16 bytes) | nonce (24 bytes) | wrapped data key`.
+//!
+//! Values written before encryption was turned on can't be read through [EncryptedStorage].
+
+use std::fmt;
+use std::path::PathBuf;
+
+use argon2::Argon2;
+use async_trait::async_trait;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::{rngs::OsRng, RngCore};
+use zeroize::Zeroizing;
+
+use crate::storage::MpcStorage;
+use crate::types::{BatchOp, MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+/// Reserved key of the keyring record in [Namespace::System] of the wrapped store
+pub const KEYRING_KEY: &[u8] = b"keyring";
+
+const KEYRING_VERSION: u8 = 1;
+const KDF_NONE: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+
+pub(crate) const SALT_LEN: usize = 16;
+pub(crate) const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+const KEYRING_LEN: usize = 2 + SALT_LEN + NONCE_LEN + 32 + TAG_LEN;
+
+/// Where the master key comes from
+#[derive(Clone, PartialEq, Eq)]
+pub enum MasterKeySource {
+    /// File holding the 32 raw key bytes
+    File(PathBuf),
+    /// Passphrase stretched with Argon2id, the salt is kept in the keyring
+    Passphrase(String),
+}
+
+impl fmt::Debug for MasterKeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => f.debug_tuple("File").field(path).finish(),
+            Self::Passphrase(_) => f.write_str("Passphrase(..)"),
+        }
+    }
+}
+
+impl MasterKeySource {
+    /// Read `MPC_STORAGE_MASTER_KEY_FILE`, or else `MPC_STORAGE_PASSPHRASE`
+    pub fn from_env() -> Option<Self> {
+        if let Ok(path) = std::env::var("MPC_STORAGE_MASTER_KEY_FILE") {
+            return Some(Self::File(path.into()));
+        }
+        std::env::var("MPC_STORAGE_PASSPHRASE").ok().map(Self::Passphrase)
+    }
+
+    pub(crate) fn kdf(&self) -> u8 {
+        match self {
+            Self::File(_) => KDF_NONE,
+            Self::Passphrase(_) => KDF_ARGON2ID,
+        }
+    }
+
+    pub(crate) fn load(&self, salt: &[u8; SALT_LEN]) -> Result<Zeroizing<[u8; 32]>, MpcStorageError> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        match self {
+            Self::File(path) => {
+                let raw = Zeroizing::new(
+                    std::fs::read(path).map_err(|_| MpcStorageError::FailToLoadMasterKey)?
+                );
+                if raw.len() != 32 {
+                    return Err(MpcStorageError::FailToLoadMasterKey);
+                }
+                key.copy_from_slice(&raw);
+            },
+            Self::Passphrase(passphrase) => {
+                Argon2::default()
+                    .hash_password_into(passphrase.as_bytes(), salt, &mut key[..])
+                    .map_err(|_| MpcStorageError::FailToLoadMasterKey)?;
+            },
+        }
+        Ok(key)
+    }
+}
+
+/// [MpcStorage] sealing values before they reach the wrapped store `S`
+pub struct EncryptedStorage<S> {
+    inner: S,
+    data_key: Zeroizing<[u8; 32]>,
+    cipher: XChaCha20Poly1305,
+}
+
+impl<S: MpcStorage> EncryptedStorage<S> {
+    /// Unwraps the data key of `inner` with the master key, generating both the data key and
+    /// the keyring on first use
+    pub async fn open(inner: S, master_key: &MasterKeySource) -> Result<Self, MpcStorageError> {
+        let data_key = match inner.get(Namespace::System, KEYRING_KEY).await {
+            Ok(keyring) => unwrap_data_key(&keyring, master_key)?,
+            Err(MpcStorageError::KeyNotInDB) => {
+                let mut data_key = Zeroizing::new([0u8; 32]);
+                OsRng.fill_bytes(&mut data_key[..]);
+                inner.put(Namespace::System, KEYRING_KEY, wrap_data_key(&data_key, master_key)?).await?;
+                data_key
+            },
+            Err(e) => return Err(e),
+        };
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&data_key[..]));
+        Ok(Self { inner, data_key, cipher })
+    }
+
+    /// Rewraps the data key under a new master key
+    ///
+    /// The keyring is replaced with a single write, a crash leaves either the old or the new
+    /// master key working.
+    pub async fn rotate_master_key(&self, new_master_key: &MasterKeySource) -> Result<(), MpcStorageError> {
+        self.inner
+            .put(Namespace::System, KEYRING_KEY, wrap_data_key(&self.data_key, new_master_key)?)
+            .await
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn seal_record(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<Vec<u8>, MpcStorageError> {
+        if is_keyring(namespace, key) {
+            return Err(MpcStorageError::FailToWriteDB);
+        }
+        let value = Zeroizing::new(value);
+
+        let mut nonce = [0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self.cipher
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: &value[..], aad: &namespace.key(key) })
+            .map_err(|_| MpcStorageError::FailToEncrypt)?;
+
+        let mut record = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        record.extend_from_slice(&nonce);
+        record.extend_from_slice(&ciphertext);
+        Ok(record)
+    }
+
+    fn open_record(&self, namespace: Namespace, key: &[u8], record: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        if record.len() < NONCE_LEN + TAG_LEN {
+            return Err(MpcStorageError::FailToDecrypt);
+        }
+        let (nonce, ciphertext) = record.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &namespace.key(key) })
+            .map_err(|_| MpcStorageError::FailToDecrypt)
+    }
+}
+
+fn is_keyring(namespace: Namespace, key: &[u8]) -> bool {
+    namespace == Namespace::System && key == KEYRING_KEY
+}
+
+fn wrap_data_key(data_key: &[u8; 32], master_key: &MasterKeySource) -> Result<Vec<u8>, MpcStorageError> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = master_key.load(&salt)?;
+    let wrapped = XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: &data_key[..], aad: &Namespace::System.key(KEYRING_KEY) })
+        .map_err(|_| MpcStorageError::FailToEncrypt)?;
+
+    let mut keyring = Vec::with_capacity(KEYRING_LEN);
+    keyring.push(KEYRING_VERSION);
+    keyring.push(master_key.kdf());
+    keyring.extend_from_slice(&salt);
+    keyring.extend_from_slice(&nonce);
+    keyring.extend_from_slice(&wrapped);
+    Ok(keyring)
+}
+
+fn unwrap_data_key(keyring: &[u8], master_key: &MasterKeySource) -> Result<Zeroizing<[u8; 32]>, MpcStorageError> {
+    if keyring.len() != KEYRING_LEN || keyring[0] != KEYRING_VERSION {
+        return Err(MpcStorageError::FailToDecrypt);
+    }
+    // the keyring was sealed by a master key of another kind
+    if keyring[1] != master_key.kdf() {
+        return Err(MpcStorageError::FailToLoadMasterKey);
+    }
+    let (salt, rest) = keyring[2..].split_at(SALT_LEN);
+    let (nonce, wrapped) = rest.split_at(NONCE_LEN);
+
+    let key = master_key.load(salt.try_into().unwrap())?;
+    let raw = Zeroizing::new(
+        XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: wrapped, aad: &Namespace::System.key(KEYRING_KEY) })
+            .map_err(|_| MpcStorageError::FailToDecrypt)?
+    );
+
+    let mut data_key = Zeroizing::new([0u8; 32]);
+    data_key.copy_from_slice(&raw);
+    Ok(data_key)
+}
+
+const ARCHIVE_VERSION: u8 = 1;
+const ARCHIVE_HEADER_LEN: usize = 4 + 2 + SALT_LEN + NONCE_LEN;
+
+/// Seals `plaintext` into a standalone archive under `key`
+///
+/// The archive is `magic | version | kdf | salt (16 bytes) | nonce (24 bytes) | ciphertext`,
+/// everything before the ciphertext being associated data. `magic` tells what the plaintext is.
+pub fn seal_archive(magic: &[u8; 4], plaintext: &[u8], key: &MasterKeySource) -> Result<Vec<u8>, MpcStorageError> {
+    let mut archive = Vec::with_capacity(ARCHIVE_HEADER_LEN + plaintext.len() + TAG_LEN);
+    archive.extend_from_slice(magic);
+    archive.push(ARCHIVE_VERSION);
+    archive.push(key.kdf());
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    archive.extend_from_slice(&salt);
+    archive.extend_from_slice(&nonce);
+
+    let key = key.load(&salt)?;
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plaintext, aad: &archive })
+        .map_err(|_| MpcStorageError::FailToEncrypt)?;
+    archive.extend_from_slice(&ciphertext);
+    Ok(archive)
+}
+
+/// Opens an archive of [seal_archive], which has to start with `magic`
+pub fn open_archive(magic: &[u8; 4], archive: &[u8], key: &MasterKeySource) -> Result<Zeroizing<Vec<u8>>, MpcStorageError> {
+    if archive.len() < ARCHIVE_HEADER_LEN || !archive.starts_with(magic) || archive[4] != ARCHIVE_VERSION {
+        return Err(MpcStorageError::MalformedRecord);
+    }
+    // sealed with a key of another kind
+    if archive[5] != key.kdf() {
+        return Err(MpcStorageError::FailToLoadMasterKey);
+    }
+    let (header, ciphertext) = archive.split_at(ARCHIVE_HEADER_LEN);
+    let (salt, nonce) = header[magic.len() + 2..].split_at(SALT_LEN);
+
+    let key = key.load(salt.try_into().unwrap())?;
+    let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key[..]))
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| MpcStorageError::FailToDecrypt)?;
+    Ok(Zeroizing::new(plaintext))
+}
+
+#[async_trait]
+impl<S: MpcStorage> MpcStorage for EncryptedStorage<S> {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        let record = self.seal_record(namespace, key, value)?;
+        self.inner.put(namespace, key, record).await
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        if is_keyring(namespace, key) {
+            return Err(MpcStorageError::KeyNotInDB);
+        }
+        let record = self.inner.get(namespace, key).await?;
+        self.open_record(namespace, key, &record)
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        if is_keyring(namespace, key) {
+            return Err(MpcStorageError::FailToDeleteDB);
+        }
+        self.inner.delete(namespace, key).await
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        let sealed = batch
+            .into_ops()
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Put { namespace, key, value } => {
+                    let value = self.seal_record(namespace, &key, value)?;
+                    Ok(BatchOp::Put { namespace, key, value })
+                },
+                BatchOp::Delete { namespace, key } if is_keyring(namespace, &key) => Err(MpcStorageError::FailToDeleteDB),
+                op => Ok(op),
+            })
+            .collect::<Result<WriteBatch, MpcStorageError>>()?;
+        self.inner.write_batch(sealed).await
+    }
+
+    /// A page may come back short of `limit` where it spanned the keyring
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        let page = self.inner.scan(namespace, prefix, limit, cursor).await?;
+        let entries = page.entries
+            .into_iter()
+            .filter(|(key, _)| !is_keyring(namespace, key))
+            .map(|(key, record)| {
+                let value = self.open_record(namespace, &key, &record)?;
+                Ok((key, value))
+            })
+            .collect::<Result<_, MpcStorageError>>()?;
+        Ok(ScanPage { entries, cursor: page.cursor })
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::InMemoryStorage;
+
+    fn passphrase(p: &str) -> MasterKeySource {
+        MasterKeySource::Passphrase(p.to_string())
+    }
+
+    #[async_std::test]
+    async fn seals_values() {
+        let inner = Arc::new(InMemoryStorage::default());
+        let db = EncryptedStorage::open(inner.clone(), &passphrase("correct horse")).await.unwrap();
+
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![1, 2, 3]));
+
+        let record = inner.get(Namespace::Shards, &[0u8; 32]).await.unwrap();
+        assert_eq!(record.len(), NONCE_LEN + 3 + TAG_LEN);
+        assert!(!record.windows(3).any(|w| w == [1, 2, 3]));
+    }
+
+    #[async_std::test]
+    async fn swapped_records_fail_to_decrypt() {
+        let inner = Arc::new(InMemoryStorage::default());
+        let db = EncryptedStorage::open(inner.clone(), &passphrase("correct horse")).await.unwrap();
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+        db.put(Namespace::Shards, &[1u8; 32], vec![4, 5, 6]).await.unwrap();
+
+        let record = inner.get(Namespace::Shards, &[0u8; 32]).await.unwrap();
+        inner.put(Namespace::Shards, &[1u8; 32], record).await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::FailToDecrypt));
+
+        // nor can a record be moved across namespaces
+        let record = inner.get(Namespace::Shards, &[1u8; 32]).await.unwrap();
+        inner.put(Namespace::Metadata, &[1u8; 32], record).await.unwrap();
+        assert_eq!(db.get(Namespace::Metadata, &[1u8; 32]).await, Err(MpcStorageError::FailToDecrypt));
+    }
+
+    #[async_std::test]
+    async fn scan_skips_keyring() {
+        let inner = Arc::new(InMemoryStorage::default());
+        let db = EncryptedStorage::open(inner.clone(), &passphrase("correct horse")).await.unwrap();
+        db.put(Namespace::System, b"schema", vec![1]).await.unwrap();
+
+        let page = db.scan(Namespace::System, b"", 10, None).await.unwrap();
+        assert_eq!(page.entries, vec![(b"schema".to_vec(), vec![1])]);
+        assert_eq!(inner.scan(Namespace::System, b"", 10, None).await.unwrap().entries.len(), 2);
+    }
+
+    #[async_std::test]
+    async fn rotate_master_key() {
+        let inner = Arc::new(InMemoryStorage::default());
+        let db = EncryptedStorage::open(inner.clone(), &passphrase("correct horse")).await.unwrap();
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+
+        let key_file = std::env::temp_dir().join(format!("skw-mpc-master-key-{}", std::process::id()));
+        std::fs::write(&key_file, [7u8; 32]).unwrap();
+        let new_master_key = MasterKeySource::File(key_file.clone());
+        db.rotate_master_key(&new_master_key).await.unwrap();
+
+        assert!(matches!(
+            EncryptedStorage::open(inner.clone(), &passphrase("correct horse")).await,
+            Err(MpcStorageError::FailToLoadMasterKey)
+        ));
+        let reopened = EncryptedStorage::open(inner.clone(), &new_master_key).await.unwrap();
+        assert_eq!(reopened.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![1, 2, 3]));
+
+        reopened.rotate_master_key(&passphrase("battery staple")).await.unwrap();
+        assert!(matches!(
+            EncryptedStorage::open(inner.clone(), &passphrase("wrong")).await,
+            Err(MpcStorageError::FailToDecrypt)
+        ));
+        std::fs::remove_file(&key_file).unwrap();
+    }
+
+    #[test]
+    fn archive_opens_under_its_magic_only() {
+        let key = passphrase("correct horse");
+        let archive = seal_archive(b"TEST", &[1, 2, 3], &key).unwrap();
+        assert_eq!(archive.len(), ARCHIVE_HEADER_LEN + 3 + TAG_LEN);
+
+        assert_eq!(open_archive(b"TEST", &archive, &key).unwrap().as_slice(), &[1, 2, 3]);
+        assert_eq!(open_archive(b"SKWB", &archive, &key), Err(MpcStorageError::MalformedRecord));
+        assert_eq!(
+            open_archive(b"TEST", &archive, &MasterKeySource::File("/nonexistent".into())),
+            Err(MpcStorageError::FailToLoadMasterKey)
+        );
+    }
+}
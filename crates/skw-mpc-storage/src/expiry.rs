@@ -0,0 +1,270 @@
+//! Records that expire
+//!
+//! [ExpiringStorage] wraps another [MpcStorage] and lets a write carry a time to live. The expiry
+//! time of a record is kept in [Namespace::System] under [EXPIRY_PREFIX] followed by the
+//! namespaced key of the record, and is written in the same batch as the record.
+//!
+//! Expiry is lazy: a read checks the expiry time and reports an expired record as missing.
+//! Expired records are deleted by [ExpiringStorage::sweep], which a [Sweeper] runs in the
+//! background. Writing a record without a TTL clears any TTL it had.
+//!
+//! ## Format
+//! Expiry times are unix milliseconds, `u64` big endian.
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+
+use crate::storage::MpcStorage;
+use crate::types::{BatchOp, MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+/// Prefix of the expiry records in [Namespace::System] of the wrapped store
+pub const EXPIRY_PREFIX: &[u8] = b"expiry/";
+
+const SWEEP_PAGE: usize = 256;
+
+/// [MpcStorage] with per-record TTLs on top of the wrapped store `S`
+pub struct ExpiringStorage<S> {
+    inner: S,
+    // serializes writes with the check-then-delete of a sweep
+    update: Mutex<()>,
+}
+
+impl<S: MpcStorage> ExpiringStorage<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner, update: Mutex::new(()) }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Stores `value` under `key` until `ttl` has passed
+    pub async fn put_with_ttl(
+        &self,
+        namespace: Namespace,
+        key: &[u8],
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<(), MpcStorageError> {
+        let expires_at = unix_millis().saturating_add(ttl.as_millis() as u64);
+        let mut batch = WriteBatch::new();
+        batch
+            .put(namespace, key, value)
+            .put(Namespace::System, &expiry_key(namespace, key), expires_at.to_be_bytes().to_vec());
+
+        let _guard = self.update.lock().await;
+        self.inner.write_batch(batch).await
+    }
+
+    /// Like [MpcStorage::get], but a record whose TTL has run out errors with `Expired` rather
+    /// than `KeyNotInDB` until it is swept
+    pub async fn get_or_expired(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        let value = self.inner.get(namespace, key).await?;
+        match self.is_expired(namespace, key, unix_millis()).await? {
+            true => Err(MpcStorageError::Expired),
+            false => Ok(value),
+        }
+    }
+
+    /// Deletes every expired record, returns how many were deleted
+    pub async fn sweep(&self) -> Result<usize, MpcStorageError> {
+        let _guard = self.update.lock().await;
+        let now = unix_millis();
+
+        let mut batch = WriteBatch::new();
+        let mut swept = 0;
+        let mut cursor = None;
+        loop {
+            let page = self.inner.scan(Namespace::System, EXPIRY_PREFIX, SWEEP_PAGE, cursor).await?;
+            for (key, expires_at) in &page.entries {
+                if decode_expiry(expires_at)? > now {
+                    continue;
+                }
+                let (namespace, record_key) = parse_expiry_key(key)?;
+                batch.delete(namespace, record_key).delete(Namespace::System, key);
+                swept += 1;
+            }
+            cursor = match page.cursor {
+                Some(cursor) => Some(cursor),
+                None => break,
+            };
+        }
+
+        if !batch.is_empty() {
+            self.inner.write_batch(batch).await?;
+        }
+        Ok(swept)
+    }
+
+    async fn is_expired(&self, namespace: Namespace, key: &[u8], now: u64) -> Result<bool, MpcStorageError> {
+        match self.inner.get(Namespace::System, &expiry_key(namespace, key)).await {
+            Ok(expires_at) => Ok(decode_expiry(&expires_at)? <= now),
+            Err(MpcStorageError::KeyNotInDB) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<S: MpcStorage + 'static> ExpiringStorage<S> {
+    /// Sweeps every `interval` on a background thread until the returned [Sweeper] is dropped
+    /// or the store is closed
+    pub fn spawn_sweeper(self: Arc<Self>, interval: Duration) -> Sweeper {
+        let (stop, stopped) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                match futures::executor::block_on(self.sweep()) {
+                    Ok(0) => {},
+                    Ok(swept) => log::debug!("Swept {} expired records", swept),
+                    Err(MpcStorageError::DBClosed) => break,
+                    Err(e) => log::warn!("Failed to sweep expired records {:?}", e),
+                }
+            }
+        });
+        Sweeper { _stop: stop }
+    }
+}
+
+/// Handle of a background sweeper, see [ExpiringStorage::spawn_sweeper]
+pub struct Sweeper {
+    _stop: mpsc::Sender<()>,
+}
+
+fn expiry_key(namespace: Namespace, key: &[u8]) -> Vec<u8> {
+    let mut raw = EXPIRY_PREFIX.to_vec();
+    raw.extend_from_slice(&namespace.key(key));
+    raw
+}
+
+fn parse_expiry_key(raw: &[u8]) -> Result<(Namespace, &[u8]), MpcStorageError> {
+    let namespaced = raw
+        .strip_prefix(EXPIRY_PREFIX)
+        .ok_or(MpcStorageError::MalformedRecord)?;
+    let (id, key) = namespaced
+        .split_first()
+        .ok_or(MpcStorageError::MalformedRecord)?;
+    let namespace = Namespace::from_id(*id)
+        .ok_or(MpcStorageError::MalformedRecord)?;
+    Ok((namespace, key))
+}
+
+fn is_expiry_record(namespace: Namespace, key: &[u8]) -> bool {
+    namespace == Namespace::System && key.starts_with(EXPIRY_PREFIX)
+}
+
+fn decode_expiry(raw: &[u8]) -> Result<u64, MpcStorageError> {
+    let raw = raw.try_into()
+        .map_err(|_| MpcStorageError::MalformedRecord)?;
+    Ok(u64::from_be_bytes(raw))
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[async_trait]
+impl<S: MpcStorage> MpcStorage for ExpiringStorage<S> {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        let mut batch = WriteBatch::new();
+        batch.put(namespace, key, value);
+        self.write_batch(batch).await
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        match self.get_or_expired(namespace, key).await {
+            Err(MpcStorageError::Expired) => Err(MpcStorageError::KeyNotInDB),
+            result => result,
+        }
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        let mut batch = WriteBatch::new();
+        batch.delete(namespace, key);
+        self.write_batch(batch).await
+    }
+
+    /// Clears the TTL of every record the batch touches
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        let mut expiries = WriteBatch::new();
+        for op in batch.ops() {
+            match op {
+                BatchOp::Put { namespace, key, .. } | BatchOp::Delete { namespace, key } => {
+                    expiries.delete(Namespace::System, &expiry_key(*namespace, key));
+                },
+            }
+        }
+        let batch = batch.into_ops().into_iter().chain(expiries.into_ops()).collect();
+
+        let _guard = self.update.lock().await;
+        self.inner.write_batch(batch).await
+    }
+
+    /// Expired records are left out, so a page may come back short of `limit`
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        let now = unix_millis();
+        let page = self.inner.scan(namespace, prefix, limit, cursor).await?;
+        let mut entries = Vec::with_capacity(page.entries.len());
+        for (key, value) in page.entries {
+            if is_expiry_record(namespace, &key) || self.is_expired(namespace, &key, now).await? {
+                continue;
+            }
+            entries.push((key, value));
+        }
+        Ok(ScanPage { entries, cursor: page.cursor })
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::InMemoryStorage;
+
+    #[async_std::test]
+    async fn expires_lazily_and_on_sweep() {
+        let db = ExpiringStorage::new(InMemoryStorage::default());
+        db.put_with_ttl(Namespace::Auth, b"challenge", vec![1], Duration::ZERO).await.unwrap();
+        db.put_with_ttl(Namespace::Auth, b"session", vec![2], Duration::from_secs(3600)).await.unwrap();
+        db.put(Namespace::Auth, b"preimage", vec![3]).await.unwrap();
+
+        assert_eq!(db.get(Namespace::Auth, b"challenge").await, Err(MpcStorageError::KeyNotInDB));
+        assert_eq!(db.get_or_expired(Namespace::Auth, b"challenge").await, Err(MpcStorageError::Expired));
+        assert_eq!(db.get(Namespace::Auth, b"session").await, Ok(vec![2]));
+        let page = db.scan(Namespace::Auth, b"", 10, None).await.unwrap();
+        assert_eq!(page.keys().collect::<Vec<_>>(), vec![&b"preimage"[..], b"session"]);
+
+        assert_eq!(db.sweep().await, Ok(1));
+        assert_eq!(db.get_or_expired(Namespace::Auth, b"challenge").await, Err(MpcStorageError::KeyNotInDB));
+        assert_eq!(db.sweep().await, Ok(0));
+    }
+
+    #[async_std::test]
+    async fn plain_write_clears_ttl() {
+        let db = ExpiringStorage::new(InMemoryStorage::default());
+        db.put_with_ttl(Namespace::Presignatures, b"a", vec![1], Duration::ZERO).await.unwrap();
+        db.put(Namespace::Presignatures, b"a", vec![2]).await.unwrap();
+
+        assert_eq!(db.get(Namespace::Presignatures, b"a").await, Ok(vec![2]));
+        assert_eq!(db.sweep().await, Ok(0));
+        assert!(db.scan(Namespace::System, b"", 10, None).await.unwrap().entries.is_empty());
+    }
+}
@@ -0,0 +1,374 @@
+//! Append-only file store
+//!
+//! Every put, delete and batch appends a record to a single log file, which is replayed into an
+//! index on open. Records are `op (1 byte) | key length (u32 LE) | key`, puts are followed by
+//! `value length (u32 LE) | value`. Keys are namespaced, see [Namespace::key]. A batch is
+//! `op | body length (u32 LE) | body`, the body being the records of its puts and deletes.
+//!
+//! A record torn by a crash can only be the last one, it is dropped on the next open - a torn
+//! batch is dropped as a whole. A failed append is cut off right away, so the index only ever
+//! reflects records that made it to disk.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::storage::{scan_map, MpcStorage};
+use crate::types::{BatchOp, MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+const PUT: u8 = 0;
+const DELETE: u8 = 1;
+const BATCH: u8 = 2;
+
+/// Live records by namespaced key
+type Index = BTreeMap<Vec<u8>, Vec<u8>>;
+
+pub struct FileStorage {
+    inner: Mutex<Option<Log>>,
+}
+
+struct Log {
+    file: File,
+    /// length of the log up to the last complete record
+    len: u64,
+    /// a failed append couldn't be cut off, nothing may be appended after it
+    torn: bool,
+    index: Index,
+}
+
+impl FileStorage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MpcStorageError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|_| MpcStorageError::FailToOpenDB)?;
+
+        let mut raw = Vec::new();
+        file.read_to_end(&mut raw)
+            .map_err(|_| MpcStorageError::FailToOpenDB)?;
+        let (index, valid_len) = replay(&raw)?;
+        if valid_len < raw.len() {
+            log::warn!("Dropping {} bytes of a torn record at the end of the storage log", raw.len() - valid_len);
+            file.set_len(valid_len as u64)
+                .map_err(|_| MpcStorageError::FailToOpenDB)?;
+        }
+
+        let log = Log { file, len: valid_len as u64, torn: false, index };
+        Ok(Self { inner: Mutex::new(Some(log)) })
+    }
+
+    fn with_log<T>(
+        &self,
+        op: impl FnOnce(&mut Log) -> Result<T, MpcStorageError>,
+    ) -> Result<T, MpcStorageError> {
+        let mut log = self.inner.lock().expect("storage lock not to be poisoned");
+        match log.as_mut() {
+            Some(log) => op(log),
+            None => Err(MpcStorageError::DBClosed),
+        }
+    }
+}
+
+impl Log {
+    /// Appends a record, cutting the log back to its previous length if it doesn't make it to disk
+    fn append(&mut self, record: &[u8]) -> Result<(), MpcStorageError> {
+        if self.torn {
+            self.file.set_len(self.len)
+                .map_err(|_| MpcStorageError::FailToWriteDB)?;
+            self.torn = false;
+        }
+
+        let written = self.file.write_all(record)
+            .map_err(|_| MpcStorageError::FailToWriteDB)
+            .and_then(|_| self.file.sync_data().map_err(|_| MpcStorageError::FailToFlushDB));
+
+        match written {
+            Ok(()) => {
+                self.len += record.len() as u64;
+                Ok(())
+            },
+            Err(e) => {
+                if self.file.set_len(self.len).is_err() {
+                    log::error!("Failed to cut a torn record off the storage log, retrying before the next append");
+                    self.torn = true;
+                }
+                Err(e)
+            },
+        }
+    }
+}
+
+fn record(op: u8, key: &[u8], value: Option<&[u8]>) -> Result<Vec<u8>, MpcStorageError> {
+    let key_len: u32 = key.len().try_into()
+        .map_err(|_| MpcStorageError::FailToWriteDB)?;
+    let mut record = Vec::with_capacity(9 + key.len() + value.map_or(0, |v| v.len()));
+    record.push(op);
+    record.extend_from_slice(&key_len.to_le_bytes());
+    record.extend_from_slice(key);
+    if let Some(value) = value {
+        let len: u32 = value.len().try_into()
+            .map_err(|_| MpcStorageError::FailToWriteDB)?;
+        record.extend_from_slice(&len.to_le_bytes());
+        record.extend_from_slice(value);
+    }
+    Ok(record)
+}
+
+/// Length-prefixed field at `pos`, with the position after it
+fn field(raw: &[u8], pos: usize) -> Option<(&[u8], usize)> {
+    let len = u32::from_le_bytes(raw.get(pos..pos + 4)?.try_into().unwrap()) as usize;
+    let bytes = raw.get(pos + 4..pos + 4 + len)?;
+    Some((bytes, pos + 4 + len))
+}
+
+/// Rebuilds the index, returns it with the length of the log up to the last complete record
+fn replay(raw: &[u8]) -> Result<(Index, usize), MpcStorageError> {
+    let mut index = Index::new();
+    let len = replay_into(&mut index, raw)?;
+    Ok((index, len))
+}
+
+/// Applies the complete records of `raw`, returns the length up to the last of them
+fn replay_into(index: &mut Index, raw: &[u8]) -> Result<usize, MpcStorageError> {
+    let mut pos = 0;
+
+    while let Some(&op) = raw.get(pos) {
+        let (key, after_key) = match field(raw, pos + 1) {
+            Some(key) => key,
+            None => break,
+        };
+        match op {
+            PUT => {
+                let (value, end) = match field(raw, after_key) {
+                    Some(value) => value,
+                    None => break,
+                };
+                index.insert(key.to_vec(), value.to_vec());
+                pos = end;
+            },
+            DELETE => {
+                index.remove(key);
+                pos = after_key;
+            },
+            // `key` is the body, known to be complete
+            BATCH => {
+                if replay_into(index, key)? != key.len() {
+                    return Err(MpcStorageError::FailToOpenDB);
+                }
+                pos = after_key;
+            },
+            _ => return Err(MpcStorageError::FailToOpenDB),
+        }
+    }
+
+    Ok(pos)
+}
+
+#[async_trait]
+impl MpcStorage for FileStorage {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        let key = namespace.key(key);
+        let record = record(PUT, &key, Some(&value))?;
+
+        self.with_log(|log| {
+            log.append(&record)?;
+            log.index.insert(key, value);
+            Ok(())
+        })
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        self.with_log(|log| log.index.get(&namespace.key(key)).cloned().ok_or(MpcStorageError::KeyNotInDB))
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        let key = namespace.key(key);
+        self.with_log(|log| {
+            if log.index.contains_key(&key) {
+                log.append(&record(DELETE, &key, None)?)?;
+                log.index.remove(&key);
+            }
+            Ok(())
+        })
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        let ops: Vec<_> = batch
+            .into_ops()
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Put { namespace, key, value } => (namespace.key(&key), Some(value)),
+                BatchOp::Delete { namespace, key } => (namespace.key(&key), None),
+            })
+            .collect();
+
+        let mut body = Vec::new();
+        for (key, value) in &ops {
+            match value {
+                Some(value) => body.extend(record(PUT, key, Some(value))?),
+                None => body.extend(record(DELETE, key, None)?),
+            }
+        }
+        let record = record(BATCH, &body, None)?;
+
+        self.with_log(|log| {
+            log.append(&record)?;
+            for (key, value) in ops {
+                match value {
+                    Some(value) => { log.index.insert(key, value); },
+                    None => { log.index.remove(&key); },
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        self.with_log(|log| Ok(scan_map(&log.index, namespace, prefix, limit, cursor.as_deref())))
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        self.with_log(|log| log.file.sync_data().map_err(|_| MpcStorageError::FailToFlushDB))
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        match self.inner.lock().expect("storage lock not to be poisoned").take() {
+            Some(log) => log.file.sync_all().map_err(|_| MpcStorageError::FailToCloseDB),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_log(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("skw-mpc-storage-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[async_std::test]
+    async fn survives_reopen() {
+        let path = temp_log("reopen");
+        {
+            let db = FileStorage::open(&path).unwrap();
+            db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+            db.put(Namespace::Shards, &[1u8; 32], vec![4, 5, 6]).await.unwrap();
+            db.put(Namespace::Shards, &[0u8; 32], vec![7]).await.unwrap();
+            db.delete(Namespace::Shards, &[1u8; 32]).await.unwrap();
+            db.close().await.unwrap();
+            assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Err(MpcStorageError::DBClosed));
+        }
+
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![7]));
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::KeyNotInDB));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn long_keys_survive_reopen() {
+        let path = temp_log("long-keys");
+        let key = vec![9u8; 300];
+        {
+            let db = FileStorage::open(&path).unwrap();
+            db.put(Namespace::Journal, &key, vec![1]).await.unwrap();
+            db.put(Namespace::Journal, b"", vec![2]).await.unwrap();
+            db.put(Namespace::Auth, &key, vec![3]).await.unwrap();
+        }
+
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Journal, &key).await, Ok(vec![1]));
+        assert_eq!(db.get(Namespace::Journal, b"").await, Ok(vec![2]));
+        let page = db.scan(Namespace::Journal, b"", 10, None).await.unwrap();
+        assert_eq!(page.keys().collect::<Vec<_>>(), vec![&b""[..], &key[..]]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn torn_batch_is_dropped_whole() {
+        let path = temp_log("batch");
+        {
+            let db = FileStorage::open(&path).unwrap();
+            db.put(Namespace::Shards, b"a", vec![1]).await.unwrap();
+
+            let mut batch = WriteBatch::new();
+            batch.delete(Namespace::Shards, b"a").put(Namespace::Shards, b"b", vec![2]);
+            db.write_batch(batch).await.unwrap();
+            assert_eq!(db.get(Namespace::Shards, b"a").await, Err(MpcStorageError::KeyNotInDB));
+
+            let mut batch = WriteBatch::new();
+            batch.put(Namespace::Shards, b"c", vec![3]).put(Namespace::Shards, b"d", vec![4]);
+            db.write_batch(batch).await.unwrap();
+        }
+        // crash after the first op of the second batch made it to disk
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 10).unwrap();
+
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Shards, b"a").await, Err(MpcStorageError::KeyNotInDB));
+        assert_eq!(db.get(Namespace::Shards, b"b").await, Ok(vec![2]));
+        assert_eq!(db.get(Namespace::Shards, b"c").await, Err(MpcStorageError::KeyNotInDB));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn failed_append_leaves_index_and_log_untouched() {
+        let path = temp_log("failed-append");
+        let db = FileStorage::open(&path).unwrap();
+        db.put(Namespace::Shards, b"a", vec![1]).await.unwrap();
+
+        // swap in a handle the log can't be written through
+        db.with_log(|log| {
+            log.file = File::open(&path).unwrap();
+            Ok(())
+        }).unwrap();
+        assert_eq!(db.delete(Namespace::Shards, b"a").await, Err(MpcStorageError::FailToWriteDB));
+        assert_eq!(db.put(Namespace::Shards, b"b", vec![2]).await, Err(MpcStorageError::FailToWriteDB));
+        assert_eq!(db.get(Namespace::Shards, b"a").await, Ok(vec![1]));
+        assert_eq!(db.get(Namespace::Shards, b"b").await, Err(MpcStorageError::KeyNotInDB));
+        drop(db);
+
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Shards, b"a").await, Ok(vec![1]));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn drops_torn_record() {
+        let path = temp_log("torn");
+        {
+            let db = FileStorage::open(&path).unwrap();
+            db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+            db.put(Namespace::Shards, &[1u8; 32], vec![4, 5, 6]).await.unwrap();
+        }
+        // crash in the middle of the second record
+        let len = std::fs::metadata(&path).unwrap().len();
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 2).unwrap();
+
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![1, 2, 3]));
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::KeyNotInDB));
+
+        // the log is usable again
+        db.put(Namespace::Shards, &[1u8; 32], vec![8]).await.unwrap();
+        drop(db);
+        let db = FileStorage::open(&path).unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Ok(vec![8]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,17 @@
-use futures::{channel::mpsc, StreamExt};
-use rusty_leveldb::{DB, Options};
+use async_trait::async_trait;
+use std::time::Instant;
 
-use crate::{DBOpIn, DBOpOut, MpcStorageConfig, MpcStorageError};
+use futures::{channel::{mpsc, oneshot}, FutureExt, SinkExt, StreamExt};
+use futures_timer::Delay;
+use rusty_leveldb::{DB, LdbIterator, Options, WriteBatch as LevelDbBatch};
+
+use crate::{DBOpIn, DBOpOut, DbServer, Durability, MpcStorage, MpcStorageConfig, MpcStorageError, Spawner};
+use crate::storage::{collect_page, scan_start};
+use crate::types::{BatchOp, Namespace, ScanPage, WriteBatch};
+
+/// Key in [Namespace::System] marking a database whose records are namespaced
+const LAYOUT_KEY: &[u8] = b"layout";
+const LAYOUT_VERSION: u8 = 1;
 
 pub fn default_mpc_storage_opt(
     db_name_or_path: String,
@@ -18,9 +28,13 @@ pub fn default_mpc_storage_opt(
     )
 }
 
-pub fn run_db_server(
-    mut config: MpcStorageConfig
-) {
+/// Opens the database of `config`, moving records written before namespaces into its legacy
+/// namespace, and returns the server of its pending ops
+///
+/// Fails if the database can't be opened, e.g. it is locked by another process or corrupt. The
+/// server runs until shut down, or until every client is dropped, serving the ops already queued
+/// before it flushes and closes the database.
+pub fn open_db_server(config: MpcStorageConfig) -> Result<DbServer, MpcStorageError> {
     let opt = {
         match config.is_in_memory() {
             false => Options::default(),
@@ -28,69 +42,361 @@ pub fn run_db_server(
         }
     };
 
-    // TODO: this unwrap is not correct
     let mut db = DB::open(config.db_name_or_path(), opt)
-        .map_err(|_| MpcStorageError::FailToOpenDB)
-        .unwrap();
+        .map_err(|e| {
+            log::error!("Failed to open {} {:?}", config.db_name_or_path(), e);
+            MpcStorageError::FailToOpenDB
+        })?;
+    migrate_layout(&mut db, config.legacy_namespace())?;
+
+    Ok(serve(db, config).boxed())
+}
+
+async fn serve(mut db: DB, mut config: MpcStorageConfig) {
+    let durability = config.durability();
+    let mut unflushed = Unflushed::default();
+    let shutdown = loop {
+        let db_op_in = match unflushed.deadline {
+            Some(deadline) => {
+                let group_commit = Delay::new(deadline.saturating_duration_since(Instant::now())).fuse();
+                futures::pin_mut!(group_commit);
+                futures::select! {
+                    db_op_in = config.db_pending_ops().next() => db_op_in,
+                    _ = group_commit => {
+                        // the writers of the group are told as well
+                        if let Err(e) = unflushed.commit(&mut db) {
+                            log::error!("Failed to flush the DB {:?}", e);
+                        }
+                        continue;
+                    },
+                }
+            },
+            None => config.db_pending_ops().next().await,
+        };
 
-    async_std::task::spawn(async move {
-        let mut graceful_terminate = false;
-        loop {
-            if graceful_terminate {
+        let Some(db_op_in) = db_op_in else {
+            // every client is gone
+            break vec![];
+        };
+        if let Some(result_sender) = serve_op(&mut db, durability, &mut unflushed, db_op_in) {
+            break vec![result_sender];
+        }
+        // the ops queued meanwhile are served before the writes so far are flushed, so reads
+        // don't wait for the flushes of the writes ahead of them
+        let mut shutdown = None;
+        while let Ok(Some(db_op_in)) = config.db_pending_ops().try_next() {
+            shutdown = serve_op(&mut db, durability, &mut unflushed, db_op_in);
+            if shutdown.is_some() {
                 break;
             }
-            let db_opt_in = config.db_pending_ops().select_next_some().await;
-            match db_opt_in {
-                DBOpIn::WriteToDB { key, value, result_sender } => {
-                    let status = db.put(&key[..], &value[..])
-                        .map_err(|_| MpcStorageError::FailToWriteDB);
-                    
-                    let flush_status = db.flush()
-                        .map_err(|_| MpcStorageError::FailToFlushDB);
-
-                    result_sender
-                        .send(DBOpOut::WriteToDB { status: status.and(flush_status) })
-                        .expect("db out receiver should not been dropped")
-                },
-                DBOpIn::ReadFromDB { key, result_sender } => {
-                    let v = db.get(&key);
-                    let status = match v {
-                        Some(v) => Ok(v),
-                        None => Err(MpcStorageError::KeyNotInDB)
-                    };
-                    result_sender
-                        .send(DBOpOut::ReadFromDB { status })
-                        .expect("db out receiver should not been dropped")
-                },
-                DBOpIn::DeleteFromDB { key, result_sender } => {
-                    let status = db.delete(&key)
-                        .map_err(|_| MpcStorageError::KeyNotInDB);
-                    result_sender
-                        .send(DBOpOut::DeleteFromDB { status })
-                        .expect("db out receiver should not been dropped")
-                },
-                DBOpIn::Shutdown { result_sender } => {
-                    let flush_status = db.flush()
-                        .map_err(|_| MpcStorageError::FailToFlushDB);
-                    let shutdown_status = db.close()
-                        .map_err(|_| MpcStorageError::FailToCloseDB);
-    
-                    // TODO: make sure no err before shutdown
-                    graceful_terminate = true;
-                    result_sender
-                        .send(DBOpOut::Shutdown { status: flush_status.and(shutdown_status) })
-                        .expect("db out receiver should not been dropped")
-                },
-                DBOpIn::ForceFlush { result_sender } => {
-                    let status = db.flush()
-                        .map_err(|_| MpcStorageError::FailToFlushDB);
-                    result_sender
-                        .send(DBOpOut::ForceFlush { status })
-                        .expect("db out receiver should not been dropped")
-                },
+        }
+        if unflushed.due {
+            if let Err(e) = unflushed.commit(&mut db) {
+                log::error!("Failed to flush the DB {:?}", e);
             }
         }
-    });
+        if let Some(result_sender) = shutdown {
+            break vec![result_sender];
+        }
+    };
+    shutdown_db(db, config, unflushed, shutdown);
+}
+
+/// Serves the ops queued so far, then flushes and closes the database
+fn shutdown_db(
+    mut db: DB,
+    mut config: MpcStorageConfig,
+    mut unflushed: Unflushed,
+    mut shutdown: Vec<oneshot::Sender<DBOpOut>>,
+) {
+    let durability = config.durability();
+    let pending_ops = config.db_pending_ops();
+    pending_ops.close();
+    while let Ok(Some(db_op_in)) = pending_ops.try_next() {
+        shutdown.extend(serve_op(&mut db, durability, &mut unflushed, db_op_in));
+    }
+
+    let flush_status = unflushed.commit(&mut db);
+    let close_status = db.close()
+        .map_err(|_| MpcStorageError::FailToCloseDB);
+    let status = flush_status.and(close_status);
+    if let Err(e) = &status {
+        log::error!("Failed to close the DB {:?}", e);
+    }
+    for result_sender in shutdown {
+        let _ = result_sender.send(DBOpOut::Shutdown { status: status.clone() });
+    }
+}
+
+/// Serves everything but a shutdown, whose reply channel is handed back
+fn serve_op(
+    db: &mut DB,
+    durability: Durability,
+    unflushed: &mut Unflushed,
+    db_op_in: DBOpIn,
+) -> Option<oneshot::Sender<DBOpOut>> {
+    match db_op_in {
+        DBOpIn::WriteToDB { namespace, key, value, result_sender } => {
+            let status = db.put(&namespace.key(&key), &value[..])
+                .map_err(|_| MpcStorageError::FailToWriteDB);
+            unflushed.acknowledge(db, durability, false, status, result_sender, |status| DBOpOut::WriteToDB { status });
+        },
+        DBOpIn::ReadFromDB { namespace, key, result_sender } => {
+            let v = db.get(&namespace.key(&key));
+            let status = match v {
+                Some(v) => Ok(v),
+                None => Err(MpcStorageError::KeyNotInDB)
+            };
+            result_sender
+                .send(DBOpOut::ReadFromDB { status })
+                .expect("db out receiver should not been dropped")
+        },
+        DBOpIn::DeleteFromDB { namespace, key, result_sender } => {
+            let status = db.delete(&namespace.key(&key))
+                .map_err(|_| MpcStorageError::FailToDeleteDB);
+            unflushed.acknowledge(db, durability, false, status, result_sender, |status| DBOpOut::DeleteFromDB { status });
+        },
+        DBOpIn::WriteBatch { batch, result_sender } => {
+            let status = db.write(leveldb_batch(batch), false)
+                .map_err(|_| MpcStorageError::FailToWriteDB);
+            unflushed.acknowledge(db, durability, true, status, result_sender, |status| DBOpOut::WriteBatch { status });
+        },
+        DBOpIn::Scan { namespace, prefix, limit, cursor, result_sender } => {
+            let status = scan(db, namespace, &prefix, limit, cursor.as_deref());
+            result_sender
+                .send(DBOpOut::Scan { status })
+                .expect("db out receiver should not been dropped")
+        },
+        DBOpIn::Shutdown { result_sender } => return Some(result_sender),
+        DBOpIn::ForceFlush { result_sender } => {
+            let status = unflushed.commit(db);
+            result_sender
+                .send(DBOpOut::ForceFlush { status })
+                .expect("db out receiver should not been dropped")
+        },
+    }
+    None
+}
+
+/// Builds the reply to a write from its status
+type Reply = fn(Result<(), MpcStorageError>) -> DBOpOut;
+
+/// Writes applied to the DB but not flushed yet, they are acknowledged with the flush
+#[derive(Default)]
+struct Unflushed {
+    /// Under group commit, when the writes are flushed at the latest
+    deadline: Option<Instant>,
+    /// A write asks for a flush once the ops queued so far are served
+    due: bool,
+    acks: Vec<(oneshot::Sender<DBOpOut>, Reply)>,
+}
+
+impl Unflushed {
+    /// Acknowledges an applied write once it is as durable as `durability` asks for
+    fn acknowledge(
+        &mut self,
+        db: &mut DB,
+        durability: Durability,
+        is_batch: bool,
+        status: Result<(), MpcStorageError>,
+        result_sender: oneshot::Sender<DBOpOut>,
+        out: Reply,
+    ) {
+        let status = match (status, durability) {
+            (Err(e), _) => Err(e),
+            (Ok(()), Durability::PerBatch) if !is_batch => Ok(()),
+            (Ok(()), Durability::EveryWrite | Durability::PerBatch) => {
+                self.due = true;
+                self.acks.push((result_sender, out));
+                return;
+            },
+            (Ok(()), Durability::GroupCommit { interval }) => {
+                self.deadline.get_or_insert_with(|| Instant::now() + interval);
+                self.acks.push((result_sender, out));
+                return;
+            },
+        };
+        result_sender
+            .send(out(status))
+            .expect("db out receiver should not been dropped")
+    }
+
+    /// Flushes the DB and acknowledges the writes waiting for it
+    fn commit(&mut self, db: &mut DB) -> Result<(), MpcStorageError> {
+        let status = db.flush()
+            .map_err(|_| MpcStorageError::FailToFlushDB);
+        self.deadline = None;
+        self.due = false;
+        for (result_sender, out) in self.acks.drain(..) {
+            // the writer may have given up waiting, the write stands anyway
+            let _ = result_sender.send(out(status.clone()));
+        }
+        status
+    }
+}
+
+fn leveldb_batch(batch: WriteBatch) -> LevelDbBatch {
+    let mut leveldb_batch = LevelDbBatch::new();
+    for op in batch.into_ops() {
+        match op {
+            BatchOp::Put { namespace, key, value } => leveldb_batch.put(&namespace.key(&key), &value),
+            BatchOp::Delete { namespace, key } => leveldb_batch.delete(&namespace.key(&key)),
+        }
+    }
+    leveldb_batch
+}
+
+/// Moves the records of a database written before namespaces, all under 32 byte keys, into
+/// `legacy_namespace` in one batch
+fn migrate_layout(db: &mut DB, legacy_namespace: Namespace) -> Result<(), MpcStorageError> {
+    let layout_key = Namespace::System.key(LAYOUT_KEY);
+    if db.get(&layout_key).is_some() {
+        return Ok(());
+    }
+
+    let mut batch = LevelDbBatch::new();
+    let mut moved = 0;
+    let mut iter = db.new_iter()
+        .map_err(|_| MpcStorageError::FailToReadDB)?;
+    let (mut key, mut value) = (vec![], vec![]);
+    while iter.advance() {
+        iter.current(&mut key, &mut value);
+        batch.put(&legacy_namespace.key(&key), &value);
+        batch.delete(&key);
+        moved += 1;
+    }
+    batch.put(&layout_key, &[LAYOUT_VERSION]);
+    db.write(batch, true)
+        .map_err(|_| MpcStorageError::FailToWriteDB)?;
+
+    if moved > 0 {
+        log::info!("Moved {} records of an unnamespaced database into {:?}", moved, legacy_namespace);
+    }
+    Ok(())
+}
+
+fn scan(
+    db: &mut DB,
+    namespace: Namespace,
+    prefix: &[u8],
+    limit: usize,
+    cursor: Option<&[u8]>,
+) -> Result<ScanPage, MpcStorageError> {
+    let (start, exclusive) = scan_start(namespace, prefix, cursor);
+    let mut iter = db.new_iter()
+        .map_err(|_| MpcStorageError::FailToReadDB)?;
+    iter.seek(&start);
+
+    let records = std::iter::from_fn(|| {
+        if !iter.valid() {
+            return None;
+        }
+        let (mut key, mut value) = (vec![], vec![]);
+        iter.current(&mut key, &mut value);
+        iter.advance();
+        Some((key, value))
+    }).skip_while(|(key, _)| exclusive && *key == start);
+    Ok(collect_page(namespace, prefix, records, limit))
+}
+
+/// [MpcStorage] served by a LevelDB server task, see [open_db_server]
+#[derive(Clone)]
+pub struct LevelDbStorage {
+    db_in: mpsc::Sender<DBOpIn>,
+}
+
+impl LevelDbStorage {
+    /// Opens the database and runs its server with `spawn`
+    pub fn open(db_name_or_path: String, in_memory: bool, spawn: Spawner) -> Result<Self, MpcStorageError> {
+        let (config, db_in) = default_mpc_storage_opt(db_name_or_path, in_memory);
+        spawn(open_db_server(config)?);
+        Ok(Self { db_in })
+    }
+
+    /// Client of an already running server
+    pub fn new(db_in: mpsc::Sender<DBOpIn>) -> Self {
+        Self { db_in }
+    }
+
+    async fn request(
+        &self,
+        op: impl FnOnce(oneshot::Sender<DBOpOut>) -> DBOpIn + Send,
+    ) -> Result<DBOpOut, MpcStorageError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.db_in
+            .clone()
+            .send(op(result_sender))
+            .await
+            .map_err(|_| MpcStorageError::DBClosed)?;
+        result_receiver
+            .await
+            .map_err(|_| MpcStorageError::DBClosed)
+    }
+}
+
+#[async_trait]
+impl MpcStorage for LevelDbStorage {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        let key = key.to_vec();
+        match self.request(|result_sender| DBOpIn::WriteToDB { namespace, key, value, result_sender }).await? {
+            DBOpOut::WriteToDB { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        let key = key.to_vec();
+        match self.request(|result_sender| DBOpIn::ReadFromDB { namespace, key, result_sender }).await? {
+            DBOpOut::ReadFromDB { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        let key = key.to_vec();
+        match self.request(|result_sender| DBOpIn::DeleteFromDB { namespace, key, result_sender }).await? {
+            DBOpOut::DeleteFromDB { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        match self.request(|result_sender| DBOpIn::WriteBatch { batch, result_sender }).await? {
+            DBOpOut::WriteBatch { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        let prefix = prefix.to_vec();
+        match self.request(|result_sender| DBOpIn::Scan { namespace, prefix, limit, cursor, result_sender }).await? {
+            DBOpOut::Scan { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        match self.request(|result_sender| DBOpIn::ForceFlush { result_sender }).await? {
+            DBOpOut::ForceFlush { status } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        match self.request(|result_sender| DBOpIn::Shutdown { result_sender }).await {
+            Ok(DBOpOut::Shutdown { status }) => status,
+            // already shut down
+            Err(MpcStorageError::DBClosed) => Ok(()),
+            Err(e) => Err(e),
+            _ => unreachable!(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -98,18 +404,22 @@ mod test {
     use super::*;
     use futures::SinkExt;
     use futures::channel::oneshot;
-    
+
+    fn spawn(server: DbServer) {
+        async_std::task::spawn(server);
+    }
+
     #[async_std::test]
     async fn in_memory() {
         let (config, mut in_pipe) = default_mpc_storage_opt("in_memory".to_string(), true);
-        // async_std::task::spawn();
-        run_db_server(config);
+        spawn(open_db_server(config).unwrap());
     
         { 
             let (i, o) = oneshot::channel();
             in_pipe
                 .send(DBOpIn::WriteToDB {
-                    key: [0u8; 32],
+                    namespace: Namespace::Shards,
+                    key: vec![0u8; 32],
                     value: vec![1, 2, 3],
                     result_sender: i,
                 })
@@ -122,7 +432,8 @@ mod test {
         {
             let (i, o) = oneshot::channel();
             in_pipe.send(DBOpIn::WriteToDB {
-                key: [1u8; 32],
+                namespace: Namespace::Shards,
+                key: vec![1u8; 32],
                 value: vec![4, 5, 6],
                 result_sender: i,
             })
@@ -135,7 +446,8 @@ mod test {
         {
             let (i, o) = oneshot::channel();
             in_pipe.send(DBOpIn::ReadFromDB {
-                key: [0u8; 32],
+                namespace: Namespace::Shards,
+                key: vec![0u8; 32],
                 result_sender: i,
             })
                 .await
@@ -161,13 +473,14 @@ mod test {
         // Run #1
         {
             let (config, mut in_pipe) = default_mpc_storage_opt("mock".to_string(), false);
-            run_db_server(config);
+            spawn(open_db_server(config).unwrap());
 
             { 
                 let (i, o) = oneshot::channel();
                 in_pipe
                     .send(DBOpIn::WriteToDB {
-                        key: [0u8; 32],
+                        namespace: Namespace::Shards,
+                        key: vec![0u8; 32],
                         value: vec![1, 2, 3],
                         result_sender: i,
                     })
@@ -180,7 +493,8 @@ mod test {
             {
                 let (i, o) = oneshot::channel();
                 in_pipe.send(DBOpIn::WriteToDB {
-                    key: [1u8; 32],
+                    namespace: Namespace::Shards,
+                    key: vec![1u8; 32],
                     value: vec![4, 5, 6],
                     result_sender: i,
                 })
@@ -193,7 +507,8 @@ mod test {
             {
                 let (i, o) = oneshot::channel();
                 in_pipe.send(DBOpIn::ReadFromDB {
-                    key: [0u8; 32],
+                    namespace: Namespace::Shards,
+                    key: vec![0u8; 32],
                     result_sender: i,
                 })
                     .await
@@ -216,11 +531,12 @@ mod test {
 
         {
             let (config, mut in_pipe) = default_mpc_storage_opt("mock".to_string(), false);
-            run_db_server(config);
+            spawn(open_db_server(config).unwrap());
             {
                 let (i, o) = oneshot::channel();
                 in_pipe.send(DBOpIn::ReadFromDB {
-                    key: [0u8; 32],
+                    namespace: Namespace::Shards,
+                    key: vec![0u8; 32],
                     result_sender: i,
                 })
                     .await
@@ -241,4 +557,141 @@ mod test {
             }
         }
     }
+
+    #[async_std::test]
+    async fn storage_trait() {
+        let db = LevelDbStorage::open("in_memory".to_string(), true, spawn).unwrap();
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![1, 2, 3]));
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::KeyNotInDB));
+
+        db.close().await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Err(MpcStorageError::DBClosed));
+    }
+
+    #[async_std::test]
+    async fn scan_pages() {
+        let db = LevelDbStorage::open("in_memory".to_string(), true, spawn).unwrap();
+        for key in [&b"ga/1"[..], b"ga/2", b"ga/3", b"oauth/1"] {
+            db.put(Namespace::Auth, key, key.to_vec()).await.unwrap();
+        }
+        db.put(Namespace::Shards, &[0u8; 32], vec![1]).await.unwrap();
+
+        let first = db.scan(Namespace::Auth, b"ga/", 2, None).await.unwrap();
+        assert_eq!(first.keys().collect::<Vec<_>>(), vec![&b"ga/1"[..], b"ga/2"]);
+        let second = db.scan(Namespace::Auth, b"ga/", 2, first.cursor).await.unwrap();
+        assert_eq!(second.keys().collect::<Vec<_>>(), vec![&b"ga/3"[..]]);
+        assert_eq!(second.cursor, None);
+        assert_eq!(db.scan(Namespace::Auth, b"", 10, None).await.unwrap().entries.len(), 4);
+    }
+
+    #[async_std::test]
+    async fn migrates_unnamespaced_records() {
+        let path = std::env::temp_dir().join(format!("skw-mpc-storage-layout-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        {
+            let mut db = DB::open(&path, Options::default()).unwrap();
+            db.put(&[7u8; 32], &[1, 2, 3]).unwrap();
+            db.close().unwrap();
+        }
+
+        let (config, db_in) = default_mpc_storage_opt(path.clone(), false);
+        spawn(open_db_server(config.with_legacy_namespace(Namespace::Auth)).unwrap());
+        let db = LevelDbStorage::new(db_in);
+        assert_eq!(db.get(Namespace::Auth, &[7u8; 32]).await, Ok(vec![1, 2, 3]));
+        assert_eq!(db.scan(Namespace::Shards, b"", 10, None).await.unwrap().entries, vec![]);
+        db.close().await.unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn write_batch() {
+        let db = LevelDbStorage::open("in_memory".to_string(), true, spawn).unwrap();
+        db.put(Namespace::Presignatures, b"a", vec![1]).await.unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch
+            .delete(Namespace::Presignatures, b"a")
+            .put(Namespace::Presignatures, b"b", vec![2])
+            .put(Namespace::Journal, b"b", vec![3]);
+        db.write_batch(batch).await.unwrap();
+
+        assert_eq!(db.get(Namespace::Presignatures, b"a").await, Err(MpcStorageError::KeyNotInDB));
+        assert_eq!(db.get(Namespace::Presignatures, b"b").await, Ok(vec![2]));
+        assert_eq!(db.get(Namespace::Journal, b"b").await, Ok(vec![3]));
+    }
+
+    #[async_std::test]
+    async fn group_commit() {
+        let (config, db_in) = default_mpc_storage_opt("in_memory".to_string(), true);
+        spawn(open_db_server(config.with_durability(Durability::GroupCommit { interval: std::time::Duration::from_millis(50) })).unwrap());
+        let db = LevelDbStorage::new(db_in);
+
+        let writer = db.clone();
+        let write = async_std::task::spawn(async move {
+            writer.put(Namespace::Journal, b"a", vec![1]).await
+        });
+        // the write is visible before its group is flushed
+        async_std::task::sleep(std::time::Duration::from_millis(10)).await;
+        assert_eq!(db.get(Namespace::Journal, b"a").await, Ok(vec![1]));
+        assert_eq!(write.await, Ok(()));
+        db.close().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn queued_ops_share_a_flush() {
+        let db = LevelDbStorage::open("in_memory".to_string(), true, spawn).unwrap();
+        db.put(Namespace::Journal, b"a", vec![0]).await.unwrap();
+
+        // puts and gets queued together share flushes, each get sees one of the puts
+        let ops = (1..=4u8).map(|i| {
+            let db = db.clone();
+            async move {
+                db.put(Namespace::Journal, b"a", vec![i]).await.unwrap();
+                db.get(Namespace::Journal, b"a").await.unwrap()
+            }
+        });
+        for value in futures::future::join_all(ops).await {
+            assert!((1..=4).contains(&value[0]));
+        }
+        db.close().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn locked_db_fails_to_open() {
+        let path = std::env::temp_dir().join(format!("skw-mpc-storage-lock-{}", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+
+        let db = LevelDbStorage::open(path.clone(), false, spawn).unwrap();
+        let (config, _) = default_mpc_storage_opt(path.clone(), false);
+        assert_eq!(open_db_server(config).err(), Some(MpcStorageError::FailToOpenDB));
+
+        db.close().await.unwrap();
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[async_std::test]
+    async fn shutdown_drains_pending_ops() {
+        let (config, db_in) = default_mpc_storage_opt("in_memory".to_string(), true);
+        let server = open_db_server(config).unwrap();
+
+        // queued before the server runs, the write after the shutdown
+        let (shutdown_sender, shutdown) = oneshot::channel();
+        db_in.clone().try_send(DBOpIn::Shutdown { result_sender: shutdown_sender }).unwrap();
+        let (write_sender, write) = oneshot::channel();
+        db_in.clone()
+            .try_send(DBOpIn::WriteToDB {
+                namespace: Namespace::Journal,
+                key: b"a".to_vec(),
+                value: vec![1],
+                result_sender: write_sender,
+            })
+            .unwrap();
+        spawn(server);
+
+        assert!(matches!(write.await, Ok(DBOpOut::WriteToDB { status: Ok(()) })));
+        assert!(matches!(shutdown.await, Ok(DBOpOut::Shutdown { status: Ok(()) })));
+        let db = LevelDbStorage::new(db_in);
+        assert_eq!(db.get(Namespace::Journal, b"a").await, Err(MpcStorageError::DBClosed));
+    }
 }
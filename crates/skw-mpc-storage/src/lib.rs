@@ -1,12 +1,36 @@
 pub mod db;
 pub mod types;
+pub mod storage;
+pub mod memory;
+pub mod file;
+pub mod versions;
+pub mod expiry;
+pub mod schema;
 
 #[cfg(feature = "leveldb-backend")]
 pub mod leveldb;
 
+#[cfg(feature = "encryption")]
+pub mod encryption;
+
+#[cfg(feature = "encryption")]
+pub mod backup;
+
 #[cfg(feature = "leveldb-backend")]
-pub use leveldb::{default_mpc_storage_opt, run_db_server};
+pub use leveldb::{default_mpc_storage_opt, open_db_server, LevelDbStorage};
+
+#[cfg(feature = "encryption")]
+pub use encryption::{EncryptedStorage, MasterKeySource};
+
+#[cfg(feature = "encryption")]
+pub use backup::{Backup, BackupRecord};
 
 // re-export
-pub use db::{DBOpIn, DBOpOut, MpcStorageConfig};
-pub use types::{MpcStorageError};
\ No newline at end of file
+pub use db::{DBOpIn, DBOpOut, DbServer, Durability, MpcStorageConfig, Spawner};
+pub use types::{MpcStorageError, CryptoHash, Namespace, ScanPage, WriteBatch, BatchOp};
+pub use storage::{MpcStorage, StorageBackend};
+pub use memory::InMemoryStorage;
+pub use file::FileStorage;
+pub use versions::{ShardStore, ShardState, ShardVersion};
+pub use expiry::{ExpiringStorage, Sweeper};
+pub use schema::{MigratingStorage, Schema, Upgrade};
\ No newline at end of file
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::storage::{scan_map, MpcStorage};
+use crate::types::{BatchOp, MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+/// Keeps everything in a map - lost when dropped
+#[derive(Debug)]
+pub struct InMemoryStorage {
+    // `None` once closed
+    db: Mutex<Option<BTreeMap<Vec<u8>, Vec<u8>>>>,
+}
+
+impl InMemoryStorage {
+    fn with_db<T>(
+        &self,
+        op: impl FnOnce(&mut BTreeMap<Vec<u8>, Vec<u8>>) -> Result<T, MpcStorageError>,
+    ) -> Result<T, MpcStorageError> {
+        let mut db = self.db.lock().expect("storage lock not to be poisoned");
+        match db.as_mut() {
+            Some(db) => op(db),
+            None => Err(MpcStorageError::DBClosed),
+        }
+    }
+}
+
+impl Default for InMemoryStorage {
+    fn default() -> Self {
+        Self { db: Mutex::new(Some(BTreeMap::new())) }
+    }
+}
+
+#[async_trait]
+impl MpcStorage for InMemoryStorage {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        self.with_db(|db| {
+            db.insert(namespace.key(key), value);
+            Ok(())
+        })
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        self.with_db(|db| db.get(&namespace.key(key)).cloned().ok_or(MpcStorageError::KeyNotInDB))
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        self.with_db(|db| {
+            db.remove(&namespace.key(key));
+            Ok(())
+        })
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        self.with_db(|db| {
+            for op in batch.into_ops() {
+                match op {
+                    BatchOp::Put { namespace, key, value } => { db.insert(namespace.key(&key), value); },
+                    BatchOp::Delete { namespace, key } => { db.remove(&namespace.key(&key)); },
+                }
+            }
+            Ok(())
+        })
+    }
+
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        self.with_db(|db| Ok(scan_map(db, namespace, prefix, limit, cursor.as_deref())))
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        self.with_db(|_| Ok(()))
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        self.db.lock().expect("storage lock not to be poisoned").take();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn put_get_delete() {
+        let db = InMemoryStorage::default();
+        db.put(Namespace::Shards, &[0u8; 32], vec![1, 2, 3]).await.unwrap();
+        db.put(Namespace::Shards, &[1u8; 32], vec![4, 5, 6]).await.unwrap();
+        db.put(Namespace::Shards, &[0u8; 32], vec![7]).await.unwrap();
+
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(vec![7]));
+        db.delete(Namespace::Shards, &[1u8; 32]).await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[1u8; 32]).await, Err(MpcStorageError::KeyNotInDB));
+
+        db.close().await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Err(MpcStorageError::DBClosed));
+    }
+
+    #[async_std::test]
+    async fn namespaces_and_scan() {
+        let db = InMemoryStorage::default();
+        db.put(Namespace::Shards, b"a", vec![0]).await.unwrap();
+        db.put(Namespace::Metadata, b"a", vec![1]).await.unwrap();
+        assert_eq!(db.get(Namespace::Shards, b"a").await, Ok(vec![0]));
+        assert_eq!(db.get(Namespace::Auth, b"a").await, Err(MpcStorageError::KeyNotInDB));
+
+        for key in [&b"ga/1"[..], b"ga/2", b"ga/3", b"oauth/1"] {
+            db.put(Namespace::Auth, key, key.to_vec()).await.unwrap();
+        }
+
+        let first = db.scan(Namespace::Auth, b"ga/", 2, None).await.unwrap();
+        assert_eq!(first.keys().collect::<Vec<_>>(), vec![&b"ga/1"[..], b"ga/2"]);
+        let second = db.scan(Namespace::Auth, b"ga/", 2, first.cursor).await.unwrap();
+        assert_eq!(second.keys().collect::<Vec<_>>(), vec![&b"ga/3"[..]]);
+        assert_eq!(second.cursor, None);
+
+        let all = db.scan(Namespace::Auth, b"", 10, None).await.unwrap();
+        assert_eq!(all.entries.len(), 4);
+        // other namespaces are not part of the scan
+        assert_eq!(db.scan(Namespace::Shards, b"", 10, None).await.unwrap().entries, vec![(b"a".to_vec(), vec![0])]);
+    }
+}
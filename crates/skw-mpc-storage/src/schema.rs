@@ -0,0 +1,295 @@
+//! Versioned records and their migrations
+//!
+//! A [Schema] gives some namespaces a schema version and the migrations that upgrade a record
+//! from one version to the next. [MigratingStorage] stores the records of those namespaces
+//! behind a header with their version and hands out bodies upgraded to the current version:
+//! a record of an older version is migrated, and written back, the first time it is read.
+//! [MigratingStorage::migrate] upgrades a whole store at once, e.g. from an offline command.
+//!
+//! Records of namespaces without a version are stored as they are.
+//!
+//! ## Format
+//! `"SKWR" | schema version (u16 LE) | body`. A record without the header was written before
+//! versioning and is taken as version 0, which upgrades to version 1 unchanged.
+
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use futures::lock::Mutex;
+
+use crate::storage::MpcStorage;
+use crate::types::{BatchOp, MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+const MAGIC: &[u8; 4] = b"SKWR";
+const HEADER_LEN: usize = MAGIC.len() + 2;
+
+const MIGRATE_PAGE: usize = 256;
+
+/// Upgrades the body of a record by one version
+pub type Upgrade = fn(&[u8]) -> Result<Vec<u8>, MpcStorageError>;
+
+/// Versions of the records of a store. Namespaces start at version 1, each migration adds one.
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    // per namespace, the upgrade from version `i + 1` to `i + 2` at index `i`
+    migrations: BTreeMap<Namespace, Vec<Upgrade>>,
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Versions the records of `namespace`, at version 1
+    pub fn with_namespace(mut self, namespace: Namespace) -> Self {
+        self.migrations.entry(namespace).or_default();
+        self
+    }
+
+    /// Adds the migration of `namespace` from version `from` to `from + 1`. Migrations of a
+    /// namespace are added in order, starting from version 1.
+    pub fn with_migration(mut self, namespace: Namespace, from: u16, upgrade: Upgrade) -> Self {
+        let migrations = self.migrations.entry(namespace).or_default();
+        assert_eq!(
+            usize::from(from), migrations.len() + 1,
+            "migrations of {:?} must be added in order", namespace
+        );
+        migrations.push(upgrade);
+        self
+    }
+
+    pub fn namespaces(&self) -> impl Iterator<Item = Namespace> + '_ {
+        self.migrations.keys().copied()
+    }
+
+    /// `None` for a namespace without a version
+    pub fn version(&self, namespace: Namespace) -> Option<u16> {
+        self.migrations
+            .get(&namespace)
+            .map(|migrations| migrations.len() as u16 + 1)
+    }
+
+    /// `body` as a record of the current version of `namespace`
+    pub fn encode(&self, namespace: Namespace, body: Vec<u8>) -> Vec<u8> {
+        match self.version(namespace) {
+            Some(version) => encode_record(version, &body),
+            None => body,
+        }
+    }
+
+    /// The body of `record` upgraded to the current version of `namespace`, and the version it
+    /// was stored at
+    pub fn decode(&self, namespace: Namespace, record: &[u8]) -> Result<(Vec<u8>, u16), MpcStorageError> {
+        let migrations = match self.migrations.get(&namespace) {
+            Some(migrations) => migrations,
+            None => return Ok((record.to_vec(), 0)),
+        };
+        let current = migrations.len() as u16 + 1;
+
+        let (stored, body) = decode_record(record);
+        if stored > current {
+            // written by a newer release
+            return Err(MpcStorageError::UnknownSchemaVersion(stored));
+        }
+        let mut body = body.to_vec();
+        for upgrade in &migrations[usize::from(stored.max(1)) - 1..] {
+            body = upgrade(&body)?;
+        }
+        Ok((body, stored))
+    }
+}
+
+fn encode_record(version: u16, body: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LEN + body.len());
+    record.extend_from_slice(MAGIC);
+    record.extend_from_slice(&version.to_le_bytes());
+    record.extend_from_slice(body);
+    record
+}
+
+fn decode_record(record: &[u8]) -> (u16, &[u8]) {
+    match record.strip_prefix(MAGIC) {
+        Some([lo, hi, body @ ..]) => (u16::from_le_bytes([*lo, *hi]), body),
+        _ => (0, record),
+    }
+}
+
+/// [MpcStorage] that keeps the records of the wrapped store `S` at the versions of a [Schema]
+pub struct MigratingStorage<S> {
+    inner: S,
+    schema: Schema,
+    // serializes writes with the read-upgrade-write back of a record
+    update: Mutex<()>,
+}
+
+impl<S: MpcStorage> MigratingStorage<S> {
+    pub fn new(inner: S, schema: Schema) -> Self {
+        Self { inner, schema, update: Mutex::new(()) }
+    }
+
+    /// The records as stored, behind their header
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Upgrades every record of an older version, returns how many were upgraded
+    pub async fn migrate(&self) -> Result<usize, MpcStorageError> {
+        let mut migrated = 0;
+        for namespace in self.schema.namespaces() {
+            let current = self.schema.version(namespace);
+            let mut cursor = None;
+            loop {
+                let _guard = self.update.lock().await;
+                let page = self.inner.scan(namespace, b"", MIGRATE_PAGE, cursor).await?;
+
+                let mut batch = WriteBatch::new();
+                for (key, record) in &page.entries {
+                    let (body, stored) = self.schema.decode(namespace, record)?;
+                    if Some(stored) != current {
+                        batch.put(namespace, key, self.schema.encode(namespace, body));
+                    }
+                }
+                if !batch.is_empty() {
+                    migrated += batch.len();
+                    self.inner.write_batch(batch).await?;
+                }
+
+                cursor = match page.cursor {
+                    Some(cursor) => Some(cursor),
+                    None => break,
+                };
+            }
+        }
+        Ok(migrated)
+    }
+}
+
+#[async_trait]
+impl<S: MpcStorage> MpcStorage for MigratingStorage<S> {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        let _guard = self.update.lock().await;
+        self.inner.put(namespace, key, self.schema.encode(namespace, value)).await
+    }
+
+    /// Writes an upgraded record back, so it is migrated only once
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        let _guard = self.update.lock().await;
+        let (body, stored) = self.schema.decode(namespace, &self.inner.get(namespace, key).await?)?;
+        if self.schema.version(namespace).map_or(false, |current| current != stored) {
+            log::debug!("Migrating record of {:?} from version {}", namespace, stored);
+            self.inner.put(namespace, key, self.schema.encode(namespace, body.clone())).await?;
+        }
+        Ok(body)
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        let _guard = self.update.lock().await;
+        self.inner.delete(namespace, key).await
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        let batch = batch
+            .into_ops()
+            .into_iter()
+            .map(|op| match op {
+                BatchOp::Put { namespace, key, value } => {
+                    BatchOp::Put { namespace, key, value: self.schema.encode(namespace, value) }
+                },
+                delete => delete,
+            })
+            .collect();
+
+        let _guard = self.update.lock().await;
+        self.inner.write_batch(batch).await
+    }
+
+    /// Records are upgraded in the page only, [MigratingStorage::migrate] writes them back
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        let page = self.inner.scan(namespace, prefix, limit, cursor).await?;
+        let entries = page.entries
+            .into_iter()
+            .map(|(key, record)| Ok((key, self.schema.decode(namespace, &record)?.0)))
+            .collect::<Result<_, MpcStorageError>>()?;
+        Ok(ScanPage { entries, cursor: page.cursor })
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        self.inner.flush().await
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::memory::InMemoryStorage;
+    use crate::versions::ShardStore;
+
+    fn reversed(body: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        Ok(body.iter().rev().copied().collect())
+    }
+
+    #[async_std::test]
+    async fn reads_records_of_the_current_release() {
+        // written by a release without record headers
+        let db = Arc::new(InMemoryStorage::default());
+        ShardStore::new(db.clone()).put_active([0u8; 32], [1u8; 32], vec![1, 2, 3]).await.unwrap();
+        db.put(Namespace::Shards, &[1u8; 32], b"{\"legacy\":1}".to_vec()).await.unwrap();
+        db.put(Namespace::Metadata, &[0u8; 32], b"{\"t\":1}".to_vec()).await.unwrap();
+        let written = db.get(Namespace::Shards, &[0u8; 32]).await.unwrap();
+
+        let schema = Schema::new()
+            .with_namespace(Namespace::Shards)
+            .with_namespace(Namespace::Metadata);
+        let shards = ShardStore::new(MigratingStorage::new(db.clone(), schema));
+        assert_eq!(shards.active([0u8; 32]).await.unwrap().shard, vec![1, 2, 3]);
+        assert_eq!(shards.active([1u8; 32]).await.unwrap().shard, b"{\"legacy\":1}".to_vec());
+
+        // upgraded on read, the body is unchanged
+        assert_eq!(db.get(Namespace::Shards, &[0u8; 32]).await, Ok(encode_record(1, &written)));
+        // the metadata record is left to migrate
+        assert_eq!(shards.storage().migrate().await, Ok(1));
+        assert_eq!(db.get(Namespace::Metadata, &[0u8; 32]).await, Ok(encode_record(1, b"{\"t\":1}")));
+        assert_eq!(shards.storage().get(Namespace::Metadata, &[0u8; 32]).await, Ok(b"{\"t\":1}".to_vec()));
+    }
+
+    #[async_std::test]
+    async fn migrates_on_read_and_offline() {
+        let db = InMemoryStorage::default();
+        db.put(Namespace::Journal, b"a", vec![1, 2]).await.unwrap();
+        db.put(Namespace::Journal, b"b", encode_record(1, &[3, 4])).await.unwrap();
+        db.put(Namespace::Journal, b"c", encode_record(2, &[5, 6])).await.unwrap();
+        db.put(Namespace::Auth, b"d", vec![7]).await.unwrap();
+
+        let schema = Schema::new().with_migration(Namespace::Journal, 1, reversed);
+        assert_eq!(schema.version(Namespace::Journal), Some(2));
+        let db = MigratingStorage::new(db, schema);
+
+        assert_eq!(db.get(Namespace::Journal, b"a").await, Ok(vec![2, 1]));
+        assert_eq!(db.inner().get(Namespace::Journal, b"a").await, Ok(encode_record(2, &[2, 1])));
+        assert_eq!(db.get(Namespace::Auth, b"d").await, Ok(vec![7]));
+
+        assert_eq!(db.migrate().await, Ok(1));
+        assert_eq!(db.inner().get(Namespace::Journal, b"b").await, Ok(encode_record(2, &[4, 3])));
+        assert_eq!(db.get(Namespace::Journal, b"c").await, Ok(vec![5, 6]));
+        assert_eq!(db.migrate().await, Ok(0));
+
+        db.inner().put(Namespace::Journal, b"e", encode_record(3, &[])).await.unwrap();
+        assert_eq!(db.get(Namespace::Journal, b"e").await, Err(MpcStorageError::UnknownSchemaVersion(3)));
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::db::Spawner;
+use crate::types::{MpcStorageError, Namespace, ScanPage, WriteBatch};
+
+/// Key shard store of a node
+///
+/// Records live in a [Namespace] under keys of any length. Implementations synchronize
+/// internally, so a single instance can be shared by the node event loop and its client requests
+/// behind an `Arc`.
+#[async_trait]
+pub trait MpcStorage: Send + Sync {
+    /// Stores `value` under `key`, replacing any previous value. Durable once it returns, unless
+    /// the store was opened with [Durability::PerBatch](crate::Durability::PerBatch).
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError>;
+
+    /// Errors with `KeyNotInDB` if nothing is stored under `key`
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError>;
+
+    /// Durable once it returns, with the same exception as [put](Self::put)
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError>;
+
+    /// Applies every op of `batch` or none of them
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError>;
+
+    /// Up to `limit` records of `namespace` whose key starts with `prefix`, in key order,
+    /// starting after `cursor` - the cursor of the previous page, if any
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError>;
+
+    async fn flush(&self) -> Result<(), MpcStorageError>;
+
+    /// Flushes and releases the store. Later operations fail with `DBClosed`.
+    async fn close(&self) -> Result<(), MpcStorageError>;
+}
+
+#[async_trait]
+impl<T: MpcStorage + ?Sized> MpcStorage for Arc<T> {
+    async fn put(&self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> Result<(), MpcStorageError> {
+        (**self).put(namespace, key, value).await
+    }
+
+    async fn get(&self, namespace: Namespace, key: &[u8]) -> Result<Vec<u8>, MpcStorageError> {
+        (**self).get(namespace, key).await
+    }
+
+    async fn delete(&self, namespace: Namespace, key: &[u8]) -> Result<(), MpcStorageError> {
+        (**self).delete(namespace, key).await
+    }
+
+    async fn write_batch(&self, batch: WriteBatch) -> Result<(), MpcStorageError> {
+        (**self).write_batch(batch).await
+    }
+
+    async fn scan(
+        &self,
+        namespace: Namespace,
+        prefix: &[u8],
+        limit: usize,
+        cursor: Option<Vec<u8>>,
+    ) -> Result<ScanPage, MpcStorageError> {
+        (**self).scan(namespace, prefix, limit, cursor).await
+    }
+
+    async fn flush(&self) -> Result<(), MpcStorageError> {
+        (**self).flush().await
+    }
+
+    async fn close(&self) -> Result<(), MpcStorageError> {
+        (**self).close().await
+    }
+}
+
+/// Storage backend a node is bootstrapped with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageBackend {
+    /// Volatile map, for tests and in-process committees
+    InMemory,
+    /// LevelDB database at `path`
+    #[cfg(feature = "leveldb-backend")]
+    LevelDb { path: String, durability: crate::db::Durability },
+    /// Append-only log file at `path`
+    File { path: String },
+    /// `backend` with values encrypted at rest, see [EncryptedStorage](crate::encryption::EncryptedStorage)
+    #[cfg(feature = "encryption")]
+    Encrypted {
+        backend: Box<StorageBackend>,
+        master_key: crate::encryption::MasterKeySource,
+    },
+}
+
+impl StorageBackend {
+    /// Opens the store, a LevelDB server is run with `spawn` on the caller's runtime
+    #[cfg_attr(not(any(feature = "leveldb-backend", feature = "encryption")), allow(unused_variables))]
+    pub fn open(&self, spawn: Spawner) -> BoxFuture<'_, Result<Arc<dyn MpcStorage>, MpcStorageError>> {
+        async move {
+            let storage: Arc<dyn MpcStorage> = match self {
+                Self::InMemory => Arc::new(crate::memory::InMemoryStorage::default()),
+                #[cfg(feature = "leveldb-backend")]
+                Self::LevelDb { path, durability } => {
+                    let (config, db_in) = crate::leveldb::default_mpc_storage_opt(path.clone(), false);
+                    spawn(crate::leveldb::open_db_server(config.with_durability(*durability))?);
+                    Arc::new(crate::leveldb::LevelDbStorage::new(db_in))
+                },
+                Self::File { path } => Arc::new(crate::file::FileStorage::open(path)?),
+                #[cfg(feature = "encryption")]
+                Self::Encrypted { backend, master_key } => Arc::new(
+                    crate::encryption::EncryptedStorage::open(backend.open(spawn).await?, master_key).await?
+                ),
+            };
+            Ok(storage)
+        }.boxed()
+    }
+}
+/// Where a scan starts in the underlying keyspace, and whether that key itself is excluded
+pub(crate) fn scan_start(namespace: Namespace, prefix: &[u8], cursor: Option<&[u8]>) -> (Vec<u8>, bool) {
+    let start = namespace.key(prefix);
+    match cursor.map(|cursor| namespace.key(cursor)) {
+        Some(after) if after >= start => (after, true),
+        _ => (start, false),
+    }
+}
+
+/// Pages records of the underlying keyspace, in key order from the scan start, until they leave
+/// `namespace` and `prefix`
+pub(crate) fn collect_page(
+    namespace: Namespace,
+    prefix: &[u8],
+    records: impl Iterator<Item = (Vec<u8>, Vec<u8>)>,
+    limit: usize,
+) -> ScanPage {
+    let limit = limit.max(1);
+    let start = namespace.key(prefix);
+    let mut entries: Vec<_> = records
+        .take_while(|(key, _)| key.starts_with(&start))
+        .map(|(key, value)| (key[1..].to_vec(), value))
+        .take(limit.saturating_add(1))
+        .collect();
+
+    let cursor = match entries.len() > limit {
+        true => {
+            entries.truncate(limit);
+            entries.last().map(|(key, _)| key.clone())
+        },
+        false => None,
+    };
+    ScanPage { entries, cursor }
+}
+
+/// Scan over a sorted map of the underlying keyspace
+pub(crate) fn scan_map(
+    map: &BTreeMap<Vec<u8>, Vec<u8>>,
+    namespace: Namespace,
+    prefix: &[u8],
+    limit: usize,
+    cursor: Option<&[u8]>,
+) -> ScanPage {
+    let (start, exclusive) = scan_start(namespace, prefix, cursor);
+    let lower = match exclusive {
+        true => Bound::Excluded(start),
+        false => Bound::Included(start),
+    };
+    let records = map
+        .range::<Vec<u8>, _>((lower, Bound::Unbounded))
+        .map(|(key, value)| (key.clone(), value.clone()));
+    collect_page(namespace, prefix, records, limit)
+}
@@ -1,10 +1,14 @@
 use serde::{Serialize, Deserialize};
 use thiserror::Error;
 
+pub type CryptoHash = [u8; 32];
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Error)]
 pub enum MpcStorageError {
     #[error("Storage: failed to open DB")]
     FailToOpenDB,
+    #[error("Storage: failed to read from DB")]
+    FailToReadDB,
     #[error("Storage: failed to write to DB")]
     FailToWriteDB,
     #[error("Storage: failed to delete from DB")]
@@ -15,4 +19,132 @@ pub enum MpcStorageError {
     FailToCloseDB,
     #[error("Storage: failed to find key in DB")]
     KeyNotInDB,
-}
\ No newline at end of file
+    #[error("Storage: record has expired")]
+    Expired,
+    #[error("Storage: DB has been closed")]
+    DBClosed,
+    #[error("Storage: failed to load the master key")]
+    FailToLoadMasterKey,
+    #[error("Storage: failed to encrypt record")]
+    FailToEncrypt,
+    #[error("Storage: failed to decrypt record, wrong master key or tampered record")]
+    FailToDecrypt,
+    #[error("Storage: malformed record")]
+    MalformedRecord,
+    #[error("Storage: key shard has no pending version {0}")]
+    VersionNotPending(u64),
+    #[error("Storage: record of unknown schema version {0}, written by a newer release?")]
+    UnknownSchemaVersion(u16),
+}
+
+/// Separate keyspaces of a store. Keys of one namespace never collide with, nor show up in a
+/// scan of, another namespace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Namespace {
+    /// Key shards, by `key_shard_id`
+    Shards,
+    /// Key metadata, by `key_shard_id`
+    Metadata,
+    /// Job journals
+    Journal,
+    /// Presignature pools
+    Presignatures,
+    /// Auth service challenges and OAuth preimages
+    Auth,
+    /// Bookkeeping of the store itself, e.g. the encryption keyring
+    System,
+}
+
+impl Namespace {
+    pub const ALL: [Namespace; 6] = [
+        Self::Shards, Self::Metadata, Self::Journal, Self::Presignatures, Self::Auth, Self::System,
+    ];
+
+    /// Leading byte of the keys of the namespace in the underlying keyspace
+    pub fn id(self) -> u8 {
+        match self {
+            Self::Shards => 1,
+            Self::Metadata => 2,
+            Self::Journal => 3,
+            Self::Presignatures => 4,
+            Self::Auth => 5,
+            Self::System => 0xff,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|ns| ns.id() == id)
+    }
+
+    /// `key` in the underlying keyspace
+    pub fn key(self, key: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(1 + key.len());
+        raw.push(self.id());
+        raw.extend_from_slice(key);
+        raw
+    }
+}
+
+/// A page of a scan, in key order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanPage {
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Pass back to continue after the last entry, `None` once the scan is complete
+    pub cursor: Option<Vec<u8>>,
+}
+
+impl ScanPage {
+    pub fn keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries.iter().map(|(key, _)| &key[..])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BatchOp {
+    Put { namespace: Namespace, key: Vec<u8>, value: Vec<u8> },
+    Delete { namespace: Namespace, key: Vec<u8> },
+}
+
+/// Puts and deletes applied in order, all together or not at all
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put(&mut self, namespace: Namespace, key: &[u8], value: Vec<u8>) -> &mut Self {
+        self.ops.push(BatchOp::Put { namespace, key: key.to_vec(), value });
+        self
+    }
+
+    pub fn delete(&mut self, namespace: Namespace, key: &[u8]) -> &mut Self {
+        self.ops.push(BatchOp::Delete { namespace, key: key.to_vec() });
+        self
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    pub fn into_ops(self) -> Vec<BatchOp> {
+        self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl FromIterator<BatchOp> for WriteBatch {
+    fn from_iter<I: IntoIterator<Item = BatchOp>>(ops: I) -> Self {
+        Self { ops: ops.into_iter().collect() }
+    }
+}
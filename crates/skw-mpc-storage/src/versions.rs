@@ -0,0 +1,376 @@
+//! Versioned key shards
+//!
+//! A key refresh produces a new shard for the same `key_shard_id`. [ShardStore] keeps every
+//! version of a shard with its epoch, so that a refresh can be staged as pending, promoted once
+//! it is known to be good or discarded if it isn't, and rolled back from while the retired
+//! version is still retained.
+//!
+//! All versions of a shard live in a single record under its `key_shard_id` in
+//! [Namespace::Shards], so each update is one atomic write.
+//!
+//! ## Format
+//! `"SKWH" | format version | count (u32 LE)`, then per version
+//! `epoch (u64 LE) | created at (u64 LE) | retired at (u64 LE, 0 if not retired) |
+//! payload id (32 bytes) | state | shard length (u32 LE) | shard`. Timestamps are unix seconds.
+//!
+//! A record without the header is a shard written before versioning and reads as the active
+//! version of epoch 0.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::lock::Mutex;
+
+use crate::storage::MpcStorage;
+use crate::types::{CryptoHash, MpcStorageError, Namespace};
+
+const MAGIC: &[u8; 4] = b"SKWH";
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardState {
+    /// The shard in use
+    Active,
+    /// Produced by a refresh, not in use until promoted
+    Pending,
+    /// Replaced by a newer version, kept for rollback until pruned
+    Retired,
+}
+
+impl ShardState {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Active => 0,
+            Self::Pending => 1,
+            Self::Retired => 2,
+        }
+    }
+
+    fn from_byte(b: u8) -> Result<Self, MpcStorageError> {
+        match b {
+            0 => Ok(Self::Active),
+            1 => Ok(Self::Pending),
+            2 => Ok(Self::Retired),
+            _ => Err(MpcStorageError::MalformedRecord),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardVersion {
+    pub epoch: u64,
+    /// Unix seconds
+    pub created_at: u64,
+    /// Unix seconds, set once the version is retired
+    pub retired_at: Option<u64>,
+    /// Job that produced the shard
+    pub payload_id: CryptoHash,
+    pub state: ShardState,
+    pub shard: Vec<u8>,
+}
+
+/// Versioned shards on top of a [MpcStorage]
+pub struct ShardStore<S> {
+    storage: S,
+    // serializes read-modify-write of history records
+    update: Mutex<()>,
+}
+
+impl<S: MpcStorage> ShardStore<S> {
+    pub fn new(storage: S) -> Self {
+        Self { storage, update: Mutex::new(()) }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// All versions of the shard, oldest first
+    pub async fn history(&self, key_shard_id: CryptoHash) -> Result<Vec<ShardVersion>, MpcStorageError> {
+        decode_history(&self.storage.get(Namespace::Shards, &key_shard_id).await?)
+    }
+
+    /// The shard in use, `KeyNotInDB` if there is none
+    pub async fn active(&self, key_shard_id: CryptoHash) -> Result<ShardVersion, MpcStorageError> {
+        self.history(key_shard_id)
+            .await?
+            .into_iter()
+            .find(|v| v.state == ShardState::Active)
+            .ok_or(MpcStorageError::KeyNotInDB)
+    }
+
+    /// The latest staged version, if any
+    pub async fn pending(&self, key_shard_id: CryptoHash) -> Result<Option<ShardVersion>, MpcStorageError> {
+        Ok(self.history(key_shard_id)
+            .await?
+            .into_iter()
+            .rev()
+            .find(|v| v.state == ShardState::Pending))
+    }
+
+    /// Stages a new version, returns its epoch. Older pending versions are retired.
+    pub async fn add_pending(
+        &self,
+        key_shard_id: CryptoHash,
+        payload_id: CryptoHash,
+        shard: Vec<u8>,
+    ) -> Result<u64, MpcStorageError> {
+        self.update(key_shard_id, |history, now| {
+            for v in history.iter_mut().filter(|v| v.state == ShardState::Pending) {
+                v.state = ShardState::Retired;
+                v.retired_at = Some(now);
+            }
+            let epoch = next_epoch(history);
+            history.push(ShardVersion {
+                epoch,
+                created_at: now,
+                retired_at: None,
+                payload_id,
+                state: ShardState::Pending,
+                shard,
+            });
+            Ok(epoch)
+        }).await
+    }
+
+    /// Makes a pending version the active one, retiring the previously active version
+    pub async fn promote(&self, key_shard_id: CryptoHash, epoch: u64) -> Result<(), MpcStorageError> {
+        self.update(key_shard_id, |history, now| {
+            if !history.iter().any(|v| v.epoch == epoch && v.state == ShardState::Pending) {
+                return Err(MpcStorageError::VersionNotPending(epoch));
+            }
+            for v in history.iter_mut() {
+                if v.epoch == epoch {
+                    v.state = ShardState::Active;
+                } else if v.state == ShardState::Active {
+                    v.state = ShardState::Retired;
+                    v.retired_at = Some(now);
+                }
+            }
+            Ok(())
+        }).await
+    }
+
+    /// Retires a pending version that won't be promoted, the active version stays in use. Like
+    /// any retired version it is kept until pruned, and its epoch is never handed out again.
+    pub async fn discard(&self, key_shard_id: CryptoHash, epoch: u64) -> Result<(), MpcStorageError> {
+        self.update(key_shard_id, |history, now| {
+            let pending = history
+                .iter_mut()
+                .find(|v| v.epoch == epoch && v.state == ShardState::Pending)
+                .ok_or(MpcStorageError::VersionNotPending(epoch))?;
+            pending.state = ShardState::Retired;
+            pending.retired_at = Some(now);
+            Ok(())
+        }).await
+    }
+
+    /// Stores the shard as the new active version in one write, e.g. after keygen. Any active or
+    /// pending version is retired.
+    pub async fn put_active(
+        &self,
+        key_shard_id: CryptoHash,
+        payload_id: CryptoHash,
+        shard: Vec<u8>,
+    ) -> Result<u64, MpcStorageError> {
+        self.update(key_shard_id, |history, now| {
+            for v in history.iter_mut().filter(|v| v.state != ShardState::Retired) {
+                v.state = ShardState::Retired;
+                v.retired_at = Some(now);
+            }
+            let epoch = next_epoch(history);
+            history.push(ShardVersion {
+                epoch,
+                created_at: now,
+                retired_at: None,
+                payload_id,
+                state: ShardState::Active,
+                shard,
+            });
+            Ok(epoch)
+        }).await
+    }
+
+    /// Drops versions retired for longer than `retention`, returns how many were dropped
+    pub async fn prune(&self, key_shard_id: CryptoHash, retention: Duration) -> Result<usize, MpcStorageError> {
+        self.update(key_shard_id, |history, now| {
+            let before = history.len();
+            history.retain(|v| match v.retired_at {
+                Some(retired_at) => retired_at + retention.as_secs() > now,
+                None => true,
+            });
+            Ok(before - history.len())
+        }).await
+    }
+
+    async fn update<T>(
+        &self,
+        key_shard_id: CryptoHash,
+        op: impl FnOnce(&mut Vec<ShardVersion>, u64) -> Result<T, MpcStorageError>,
+    ) -> Result<T, MpcStorageError> {
+        let _guard = self.update.lock().await;
+        let mut history = match self.storage.get(Namespace::Shards, &key_shard_id).await {
+            Ok(record) => decode_history(&record)?,
+            Err(MpcStorageError::KeyNotInDB) => vec![],
+            Err(e) => return Err(e),
+        };
+        let result = op(&mut history, unix_now())?;
+        self.storage.put(Namespace::Shards, &key_shard_id, encode_history(&history)).await?;
+        Ok(result)
+    }
+}
+
+fn next_epoch(history: &[ShardVersion]) -> u64 {
+    history.iter().map(|v| v.epoch + 1).max().unwrap_or(0)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn encode_history(history: &[ShardVersion]) -> Vec<u8> {
+    let mut raw = Vec::new();
+    raw.extend_from_slice(MAGIC);
+    raw.push(FORMAT_VERSION);
+    raw.extend_from_slice(&(history.len() as u32).to_le_bytes());
+    for v in history {
+        raw.extend_from_slice(&v.epoch.to_le_bytes());
+        raw.extend_from_slice(&v.created_at.to_le_bytes());
+        raw.extend_from_slice(&v.retired_at.unwrap_or(0).to_le_bytes());
+        raw.extend_from_slice(&v.payload_id);
+        raw.push(v.state.to_byte());
+        raw.extend_from_slice(&(v.shard.len() as u32).to_le_bytes());
+        raw.extend_from_slice(&v.shard);
+    }
+    raw
+}
+
+fn decode_history(raw: &[u8]) -> Result<Vec<ShardVersion>, MpcStorageError> {
+    if !raw.starts_with(MAGIC) {
+        return Ok(vec![ShardVersion {
+            epoch: 0,
+            created_at: 0,
+            retired_at: None,
+            payload_id: [0u8; 32],
+            state: ShardState::Active,
+            shard: raw.to_vec(),
+        }]);
+    }
+
+    let mut reader = Reader::new(&raw[MAGIC.len()..]);
+    if reader.take(1)?[0] != FORMAT_VERSION {
+        return Err(MpcStorageError::MalformedRecord);
+    }
+    let count = reader.u32()?;
+    let mut history = Vec::with_capacity(count.min(64) as usize);
+    for _ in 0..count {
+        let epoch = reader.u64()?;
+        let created_at = reader.u64()?;
+        let retired_at = Some(reader.u64()?).filter(|t| *t != 0);
+        let payload_id = reader.take(32)?.try_into().unwrap();
+        let state = ShardState::from_byte(reader.take(1)?[0])?;
+        let len = reader.u32()? as usize;
+        let shard = reader.take(len)?.to_vec();
+        history.push(ShardVersion { epoch, created_at, retired_at, payload_id, state, shard });
+    }
+    Ok(history)
+}
+
+/// Reads little endian fields off a record, `MalformedRecord` once it runs out
+pub(crate) struct Reader<'a> {
+    raw: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(raw: &'a [u8]) -> Self {
+        Self { raw, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], MpcStorageError> {
+        let bytes = self.raw
+            .get(self.pos..self.pos + n)
+            .ok_or(MpcStorageError::MalformedRecord)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, MpcStorageError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, MpcStorageError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::InMemoryStorage;
+
+    #[async_std::test]
+    async fn refresh_lifecycle() {
+        let store = ShardStore::new(InMemoryStorage::default());
+        let id = [0u8; 32];
+
+        assert_eq!(store.put_active(id, [1u8; 32], vec![1]).await, Ok(0));
+        assert_eq!(store.add_pending(id, [2u8; 32], vec![2]).await, Ok(1));
+        // pending version is not used yet
+        assert_eq!(store.active(id).await.unwrap().shard, vec![1]);
+
+        store.promote(id, 1).await.unwrap();
+        let active = store.active(id).await.unwrap();
+        assert_eq!((active.epoch, active.payload_id, active.shard), (1, [2u8; 32], vec![2]));
+        assert_eq!(store.promote(id, 0).await, Err(MpcStorageError::VersionNotPending(0)));
+
+        let history = store.history(id).await.unwrap();
+        assert_eq!(history[0].state, ShardState::Retired);
+        assert!(history[0].retired_at.is_some());
+
+        assert_eq!(store.prune(id, Duration::from_secs(3600)).await, Ok(0));
+        assert_eq!(store.prune(id, Duration::ZERO).await, Ok(1));
+        assert_eq!(store.history(id).await.unwrap().len(), 1);
+    }
+
+    #[async_std::test]
+    async fn discarded_refresh_keeps_the_active_shard() {
+        let store = ShardStore::new(InMemoryStorage::default());
+        let id = [0u8; 32];
+
+        store.put_active(id, [1u8; 32], vec![1]).await.unwrap();
+        assert_eq!(store.add_pending(id, [2u8; 32], vec![2]).await, Ok(1));
+        assert_eq!(store.pending(id).await.unwrap().map(|v| v.epoch), Some(1));
+
+        store.discard(id, 1).await.unwrap();
+        assert_eq!(store.pending(id).await, Ok(None));
+        assert_eq!(store.active(id).await.unwrap().shard, vec![1]);
+        assert_eq!(store.history(id).await.unwrap()[1].state, ShardState::Retired);
+
+        // neither a discarded nor the active version can be discarded or promoted
+        assert_eq!(store.discard(id, 1).await, Err(MpcStorageError::VersionNotPending(1)));
+        assert_eq!(store.discard(id, 0).await, Err(MpcStorageError::VersionNotPending(0)));
+        assert_eq!(store.promote(id, 1).await, Err(MpcStorageError::VersionNotPending(1)));
+
+        // the next refresh is staged as usual
+        assert_eq!(store.add_pending(id, [3u8; 32], vec![3]).await, Ok(2));
+        store.promote(id, 2).await.unwrap();
+        assert_eq!(store.active(id).await.unwrap().shard, vec![3]);
+    }
+
+    #[async_std::test]
+    async fn unversioned_record_is_active() {
+        let store = ShardStore::new(InMemoryStorage::default());
+        let id = [0u8; 32];
+        store.storage().put(Namespace::Shards, &id, b"{\"legacy\":1}".to_vec()).await.unwrap();
+
+        let active = store.active(id).await.unwrap();
+        assert_eq!((active.epoch, active.shard), (0, b"{\"legacy\":1}".to_vec()));
+
+        // the legacy shard is kept as history of the first refresh
+        assert_eq!(store.put_active(id, [1u8; 32], vec![1]).await, Ok(1));
+        assert_eq!(store.history(id).await.unwrap()[0].state, ShardState::Retired);
+    }
+}
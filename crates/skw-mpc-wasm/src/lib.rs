@@ -1,6 +1,6 @@
 #![cfg(target_arch = "wasm32")]
 
-use skw_mpc_client::{swarm::{new_swarm_node, MpcP2pRequest}, async_executor};
+use skw_mpc_client::{swarm::{new_swarm_node, MpcP2pRequest, SkwMpcP2pCodec}, async_executor};
 use wasm_bindgen::prelude::*;
 use skw_mpc_payload::{PayloadHeader, AuthHeader};
 use std::panic;
@@ -16,7 +16,7 @@ pub async fn ext_run_keygen(auth_header: &str, payload: &str, client_identity: &
 
     let request: PayloadHeader = serde_json::from_str(payload).unwrap();
     let auth_header: AuthHeader = serde_json::from_str(auth_header).unwrap();
-    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None );
+    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None, SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     let client_node = (
@@ -55,7 +55,7 @@ pub async fn ext_run_sign(auth_header: &str, payload: &str, local_key: &str, cli
     let auth_header: AuthHeader = serde_json::from_str(auth_header).unwrap();
     let local_key = local_key.as_bytes();
 
-    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None );
+    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None, SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     let client_node = (
@@ -94,7 +94,7 @@ pub async fn ext_run_key_refreh(auth_header: &str, payload: &str, client_identit
     let request: PayloadHeader = serde_json::from_str(payload).unwrap();
     let auth_header: AuthHeader = serde_json::from_str(auth_header).unwrap();
 
-    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None );
+    let ( _, mut client, event_loop, mut shutdown_handler) = new_swarm_node( None, SkwMpcP2pCodec::default() );
     async_executor(event_loop.run());
 
     let client_node = (
@@ -2,18 +2,24 @@
 
 use std::fmt::{self, Debug};
 use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
 
 use futures::future::{Either, FutureExt};
 use futures::sink::Sink;
 use futures::stream::{self, FusedStream, Stream, StreamExt};
 use futures::SinkExt;
+use tokio::sync::oneshot;
 use tokio::time::{self, timeout_at};
 
-use skw_mpc_payload::{Payload, PayloadHeader};
-
+use crate::envelope::{Envelope, Header};
+use crate::transcript::{Entry, Recorder};
 use crate::{IsCritical, Msg, StateMachine};
+use offload::{Inline, Offload};
 use watcher::{BlindWatcher, ProtocolWatcher, When};
 
+pub use crate::envelope::InvalidMessage;
+
+pub mod offload;
 pub mod watcher;
 
 /// Executes protocol in async environment using [tokio] backend
@@ -21,39 +27,56 @@ pub mod watcher;
 /// Note that if the protocol has some cryptographical assumptions on transport channel (e.g. messages
 /// should be encrypted, authenticated), then stream and sink must meet these assumptions (e.g. encrypt,
 /// authenticate messages)
-pub struct AsyncProtocol<SM, I, O, W = BlindWatcher> {
-    payload_header: PayloadHeader,
-    
+///
+/// Messages are exchanged in [envelopes](crate::envelope) `E` carrying the job's header, bare
+/// [Msg]s by default.
+pub struct AsyncProtocol<SM, I, O, W = BlindWatcher, E = Msg<<SM as StateMachine>::MessageBody>>
+where
+    SM: StateMachine,
+    E: Envelope<SM::MessageBody>,
+{
+    header: E::Header,
+
     state: Option<SM>,
     incoming: I,
     outgoing: O,
     deadline: Option<time::Instant>,
     current_round: Option<u16>,
+    round_started_at: Option<time::Instant>,
     watcher: W,
+    offload: Box<dyn Offload>,
+    recorder: Option<Box<dyn Recorder<E>>>,
 }
 
-impl<SM, I, O> AsyncProtocol<SM, I, O, BlindWatcher> {
+impl<SM, I, O, E> AsyncProtocol<SM, I, O, BlindWatcher, E>
+where
+    SM: StateMachine,
+    E: Envelope<SM::MessageBody>,
+{
     /// Constructs new protocol executor from initial state, channels of incoming and outgoing
-    /// messages
-    pub fn new(state: SM, incoming: I, outgoing: O, 
-        
-        payload_header: PayloadHeader, 
-
-    ) -> Self {
+    /// messages, and header of the job
+    pub fn new(state: SM, incoming: I, outgoing: O, header: E::Header) -> Self {
         Self {
-            payload_header,
+            header,
 
             state: Some(state),
             incoming,
             outgoing,
             deadline: None,
             current_round: None,
+            round_started_at: None,
             watcher: BlindWatcher,
+            offload: Box::new(Inline),
+            recorder: None,
         }
     }
 }
 
-impl<SM, I, O, W> AsyncProtocol<SM, I, O, W> {
+impl<SM, I, O, W, E> AsyncProtocol<SM, I, O, W, E>
+where
+    SM: StateMachine,
+    E: Envelope<SM::MessageBody>,
+{
     /// Sets new protocol watcher
     ///
     /// Protocol watcher looks after protocol execution. See list of observable events in
@@ -61,27 +84,52 @@ impl<SM, I, O, W> AsyncProtocol<SM, I, O, W> {
     ///
     /// Default watcher: [BlindWatcher] that does nothing with received events. For development
     /// purposes it's convenient to pick [StderrWatcher](watcher::StderrWatcher).
-    pub fn set_watcher<WR>(self, watcher: WR) -> AsyncProtocol<SM, I, O, WR> {
+    pub fn set_watcher<WR>(self, watcher: WR) -> AsyncProtocol<SM, I, O, WR, E> {
         AsyncProtocol {
-            payload_header: self.payload_header,
+            header: self.header,
 
             state: self.state,
             incoming: self.incoming,
             outgoing: self.outgoing,
             deadline: self.deadline,
             current_round: self.current_round,
+            round_started_at: self.round_started_at,
             watcher,
+            offload: self.offload,
+            recorder: self.recorder,
         }
     }
+
+    /// Sets where [proceed](crate::StateMachine::proceed) is executed
+    ///
+    /// Default: [Inline](offload::Inline) that blocks the executor for as long as proceeding
+    /// takes. Nodes running several protocols at once should offload it, e.g. to
+    /// [TokioBlocking](offload::TokioBlocking), so that protocols progress in parallel.
+    pub fn set_offload<X: Offload + 'static>(mut self, offload: X) -> Self {
+        self.offload = Box::new(offload);
+        self
+    }
+
+    /// Records every received and sent message and every round transition
+    ///
+    /// See [transcript](crate::transcript) for what the transcript contains and how to replay it.
+    pub fn set_recorder<R>(mut self, recorder: R) -> Self
+    where
+        R: Recorder<E> + 'static,
+    {
+        self.recorder = Some(Box::new(recorder));
+        self
+    }
 }
 
-impl<SM, I, O, IErr, W> AsyncProtocol<SM, I, O, W>
+impl<SM, I, O, IErr, W, E> AsyncProtocol<SM, I, O, W, E>
 where
     SM: StateMachine,
-    SM::Err: Send,
+    SM::Err: Send + 'static,
     SM: Send + 'static,
-    I: Stream<Item = Result<Payload<Msg<SM::MessageBody>>, IErr>> + FusedStream + Unpin,
-    O: Sink<Payload<Msg<SM::MessageBody>>> + Unpin,
+    E: Envelope<SM::MessageBody>,
+    I: Stream<Item = Result<E, IErr>> + FusedStream + Unpin,
+    O: Sink<E> + Unpin,
     W: ProtocolWatcher<SM>,
 {
     /// Get a reference to the inner state machine.
@@ -142,19 +190,29 @@ where
         let state = self.state.as_mut().ok_or(InternalError::MissingState)?;
         match Self::enforce_timeout(self.deadline, self.incoming.next()).await {
 
-            // TODO: do any kinda pre-flight verification here on header
-            // 1. Validate if this msg is addressed to us
-
-            Ok(Some(Ok(msg))) => match state.handle_incoming(msg.body) {
-                Ok(()) => (),
-                Err(err) if err.is_critical() => return Err(Error::HandleIncoming(err)),
-                Err(err) => self
-                    .watcher
-                    .caught_non_critical_error(When::HandleIncoming, err),
-            },
+            Ok(Some(Ok(msg))) => {
+                let party = (state.party_ind(), state.parties());
+                if let Err(err) = validate_incoming(&self.header, party, &msg) {
+                    self.watcher.caught_invalid_message(msg.msg(), err);
+                    return Ok(());
+                }
+
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.record(Entry::Incoming(&msg));
+                }
+                self.watcher.message_received(msg.msg());
+                match state.handle_incoming(msg.unwrap()) {
+                    Ok(()) => (),
+                    Err(err) if err.is_critical() => return Err(Error::HandleIncoming(err)),
+                    Err(err) => self
+                        .watcher
+                        .caught_non_critical_error(When::HandleIncoming, err),
+                }
+            }
             Ok(Some(Err(err))) => return Err(Error::Recv(err)),
             Ok(None) => return Err(Error::RecvEof),
             Err(_) => {
+                self.watcher.round_timed_out(state.current_round());
                 let err = state.round_timeout_reached();
                 return Err(Error::HandleIncomingTimeout(err));
             }
@@ -165,11 +223,29 @@ where
     async fn proceed_if_needed(&mut self) -> Result<(), Error<SM::Err, IErr, O::Error>> {
         let mut state = self.state.take().ok_or(InternalError::MissingState)?;
         if state.wants_to_proceed() {
-            let result = state.proceed();
-            // let (result, s) = async_std::task::spawn_blocking(move || (state.proceed(), state))
-            //     .await;
-            //     // .map_err(|_| Error::ProceedPanicked)?;
-            // state = s;
+            let round = state.current_round();
+            let started_at = time::Instant::now();
+            let (tx, rx) = oneshot::channel();
+            self.offload.offload(Box::new(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(move || {
+                    let result = state.proceed();
+                    (state, result)
+                }));
+                let _ = tx.send(result.map_err(offload::panic_message));
+            }));
+
+            // on panic the state machine is lost, it's reported by `state_machine_ref` afterwards
+            let (s, result) = match rx.await {
+                Ok(Ok(proceeded)) => proceeded,
+                Ok(Err(panic)) => return Err(Error::ProceedPanicked(panic)),
+                Err(_) => {
+                    return Err(Error::ProceedPanicked(
+                        "offload dropped the task".to_string(),
+                    ))
+                }
+            };
+            state = s;
+            self.watcher.proceeded(round, started_at.elapsed());
 
             match result {
                 Ok(()) => (),
@@ -185,16 +261,19 @@ where
         let state = self.state.as_mut().ok_or(InternalError::MissingState)?;
 
         if !state.message_queue().is_empty() {
-            let mut msgs = stream::iter(state
+            let msgs: Vec<_> = state
                 .message_queue()
                 .drain(..)
-                .map(|m| {
-                    Ok(Payload {
-                        payload_header: self.payload_header.clone(),
-                        body: m,
-                    })
-                })
-            );
+                .map(|m| E::wrap(self.header.clone(), m))
+                .collect();
+            for msg in &msgs {
+                self.watcher.message_sent(msg.msg());
+                if let Some(recorder) = self.recorder.as_mut() {
+                    recorder.record(Entry::Outgoing(msg));
+                }
+            }
+
+            let mut msgs = stream::iter(msgs.into_iter().map(Ok));
 
             self.outgoing
                 .send_all(&mut msgs)
@@ -227,9 +306,21 @@ where
         let state = self.state.as_mut().ok_or(InternalError::MissingState)?;
         let round_n = state.current_round();
         if self.current_round != Some(round_n) {
+            let now = time::Instant::now();
+            if let (Some(round), Some(started_at)) = (self.current_round, self.round_started_at) {
+                self.watcher.round_finished(round, now - started_at);
+            }
+            if !state.is_finished() {
+                self.watcher.round_started(round_n);
+            }
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.record(Entry::Round(round_n));
+            }
+
             self.current_round = Some(round_n);
+            self.round_started_at = Some(now);
             self.deadline = match state.round_timeout() {
-                Some(timeout) => Some(time::Instant::now() + timeout),
+                Some(timeout) => Some(now + timeout),
                 None => None,
             }
         }
@@ -250,6 +341,30 @@ where
     }
 }
 
+/// Checks that a received message belongs to the job and is meant for `party` (its index and the
+/// number of parties), before it gets to the state machine
+fn validate_incoming<B, E: Envelope<B>>(
+    job: &E::Header,
+    (party_i, party_n): (u16, u16),
+    envelope: &E,
+) -> Result<(), InvalidMessage> {
+    envelope.header().check(job)?;
+
+    let msg = envelope.msg();
+    if msg.sender == party_i {
+        return Err(InvalidMessage::OwnMessage);
+    }
+    if msg.sender == 0 || msg.sender > party_n {
+        return Err(InvalidMessage::SenderOutOfRange { sender: msg.sender });
+    }
+    match msg.receiver {
+        Some(receiver) if receiver != party_i => {
+            Err(InvalidMessage::NotAddressedToUs { receiver })
+        }
+        _ => Ok(()),
+    }
+}
+
 /// Represents error that can occur while executing protocol
 #[derive(Debug)]
 #[non_exhaustive]
@@ -264,8 +379,9 @@ pub enum Error<E, RE, SE> {
     HandleIncoming(E),
     /// Round timeout exceed when executor was waiting for new messages from other parties
     HandleIncomingTimeout(E),
-    /// [Proceed method](crate::StateMachine::proceed) panicked
-    ProceedPanicked(tokio::task::JoinError),
+    /// [Proceed method](crate::StateMachine::proceed) panicked, or the
+    /// [offload](AsyncProtocol::set_offload) never ran it
+    ProceedPanicked(String),
     /// State machine [proceeding](crate::StateMachine::proceed) produced critical error
     Proceed(E),
     /// StateMachine's [pick_output](crate::StateMachine::pick_output) method return error
@@ -350,10 +466,10 @@ where
             Self::Send(err) => Some(err),
             Self::HandleIncoming(err) => Some(err),
             Self::HandleIncomingTimeout(err) => Some(err),
-            Self::ProceedPanicked(err) => Some(err),
             Self::Proceed(err) => Some(err),
             Self::Finish(err) => Some(err),
             Self::RecvEof => None,
+            Self::ProceedPanicked(_) => None,
             Self::Exhausted => None,
             Self::BadStateMachine(_) => None,
             Self::InternalError(_) => None,
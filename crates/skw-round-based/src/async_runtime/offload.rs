@@ -0,0 +1,95 @@
+//! Where [AsyncProtocol](super::AsyncProtocol) runs [proceed](crate::StateMachine::proceed)
+//!
+//! Proceeding a round may involve heavy computations (e.g. Paillier key generation, range proofs)
+//! that would block the async executor and every other task scheduled on it. An [Offload] moves
+//! that work elsewhere:
+//!
+//! * [Inline] runs it in place, on the executor. This is the default
+//! * [TokioBlocking] runs it on tokio's blocking thread pool
+//! * [RayonPool] runs it on a rayon thread pool (requires `rayon` feature)
+//! * any `Fn(Task)` closure hands it to a caller-supplied executor
+
+use std::any::Any;
+
+/// Unit of blocking work handed to an [Offload]
+pub type Task = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs blocking work outside of the async executor
+///
+/// Implementation must eventually run every given task exactly once. Panics in the task are
+/// caught by [AsyncProtocol](super::AsyncProtocol) before they reach the offload.
+pub trait Offload: Send + Sync {
+    /// Schedules the task
+    fn offload(&self, task: Task);
+}
+
+impl<F> Offload for F
+where
+    F: Fn(Task) + Send + Sync,
+{
+    fn offload(&self, task: Task) {
+        self(task)
+    }
+}
+
+/// Runs the task in place, blocking the current executor thread
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Inline;
+
+impl Offload for Inline {
+    fn offload(&self, task: Task) {
+        task()
+    }
+}
+
+/// Runs the task on tokio's blocking thread pool, see [tokio::task::spawn_blocking]
+///
+/// Must be used from within a tokio runtime.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioBlocking;
+
+impl Offload for TokioBlocking {
+    fn offload(&self, task: Task) {
+        drop(tokio::task::spawn_blocking(task));
+    }
+}
+
+/// Runs the task on a rayon thread pool
+#[cfg(feature = "rayon")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rayon")))]
+#[derive(Debug, Clone, Default)]
+pub struct RayonPool(Option<std::sync::Arc<rayon::ThreadPool>>);
+
+#[cfg(feature = "rayon")]
+impl RayonPool {
+    /// Rayon's global thread pool
+    pub fn global() -> Self {
+        Self(None)
+    }
+
+    /// Dedicated thread pool, e.g. to bound the number of threads busy with protocols
+    pub fn new(pool: std::sync::Arc<rayon::ThreadPool>) -> Self {
+        Self(Some(pool))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Offload for RayonPool {
+    fn offload(&self, task: Task) {
+        match &self.0 {
+            Some(pool) => pool.spawn(task),
+            None => rayon::spawn(task),
+        }
+    }
+}
+
+/// Human readable message of a caught panic
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(msg) => msg.to_string(),
+            Err(_) => "unknown panic payload".to_string(),
+        },
+    }
+}
@@ -1,18 +1,33 @@
 //! Mechanism for tracking protocol execution
 
 use std::fmt::Debug;
+use std::time::Duration;
 
-use crate::StateMachine;
+use crate::envelope::InvalidMessage;
+use crate::{Msg, StateMachine};
 
 /// Looks after protocol execution in [AsyncProtocol](super::AsyncProtocol)
 ///
-/// Currently it's only able to see caught non critical errors, API will be expanded (see [#1][issue]).
-/// It will be able to track incoming messages, changing round number, etc.
-///
-/// [issue]: https://github.com/ZenGo-X/round-based-protocol/issues/1
+/// Every event but [caught_non_critical_error](ProtocolWatcher::caught_non_critical_error) has
+/// an empty default implementation, so a watcher only picks the events it cares about.
 pub trait ProtocolWatcher<SM: StateMachine> {
     /// StateMachine produced a not critical error. Execution continues.
     fn caught_non_critical_error(&mut self, when: When, err: SM::Err);
+
+    /// Protocol entered round `round`
+    fn round_started(&mut self, _round: u16) {}
+    /// Protocol left round `round` after `took` since it was started
+    fn round_finished(&mut self, _round: u16, _took: Duration) {}
+    /// Message is about to be handed to the state machine
+    fn message_received(&mut self, _msg: &Msg<SM::MessageBody>) {}
+    /// Received message failed pre-flight validation and was dropped. Execution continues.
+    fn caught_invalid_message(&mut self, _msg: &Msg<SM::MessageBody>, _err: InvalidMessage) {}
+    /// Message is about to be sent
+    fn message_sent(&mut self, _msg: &Msg<SM::MessageBody>) {}
+    /// State machine [proceeded](crate::StateMachine::proceed) at round `round`, which took `took`
+    fn proceeded(&mut self, _round: u16, _took: Duration) {}
+    /// Round timeout was reached, the protocol is about to fail
+    fn round_timed_out(&mut self, _round: u16) {}
 }
 
 /// Claims at which stage event occurred
@@ -43,4 +58,111 @@ where
     fn caught_non_critical_error(&mut self, when: When, err: SM::Err) {
         eprintln!("Caught non critical error at {:?}: {:?}", when, err);
     }
+
+    fn caught_invalid_message(&mut self, msg: &Msg<SM::MessageBody>, err: InvalidMessage) {
+        eprintln!("Dropped message from party {}: {}", msg.sender, err);
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub use tracing_watcher::TracingWatcher;
+
+#[cfg(feature = "tracing")]
+mod tracing_watcher {
+    use std::fmt::Debug;
+    use std::time::Duration;
+
+    use tracing::{debug, debug_span, info, warn, Span};
+
+    use super::{InvalidMessage, ProtocolWatcher, When};
+    use crate::{Msg, StateMachine};
+
+    /// Watcher that reports every event through [tracing]
+    ///
+    /// Events are emitted within a `round` span, which itself is a child of the given span of the
+    /// job, e.g. one tagged with the job's id.
+    #[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+    pub struct TracingWatcher<B> {
+        job: Span,
+        round: Span,
+        message_size: Option<fn(&B) -> usize>,
+    }
+
+    impl<B> TracingWatcher<B> {
+        /// Watcher of the job traced by `job` span
+        pub fn new(job: Span) -> Self {
+            Self {
+                round: job.clone(),
+                job,
+                message_size: None,
+            }
+        }
+
+        /// Records the size of every sent and received message, as measured by `message_size`
+        pub fn with_message_size(mut self, message_size: fn(&B) -> usize) -> Self {
+            self.message_size = Some(message_size);
+            self
+        }
+
+        fn size_of(&self, body: &B) -> Option<usize> {
+            self.message_size.map(|size| size(body))
+        }
+    }
+
+    impl<SM> ProtocolWatcher<SM> for TracingWatcher<SM::MessageBody>
+    where
+        SM: StateMachine,
+        SM::Err: Debug,
+    {
+        fn caught_non_critical_error(&mut self, when: When, err: SM::Err) {
+            warn!(parent: &self.round, ?when, ?err, "caught non critical error");
+        }
+
+        fn caught_invalid_message(&mut self, msg: &Msg<SM::MessageBody>, err: InvalidMessage) {
+            warn!(
+                parent: &self.round,
+                sender = msg.sender,
+                receiver = ?msg.receiver,
+                %err,
+                "dropped invalid message"
+            );
+        }
+
+        fn round_started(&mut self, round: u16) {
+            self.round = debug_span!(parent: &self.job, "round", round);
+            debug!(parent: &self.round, "round started");
+        }
+
+        fn round_finished(&mut self, round: u16, took: Duration) {
+            info!(parent: &self.round, round, ?took, "round finished");
+        }
+
+        fn message_received(&mut self, msg: &Msg<SM::MessageBody>) {
+            debug!(
+                parent: &self.round,
+                sender = msg.sender,
+                receiver = ?msg.receiver,
+                size = ?self.size_of(&msg.body),
+                "message received"
+            );
+        }
+
+        fn message_sent(&mut self, msg: &Msg<SM::MessageBody>) {
+            debug!(
+                parent: &self.round,
+                sender = msg.sender,
+                receiver = ?msg.receiver,
+                size = ?self.size_of(&msg.body),
+                "message sent"
+            );
+        }
+
+        fn proceeded(&mut self, round: u16, took: Duration) {
+            debug!(parent: &self.round, round, ?took, "proceeded");
+        }
+
+        fn round_timed_out(&mut self, round: u16) {
+            warn!(parent: &self.round, round, "round timed out");
+        }
+    }
 }
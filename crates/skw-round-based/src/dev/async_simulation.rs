@@ -2,15 +2,21 @@ use std::fmt::Debug;
 use std::iter;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
-use futures::future::ready;
+use futures::future::{select, Either};
 use futures::sink::Sink;
-use futures::stream::{FusedStream, StreamExt};
+use futures::stream::FusedStream;
 use tokio::sync::broadcast;
+use tokio::time::{sleep_until, Instant};
 
-use crate::async_runtime::{self, watcher::StderrWatcher, AsyncProtocol};
+use crate::async_runtime::{self, offload::TokioBlocking, watcher::StderrWatcher, AsyncProtocol};
 use crate::{Msg, StateMachine};
-use skw_mpc_payload::{Payload, PayloadHeader};
+
+use super::faults::NetworkFaults;
+
+/// Wall clock length of one [Fault::Delay](super::Fault::Delay) tick in [AsyncSimulation]
+pub const ASYNC_TICK: Duration = Duration::from_millis(10);
 
 /// Emulates running protocol between local parties using [AsyncProtocol](crate::AsyncProtocol)
 ///
@@ -19,10 +25,13 @@ use skw_mpc_payload::{Payload, PayloadHeader};
 ///
 /// Compared to [Simulation], AsyncSimulation requires [tokio] runtime and introduces parallelism,
 /// so it's more suitable for writing tests (whereas [Simulation] is more suitable for writing
-/// benchmarks).
+/// benchmarks). Parties proceed on tokio's blocking thread pool, as nodes are expected to.
 ///
 /// [Simulation]: super::Simulation
 ///
+/// Messages can be dropped, delayed, duplicated, reordered or corrupted by
+/// [injecting faults](AsyncSimulation::inject_faults), parties then run into their round timeouts.
+///
 /// ## Limitations
 /// * Doesn't log process of protocol execution (except for occurring non critical errors). Limited
 ///   by [ProtocolWatcher](crate::async_runtime::watcher::ProtocolWatcher) API (to be expanded).
@@ -35,7 +44,7 @@ use skw_mpc_payload::{Payload, PayloadHeader};
 /// # async fn async_simulation<Party>()
 /// # where Party: StateMachine + Builder + Send + 'static,
 /// #       Party::MessageBody: Send + Clone + Unpin + 'static,
-/// #       Party::Err: Send + std::fmt::Debug,
+/// #       Party::Err: Send + std::fmt::Debug + 'static,
 /// #       Party::Output: Send,
 /// # {
 /// let results: Vec<Result<Party::Output, _>> = AsyncSimulation::new()
@@ -47,7 +56,7 @@ use skw_mpc_payload::{Payload, PayloadHeader};
 /// # }
 /// ```
 pub struct AsyncSimulation<SM: StateMachine> {
-    tx: broadcast::Sender<Payload<Msg<SM::MessageBody>>>,
+    tx: broadcast::Sender<Msg<SM::MessageBody>>,
     parties: Vec<
         Option<
             AsyncProtocol<SM, 
@@ -58,13 +67,14 @@ pub struct AsyncSimulation<SM: StateMachine> {
         >,
     >,
     exhausted: bool,
+    faults: Option<NetworkFaults<SM::MessageBody>>,
 }
 
 impl<SM> AsyncSimulation<SM>
 where
     SM: StateMachine + Send + 'static,
     SM::MessageBody: Send + Clone + Unpin + 'static,
-    SM::Err: Send + Debug,
+    SM::Err: Send + Debug + 'static,
     SM::Output: Send,
 {
     /// Creates new simulation
@@ -74,18 +84,37 @@ where
             tx,
             parties: vec![],
             exhausted: false,
+            faults: None,
         }
     }
 
+    /// Delivers messages through an adversarial network instead of a perfect one
+    ///
+    /// Applies to parties added afterwards. One tick of [Fault::Delay](super::Fault::Delay) is
+    /// [ASYNC_TICK].
+    ///
+    /// ## Panics
+    /// * Parties were already added
+    pub fn inject_faults(&mut self, faults: NetworkFaults<SM::MessageBody>) -> &mut Self {
+        assert!(
+            self.parties.is_empty(),
+            "faults must be injected before adding parties"
+        );
+        self.faults = Some(faults);
+        self
+    }
+
     /// Adds protocol participant
     pub fn add_party(&mut self, party: SM) -> &mut Self {
         let rx = self.tx.subscribe();
 
-        let incoming = incoming(rx, party.party_ind());
+        let incoming = incoming(rx, party.party_ind(), self.faults.clone());
         let outgoing = Outgoing {
             sender: self.tx.clone(),
         };
-        let party = AsyncProtocol::new(party, incoming, outgoing, PayloadHeader::default()).set_watcher(StderrWatcher);
+        let party = AsyncProtocol::new(party, incoming, outgoing, ())
+            .set_watcher(StderrWatcher)
+            .set_offload(TokioBlocking);
         self.parties.push(Some(party));
         self
     }
@@ -134,39 +163,73 @@ where
 }
 
 type Incoming<M> =
-    Pin<Box<dyn FusedStream<Item = Result<Payload<Msg<M>>, broadcast::error::RecvError>> + Send>>;
+    Pin<Box<dyn FusedStream<Item = Result<Msg<M>, broadcast::error::RecvError>> + Send>>;
 
 fn incoming<M: Clone + Send + Unpin + 'static>(
-    mut rx: broadcast::Receiver<Payload<Msg<M>>>,
+    mut rx: broadcast::Receiver<Msg<M>>,
     me: u16,
+    faults: Option<NetworkFaults<M>>,
 ) -> Incoming<M> {
     let stream = async_stream::stream! {
+        let mut rng = faults.as_ref().map(|f| f.rng(me));
+        let mut delayed: Vec<(Instant, Msg<M>)> = vec![];
+
         loop {
-            let item = rx.recv().await;
-            yield item
+            // computed before any yield, borrows of `delayed` can't be held across one
+            let due_now = delayed.iter().position(|(due, _)| *due <= Instant::now());
+            if let Some(i) = due_now {
+                yield Ok(delayed.remove(i).1);
+                continue;
+            }
+
+            let next_due = delayed.iter().map(|(due, _)| *due).min();
+            let item = match next_due {
+                None => rx.recv().await,
+                Some(due) => match select(Box::pin(rx.recv()), Box::pin(sleep_until(due))).await {
+                    Either::Left((item, _)) => item,
+                    // a delayed message is due
+                    Either::Right(_) => continue,
+                },
+            };
+
+            let msg = match item {
+                Ok(m) if m.sender != me && (m.receiver.is_none() || m.receiver == Some(me)) => m,
+                Ok(_) => continue,
+                Err(err) => {
+                    yield Err(err);
+                    continue;
+                }
+            };
+
+            // owned, `M` is not `Sync` so neither `msg` nor `faults` may be borrowed across a yield
+            let deliveries = match (&faults, &mut rng) {
+                (Some(faults), Some(rng)) => faults.apply(rng, &msg, me),
+                _ => vec![(0, msg)],
+            };
+            for (ticks, msg) in deliveries {
+                if ticks == 0 {
+                    yield Ok(msg);
+                } else {
+                    delayed.push((Instant::now() + ASYNC_TICK * ticks, msg));
+                }
+            }
         }
     };
-    let stream = StreamExt::filter(stream, move |m| {
-        ready(match m {
-            Ok(m) => m.body.sender != me && (m.body.receiver.is_none() || m.body.receiver == Some(me)),
-            Err(_) => true,
-        })
-    });
     Box::pin(stream)
 }
 
 struct Outgoing<M> {
-    sender: broadcast::Sender<Payload<Msg<M>>>,
+    sender: broadcast::Sender<Msg<M>>,
 }
 
-impl<M> Sink<Payload<Msg<M>>> for Outgoing<M> {
-    type Error = broadcast::error::SendError<Payload<Msg<M>>>;
+impl<M> Sink<Msg<M>> for Outgoing<M> {
+    type Error = broadcast::error::SendError<Msg<M>>;
 
     fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
-    fn start_send(self: Pin<&mut Self>, item: Payload<Msg<M>>) -> Result<(), Self::Error> {
+    fn start_send(self: Pin<&mut Self>, item: Msg<M>) -> Result<(), Self::Error> {
         self.sender.send(item).map(|_| ())
     }
 
@@ -188,7 +251,7 @@ pub enum AsyncSimulationError<SM: StateMachine> {
         async_runtime::Error<
             SM::Err,
             broadcast::error::RecvError,
-            broadcast::error::SendError<Payload<Msg<SM::MessageBody>>>,
+            broadcast::error::SendError<Msg<SM::MessageBody>>,
         >,
     ),
     /// Protocol execution produced a panic
@@ -0,0 +1,243 @@
+use crate::sm::Msg;
+
+/// Longest delay, in ticks, a [Fault::Reorder]ed message may get
+pub const MAX_REORDER_TICKS: u32 = 4;
+
+/// What happens to a message matched by a [FaultRule]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// Message is never delivered
+    Drop,
+    /// Message is delivered the given number of ticks late
+    Delay(u32),
+    /// Message is delivered twice in a row
+    Duplicate,
+    /// Message body is tampered with by the [corruption](NetworkFaults::corrupt_with) function
+    Corrupt,
+    /// Message is delivered after a random delay of up to [MAX_REORDER_TICKS], so it may overtake
+    /// or be overtaken by other messages
+    Reorder,
+}
+
+/// Selects messages on their way to one receiver and the fault applied to them
+///
+/// A broadcast message is matched separately for every receiver, so a rule restricted with
+/// [to](FaultRule::to) affects only that party's copy.
+#[derive(Clone)]
+pub struct FaultRule<B> {
+    fault: Fault,
+    from: Option<u16>,
+    to: Option<u16>,
+    matching: Option<fn(&Msg<B>) -> bool>,
+    probability: f64,
+}
+
+impl<B> FaultRule<B> {
+    /// Applies `fault` to every message
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            from: None,
+            to: None,
+            matching: None,
+            probability: 1.0,
+        }
+    }
+
+    /// Only messages sent by `party`
+    pub fn from(mut self, party: u16) -> Self {
+        self.from = Some(party);
+        self
+    }
+
+    /// Only messages delivered to `party`
+    pub fn to(mut self, party: u16) -> Self {
+        self.to = Some(party);
+        self
+    }
+
+    /// Only messages `matching` returns `true` for, e.g. messages of a specific round
+    pub fn matching(mut self, matching: fn(&Msg<B>) -> bool) -> Self {
+        self.matching = Some(matching);
+        self
+    }
+
+    /// Applies the fault to a matched message with the given probability only
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability;
+        self
+    }
+
+    fn matches(&self, msg: &Msg<B>, receiver: u16) -> bool {
+        self.from.map(|from| from == msg.sender).unwrap_or(true)
+            && self.to.map(|to| to == receiver).unwrap_or(true)
+            && self.matching.map(|matching| matching(msg)).unwrap_or(true)
+    }
+}
+
+/// Adversarial message delivery for [Simulation](super::Simulation) and
+/// [AsyncSimulation](super::AsyncSimulation)
+///
+/// Every message on its way to a receiver is checked against the rules in the order they were
+/// added, the first matching rule (that passes its probability check) decides what happens to it.
+/// All randomness comes from `seed`, so a failing run can be reproduced.
+///
+/// ## Example
+/// ```no_run
+/// # use skw_round_based::Msg;
+/// # use skw_round_based::dev::{Fault, FaultRule, NetworkFaults};
+/// # fn is_last_round(_: &Msg<u32>) -> bool { true }
+/// let mut faults = NetworkFaults::<u32>::new(42);
+/// faults
+///     // party 2 never gets to party 1 at the last round
+///     .add_rule(FaultRule::new(Fault::Drop).from(2).to(1).matching(is_last_round))
+///     // and the network is lossy in general
+///     .add_rule(FaultRule::new(Fault::Reorder).with_probability(0.3));
+/// ```
+#[derive(Clone)]
+pub struct NetworkFaults<B> {
+    seed: u64,
+    rules: Vec<FaultRule<B>>,
+    corrupt: Option<fn(&mut B, u64)>,
+}
+
+impl<B> NetworkFaults<B> {
+    /// No faults, yet
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rules: vec![],
+            corrupt: None,
+        }
+    }
+
+    /// Appends a rule
+    pub fn add_rule(&mut self, rule: FaultRule<B>) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Sets how [Fault::Corrupt] tampers with a message body, given a random value
+    pub fn corrupt_with(&mut self, corrupt: fn(&mut B, u64)) -> &mut Self {
+        self.corrupt = Some(corrupt);
+        self
+    }
+
+    /// Independent random stream for `party`
+    pub(crate) fn rng(&self, party: u16) -> FaultRng {
+        FaultRng(self.seed ^ u64::from(party).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+}
+
+impl<B: Clone> NetworkFaults<B> {
+    /// Copies of `msg` to deliver to `receiver`, each with its delay in ticks
+    pub(crate) fn apply(&self, rng: &mut FaultRng, msg: &Msg<B>, receiver: u16) -> Vec<(u32, Msg<B>)> {
+        let fault = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(msg, receiver))
+            .find(|rule| rng.chance(rule.probability))
+            .map(|rule| rule.fault);
+
+        match fault {
+            None => vec![(0, msg.clone())],
+            Some(Fault::Drop) => vec![],
+            Some(Fault::Delay(ticks)) => vec![(ticks, msg.clone())],
+            Some(Fault::Duplicate) => vec![(0, msg.clone()), (0, msg.clone())],
+            Some(Fault::Corrupt) => {
+                let corrupt = self
+                    .corrupt
+                    .expect("Fault::Corrupt requires NetworkFaults::corrupt_with");
+                let mut msg = msg.clone();
+                corrupt(&mut msg.body, rng.next_u64());
+                vec![(0, msg)]
+            }
+            Some(Fault::Reorder) => {
+                let ticks = (rng.next_u64() % u64::from(MAX_REORDER_TICKS + 1)) as u32;
+                vec![(ticks, msg.clone())]
+            }
+        }
+    }
+}
+
+/// SplitMix64, small and stable across platforms and releases
+#[derive(Clone, Debug)]
+pub(crate) struct FaultRng(u64);
+
+impl FaultRng {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49EB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability >= 1.0 {
+            return true;
+        }
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+/// Messages in flight between the parties of a [Simulation](super::Simulation)
+///
+/// Time is counted in ticks, one tick per delivery step of the simulation.
+pub(crate) struct Network<'f, B> {
+    faults: Option<&'f NetworkFaults<B>>,
+    rng: FaultRng,
+    parties: Vec<u16>,
+    tick: u32,
+    in_flight: Vec<(u32, u16, Msg<B>)>,
+}
+
+impl<'f, B: Clone> Network<'f, B> {
+    pub fn new(faults: Option<&'f NetworkFaults<B>>, parties: Vec<u16>) -> Self {
+        Self {
+            rng: faults.map(|f| f.rng(0)).unwrap_or(FaultRng(0)),
+            faults,
+            parties,
+            tick: 0,
+            in_flight: vec![],
+        }
+    }
+
+    pub fn send(&mut self, msg: Msg<B>) {
+        let receivers: Vec<u16> = match msg.receiver {
+            Some(receiver) => vec![receiver],
+            None => self
+                .parties
+                .iter()
+                .copied()
+                .filter(|p| *p != msg.sender)
+                .collect(),
+        };
+
+        for receiver in receivers {
+            let copies = match self.faults {
+                Some(faults) => faults.apply(&mut self.rng, &msg, receiver),
+                None => vec![(0, msg.clone())],
+            };
+            for (delay, msg) in copies {
+                self.in_flight.push((self.tick + 1 + delay, receiver, msg));
+            }
+        }
+    }
+
+    /// Advances time by one tick, returning the messages due with their receivers
+    pub fn deliver(&mut self) -> Vec<(u16, Msg<B>)> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (due, later): (Vec<_>, Vec<_>) = self
+            .in_flight
+            .drain(..)
+            .partition(|(due, _, _)| *due <= tick);
+        self.in_flight = later;
+        due.into_iter().map(|(_, receiver, msg)| (receiver, msg)).collect()
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
@@ -3,9 +3,11 @@
 #[cfg(feature = "async-runtime")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async-runtime")))]
 mod async_simulation;
+mod faults;
 mod simulation;
 
 #[cfg(feature = "async-runtime")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async-runtime")))]
 pub use async_simulation::*;
+pub use faults::{Fault, FaultRule, NetworkFaults, MAX_REORDER_TICKS};
 pub use simulation::*;
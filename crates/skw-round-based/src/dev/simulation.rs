@@ -7,6 +7,8 @@ mod benchmark;
 use benchmark::Benchmark;
 pub use benchmark::{BenchmarkResults, Measurements};
 
+use super::faults::{Network, NetworkFaults};
+
 /// Emulates running protocol between local parties
 ///
 /// Takes parties (every party is instance of [StateMachine](crate::sm::StateMachine)) and
@@ -18,6 +20,11 @@ pub use benchmark::{BenchmarkResults, Measurements};
 /// any parallelism). It makes this simulation more useful for writing benchmarks that detect
 /// performance regression.
 ///
+/// Messages can be dropped, delayed, duplicated, reordered or corrupted by
+/// [injecting faults](Simulation::inject_faults). If no party can make progress anymore, the first
+/// unfinished party with a [round timeout](StateMachine::round_timeout) is timed out and its
+/// [timeout error](StateMachine::round_timeout_reached) is returned.
+///
 /// ## Limitations
 /// * No proper error handling. It should attach a context to returning error (like current round,
 ///   what we was doing when error occurred, etc.). The only way to determine error context is to
@@ -44,20 +51,22 @@ pub use benchmark::{BenchmarkResults, Measurements};
 /// # Ok(())
 /// # }
 /// ```
-pub struct Simulation<P> {
+pub struct Simulation<P: StateMachine> {
     /// Parties running a protocol
     ///
     /// Field is exposed mainly to allow examining parties state after simulation is completed.
     pub parties: Vec<P>,
     benchmark: Benchmark,
+    faults: Option<NetworkFaults<P::MessageBody>>,
 }
 
-impl<P> Simulation<P> {
+impl<P: StateMachine> Simulation<P> {
     /// Creates new simulation
     pub fn new() -> Self {
         Self {
             parties: vec![],
             benchmark: Benchmark::disabled(),
+            faults: None,
         }
     }
 
@@ -86,6 +95,14 @@ impl<P> Simulation<P> {
     pub fn benchmark_results(&self) -> Option<&BenchmarkResults> {
         self.benchmark.results()
     }
+
+    /// Delivers messages through an adversarial network instead of a perfect one
+    ///
+    /// One tick of [Fault::Delay](super::Fault::Delay) is one delivery step of the simulation.
+    pub fn inject_faults(&mut self, faults: NetworkFaults<P::MessageBody>) -> &mut Self {
+        self.faults = Some(faults);
+        self
+    }
 }
 
 impl<P> Simulation<P>
@@ -111,14 +128,16 @@ where
             .iter_mut()
             .map(|p| Party { state: p })
             .collect();
+        let mut network = Network::new(
+            self.faults.as_ref(),
+            parties.iter().map(|p| p.state.party_ind()).collect(),
+        );
 
         debug!("Simulation starts");
 
-        let mut msgs_pull = vec![];
-
         for party in &mut parties {
             party.proceed_if_needed(&mut self.benchmark)?;
-            party.send_outgoing(&mut msgs_pull);
+            party.send_outgoing(&mut network);
         }
 
         if let Some(results) = finish_if_possible(&mut parties)? {
@@ -126,21 +145,26 @@ where
         }
 
         loop {
-            let msgs_pull_frozen = msgs_pull.split_off(0);
+            let delivered = network.deliver();
+            let mut progressed = !delivered.is_empty();
 
             for party in &mut parties {
-                party.handle_incoming(&msgs_pull_frozen)?;
-                party.send_outgoing(&mut msgs_pull);
+                party.handle_incoming(&delivered)?;
+                party.send_outgoing(&mut network);
             }
 
             for party in &mut parties {
-                party.proceed_if_needed(&mut self.benchmark)?;
-                party.send_outgoing(&mut msgs_pull);
+                progressed |= party.proceed_if_needed(&mut self.benchmark)?;
+                party.send_outgoing(&mut network);
             }
 
             if let Some(results) = finish_if_possible(&mut parties)? {
                 return Ok(results);
             }
+
+            if !progressed && network.is_idle() {
+                return Err(time_out(&mut parties));
+            }
         }
     }
 }
@@ -156,9 +180,10 @@ where
     P::Err: Debug,
     P::MessageBody: Debug + Clone,
 {
-    pub fn proceed_if_needed(&mut self, benchmark: &mut Benchmark) -> Result<(), P::Err> {
+    /// Returns whether the party proceeded
+    pub fn proceed_if_needed(&mut self, benchmark: &mut Benchmark) -> Result<bool, P::Err> {
         if !self.state.wants_to_proceed() {
-            return Ok(());
+            return Ok(false);
         }
 
         debug!("Party {} wants to proceed", self.state.party_ind());
@@ -184,10 +209,10 @@ where
         debug!("  - took  : {:?}", duration);
         debug!("");
 
-        Ok(())
+        Ok(true)
     }
 
-    pub fn send_outgoing(&mut self, msgs_pull: &mut Vec<Msg<P::MessageBody>>) {
+    pub fn send_outgoing(&mut self, network: &mut Network<P::MessageBody>) {
         if !self.state.message_queue().is_empty() {
             debug!(
                 "Party {} sends {} message(s)",
@@ -196,15 +221,15 @@ where
             );
             debug!("");
 
-            msgs_pull.append(self.state.message_queue())
+            for msg in self.state.message_queue().drain(..) {
+                network.send(msg)
+            }
         }
     }
 
-    pub fn handle_incoming(&mut self, msgs_pull: &[Msg<P::MessageBody>]) -> Result<(), P::Err> {
-        for msg in msgs_pull {
-            if Some(self.state.party_ind()) != msg.receiver
-                && (msg.receiver.is_some() || msg.sender == self.state.party_ind())
-            {
+    pub fn handle_incoming(&mut self, delivered: &[(u16, Msg<P::MessageBody>)]) -> Result<(), P::Err> {
+        for (receiver, msg) in delivered {
+            if *receiver != self.state.party_ind() {
                 continue;
             }
             debug!(
@@ -277,3 +302,22 @@ where
         Ok(None)
     }
 }
+
+/// Nothing is in flight and nobody wants to proceed - time out the first waiting party
+fn time_out<P>(parties: &mut [Party<P>]) -> P::Err
+where
+    P: StateMachine,
+    P: Debug,
+    P::Err: Debug,
+    P::MessageBody: Debug + Clone,
+{
+    let party = parties
+        .iter_mut()
+        .find(|p| !p.state.is_finished() && p.state.round_timeout().is_some())
+        .expect("simulation stalled and no party has a round timeout to report it");
+
+    debug!("Simulation stalled, party {} times out", party.state.party_ind());
+    debug!("");
+
+    party.state.round_timeout_reached()
+}
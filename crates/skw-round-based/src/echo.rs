@@ -0,0 +1,477 @@
+//! Echo broadcast (reliable broadcast) on top of any [StateMachine]
+//!
+//! A plain broadcast is just the same message sent to everyone, so a malicious sender can hand
+//! different parties different "broadcast" values. [EchoBroadcast] makes every party echo the
+//! digests of all broadcasts it received in a round and compares the echoes before letting the
+//! round's outcome leave the party. If two parties saw different broadcasts from the same sender
+//! the protocol aborts with [EchoError::Equivocation] naming that sender.
+//!
+//! Outgoing messages of round `k + 1` are held back until round `k` is verified. Rounds may still
+//! be advanced inside [handle_incoming](StateMachine::handle_incoming), but nothing computed from
+//! an inconsistent broadcast is ever sent.
+//!
+//! The wrapped state machine is expected to queue the messages of round `k` when it enters round
+//! `k`. Messages of rounds it has not reached yet are kept aside until it does, so every call into
+//! it advances at most one round and its outgoing messages are tagged with the round they were
+//! sent at.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sm::{IsCritical, Msg, StateMachine};
+
+/// Digest of a broadcast message body, e.g. its sha256 hash
+pub type Digest = [u8; 32];
+
+/// Message transmitted by [EchoBroadcast]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum EchoMsg<B> {
+    /// Message of the wrapped protocol, tagged with the round it was sent at
+    Protocol { round: u16, body: B },
+    /// Digests of the broadcasts received at `round`, indexed by `sender - 1`
+    Echo {
+        round: u16,
+        digests: Vec<Option<Digest>>,
+    },
+}
+
+/// Wraps a [StateMachine] so that its broadcast messages are echoed and cross-checked
+pub struct EchoBroadcast<SM: StateMachine> {
+    inner: SM,
+    digest: fn(&SM::MessageBody) -> Digest,
+
+    rounds: BTreeMap<u16, RoundEcho>,
+    /// messages of the inner state machine waiting for the previous round to be verified
+    held: Vec<(u16, Msg<SM::MessageBody>)>,
+    /// incoming messages of rounds the inner state machine has not reached yet
+    early: Vec<(u16, Msg<SM::MessageBody>)>,
+    queue: Vec<Msg<EchoMsg<SM::MessageBody>>>,
+}
+
+#[derive(Debug)]
+struct RoundEcho {
+    /// digest of the broadcast received from every party, ours included
+    received: Vec<Option<Digest>>,
+    /// what we echoed to the others
+    sent: Option<Vec<Option<Digest>>>,
+    /// echoes of the other parties
+    echoes: Vec<Option<Vec<Option<Digest>>>>,
+    verified: bool,
+}
+
+impl RoundEcho {
+    fn new(parties: u16) -> Self {
+        Self {
+            received: vec![None; usize::from(parties)],
+            sent: None,
+            echoes: vec![None; usize::from(parties)],
+            verified: false,
+        }
+    }
+
+    fn has_broadcasts(&self) -> bool {
+        self.received.iter().any(Option::is_some)
+    }
+
+    fn settled(&self) -> bool {
+        self.verified || !self.has_broadcasts()
+    }
+}
+
+impl<SM: StateMachine> EchoBroadcast<SM> {
+    /// Wraps `inner`, hashing broadcast bodies with `digest`
+    ///
+    /// `digest` must be collision resistant over the message encoding, otherwise an equivocating
+    /// sender could pick two different messages with the same digest.
+    pub fn new(inner: SM, digest: fn(&SM::MessageBody) -> Digest) -> Self {
+        let mut echo = Self {
+            inner,
+            digest,
+            rounds: BTreeMap::new(),
+            held: vec![],
+            early: vec![],
+            queue: vec![],
+        };
+        // messages queued on construction belong to the initial round
+        echo.take_outgoing();
+        echo
+    }
+
+    /// Wrapped state machine
+    pub fn inner(&self) -> &SM {
+        &self.inner
+    }
+
+    /// Unwraps the state machine
+    pub fn into_inner(self) -> SM {
+        self.inner
+    }
+
+    fn round_mut(&mut self, round: u16) -> &mut RoundEcho {
+        let parties = self.inner.parties();
+        self.rounds
+            .entry(round)
+            .or_insert_with(|| RoundEcho::new(parties))
+    }
+
+    fn settled(&self, round: u16) -> bool {
+        self.rounds.get(&round).map(RoundEcho::settled).unwrap_or(true)
+    }
+
+    /// Tags the messages the inner state machine just queued with the round it is at, remembering
+    /// our own broadcasts
+    ///
+    /// Must run after every call into the inner state machine, before it may move on.
+    fn take_outgoing(&mut self) {
+        let me = self.inner.party_ind();
+        let round = self.inner.current_round();
+
+        let outgoing: Vec<_> = self.inner.message_queue().drain(..).collect();
+        for msg in outgoing {
+            if msg.receiver.is_none() {
+                let digest = (self.digest)(&msg.body);
+                self.round_mut(round).received[usize::from(me - 1)] = Some(digest);
+            }
+            self.held.push((round, msg));
+        }
+    }
+
+    /// Hands `msg` to the inner state machine, or keeps it aside if it belongs to a later round
+    ///
+    /// Once the inner state machine reaches the round of a message kept aside, the message is
+    /// delivered too. The first error of the inner state machine is returned.
+    fn deliver(&mut self, round: u16, msg: Msg<SM::MessageBody>) -> Result<(), SM::Err> {
+        if round > self.inner.current_round() {
+            self.early.push((round, msg));
+            return Ok(());
+        }
+        let result = self.inner.handle_incoming(msg);
+        self.take_outgoing();
+        result.and(self.deliver_early())
+    }
+
+    fn deliver_early(&mut self) -> Result<(), SM::Err> {
+        let mut result = Ok(());
+        while let Some(i) = self
+            .early
+            .iter()
+            .position(|(round, _)| *round <= self.inner.current_round())
+        {
+            let (_, msg) = self.early.remove(i);
+            let delivered = self.inner.handle_incoming(msg);
+            self.take_outgoing();
+            result = result.and(delivered);
+        }
+        result
+    }
+
+    /// Moves the tagged messages of the inner state machine through the echo rounds
+    fn sync(&mut self) -> Result<(), EchoError<SM::Err>> {
+        let me = self.inner.party_ind();
+        let current_round = self.inner.current_round();
+
+        // 1. echo every round the inner state machine is done receiving
+        let done_receiving = |round: u16| {
+            current_round > round
+                || (current_round == round && self.inner.wants_to_proceed())
+                || self.inner.is_finished()
+        };
+        let mut echoes = vec![];
+        for (round, state) in &mut self.rounds {
+            if state.sent.is_none() && state.has_broadcasts() && done_receiving(*round) {
+                state.sent = Some(state.received.clone());
+                echoes.push(Msg {
+                    sender: me,
+                    receiver: None,
+                    body: EchoMsg::Echo {
+                        round: *round,
+                        digests: state.received.clone(),
+                    },
+                });
+            }
+        }
+        self.queue.append(&mut echoes);
+
+        // 2. cross-check rounds all echoes are in for
+        for (round, state) in &mut self.rounds {
+            if state.verified {
+                continue;
+            }
+            let sent = match &state.sent {
+                Some(sent) => sent,
+                None => continue,
+            };
+            let others_echoed = state
+                .echoes
+                .iter()
+                .enumerate()
+                .all(|(i, echo)| i == usize::from(me - 1) || echo.is_some());
+            if !others_echoed {
+                continue;
+            }
+
+            for (i, echo) in state.echoes.iter().enumerate() {
+                let echo = match echo {
+                    Some(echo) => echo,
+                    None => continue,
+                };
+                if let Some(origin) = (0..sent.len()).find(|j| sent[*j] != echo[*j]) {
+                    return Err(EchoError::Equivocation {
+                        round: *round,
+                        sender: origin as u16 + 1,
+                        echoed_by: i as u16 + 1,
+                    });
+                }
+            }
+            state.verified = true;
+        }
+
+        // 3. release messages whose previous round is verified
+        let held = std::mem::take(&mut self.held);
+        for (round, msg) in held {
+            if round == 0 || self.settled(round - 1) {
+                self.queue.push(Msg {
+                    sender: msg.sender,
+                    receiver: msg.receiver,
+                    body: EchoMsg::Protocol {
+                        round,
+                        body: msg.body,
+                    },
+                });
+            } else {
+                self.held.push((round, msg));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parties whose echo is still missing in the first round waiting for echoes
+    fn missing_echoes(&self) -> Option<(u16, Vec<u16>)> {
+        let me = usize::from(self.inner.party_ind() - 1);
+        self.rounds
+            .iter()
+            .find(|(_, state)| state.sent.is_some() && !state.verified)
+            .map(|(round, state)| {
+                let parties = state
+                    .echoes
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, echo)| *i != me && echo.is_none())
+                    .map(|(i, _)| i as u16 + 1)
+                    .collect();
+                (*round, parties)
+            })
+    }
+}
+
+impl<SM> StateMachine for EchoBroadcast<SM>
+where
+    SM: StateMachine,
+{
+    type MessageBody = EchoMsg<SM::MessageBody>;
+    type Err = EchoError<SM::Err>;
+    type Output = SM::Output;
+
+    fn handle_incoming(&mut self, msg: Msg<Self::MessageBody>) -> Result<(), Self::Err> {
+        let parties = self.inner.parties();
+        if msg.sender == 0 || msg.sender > parties {
+            return Err(EchoError::UnknownSender(msg.sender));
+        }
+        let origin = usize::from(msg.sender - 1);
+
+        match msg.body {
+            EchoMsg::Protocol { round, body } => {
+                if msg.receiver.is_none() {
+                    let digest = (self.digest)(&body);
+                    let slot = &mut self.round_mut(round).received[origin];
+                    match *slot {
+                        Some(seen) if seen != digest => {
+                            return Err(EchoError::Equivocation {
+                                round,
+                                sender: msg.sender,
+                                echoed_by: msg.sender,
+                            })
+                        }
+                        _ => *slot = Some(digest),
+                    }
+                }
+
+                let result = self
+                    .deliver(
+                        round,
+                        Msg {
+                            sender: msg.sender,
+                            receiver: msg.receiver,
+                            body,
+                        },
+                    )
+                    .map_err(EchoError::Inner);
+                self.sync()?;
+                result
+            }
+            EchoMsg::Echo { round, digests } => {
+                if msg.receiver.is_some() || digests.len() != usize::from(parties) {
+                    return Err(EchoError::MalformedEcho {
+                        round,
+                        sender: msg.sender,
+                    });
+                }
+                let echo = &mut self.round_mut(round).echoes[origin];
+                // the first echo counts, a second one can't undo what we already compared
+                if echo.is_none() {
+                    *echo = Some(digests);
+                }
+                self.sync()
+            }
+        }
+    }
+
+    fn message_queue(&mut self) -> &mut Vec<Msg<Self::MessageBody>> {
+        &mut self.queue
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        self.inner.wants_to_proceed() && self.settled(self.inner.current_round())
+    }
+
+    fn proceed(&mut self) -> Result<(), Self::Err> {
+        let result = if self.wants_to_proceed() {
+            let proceeded = self.inner.proceed();
+            self.take_outgoing();
+            proceeded.and(self.deliver_early()).map_err(EchoError::Inner)
+        } else {
+            Ok(())
+        };
+        self.sync()?;
+        result
+    }
+
+    fn round_timeout(&self) -> Option<Duration> {
+        self.inner.round_timeout()
+    }
+
+    fn round_timeout_reached(&mut self) -> Self::Err {
+        match self.missing_echoes() {
+            Some((round, parties)) => EchoError::MissingEchoes { round, parties },
+            None => EchoError::Inner(self.inner.round_timeout_reached()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.inner.is_finished()
+            && self.held.is_empty()
+            && self.rounds.values().all(RoundEcho::settled)
+    }
+
+    fn pick_output(&mut self) -> Option<Result<Self::Output, Self::Err>> {
+        if !self.is_finished() {
+            return None;
+        }
+        self.inner
+            .pick_output()
+            .map(|result| result.map_err(EchoError::Inner))
+    }
+
+    fn current_round(&self) -> u16 {
+        self.inner.current_round()
+    }
+
+    fn total_rounds(&self) -> Option<u16> {
+        self.inner.total_rounds()
+    }
+
+    fn party_ind(&self) -> u16 {
+        self.inner.party_ind()
+    }
+
+    fn parties(&self) -> u16 {
+        self.inner.parties()
+    }
+}
+
+impl<SM> fmt::Debug for EchoBroadcast<SM>
+where
+    SM: StateMachine + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EchoBroadcast")
+            .field("inner", &self.inner)
+            .field("rounds", &self.rounds)
+            .field("held", &self.held.len())
+            .field("early", &self.early.len())
+            .field("queue", &self.queue.len())
+            .finish()
+    }
+}
+
+/// Error of [EchoBroadcast]
+#[derive(Debug)]
+pub enum EchoError<E> {
+    /// Error of the wrapped state machine
+    Inner(E),
+    /// `sender` broadcast different messages at `round`, as the echo of `echoed_by` revealed
+    ///
+    /// `echoed_by` equals `sender` if we received two different broadcasts from it ourselves.
+    Equivocation {
+        round: u16,
+        sender: u16,
+        echoed_by: u16,
+    },
+    /// Echo is not a broadcast or doesn't hold a digest slot per party
+    MalformedEcho { round: u16, sender: u16 },
+    /// Round timeout reached while waiting for the echoes of `parties`
+    MissingEchoes { round: u16, parties: Vec<u16> },
+    /// Message came from a party index out of `[1; n]`
+    UnknownSender(u16),
+}
+
+impl<E: IsCritical> IsCritical for EchoError<E> {
+    fn is_critical(&self) -> bool {
+        match self {
+            Self::Inner(err) => err.is_critical(),
+            Self::Equivocation { .. } | Self::MalformedEcho { .. } | Self::MissingEchoes { .. } => {
+                true
+            }
+            Self::UnknownSender(_) => false,
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for EchoError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Inner(err) => write!(f, "{err}"),
+            Self::Equivocation {
+                round,
+                sender,
+                echoed_by,
+            } => write!(
+                f,
+                "party {sender} equivocated at round {round} (echo of party {echoed_by} disagrees)"
+            ),
+            Self::MalformedEcho { round, sender } => {
+                write!(f, "malformed echo of party {sender} at round {round}")
+            }
+            Self::MissingEchoes { round, parties } => {
+                write!(f, "no echo of round {round} from parties {parties:?}")
+            }
+            Self::UnknownSender(sender) => write!(f, "message from unknown party {sender}"),
+        }
+    }
+}
+
+impl<E> std::error::Error for EchoError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Inner(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Framing of protocol messages on the wire
+//!
+//! [AsyncProtocol](crate::AsyncProtocol) doesn't send bare [Msg]s: every outgoing message is
+//! [wrapped](Envelope::wrap) together with the job's [Header], and every incoming one is checked
+//! against it before being [unwrapped](Envelope::unwrap) and handed to the state machine. The
+//! envelope is up to the application, a [Msg] itself is an envelope without header.
+
+use std::fmt;
+
+use crate::Msg;
+
+/// Describes the job every message of a protocol execution belongs to
+pub trait Header: Clone {
+    /// Checks that a message carrying this header belongs to the job described by `job`
+    fn check(&self, job: &Self) -> Result<(), InvalidMessage>;
+}
+
+impl Header for () {
+    fn check(&self, _job: &Self) -> Result<(), InvalidMessage> {
+        Ok(())
+    }
+}
+
+/// Protocol message with the [Header] of its job, as sent and received by transport
+pub trait Envelope<B>: Sized {
+    type Header: Header;
+
+    /// Frames an outgoing message
+    fn wrap(header: Self::Header, msg: Msg<B>) -> Self;
+    /// Header of the job the message belongs to
+    fn header(&self) -> &Self::Header;
+    /// The framed message
+    fn msg(&self) -> &Msg<B>;
+    /// Takes the message out of the frame
+    fn unwrap(self) -> Msg<B>;
+}
+
+impl<B> Envelope<B> for Msg<B> {
+    type Header = ();
+
+    fn wrap(_header: (), msg: Msg<B>) -> Self {
+        msg
+    }
+
+    fn header(&self) -> &() {
+        &()
+    }
+
+    fn msg(&self) -> &Msg<B> {
+        self
+    }
+
+    fn unwrap(self) -> Msg<B> {
+        self
+    }
+}
+
+/// Reason why a received message was dropped before reaching the state machine
+///
+/// Such messages are reported to the
+/// [watcher](crate::async_runtime::watcher::ProtocolWatcher::caught_invalid_message) and never
+/// fail the protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InvalidMessage {
+    /// Message belongs to another job
+    ForeignJob,
+    /// Message belongs to the job, but its header contradicts the job's one (e.g. the threshold,
+    /// number of parties or committee differ)
+    HeaderMismatch,
+    /// Sender claims to be this party
+    OwnMessage,
+    /// Sender index is not in range `[1; n]`
+    SenderOutOfRange { sender: u16 },
+    /// P2P message for another party
+    NotAddressedToUs { receiver: u16 },
+}
+
+impl fmt::Display for InvalidMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ForeignJob => write!(f, "message belongs to another job"),
+            Self::HeaderMismatch => write!(f, "message header doesn't match the job's header"),
+            Self::OwnMessage => write!(f, "message claims to be sent by ourselves"),
+            Self::SenderOutOfRange { sender } => {
+                write!(f, "sender index {sender} is out of range")
+            }
+            Self::NotAddressedToUs { receiver } => {
+                write!(f, "message is addressed to party {receiver}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidMessage {}
@@ -0,0 +1,238 @@
+//! Recording protocol executions and replaying them offline
+//!
+//! [AsyncProtocol](crate::AsyncProtocol) with a [recorder](crate::AsyncProtocol::set_recorder)
+//! writes every received and sent message and every round transition of a party to a
+//! transcript. A transcript can later be fed back into a fresh instance of the same party with
+//! [replay].
+//!
+//! ## Determinism
+//! Replay only reproduces the recorded execution if the party is deterministic given its local
+//! inputs. Protocols like GG20 keygen and signing draw fresh randomness (Paillier keys, nonces,
+//! commitments) every round, so such a party has to be constructed from a seed for the
+//! randomness it draws, and the replayed instance from the same seed. [replay] checks that the
+//! party sends exactly the messages it sent originally, and stops with [ReplayError::Diverged]
+//! at the first one it doesn't.
+//!
+//! Transcripts never contain the state machine itself. The application recording a party
+//! writes a [TranscriptHeader] first, naming the protocol and the party's local inputs: public
+//! ones in the clear, secret ones (its key share, the seed) encrypted by the application or left
+//! out, in which case they have to be supplied again to replay. Messages are recorded as they
+//! were on the wire though, so p2p messages of the protocol are in the clear and a transcript
+//! should be kept as private as the traffic itself.
+//!
+//! ## Format
+//! One JSON encoded [TranscriptEntry] per line.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::envelope::Envelope;
+use crate::{IsCritical, Msg, StateMachine};
+
+/// A recorded event of protocol execution, messages are kept in their
+/// [envelopes](crate::envelope) `E`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TranscriptEntry<E> {
+    /// Who was recorded, written by the application before the execution
+    Header(TranscriptHeader),
+    /// Party entered the round
+    Round(u16),
+    /// Message received by the party
+    Incoming(E),
+    /// Message sent by the party
+    Outgoing(E),
+}
+
+/// Borrowed [TranscriptEntry], serialized the same way
+#[derive(Debug, Serialize)]
+pub enum Entry<'a, E> {
+    Header(&'a TranscriptHeader),
+    Round(u16),
+    Incoming(&'a E),
+    Outgoing(&'a E),
+}
+
+/// Protocol and local inputs of the recorded party
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    /// Protocol the party runs, as named by the application
+    pub protocol: String,
+    /// Local inputs which aren't secret, e.g. the party index and the threshold
+    pub inputs: serde_json::Value,
+    /// Secret local inputs and the RNG seed, encrypted by the application. `None` if they were
+    /// left out of the transcript.
+    pub sealed_inputs: Option<Vec<u8>>,
+}
+
+/// Receives transcript entries from [AsyncProtocol](crate::AsyncProtocol)
+///
+/// Recording is best effort: failing to record an entry never affects protocol execution.
+pub trait Recorder<E>: Send {
+    fn record(&mut self, entry: Entry<'_, E>);
+}
+
+/// Writes transcript in JSON lines to `W`
+pub struct TranscriptWriter<W> {
+    out: W,
+}
+
+impl<W: Write> TranscriptWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    /// Appends an entry and flushes it, so the transcript survives a crash of the process
+    pub fn write(&mut self, entry: &Entry<'_, impl Serialize>) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, entry)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W, E> Recorder<E> for TranscriptWriter<W>
+where
+    W: Write + Send,
+    E: Serialize,
+{
+    fn record(&mut self, entry: Entry<'_, E>) {
+        if let Err(err) = self.write(&entry) {
+            log::warn!("Failed to record transcript entry: {}", err);
+        }
+    }
+}
+
+/// Reads a transcript written by [TranscriptWriter]
+pub fn read_transcript<E, R>(input: R) -> io::Result<Vec<TranscriptEntry<E>>>
+where
+    E: DeserializeOwned,
+    R: BufRead,
+{
+    input
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Feeds recorded incoming messages into `party`, in the order they were received
+///
+/// Every message the party sends is compared with the one recorded at the same position, round
+/// transitions and the header are skipped. Returns the party's output, or the first critical
+/// error it runs into. That is the outcome of the recorded execution only if `party` was
+/// constructed from the same local inputs and draws the same randomness, see
+/// [Determinism](self#determinism).
+pub fn replay<SM, E, I>(mut party: SM, transcript: I) -> Result<SM::Output, ReplayError<SM::Err>>
+where
+    SM: StateMachine,
+    SM::MessageBody: Serialize,
+    E: Envelope<SM::MessageBody>,
+    I: IntoIterator<Item = TranscriptEntry<E>>,
+{
+    let mut sent = VecDeque::new();
+    let mut round = party.current_round();
+    proceed_if_needed(&mut party, &mut sent)?;
+    for entry in transcript {
+        match entry {
+            TranscriptEntry::Incoming(_) if party.is_finished() => (),
+            TranscriptEntry::Incoming(envelope) => {
+                match party.handle_incoming(envelope.unwrap()) {
+                    Err(err) if err.is_critical() => return Err(ReplayError::HandleIncoming(err)),
+                    _ => (),
+                }
+                proceed_if_needed(&mut party, &mut sent)?;
+            }
+            TranscriptEntry::Outgoing(envelope) => match sent.pop_front() {
+                Some(msg) if same_message(&msg, envelope.msg()) => (),
+                _ => return Err(ReplayError::Diverged { round }),
+            },
+            TranscriptEntry::Round(recorded) => round = recorded,
+            TranscriptEntry::Header(_) => (),
+        }
+    }
+
+    if !party.is_finished() {
+        return Err(ReplayError::Incomplete {
+            round: party.current_round(),
+        });
+    }
+    match party.pick_output() {
+        Some(result) => result.map_err(ReplayError::Finish),
+        None => Err(ReplayError::Incomplete {
+            round: party.current_round(),
+        }),
+    }
+}
+
+fn proceed_if_needed<SM: StateMachine>(
+    party: &mut SM,
+    sent: &mut VecDeque<Msg<SM::MessageBody>>,
+) -> Result<(), ReplayError<SM::Err>> {
+    if party.wants_to_proceed() {
+        match party.proceed() {
+            Err(err) if err.is_critical() => return Err(ReplayError::Proceed(err)),
+            _ => (),
+        }
+    }
+    sent.extend(party.message_queue().drain(..));
+    Ok(())
+}
+
+fn same_message<B: Serialize>(sent: &Msg<B>, recorded: &Msg<B>) -> bool {
+    match (serde_json::to_value(sent), serde_json::to_value(recorded)) {
+        (Ok(sent), Ok(recorded)) => sent == recorded,
+        _ => false,
+    }
+}
+
+/// Outcome of a [replay] that didn't produce the output
+#[derive(Debug)]
+pub enum ReplayError<E> {
+    /// [Handling](crate::StateMachine::handle_incoming) a recorded message produced critical error
+    HandleIncoming(E),
+    /// [Proceeding](crate::StateMachine::proceed) produced critical error
+    Proceed(E),
+    /// [pick_output](crate::StateMachine::pick_output) returned error
+    Finish(E),
+    /// Transcript ended before the protocol did, e.g. because the party was waiting for
+    /// messages when its round timed out
+    Incomplete { round: u16 },
+    /// Party sent a message other than the recorded one, or none, at the recorded round. It
+    /// wasn't constructed from the same local inputs and seed, or draws randomness from elsewhere.
+    Diverged { round: u16 },
+}
+
+impl<E: fmt::Display> fmt::Display for ReplayError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HandleIncoming(err) => write!(f, "handle recorded message: {err}"),
+            Self::Proceed(err) => write!(f, "round proceed error: {err}"),
+            Self::Finish(err) => write!(f, "couldn't finish protocol: {err}"),
+            Self::Incomplete { round } => {
+                write!(f, "transcript ended while the party was at round {round}")
+            }
+            Self::Diverged { round } => {
+                write!(f, "party sent another message than recorded at round {round}")
+            }
+        }
+    }
+}
+
+impl<E> std::error::Error for ReplayError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::HandleIncoming(err) | Self::Proceed(err) | Self::Finish(err) => Some(err),
+            Self::Incomplete { .. } | Self::Diverged { .. } => None,
+        }
+    }
+}
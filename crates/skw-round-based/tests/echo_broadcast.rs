@@ -0,0 +1,114 @@
+use sha2::{Digest as _, Sha256};
+
+use skw_round_based::dev::Simulation;
+use skw_round_based::echo::{Digest, EchoBroadcast, EchoError, EchoMsg};
+use skw_round_based::{IsCritical, Msg, StateMachine};
+
+use crate::silly_protocol::{MultiPartyGenRandom, ProtocolMessage};
+
+#[allow(dead_code)]
+mod silly_protocol;
+
+fn digest(msg: &ProtocolMessage) -> Digest {
+    Sha256::digest(format!("{:?}", msg).as_bytes()).into()
+}
+
+fn party(i: u16, seed: u32) -> EchoBroadcast<MultiPartyGenRandom> {
+    let mut rnd = rand::thread_rng();
+    EchoBroadcast::new(
+        MultiPartyGenRandom::with_fixed_seed(i, 3, seed, &mut rnd),
+        digest,
+    )
+}
+
+#[test]
+fn echo_broadcast_honest_parties() {
+    let result = Simulation::new()
+        .add_party(party(1, 10))
+        .add_party(party(2, 20))
+        .add_party(party(3, 30))
+        .run()
+        .expect("simulation failed");
+    assert_eq!(result, vec![10 ^ 20 ^ 30; 3]);
+}
+
+#[test]
+fn echo_broadcast_detects_equivocation() {
+    // party 1 runs twice with different seeds: one instance talks to party 2, the other to party 3
+    let mut parties = vec![party(1, 10), party(1, 11), party(2, 20), party(3, 30)];
+    let recipients: [&[usize]; 4] = [&[2], &[3], &[0, 1, 3], &[0, 1, 2]];
+
+    for _ in 0..10 {
+        let mut outgoing: Vec<(usize, Msg<EchoMsg<ProtocolMessage>>)> = vec![];
+        for (i, p) in parties.iter_mut().enumerate() {
+            if p.wants_to_proceed() {
+                p.proceed().expect("proceed failed");
+            }
+            outgoing.extend(p.message_queue().drain(..).map(|m| (i, m)));
+        }
+
+        for (from, msg) in outgoing {
+            for &to in recipients[from] {
+                if msg.receiver.is_some() && msg.receiver != Some(parties[to].party_ind()) {
+                    continue;
+                }
+                match parties[to].handle_incoming(msg.clone()) {
+                    Err(EchoError::Equivocation { round, sender, .. }) => {
+                        assert_eq!((round, sender), (1, 1));
+                        return;
+                    }
+                    Err(err) => assert!(!err.is_critical(), "unexpected error: {:?}", err),
+                    Ok(()) => (),
+                }
+            }
+        }
+
+        assert!(
+            parties.iter().all(|p| !p.is_finished()),
+            "protocol finished despite equivocation"
+        );
+    }
+
+    panic!("equivocation was not detected")
+}
+
+#[test]
+fn echo_broadcast_tags_messages_with_their_round() {
+    let mut parties = vec![party(1, 10), party(2, 20), party(3, 30)];
+
+    // parties 2 and 3 start first, so party 1 receives round 1 before it left round 0
+    let mut outgoing: Vec<Msg<EchoMsg<ProtocolMessage>>> = vec![];
+    for p in &mut parties[1..] {
+        p.proceed().expect("proceed failed");
+        outgoing.append(p.message_queue());
+    }
+
+    for _ in 0..10 {
+        for msg in outgoing.drain(..) {
+            if let EchoMsg::Protocol { round, body } = &msg.body {
+                let expected = if format!("{:?}", body).contains("Round1") { 1 } else { 2 };
+                assert_eq!(*round, expected, "{:?} tagged with round {}", body, round);
+            }
+            for p in parties.iter_mut().filter(|p| p.party_ind() != msg.sender) {
+                if msg.receiver.is_none() || msg.receiver == Some(p.party_ind()) {
+                    p.handle_incoming(msg.clone()).expect("handle incoming failed");
+                }
+            }
+        }
+        for p in &mut parties {
+            if p.wants_to_proceed() {
+                p.proceed().expect("proceed failed");
+            }
+            outgoing.append(p.message_queue());
+        }
+        if parties.iter().all(|p| p.is_finished()) {
+            break;
+        }
+    }
+
+    let outputs: Vec<_> = parties
+        .iter_mut()
+        .map(|p| p.pick_output().expect("protocol not finished").expect("protocol failed"))
+        .collect();
+    assert_eq!(outputs, vec![10 ^ 20 ^ 30; 3]);
+}
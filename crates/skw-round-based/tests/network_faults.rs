@@ -0,0 +1,102 @@
+use skw_round_based::async_runtime;
+use skw_round_based::dev::{
+    AsyncSimulation, AsyncSimulationError, Fault, FaultRule, NetworkFaults, Simulation,
+};
+use skw_round_based::Msg;
+
+use crate::silly_protocol::{Error, MultiPartyGenRandom, ProceedError, ProtocolMessage};
+
+#[allow(dead_code)]
+mod silly_protocol;
+
+fn is_reveal(msg: &Msg<ProtocolMessage>) -> bool {
+    msg.body.is_reveal()
+}
+
+fn simulate(faults: NetworkFaults<ProtocolMessage>) -> Result<Vec<u32>, Error> {
+    let mut rnd = rand::thread_rng();
+    Simulation::<MultiPartyGenRandom>::new()
+        .inject_faults(faults)
+        .add_party(MultiPartyGenRandom::with_fixed_seed(1, 3, 10, &mut rnd))
+        .add_party(MultiPartyGenRandom::with_fixed_seed(2, 3, 20, &mut rnd))
+        .add_party(MultiPartyGenRandom::with_fixed_seed(3, 3, 30, &mut rnd))
+        .run()
+}
+
+fn blamed(result: Result<Vec<u32>, Error>) -> Option<Vec<u16>> {
+    match result {
+        Err(Error::ProceedRound(ProceedError::PartiesDidntRevealItsSeed { party_ind })) => {
+            Some(party_ind)
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn delayed_and_reordered_messages() {
+    for seed in 0..16 {
+        let mut faults = NetworkFaults::new(seed);
+        faults
+            .add_rule(FaultRule::new(Fault::Delay(3)).from(3))
+            .add_rule(FaultRule::new(Fault::Reorder).with_probability(0.5));
+
+        let result = simulate(faults).expect("simulation failed");
+        assert_eq!(result, vec![10 ^ 20 ^ 30; 3], "seed {}", seed);
+    }
+}
+
+#[test]
+fn dropped_reveal_times_out() {
+    let mut faults = NetworkFaults::new(0);
+    faults.add_rule(FaultRule::new(Fault::Drop).from(2).to(1).matching(is_reveal));
+
+    assert_eq!(blamed(simulate(faults)), Some(vec![2]));
+}
+
+#[test]
+fn corrupted_reveal_is_blamed() {
+    let mut faults = NetworkFaults::new(0);
+    faults
+        .corrupt_with(ProtocolMessage::corrupt)
+        .add_rule(FaultRule::new(Fault::Corrupt).from(3).to(1).matching(is_reveal));
+
+    assert_eq!(blamed(simulate(faults)), Some(vec![3]));
+}
+
+#[test]
+fn duplicated_message_is_rejected() {
+    let mut faults = NetworkFaults::new(0);
+    faults.add_rule(FaultRule::new(Fault::Duplicate).from(2).to(1));
+
+    assert!(matches!(simulate(faults), Err(Error::HandleMsg(_))));
+}
+
+#[tokio::test]
+async fn async_simulation_with_dropped_reveal() {
+    let mut faults = NetworkFaults::new(0);
+    faults.add_rule(FaultRule::new(Fault::Drop).from(3).matching(is_reveal));
+
+    let mut rnd = rand::thread_rng();
+    let results = AsyncSimulation::<MultiPartyGenRandom>::new()
+        .inject_faults(faults)
+        .add_party(MultiPartyGenRandom::with_fixed_seed(1, 3, 43, &mut rnd))
+        .add_party(MultiPartyGenRandom::with_fixed_seed(2, 3, 44, &mut rnd))
+        .add_party(MultiPartyGenRandom::with_fixed_seed(3, 3, 45, &mut rnd))
+        .run()
+        .await;
+
+    let blamed = |x| match x {
+        &Err(AsyncSimulationError::ProtocolExecution(
+            async_runtime::Error::HandleIncomingTimeout(Error::ProceedRound(
+                ProceedError::PartiesDidntRevealItsSeed { ref party_ind },
+            )),
+        )) => Some(party_ind.clone()),
+        _ => None,
+    };
+    let predicate = |(i, x)| match i {
+        0..=1 => blamed(x) == Some(vec![3]),
+        2 => x.is_ok() && *x.as_ref().unwrap() == 43 ^ 44 ^ 45,
+        _ => unreachable!(),
+    };
+    assert!(results.iter().enumerate().all(predicate))
+}
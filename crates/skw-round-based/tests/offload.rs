@@ -0,0 +1,172 @@
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::{sink, stream, StreamExt};
+
+use skw_round_based::async_runtime::offload::{Inline, Offload, TokioBlocking};
+use skw_round_based::async_runtime::{self, AsyncProtocol};
+use skw_round_based::{IsCritical, Msg, StateMachine};
+
+/// Single party protocol whose only round is proceeded by `work`
+struct Heavy {
+    work: Arc<dyn Fn() -> Result<(), Stuck> + Send + Sync>,
+    finished: bool,
+    queue: Vec<Msg<()>>,
+}
+
+impl Heavy {
+    fn new(work: impl Fn() -> Result<(), Stuck> + Send + Sync + 'static) -> Self {
+        Self {
+            work: Arc::new(work),
+            finished: false,
+            queue: vec![],
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Stuck;
+
+impl IsCritical for Stuck {
+    fn is_critical(&self) -> bool {
+        true
+    }
+}
+
+impl StateMachine for Heavy {
+    type MessageBody = ();
+    type Err = Stuck;
+    type Output = ();
+
+    fn handle_incoming(&mut self, _msg: Msg<()>) -> Result<(), Stuck> {
+        Ok(())
+    }
+
+    fn message_queue(&mut self) -> &mut Vec<Msg<()>> {
+        &mut self.queue
+    }
+
+    fn wants_to_proceed(&self) -> bool {
+        !self.finished
+    }
+
+    fn proceed(&mut self) -> Result<(), Stuck> {
+        (self.work)()?;
+        self.finished = true;
+        Ok(())
+    }
+
+    fn round_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn round_timeout_reached(&mut self) -> Stuck {
+        Stuck
+    }
+
+    fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn pick_output(&mut self) -> Option<Result<(), Stuck>> {
+        self.finished.then(|| Ok(()))
+    }
+
+    fn current_round(&self) -> u16 {
+        u16::from(self.finished)
+    }
+
+    fn total_rounds(&self) -> Option<u16> {
+        Some(1)
+    }
+
+    fn party_ind(&self) -> u16 {
+        1
+    }
+
+    fn parties(&self) -> u16 {
+        1
+    }
+}
+
+async fn run<X: Offload + 'static>(
+    sm: Heavy,
+    offload: X,
+) -> Result<(), async_runtime::Error<Stuck, Infallible, Infallible>> {
+    AsyncProtocol::new(
+        sm,
+        stream::empty::<Result<Msg<()>, Infallible>>().fuse(),
+        sink::drain(),
+        (),
+    )
+    .set_offload(offload)
+    .run()
+    .await
+}
+
+/// Two protocols whose proceed only returns once both of them are proceeding at the same time
+async fn run_in_parallel<X: Offload + Clone + 'static>(offload: X, patience: Duration) -> bool {
+    let proceeding = Arc::new(AtomicUsize::new(0));
+    let rendezvous = move || {
+        let proceeding = proceeding.clone();
+        Heavy::new(move || {
+            proceeding.fetch_add(1, Ordering::SeqCst);
+            let started_at = Instant::now();
+            while proceeding.load(Ordering::SeqCst) < 2 {
+                if started_at.elapsed() > patience {
+                    return Err(Stuck);
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            Ok(())
+        })
+    };
+
+    let (a, b) = futures::join!(
+        run(rendezvous(), offload.clone()),
+        run(rendezvous(), offload)
+    );
+    a.is_ok() && b.is_ok()
+}
+
+#[tokio::test]
+async fn inline_blocks_the_executor() {
+    assert!(!run_in_parallel(Inline, Duration::from_millis(200)).await);
+}
+
+#[tokio::test]
+async fn tokio_blocking_proceeds_in_parallel() {
+    assert!(run_in_parallel(TokioBlocking, Duration::from_secs(5)).await);
+}
+
+#[cfg(feature = "rayon")]
+#[tokio::test]
+async fn rayon_pool_proceeds_in_parallel() {
+    use skw_round_based::async_runtime::offload::RayonPool;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    let offload = RayonPool::new(Arc::new(pool));
+    assert!(run_in_parallel(offload, Duration::from_secs(5)).await);
+}
+
+#[tokio::test]
+async fn panic_in_proceed_is_reported() {
+    let sm = Heavy::new(|| panic!("proceed exploded"));
+    let result = run(sm, TokioBlocking).await;
+    assert!(
+        matches!(&result, Err(async_runtime::Error::ProceedPanicked(msg)) if msg == "proceed exploded"),
+        "unexpected result: {:?}",
+        result
+    );
+
+    let sm = Heavy::new(|| panic!("proceed exploded"));
+    assert!(matches!(
+        run(sm, Inline).await,
+        Err(async_runtime::Error::ProceedPanicked(_))
+    ));
+}
@@ -0,0 +1,122 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use futures::{sink, stream, StreamExt};
+
+use skw_round_based::async_runtime::{self, watcher::ProtocolWatcher, watcher::When};
+use skw_round_based::async_runtime::AsyncProtocol;
+use skw_round_based::envelope::{Envelope, Header, InvalidMessage};
+use skw_round_based::{Msg, StateMachine};
+
+use crate::silly_protocol::{MultiPartyGenRandom, ProtocolMessage};
+
+#[allow(dead_code)]
+mod silly_protocol;
+
+#[derive(Clone)]
+struct Job {
+    id: u64,
+    n: u16,
+}
+
+impl Header for Job {
+    fn check(&self, job: &Self) -> Result<(), InvalidMessage> {
+        if self.id != job.id {
+            Err(InvalidMessage::ForeignJob)
+        } else if self.n != job.n {
+            Err(InvalidMessage::HeaderMismatch)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+struct Framed {
+    job: Job,
+    msg: Msg<ProtocolMessage>,
+}
+
+impl Envelope<ProtocolMessage> for Framed {
+    type Header = Job;
+
+    fn wrap(job: Job, msg: Msg<ProtocolMessage>) -> Self {
+        Self { job, msg }
+    }
+
+    fn header(&self) -> &Job {
+        &self.job
+    }
+
+    fn msg(&self) -> &Msg<ProtocolMessage> {
+        &self.msg
+    }
+
+    fn unwrap(self) -> Msg<ProtocolMessage> {
+        self.msg
+    }
+}
+
+#[derive(Clone, Default)]
+struct Dropped(Arc<Mutex<Vec<InvalidMessage>>>);
+
+impl ProtocolWatcher<MultiPartyGenRandom> for Dropped {
+    fn caught_non_critical_error(&mut self, _when: When, err: silly_protocol::Error) {
+        panic!("message reached the state machine: {:?}", err)
+    }
+
+    fn caught_invalid_message(&mut self, _msg: &Msg<ProtocolMessage>, err: InvalidMessage) {
+        self.0.lock().unwrap().push(err)
+    }
+}
+
+#[tokio::test]
+async fn invalid_messages_are_dropped() {
+    let mut rnd = rand::thread_rng();
+    let job = Job { id: 1, n: 3 };
+
+    // a genuine round 1 message of party 2
+    let mut party2 = MultiPartyGenRandom::with_fixed_seed(2, 3, 20, &mut rnd);
+    party2.proceed().unwrap();
+    let msg = party2.message_queue().pop().unwrap();
+
+    let framed = |job: Job, edit: fn(&mut Msg<ProtocolMessage>)| {
+        let mut msg = msg.clone();
+        edit(&mut msg);
+        Framed::wrap(job, msg)
+    };
+    let foreign_job = framed(Job { id: 2, n: 3 }, |_| ());
+    let other_n = framed(Job { id: 1, n: 4 }, |_| ());
+    let own = framed(job.clone(), |m| m.sender = 1);
+    let out_of_range = framed(job.clone(), |m| m.sender = 4);
+    let for_party_3 = framed(job.clone(), |m| m.receiver = Some(3));
+
+    let incoming = stream::iter(
+        vec![foreign_job, other_n, own, out_of_range, for_party_3]
+            .into_iter()
+            .map(Ok::<_, Infallible>),
+    )
+    .fuse();
+
+    let dropped = Dropped::default();
+    let result = AsyncProtocol::new(
+        MultiPartyGenRandom::with_fixed_seed(1, 3, 10, &mut rnd),
+        incoming,
+        sink::drain(),
+        job,
+    )
+    .set_watcher(dropped.clone())
+    .run()
+    .await;
+
+    assert!(matches!(result, Err(async_runtime::Error::RecvEof)));
+    assert_eq!(
+        *dropped.0.lock().unwrap(),
+        vec![
+            InvalidMessage::ForeignJob,
+            InvalidMessage::HeaderMismatch,
+            InvalidMessage::OwnMessage,
+            InvalidMessage::SenderOutOfRange { sender: 4 },
+            InvalidMessage::NotAddressedToUs { receiver: 3 },
+        ]
+    );
+}
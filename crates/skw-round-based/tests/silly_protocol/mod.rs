@@ -3,6 +3,7 @@ use std::mem::replace;
 use std::time::Duration;
 
 use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
 
 use skw_round_based::containers::{
     push::{Push, PushExt},
@@ -300,15 +301,32 @@ pub enum R {
 /// Protocol message
 ///
 /// Hides message structure so it could be changed without breaking semver policy.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProtocolMessage(M);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 enum M {
     Round1(rounds::CommittedSeed),
     Round2(rounds::RevealedSeed),
 }
 
+// not every test crate including this module tampers with messages
+#[allow(dead_code)]
+impl ProtocolMessage {
+    /// Whether the message reveals the seed (round 2)
+    pub fn is_reveal(&self) -> bool {
+        matches!(self.0, M::Round2(_))
+    }
+
+    /// Tamper with the message, as a malicious party or a broken link would
+    pub fn corrupt(&mut self, noise: u64) {
+        match &mut self.0 {
+            M::Round1(m) => m.corrupt(noise),
+            M::Round2(m) => m.corrupt(noise),
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
@@ -2,6 +2,7 @@ use skw_round_based::containers::push::Push;
 use skw_round_based::containers::{self, BroadcastMsgs, Store};
 use skw_round_based::Msg;
 use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub struct Round0 {
@@ -127,15 +128,29 @@ pub type OutputRandomValue = u32;
 
 // Messages
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CommittedSeed([u8; 32]);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RevealedSeed {
     seed: u32,
     blinding: [u8; 32],
 }
 
+#[allow(dead_code)]
+impl CommittedSeed {
+    pub fn corrupt(&mut self, noise: u64) {
+        self.0[0] ^= noise as u8 | 1;
+    }
+}
+
+#[allow(dead_code)]
+impl RevealedSeed {
+    pub fn corrupt(&mut self, noise: u64) {
+        self.seed ^= noise as u32 | 1;
+    }
+}
+
 // Errors
 
 type Result<T> = std::result::Result<T, ProceedError>;
@@ -0,0 +1,129 @@
+use rand::{rngs::StdRng, SeedableRng};
+use skw_round_based::transcript::{
+    read_transcript, replay, Entry, Recorder, ReplayError, TranscriptEntry, TranscriptHeader,
+    TranscriptWriter,
+};
+use skw_round_based::{Msg, StateMachine};
+
+use crate::silly_protocol::{Error, MultiPartyGenRandom, ProceedError, ProtocolMessage};
+
+#[allow(dead_code)]
+mod silly_protocol;
+
+/// Party whose blinding is drawn from `seed` too, so that it can be replayed
+fn party(i: u16, seed: u32) -> MultiPartyGenRandom {
+    let mut rnd = StdRng::seed_from_u64(seed.into());
+    MultiPartyGenRandom::with_fixed_seed(i, 3, seed, &mut rnd)
+}
+
+fn header() -> TranscriptHeader {
+    TranscriptHeader {
+        protocol: "silly".into(),
+        inputs: serde_json::json!({ "i": 1, "n": 3 }),
+        sealed_inputs: None,
+    }
+}
+
+/// Runs honest parties to completion, recording party 1
+fn record_party_1() -> Vec<u8> {
+    let mut parties = vec![party(1, 10), party(2, 20), party(3, 30)];
+    let mut recorder = TranscriptWriter::new(vec![]);
+    recorder.record(Entry::<Msg<ProtocolMessage>>::Header(&header()));
+    let mut round = None;
+
+    while !parties.iter().all(|p| p.is_finished()) {
+        let mut outgoing = vec![];
+        for p in parties.iter_mut() {
+            if p.wants_to_proceed() {
+                p.proceed().expect("proceed failed");
+            }
+            outgoing.extend(p.message_queue().drain(..));
+        }
+        if round != Some(parties[0].current_round()) {
+            round = Some(parties[0].current_round());
+            recorder.record(Entry::<Msg<ProtocolMessage>>::Round(parties[0].current_round()));
+        }
+
+        for msg in outgoing {
+            if msg.sender == 1 {
+                recorder.record(Entry::Outgoing(&msg));
+            }
+            for p in parties.iter_mut() {
+                let i = p.party_ind();
+                if i == msg.sender || msg.receiver.map(|r| r != i).unwrap_or(false) {
+                    continue;
+                }
+                if i == 1 {
+                    recorder.record(Entry::Incoming(&msg));
+                }
+                p.handle_incoming(msg.clone()).expect("handle message failed");
+            }
+        }
+    }
+
+    recorder.into_inner()
+}
+
+#[test]
+fn replay_reproduces_output() {
+    let raw = record_party_1();
+    let transcript = read_transcript::<Msg<ProtocolMessage>, _>(&raw[..]).unwrap();
+
+    assert!(matches!(&transcript[0], TranscriptEntry::Header(h) if *h == header()));
+    assert!(matches!(transcript[1], TranscriptEntry::Round(_)));
+    assert!(transcript
+        .iter()
+        .any(|e| matches!(e, TranscriptEntry::Outgoing(_))));
+
+    // secret seed of party 1 isn't in the transcript, it's provided again
+    assert_eq!(replay(party(1, 10), transcript).unwrap(), 10 ^ 20 ^ 30);
+}
+
+#[test]
+fn replay_reproduces_failure() {
+    let raw = record_party_1();
+    let mut transcript = read_transcript::<Msg<ProtocolMessage>, _>(&raw[..]).unwrap();
+    for entry in transcript.iter_mut() {
+        if let TranscriptEntry::Incoming(msg) = entry {
+            if msg.sender == 3 && msg.body.is_reveal() {
+                msg.body.corrupt(1);
+            }
+        }
+    }
+
+    match replay(party(1, 10), transcript) {
+        Err(ReplayError::Proceed(Error::ProceedRound(
+            ProceedError::PartiesDidntRevealItsSeed { party_ind },
+        ))) => assert_eq!(party_ind, vec![3]),
+        result => panic!("unexpected replay result: {:?}", result),
+    }
+}
+
+#[test]
+fn replay_of_truncated_transcript() {
+    let raw = record_party_1();
+    let transcript = read_transcript::<Msg<ProtocolMessage>, _>(&raw[..]).unwrap();
+    let truncated: Vec<_> = transcript
+        .into_iter()
+        .filter(|e| !matches!(e, TranscriptEntry::Incoming(m) if m.body.is_reveal()))
+        .collect();
+
+    assert!(matches!(
+        replay(party(1, 10), truncated),
+        Err(ReplayError::Incomplete { .. })
+    ));
+}
+
+#[test]
+fn replay_detects_divergence() {
+    let raw = record_party_1();
+    let transcript = read_transcript::<Msg<ProtocolMessage>, _>(&raw[..]).unwrap();
+
+    // blinding drawn from another seed changes the commitment party 1 sends first
+    let mut rnd = StdRng::seed_from_u64(11);
+    let party_1 = MultiPartyGenRandom::with_fixed_seed(1, 3, 10, &mut rnd);
+    assert!(matches!(
+        replay(party_1, transcript),
+        Err(ReplayError::Diverged { round: 1 })
+    ));
+}
@@ -0,0 +1,112 @@
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::{sink, stream, StreamExt};
+
+use skw_round_based::async_runtime::{self, watcher::ProtocolWatcher, watcher::When};
+use skw_round_based::async_runtime::AsyncProtocol;
+use skw_round_based::{Msg, StateMachine};
+
+use crate::silly_protocol::{MultiPartyGenRandom, ProtocolMessage};
+
+#[allow(dead_code)]
+mod silly_protocol;
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    RoundStarted(u16),
+    RoundFinished(u16),
+    MessageReceived { sender: u16 },
+    MessageSent { receiver: Option<u16> },
+    Proceeded(u16),
+    RoundTimedOut(u16),
+}
+
+#[derive(Clone, Default)]
+struct Recording(Arc<Mutex<Vec<Event>>>);
+
+impl Recording {
+    fn push(&self, event: Event) {
+        self.0.lock().unwrap().push(event)
+    }
+}
+
+impl ProtocolWatcher<MultiPartyGenRandom> for Recording {
+    fn caught_non_critical_error(&mut self, _when: When, err: silly_protocol::Error) {
+        panic!("unexpected non critical error: {:?}", err)
+    }
+
+    fn round_started(&mut self, round: u16) {
+        self.push(Event::RoundStarted(round))
+    }
+
+    fn round_finished(&mut self, round: u16, _took: Duration) {
+        self.push(Event::RoundFinished(round))
+    }
+
+    fn message_received(&mut self, msg: &Msg<ProtocolMessage>) {
+        self.push(Event::MessageReceived { sender: msg.sender })
+    }
+
+    fn message_sent(&mut self, msg: &Msg<ProtocolMessage>) {
+        self.push(Event::MessageSent { receiver: msg.receiver })
+    }
+
+    fn proceeded(&mut self, round: u16, _took: Duration) {
+        self.push(Event::Proceeded(round))
+    }
+
+    fn round_timed_out(&mut self, round: u16) {
+        self.push(Event::RoundTimedOut(round))
+    }
+}
+
+#[tokio::test]
+async fn watcher_sees_every_event() {
+    let mut rnd = rand::thread_rng();
+
+    // round 1 messages of parties 2 and 3, who never reveal their seeds afterwards
+    let mut round1 = vec![];
+    for (i, seed) in [(2, 20), (3, 30)] {
+        let mut party = MultiPartyGenRandom::with_fixed_seed(i, 3, seed, &mut rnd);
+        party.proceed().unwrap();
+        round1.append(party.message_queue());
+    }
+    let incoming = stream::iter(round1.into_iter().map(Ok::<_, Infallible>))
+        .chain(stream::pending())
+        .fuse();
+
+    let recording = Recording::default();
+    let result = AsyncProtocol::new(
+        MultiPartyGenRandom::with_fixed_seed(1, 3, 10, &mut rnd),
+        incoming,
+        sink::drain(),
+        (),
+    )
+    .set_watcher(recording.clone())
+    .run()
+    .await;
+
+    assert!(matches!(
+        result,
+        Err(async_runtime::Error::HandleIncomingTimeout(_))
+    ));
+    assert_eq!(
+        *recording.0.lock().unwrap(),
+        vec![
+            Event::RoundStarted(0),
+            Event::Proceeded(0),
+            Event::MessageSent { receiver: None },
+            Event::RoundFinished(0),
+            Event::RoundStarted(1),
+            Event::MessageReceived { sender: 2 },
+            Event::MessageReceived { sender: 3 },
+            // round 1 proceeds right on the last message, it's cheap
+            Event::MessageSent { receiver: None },
+            Event::RoundFinished(1),
+            Event::RoundStarted(2),
+            Event::RoundTimedOut(2),
+        ]
+    );
+}
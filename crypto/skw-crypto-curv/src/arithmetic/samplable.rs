@@ -1,5 +1,3 @@
-use rand::{rngs::OsRng, RngCore};
-
 use super::traits::{BitManipulation, Converter, Samplable, Zero};
 use super::BigInt;
 
@@ -35,10 +33,9 @@ impl Samplable for BigInt {
         if bit_size == 0 {
             return BigInt::zero();
         }
-        let mut rng = OsRng;
         let bytes = (bit_size - 1) / 8 + 1;
         let mut buf: Vec<u8> = vec![0; bytes];
-        rng.fill_bytes(&mut buf);
+        crate::rng::fill_bytes(&mut buf);
         BigInt::from_bytes(&buf) >> (bytes * 8 - bit_size)
     }
 
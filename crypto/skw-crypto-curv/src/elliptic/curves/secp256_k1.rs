@@ -161,7 +161,13 @@ impl ECScalar for Secp256k1Scalar {
     type ScalarLength = typenum::U32;
 
     fn random() -> Secp256k1Scalar {
-        let sk = SK(SecretKey::new(&mut rand_legacy::thread_rng()));
+        let mut bytes = Zeroizing::new([0u8; 32]);
+        let sk = loop {
+            crate::rng::fill_bytes(&mut bytes[..]);
+            if let Ok(sk) = SecretKey::from_slice(&bytes[..]) {
+                break SK(sk);
+            }
+        };
         Secp256k1Scalar {
             purpose: "random",
             fe: Zeroizing::new(Some(sk)),
@@ -11,6 +11,8 @@ pub use crate::arithmetic::BigInt;
 
 pub mod cryptographic_primitives;
 
+pub mod rng;
+
 mod marker;
 pub use marker::HashChoice;
 
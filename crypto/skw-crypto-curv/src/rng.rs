@@ -0,0 +1,73 @@
+/*
+    This file is part of Curv library
+    Copyright 2018 by Kzen Networks
+    (https://github.com/KZen-networks/curv)
+    License MIT: <https://github.com/KZen-networks/curv/blob/master/LICENSE>
+*/
+
+//! Randomness behind [`BigInt::sample`](crate::arithmetic::Samplable::sample) and
+//! `Secp256k1Scalar::random`
+//!
+//! Bytes come from the OS unless the calling thread runs inside [`with_seed`], which makes every
+//! sample drawn on that thread reproducible. Samples drawn on other threads (e.g. rayon workers)
+//! still come from the OS.
+
+use std::cell::RefCell;
+
+use rand::rngs::{OsRng, StdRng};
+use rand::{RngCore, SeedableRng};
+
+thread_local! {
+    static SEEDED: RefCell<Option<StdRng>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` drawing every sample of the current thread from a generator seeded with `seed`
+///
+/// Scopes nest: the enclosing generator, if any, is restored once `f` returns or unwinds.
+pub fn with_seed<T>(seed: [u8; 32], f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<StdRng>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            SEEDED.with(|rng| *rng.borrow_mut() = previous);
+        }
+    }
+
+    let previous = SEEDED.with(|rng| rng.borrow_mut().replace(StdRng::from_seed(seed)));
+    let _restore = Restore(previous);
+    f()
+}
+
+/// Fills `dest` from the seeded generator of [`with_seed`], or from the OS outside of it
+pub fn fill_bytes(dest: &mut [u8]) {
+    SEEDED.with(|rng| match rng.borrow_mut().as_mut() {
+        Some(rng) => rng.fill_bytes(dest),
+        None => OsRng.fill_bytes(dest),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arithmetic::traits::*;
+    use crate::BigInt;
+
+    #[test]
+    fn same_seed_same_samples() {
+        let sample = || (BigInt::sample(256), BigInt::sample_below(&BigInt::from(1_000_003)));
+        assert_eq!(with_seed([7; 32], sample), with_seed([7; 32], sample));
+        assert_ne!(with_seed([7; 32], sample), with_seed([8; 32], sample));
+    }
+
+    #[test]
+    fn scopes_nest() {
+        let outer = with_seed([1; 32], || {
+            let first = BigInt::sample(128);
+            let inner = with_seed([2; 32], || BigInt::sample(128));
+            (first, inner, BigInt::sample(128))
+        });
+        let expected = with_seed([1; 32], || (BigInt::sample(128), BigInt::sample(128)));
+        assert_eq!((outer.0, outer.2), expected);
+        assert_eq!(outer.1, with_seed([2; 32], || BigInt::sample(128)));
+    }
+}